@@ -20,12 +20,20 @@ mod server_api;
 mod transactions_api;
 mod utils;
 
+use self::blocks_api::BlockFilterHandler;
 use self::blocks_api::BlockHandler;
+use self::blocks_api::BlockStatsHandler;
 use self::blocks_api::HeaderHandler;
 use self::chain_api::ChainCompactHandler;
 use self::chain_api::ChainHandler;
+use self::chain_api::ChainOrphansHandler;
+use self::chain_api::ChainProofHandler;
 use self::chain_api::ChainValidationHandler;
+use self::chain_api::KernelProofHandler;
 use self::chain_api::OutputHandler;
+use self::chain_api::OutputProofHandler;
+use self::chain_api::OutputsAtHeightHandler;
+use self::chain_api::PaymentProofHandler;
 use self::peers_api::PeerHandler;
 use self::peers_api::PeersAllHandler;
 use self::peers_api::PeersConnectedHandler;
@@ -89,12 +97,22 @@ pub fn build_router(
 ) -> Result<Router, RouterError> {
 	let route_list = vec![
 		"get blocks".to_string(),
+		"get blocks/xxx/stats".to_string(),
+		"get blocks/stats?start_height=101&end_height=200".to_string(),
+		"get blocks/xxx/filter".to_string(),
+		"get blocks/filters?start_height=101&end_height=200".to_string(),
 		"get headers".to_string(),
 		"get chain".to_string(),
 		"post chain/compact".to_string(),
 		"get chain/validate".to_string(),
+		"get chain/orphans".to_string(),
+		"get chain/proof".to_string(),
 		"get chain/outputs/byids?id=xxx,yyy,zzz".to_string(),
 		"get chain/outputs/byheight?start_height=101&end_height=200".to_string(),
+		"get chain/outputs/at/101?commits=xxx,yyy,zzz".to_string(),
+		"get chain/outputs/proof/xxx".to_string(),
+		"get chain/kernels/proof/xxx".to_string(),
+		"post chain/kernels/payment_proof".to_string(),
 		"get status".to_string(),
 		"get txhashset/roots".to_string(),
 		"get txhashset/lastoutputs?n=10".to_string(),
@@ -116,9 +134,33 @@ pub fn build_router(
 		chain: Arc::downgrade(&chain),
 	};
 
+	let outputs_at_height_handler = OutputsAtHeightHandler {
+		chain: Arc::downgrade(&chain),
+	};
+
+	let output_proof_handler = OutputProofHandler {
+		chain: Arc::downgrade(&chain),
+		peers: Arc::downgrade(&peers),
+	};
+
+	let kernel_proof_handler = KernelProofHandler {
+		chain: Arc::downgrade(&chain),
+	};
+
+	let payment_proof_handler = PaymentProofHandler {
+		chain: Arc::downgrade(&chain),
+	};
+
 	let block_handler = BlockHandler {
 		chain: Arc::downgrade(&chain),
 	};
+	let block_stats_handler = Arc::new(BlockStatsHandler {
+		chain: Arc::downgrade(&chain),
+	});
+	let block_filter_handler = Arc::new(BlockFilterHandler {
+		chain: Arc::downgrade(&chain),
+		peers: Arc::downgrade(&peers),
+	});
 	let header_handler = HeaderHandler {
 		chain: Arc::downgrade(&chain),
 	};
@@ -131,6 +173,12 @@ pub fn build_router(
 	let chain_validation_handler = ChainValidationHandler {
 		chain: Arc::downgrade(&chain),
 	};
+	let chain_orphans_handler = ChainOrphansHandler {
+		chain: Arc::downgrade(&chain),
+	};
+	let chain_proof_handler = ChainProofHandler {
+		chain: Arc::downgrade(&chain),
+	};
 	let status_handler = StatusHandler {
 		chain: Arc::downgrade(&chain),
 		peers: Arc::downgrade(&peers),
@@ -157,12 +205,28 @@ pub fn build_router(
 	let mut router = Router::new();
 
 	router.add_route("/v1/", Arc::new(index_handler))?;
+	// The literal "stats" and "filters" routes must be added before the "*" route,
+	// as wildcards also match literal path elements.
+	router.add_route("/v1/blocks/stats", block_stats_handler.clone())?;
+	router.add_route("/v1/blocks/filters", block_filter_handler.clone())?;
 	router.add_route("/v1/blocks/*", Arc::new(block_handler))?;
+	router.add_route("/v1/blocks/*/stats", block_stats_handler)?;
+	router.add_route("/v1/blocks/*/filter", block_filter_handler)?;
 	router.add_route("/v1/headers/*", Arc::new(header_handler))?;
 	router.add_route("/v1/chain", Arc::new(chain_tip_handler))?;
+	// Same for the literal "at" and "proof" output routes.
+	router.add_route(
+		"/v1/chain/outputs/at/*",
+		Arc::new(outputs_at_height_handler),
+	)?;
+	router.add_route("/v1/chain/outputs/proof/*", Arc::new(output_proof_handler))?;
 	router.add_route("/v1/chain/outputs/*", Arc::new(output_handler))?;
+	router.add_route("/v1/chain/kernels/proof/*", Arc::new(kernel_proof_handler))?;
+	router.add_route("/v1/chain/kernels/payment_proof", Arc::new(payment_proof_handler))?;
 	router.add_route("/v1/chain/compact", Arc::new(chain_compact_handler))?;
 	router.add_route("/v1/chain/validate", Arc::new(chain_validation_handler))?;
+	router.add_route("/v1/chain/orphans", Arc::new(chain_orphans_handler))?;
+	router.add_route("/v1/chain/proof", Arc::new(chain_proof_handler))?;
 	router.add_route("/v1/txhashset/*", Arc::new(txhashset_handler))?;
 	router.add_route("/v1/status", Arc::new(status_handler))?;
 	router.add_route("/v1/pool", Arc::new(pool_info_handler))?;
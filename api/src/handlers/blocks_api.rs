@@ -15,6 +15,7 @@ use super::utils::{get_output, w};
 use crate::chain;
 use crate::core::core::hash::Hash;
 use crate::core::core::hash::Hashed;
+use crate::p2p;
 use crate::rest::*;
 use crate::router::{Handler, ResponseFuture};
 use crate::types::*;
@@ -24,6 +25,11 @@ use failure::ResultExt;
 use hyper::{Body, Request, StatusCode};
 use regex::Regex;
 use std::sync::Weak;
+use std::time::Duration;
+
+/// How long the block filter handler waits for a filter from a peer, blocking
+/// the API thread serving the request meanwhile.
+pub const BLOCK_FILTER_TIMEOUT: Duration = Duration::from_secs(10);
 
 /// Gets block headers given either a hash or height or an output commit.
 /// GET /v1/headers/<hash>
@@ -100,17 +106,22 @@ impl BlockHandler {
 
 	// Try to decode the string as a height or a hash.
 	fn parse_input(&self, input: String) -> Result<Hash, Error> {
-		if let Ok(height) = input.parse() {
-			match w(&self.chain)?.get_header_by_height(height) {
-				Ok(header) => return Ok(header.hash()),
-				Err(_) => return Err(ErrorKind::NotFound)?,
-			}
+		parse_block_input(&self.chain, input)
+	}
+}
+
+// Try to decode the string as a height (on the current chain) or a hash.
+fn parse_block_input(chain: &Weak<chain::Chain>, input: String) -> Result<Hash, Error> {
+	if let Ok(height) = input.parse() {
+		match w(chain)?.get_header_by_height(height) {
+			Ok(header) => return Ok(header.hash()),
+			Err(_) => return Err(ErrorKind::NotFound)?,
 		}
-		check_block_param(&input)?;
-		let vec = util::from_hex(input)
-			.map_err(|e| ErrorKind::Argument(format!("invalid input: {}", e)))?;
-		Ok(Hash::from_vec(&vec))
 	}
+	check_block_param(&input)?;
+	let vec =
+		util::from_hex(input).map_err(|e| ErrorKind::Argument(format!("invalid input: {}", e)))?;
+	Ok(Hash::from_vec(&vec))
 }
 
 fn check_block_param(input: &String) -> Result<(), Error> {
@@ -152,3 +163,166 @@ impl Handler for BlockHandler {
 		}
 	}
 }
+
+/// Maximum number of blocks returned by a single block stats range query.
+const MAX_BLOCK_STATS: u64 = 1000;
+
+/// Maximum number of blocks returned by a single block filters range query.
+const MAX_BLOCK_FILTERS: u64 = 1000;
+
+/// Gets the stats recorded for blocks on the current chain. These are still
+/// available once the full block has been pruned by compaction.
+/// GET /v1/blocks/<hash>/stats
+/// GET /v1/blocks/<height>/stats
+/// GET /v1/blocks/stats?start_height=101&end_height=200
+pub struct BlockStatsHandler {
+	pub chain: Weak<chain::Chain>,
+}
+
+impl BlockStatsHandler {
+	fn get_stats(&self, h: &Hash) -> Result<BlockStatsPrintable, Error> {
+		let stats = w(&self.chain)?
+			.get_block_stats(h)
+			.context(ErrorKind::NotFound)?;
+		Ok(BlockStatsPrintable::from_stats(h, stats))
+	}
+
+	// Stats for the blocks between start_height and end_height (inclusive),
+	// limited to MAX_BLOCK_STATS blocks. Blocks we have no stats for (accepted
+	// prior to stats being recorded) are skipped.
+	fn get_stats_range(&self, req: &Request<Body>) -> Result<Vec<BlockStatsPrintable>, Error> {
+		let params = QueryParams::from(req.uri().query());
+		let start_height: u64 = parse_param!(params, "start_height", 0);
+		let end_height: u64 = parse_param!(params, "end_height", start_height);
+		if end_height < start_height {
+			return Err(ErrorKind::Argument(format!(
+				"end_height {} below start_height {}",
+				end_height, start_height
+			)))?;
+		}
+
+		let chain = w(&self.chain)?;
+		let head = chain
+			.head()
+			.map_err(|e| ErrorKind::Internal(format!("chain error: {}", e)))?;
+		let end_height = end_height
+			.min(head.height)
+			.min(start_height.saturating_add(MAX_BLOCK_STATS - 1));
+
+		let mut res = vec![];
+		for height in start_height..=end_height {
+			let header = chain
+				.get_header_by_height(height)
+				.map_err(|e| ErrorKind::Internal(format!("chain error: {}", e)))?;
+			let h = header.hash();
+			if let Ok(stats) = chain.get_block_stats(&h) {
+				res.push(BlockStatsPrintable::from_stats(&h, stats));
+			}
+		}
+		Ok(res)
+	}
+}
+
+impl Handler for BlockStatsHandler {
+	fn get(&self, req: Request<Body>) -> ResponseFuture {
+		let mut path_elems = req.uri().path().trim_end_matches('/').rsplit('/');
+		if path_elems.next() != Some("stats") {
+			return response(StatusCode::BAD_REQUEST, "invalid url");
+		}
+		match path_elems.next() {
+			None => response(StatusCode::BAD_REQUEST, "invalid url"),
+			Some("blocks") => result_to_response(self.get_stats_range(&req)),
+			Some(el) => match parse_block_input(&self.chain, el.to_string()) {
+				Ok(h) => result_to_response(self.get_stats(&h)),
+				Err(e) => response(
+					StatusCode::BAD_REQUEST,
+					format!("failed to parse input: {}", e),
+				),
+			},
+		}
+	}
+}
+
+/// Gets the compact filters of blocks on the current chain, over the output
+/// commitments they create and spend. Wallets test their commitments against
+/// these to find the blocks that may be relevant to them. These are still
+/// available once the full block has been pruned by compaction.
+/// The filter of a single block we don't have (header-only nodes) is asked
+/// to one of our peers, blocking the API thread until it comes in or up to
+/// `BLOCK_FILTER_TIMEOUT`. Filters aren't committed to in the block header,
+/// so such a filter can't be verified.
+/// GET /v1/blocks/<hash>/filter
+/// GET /v1/blocks/<height>/filter
+/// GET /v1/blocks/filters?start_height=101&end_height=200
+pub struct BlockFilterHandler {
+	pub chain: Weak<chain::Chain>,
+	pub peers: Weak<p2p::Peers>,
+}
+
+impl BlockFilterHandler {
+	fn get_filter(&self, h: &Hash) -> Result<BlockFilterPrintable, Error> {
+		let chain = w(&self.chain)?;
+		let header = chain.get_block_header(h).context(ErrorKind::NotFound)?;
+		let filter = match chain.get_block_filter(h) {
+			Ok(filter) => filter,
+			Err(_) => w(&self.peers)?
+				.request_block_filter(*h, BLOCK_FILTER_TIMEOUT)
+				.ok_or_else(|| ErrorKind::NotFound)?,
+		};
+		Ok(BlockFilterPrintable::from_filter(header.height, &filter))
+	}
+
+	// Filters of the blocks between start_height and end_height (inclusive),
+	// limited to MAX_BLOCK_FILTERS blocks. Blocks we have no filter for (and
+	// can't build one for) are skipped, we don't ask our peers for them.
+	fn get_filters_range(&self, req: &Request<Body>) -> Result<Vec<BlockFilterPrintable>, Error> {
+		let params = QueryParams::from(req.uri().query());
+		let start_height: u64 = parse_param!(params, "start_height", 0);
+		let end_height: u64 = parse_param!(params, "end_height", start_height);
+		if end_height < start_height {
+			return Err(ErrorKind::Argument(format!(
+				"end_height {} below start_height {}",
+				end_height, start_height
+			)))?;
+		}
+
+		let chain = w(&self.chain)?;
+		let head = chain
+			.head()
+			.map_err(|e| ErrorKind::Internal(format!("chain error: {}", e)))?;
+		let end_height = end_height
+			.min(head.height)
+			.min(start_height.saturating_add(MAX_BLOCK_FILTERS - 1));
+
+		let mut res = vec![];
+		for height in start_height..=end_height {
+			let header = chain
+				.get_header_by_height(height)
+				.map_err(|e| ErrorKind::Internal(format!("chain error: {}", e)))?;
+			if let Ok(filter) = chain.get_block_filter(&header.hash()) {
+				res.push(BlockFilterPrintable::from_filter(height, &filter));
+			}
+		}
+		Ok(res)
+	}
+}
+
+impl Handler for BlockFilterHandler {
+	fn get(&self, req: Request<Body>) -> ResponseFuture {
+		let mut path_elems = req.uri().path().trim_end_matches('/').rsplit('/');
+		match path_elems.next() {
+			Some("filters") => result_to_response(self.get_filters_range(&req)),
+			Some("filter") => match path_elems.next() {
+				None | Some("blocks") => response(StatusCode::BAD_REQUEST, "invalid url"),
+				Some(el) => match parse_block_input(&self.chain, el.to_string()) {
+					Ok(h) => result_to_response(self.get_filter(&h)),
+					Err(e) => response(
+						StatusCode::BAD_REQUEST,
+						format!("failed to parse input: {}", e),
+					),
+				},
+			},
+			_ => response(StatusCode::BAD_REQUEST, "invalid url"),
+		}
+	}
+}
@@ -15,6 +15,9 @@
 use super::utils::{get_output, w};
 use crate::chain;
 use crate::core::core::hash::Hashed;
+use crate::core::libtx::payment_proof::PaymentProof;
+use crate::core::ser;
+use crate::p2p;
 use crate::rest::*;
 use crate::router::{Handler, ResponseFuture};
 use crate::types::*;
@@ -22,8 +25,14 @@ use crate::util;
 use crate::util::secp::pedersen::Commitment;
 use crate::web::*;
 use failure::ResultExt;
+use futures::Future;
 use hyper::{Body, Request, StatusCode};
-use std::sync::Weak;
+use std::sync::{Arc, Weak};
+use std::time::Duration;
+
+/// How long the output proof handler waits for a proof from a peer, blocking
+/// the API thread serving the request meanwhile.
+pub const OUTPUT_PROOF_TIMEOUT: Duration = Duration::from_secs(10);
 
 /// Chain handler. Get the head details.
 /// GET /v1/chain
@@ -64,6 +73,50 @@ impl Handler for ChainValidationHandler {
 	}
 }
 
+/// Orphan blocks handler. Lists the blocks we received before their parent,
+/// ordered by height.
+/// GET /v1/chain/orphans
+pub struct ChainOrphansHandler {
+	pub chain: Weak<chain::Chain>,
+}
+
+impl ChainOrphansHandler {
+	fn get_orphans(&self) -> Result<Vec<Orphan>, Error> {
+		let orphans = w(&self.chain)?.orphans();
+		Ok(orphans.iter().map(Orphan::from_info).collect())
+	}
+}
+
+impl Handler for ChainOrphansHandler {
+	fn get(&self, _req: Request<Body>) -> ResponseFuture {
+		result_to_response(self.get_orphans())
+	}
+}
+
+/// Chain proof handler. A succinct proof of the work behind our chain, for
+/// light clients to check it without downloading all the headers.
+/// GET /v1/chain/proof
+pub struct ChainProofHandler {
+	pub chain: Weak<chain::Chain>,
+}
+
+impl ChainProofHandler {
+	fn get_chain_proof(&self) -> Result<ChainProof, Error> {
+		let proof = w(&self.chain)?
+			.get_chain_proof()
+			.map_err(|e| ErrorKind::Internal(format!("failed to build chain proof: {}", e)))?;
+		let bytes = ser::ser_vec(&proof, ser::ProtocolVersion::local())
+			.map_err(|e| ErrorKind::Internal(format!("failed to serialize chain proof: {}", e)))?;
+		Ok(ChainProof::from_proof(&proof, bytes))
+	}
+}
+
+impl Handler for ChainProofHandler {
+	fn get(&self, _req: Request<Body>) -> ResponseFuture {
+		result_to_response(self.get_chain_proof())
+	}
+}
+
 /// Chain compaction handler. Trigger a compaction of the chain state to regain
 /// storage space.
 /// POST /v1/chain/compact
@@ -191,3 +244,182 @@ impl Handler for OutputHandler {
 		}
 	}
 }
+
+/// Outputs at height handler. Which of the provided outputs were unspent as of
+/// the block at the given height on the current chain.
+/// Only heights within the rewindable range (tail to head) are supported.
+/// GET /v1/chain/outputs/at/<height>?commits=xxx,yyy,zzz
+/// GET /v1/chain/outputs/at/<height>?commits=xxx&commits=yyy
+pub struct OutputsAtHeightHandler {
+	pub chain: Weak<chain::Chain>,
+}
+
+impl OutputsAtHeightHandler {
+	fn outputs_at_height(
+		&self,
+		height: u64,
+		req: &Request<Body>,
+	) -> Result<OutputsAtHeight, Error> {
+		let mut commitments: Vec<Commitment> = vec![];
+
+		let query = must_get_query!(req);
+		let params = QueryParams::from(query);
+		let mut invalid = None;
+		params.process_multival_param("commits", |id| match util::from_hex(String::from(id)) {
+			Ok(x) => commitments.push(Commitment::from_vec(x)),
+			Err(_) => invalid = Some(id.to_owned()),
+		});
+		if let Some(id) = invalid {
+			return Err(ErrorKind::Argument(format!(
+				"Not a valid commitment: {}",
+				id
+			)))?;
+		}
+
+		let chain = w(&self.chain)?;
+		let res = chain.utxo_view_at(height, |utxo| {
+			let header = utxo.get_header_by_height(height)?;
+			let mut outputs = vec![];
+			for commit in commitments {
+				if let Some((_, pos)) = utxo.get_unspent(&commit) {
+					let output_header = utxo.get_header_for_output_pos(pos)?;
+					outputs.push(Output::new(&commit, output_header.height, pos));
+				}
+			}
+			Ok(OutputsAtHeight {
+				header: BlockHeaderInfo::from_header(&header),
+				outputs,
+			})
+		});
+
+		res.map_err(|e| match e.kind() {
+			chain::ErrorKind::RewindOutOfRange(_, _, _) => {
+				ErrorKind::Argument(format!("{}", e.kind())).into()
+			}
+			_ => ErrorKind::Internal(format!("failed to get outputs at height: {}", e)).into(),
+		})
+	}
+}
+
+impl Handler for OutputsAtHeightHandler {
+	fn get(&self, req: Request<Body>) -> ResponseFuture {
+		let height = match right_path_element!(req).parse::<u64>() {
+			Ok(height) => height,
+			Err(_) => return response(StatusCode::BAD_REQUEST, "invalid height"),
+		};
+		result_to_response(self.outputs_at_height(height, &req))
+	}
+}
+
+/// Output proof handler. Merkle proof of an unspent output against the output
+/// root of a block on our header chain. Header-only nodes, which have no
+/// outputs of their own, get it from one of their peers and verify it first.
+/// Asking a peer blocks the API thread until the proof comes in or up to
+/// `OUTPUT_PROOF_TIMEOUT`.
+/// GET /v1/chain/outputs/proof/<commit>
+pub struct OutputProofHandler {
+	pub chain: Weak<chain::Chain>,
+	pub peers: Weak<p2p::Peers>,
+}
+
+impl OutputProofHandler {
+	fn get_output_proof(&self, id: &str) -> Result<OutputProof, Error> {
+		let c = util::from_hex(String::from(id)).context(ErrorKind::Argument(format!(
+			"Not a valid commitment: {}",
+			id
+		)))?;
+		let commit = Commitment::from_vec(c);
+
+		let chain = w(&self.chain)?;
+		let proof = match chain.get_output_proof(commit) {
+			Ok(proof) => proof,
+			Err(_) => w(&self.peers)?
+				.request_output_proof(commit, OUTPUT_PROOF_TIMEOUT)
+				.ok_or_else(|| ErrorKind::NotFound)?,
+		};
+		let header = chain
+			.get_block_header(&proof.block_hash)
+			.map_err(|_| ErrorKind::NotFound)?;
+		Ok(OutputProof::from_proof(&header, &proof))
+	}
+}
+
+impl Handler for OutputProofHandler {
+	fn get(&self, req: Request<Body>) -> ResponseFuture {
+		result_to_response(self.get_output_proof(right_path_element!(req)))
+	}
+}
+
+/// Kernel proof handler. Merkle proof of a kernel against the kernel root of
+/// the block it was included in, for light clients to verify a payment with
+/// the block headers alone.
+/// GET /v1/chain/kernels/proof/<excess>
+pub struct KernelProofHandler {
+	pub chain: Weak<chain::Chain>,
+}
+
+impl KernelProofHandler {
+	fn get_kernel_proof(&self, id: &str) -> Result<KernelProof, Error> {
+		let c = util::from_hex(String::from(id)).context(ErrorKind::Argument(format!(
+			"Not a valid commitment: {}",
+			id
+		)))?;
+		let excess = Commitment::from_vec(c);
+
+		let chain = w(&self.chain)?;
+		let proof = chain
+			.get_kernel_merkle_proof(&excess)
+			.map_err(|_| ErrorKind::NotFound)?;
+		let header = chain
+			.get_block_header(&proof.block_hash)
+			.map_err(|_| ErrorKind::NotFound)?;
+		Ok(KernelProof::from_proof(&header, &proof))
+	}
+}
+
+impl Handler for KernelProofHandler {
+	fn get(&self, req: Request<Body>) -> ResponseFuture {
+		result_to_response(self.get_kernel_proof(right_path_element!(req)))
+	}
+}
+
+/// Payment proof handler. Checks the receiver signature of a payment proof and
+/// that its kernel is on chain, returning the merkle proof of the kernel.
+/// POST /v1/chain/kernels/payment_proof
+pub struct PaymentProofHandler {
+	pub chain: Weak<chain::Chain>,
+}
+
+impl PaymentProofHandler {
+	fn verify_payment_proof(
+		chain: Arc<chain::Chain>,
+		proof: PaymentProof,
+	) -> Result<KernelProof, Error> {
+		let kernel_proof = chain
+			.verify_payment_proof(&proof)
+			.map_err(|e| match e.kind() {
+				chain::ErrorKind::InvalidPaymentProof(msg) => ErrorKind::Argument(msg),
+				_ => ErrorKind::NotFound,
+			})?;
+		let header = chain
+			.get_block_header(&kernel_proof.block_hash)
+			.map_err(|_| ErrorKind::NotFound)?;
+		Ok(KernelProof::from_proof(&header, &kernel_proof))
+	}
+}
+
+impl Handler for PaymentProofHandler {
+	fn post(&self, req: Request<Body>) -> ResponseFuture {
+		let chain = match w(&self.chain) {
+			Ok(chain) => chain,
+			Err(e) => return result_to_response::<KernelProof>(Err(e)),
+		};
+		Box::new(
+			parse_body(req).then(move |res: Result<PaymentProof, Error>| {
+				result_to_response(
+					res.and_then(|proof| PaymentProofHandler::verify_payment_proof(chain, proof)),
+				)
+			}),
+		)
+	}
+}
@@ -68,6 +68,7 @@ impl PoolPushHandler {
 			Ok(p) => p,
 			Err(e) => return Box::new(err(e)),
 		};
+		let chain_params = pool_arc.read().params.clone();
 
 		Box::new(
 			parse_body(req)
@@ -76,8 +77,12 @@ impl PoolPushHandler {
 						.map_err(|e| ErrorKind::RequestError(format!("Bad request: {}", e)).into())
 				})
 				.and_then(move |tx_bin| {
-					ser::deserialize(&mut &tx_bin[..])
-						.map_err(|e| ErrorKind::RequestError(format!("Bad request: {}", e)).into())
+					ser::deserialize_with_params(
+						&mut &tx_bin[..],
+						ser::ProtocolVersion::local(),
+						&chain_params,
+					)
+					.map_err(|e| ErrorKind::RequestError(format!("Bad request: {}", e)).into())
 				})
 				.and_then(move |tx: Transaction| {
 					let source = pool::TxSource {
@@ -378,4 +378,49 @@ mod tests {
 		assert_eq!(call_handler("/v1/zzz/2"), 103);
 		assert_eq!(call_handler("/v1/zzz/2/zzz"), 106);
 	}
+
+	// Literal routes sharing a prefix with a wildcard route, as the output
+	// routes of the node API.
+	#[test]
+	fn test_literal_before_wildcard() {
+		let mut routes = Router::new();
+		routes
+			.add_route("/v1/chain/outputs/at/*", Arc::new(HandlerImpl(201)))
+			.unwrap();
+		routes
+			.add_route("/v1/chain/outputs/proof/*", Arc::new(HandlerImpl(202)))
+			.unwrap();
+		routes
+			.add_route("/v1/chain/outputs/*", Arc::new(HandlerImpl(203)))
+			.unwrap();
+
+		let call_handler = |url| {
+			let mut event_loop = Core::new().unwrap();
+			let task = routes
+				.get(url)
+				.unwrap()
+				.next()
+				.unwrap()
+				.get(Request::new(Body::default()))
+				.and_then(|resp| ok(resp.status().as_u16()));
+			event_loop.run(task).unwrap()
+		};
+
+		assert_eq!(call_handler("/v1/chain/outputs/at/100"), 201);
+		assert_eq!(call_handler("/v1/chain/outputs/proof/08a1b2"), 202);
+		assert_eq!(call_handler("/v1/chain/outputs/08a1b2"), 203);
+		assert_eq!(call_handler("/v1/chain/outputs/08a1b2,08c3d4"), 203);
+
+		// Added after the wildcard, the literal routes get swallowed by it.
+		let mut routes = Router::new();
+		routes
+			.add_route("/v1/chain/outputs/*", Arc::new(HandlerImpl(203)))
+			.unwrap();
+		routes
+			.add_route("/v1/chain/outputs/at/*", Arc::new(HandlerImpl(201)))
+			.unwrap();
+		assert!(routes
+			.add_route("/v1/chain/outputs/proof/*", Arc::new(HandlerImpl(202)))
+			.is_err());
+	}
 }
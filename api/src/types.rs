@@ -74,7 +74,7 @@ pub struct Status {
 impl Status {
 	pub fn from_tip_and_peers(current_tip: chain::Tip, connections: u32) -> Status {
 		Status {
-			protocol_version: p2p::msg::PROTOCOL_VERSION,
+			protocol_version: ser::ProtocolVersion::local().into(),
 			user_agent: p2p::msg::USER_AGENT.to_string(),
 			connections: connections,
 			tip: Tip::from_tip(current_tip),
@@ -666,6 +666,155 @@ pub struct BlockOutputs {
 	pub outputs: Vec<OutputPrintable>,
 }
 
+// The requested outputs that were unspent as of a given block
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct OutputsAtHeight {
+	/// The block header the UTXO set was rewound to
+	pub header: BlockHeaderInfo,
+	/// The requested outputs that were unspent as of this block
+	pub outputs: Vec<Output>,
+}
+
+// Merkle proof of an unspent output against the output root of a block on
+// our header chain
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct OutputProof {
+	/// The block header the proof is against
+	pub header: BlockHeaderInfo,
+	/// The proven output
+	pub output: Output,
+	/// The merkle proof, hex encoded
+	pub merkle_proof: String,
+}
+
+impl OutputProof {
+	pub fn from_proof(header: &core::BlockHeader, proof: &chain::OutputProof) -> OutputProof {
+		OutputProof {
+			header: BlockHeaderInfo::from_header(header),
+			output: Output::new(&proof.output.commit, header.height, proof.pos),
+			merkle_proof: proof.proof.to_hex(),
+		}
+	}
+}
+
+// Merkle proof of a kernel against the kernel root of the block it was
+// included in
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct KernelProof {
+	/// The header of the block the kernel was included in
+	pub header: BlockHeaderPrintable,
+	/// The proven kernel
+	pub kernel: TxKernelPrintable,
+	/// Position of the kernel in the kernel MMR
+	pub mmr_index: u64,
+	/// The merkle proof, hex encoded
+	pub merkle_proof: String,
+}
+
+impl KernelProof {
+	pub fn from_proof(header: &core::BlockHeader, proof: &chain::KernelProof) -> KernelProof {
+		KernelProof {
+			header: BlockHeaderPrintable::from_header(header),
+			kernel: TxKernelPrintable::from_txkernel(&proof.kernel),
+			mmr_index: proof.pos,
+			merkle_proof: proof.proof.to_hex(),
+		}
+	}
+}
+
+// Succinct proof of the work behind the chain tip
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ChainProof {
+	/// The tip the proof is for
+	pub tip: BlockHeaderPrintable,
+	/// Heights of the sampled headers
+	pub heights: Vec<u64>,
+	/// The binary serialized proof, hex encoded
+	pub proof: String,
+}
+
+impl ChainProof {
+	pub fn from_proof(proof: &core::chain_proof::ChainProof, bytes: Vec<u8>) -> ChainProof {
+		ChainProof {
+			tip: BlockHeaderPrintable::from_header(&proof.tip),
+			heights: proof.headers.iter().map(|x| x.header.height).collect(),
+			proof: util::to_hex(bytes),
+		}
+	}
+}
+
+// Compact filter of a block, over the output commitments it creates and spends
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct BlockFilterPrintable {
+	/// Hash of the block
+	pub hash: String,
+	/// Height of the block
+	pub height: u64,
+	/// Number of commitments in the filter
+	pub n: u64,
+	/// The Golomb-coded set, hex encoded
+	pub filter: String,
+}
+
+impl BlockFilterPrintable {
+	pub fn from_filter(
+		height: u64,
+		filter: &core::block_filter::BlockFilter,
+	) -> BlockFilterPrintable {
+		BlockFilterPrintable {
+			hash: filter.block_hash.to_hex(),
+			height,
+			n: filter.n,
+			filter: util::to_hex(filter.data.clone()),
+		}
+	}
+}
+
+// Stats for a block on the current chain
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct BlockStatsPrintable {
+	/// Hash of the block
+	pub hash: String,
+	/// The stats recorded when the block was accepted
+	pub stats: chain::BlockStats,
+}
+
+impl BlockStatsPrintable {
+	pub fn from_stats(hash: &core::hash::Hash, stats: chain::BlockStats) -> BlockStatsPrintable {
+		BlockStatsPrintable {
+			hash: util::to_hex(hash.to_vec()),
+			stats,
+		}
+	}
+}
+
+// An orphan block in our orphan pool, received before its parent
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Orphan {
+	/// Hash of the block
+	pub hash: String,
+	/// Height of the block
+	pub height: u64,
+	/// Hash of the (missing) previous block
+	pub previous: String,
+	/// Address of the peer we received the block from, if any
+	pub peer: Option<String>,
+	/// rfc3339 timestamp at which we received the block
+	pub received: String,
+}
+
+impl Orphan {
+	pub fn from_info(info: &chain::OrphanInfo) -> Orphan {
+		Orphan {
+			hash: util::to_hex(info.hash.to_vec()),
+			height: info.height,
+			previous: util::to_hex(info.prev_hash.to_vec()),
+			peer: info.peer.clone(),
+			received: info.received.to_rfc3339(),
+		}
+	}
+}
+
 // For traversing all outputs in the UTXO set
 // transactions in the block
 #[derive(Debug, Serialize, Deserialize, Clone)]
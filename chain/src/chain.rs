@@ -15,24 +15,33 @@
 //! Facade and handler for the rest of the blockchain implementation
 //! and mostly the chain pipeline.
 
+use crate::core::core::block_filter::BlockFilter;
+use crate::core::core::chain_proof::{self, ChainProof, SampledHeader};
 use crate::core::core::hash::{Hash, Hashed, ZERO_HASH};
 use crate::core::core::merkle_proof::MerkleProof;
+use crate::core::core::pmmr::{self, BitmapSegment, Segment, SegmentIdentifier};
 use crate::core::core::verifier_cache::VerifierCache;
 use crate::core::core::{
 	Block, BlockHeader, BlockSums, Committed, Output, OutputIdentifier, Transaction, TxKernelEntry,
 };
-use crate::core::global;
+use crate::core::global::ChainParams;
+use crate::core::libtx::payment_proof::PaymentProof;
 use crate::core::pow;
+use crate::core::ser::{self, Readable, Writeable};
 use crate::error::{Error, ErrorKind};
 use crate::pipe;
 use crate::store;
 use crate::txhashset;
-use crate::txhashset::TxHashSet;
+use crate::txhashset::{TxHashSet, UTXOView};
 use crate::types::{
-	BlockStatus, ChainAdapter, NoStatus, Options, Tip, TxHashSetRoots, TxHashsetWriteStatus,
+	BlockStats, BlockStatus, ChainAdapter, KernelProof, NoStatus, Options, OrphanConfig,
+	OrphanEviction, OrphanInfo, OutputProof, ReindexStatus, SegmentType, Tip, TxHashSetRoots,
+	TxHashsetWriteStatus,
 };
 use crate::util::secp::pedersen::{Commitment, RangeProof};
-use crate::util::{Mutex, RwLock, StopState};
+use crate::util::{static_secp_instance, Mutex, RwLock, StopState};
+use chrono::prelude::{DateTime, TimeZone, Utc};
+use croaring::Bitmap;
 use grin_store::Error::NotFoundErr;
 use std::collections::HashMap;
 use std::fs::{self, File};
@@ -41,36 +50,122 @@ use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 
-/// Orphan pool size is limited by MAX_ORPHAN_SIZE
+/// Default maximum number of orphans in the orphan pool.
 pub const MAX_ORPHAN_SIZE: usize = 200;
 
-/// When evicting, very old orphans are evicted first
-const MAX_ORPHAN_AGE_SECS: u64 = 300;
+/// Default age of the orphans evicted first, whatever the eviction policy.
+pub const MAX_ORPHAN_AGE_SECS: u64 = 300;
 
 #[derive(Debug, Clone)]
-struct Orphan {
+pub(crate) struct Orphan {
 	block: Block,
 	opts: Options,
-	added: Instant,
+	// address of the peer we got the block from, if any
+	peer: Option<String>,
+	received: DateTime<Utc>,
+}
+
+impl Orphan {
+	fn info(&self) -> OrphanInfo {
+		OrphanInfo {
+			hash: self.block.hash(),
+			height: self.block.header.height,
+			prev_hash: self.block.header.prev_hash,
+			peer: self.peer.clone(),
+			received: self.received,
+		}
+	}
+
+	fn age(&self) -> Duration {
+		(Utc::now() - self.received)
+			.to_std()
+			.unwrap_or(Duration::from_secs(0))
+	}
+}
+
+impl Hashed for Orphan {
+	/// The hash of the orphan block.
+	fn hash(&self) -> Hash {
+		self.block.hash()
+	}
+}
+
+impl ser::Writeable for Orphan {
+	fn write<W: ser::Writer>(&self, writer: &mut W) -> Result<(), ser::Error> {
+		self.block.write(writer)?;
+		writer.write_u32(self.opts.bits())?;
+		writer.write_bytes(&self.peer.clone().unwrap_or(String::new()).as_bytes())?;
+		writer.write_i64(self.received.timestamp())
+	}
+}
+
+impl ser::Readable for Orphan {
+	fn read(reader: &mut dyn ser::Reader) -> Result<Orphan, ser::Error> {
+		let block = Block::read(reader)?;
+		let opts = Options::from_bits_truncate(reader.read_u32()?);
+		let peer = String::from_utf8(reader.read_bytes_len_prefix()?)
+			.map_err(|_| ser::Error::CorruptedData)?;
+		let received = Utc.timestamp(reader.read_i64()?, 0);
+		Ok(Orphan {
+			block,
+			opts,
+			peer: if peer.is_empty() { None } else { Some(peer) },
+			received,
+		})
+	}
 }
 
 pub struct OrphanBlockPool {
+	config: OrphanConfig,
 	// blocks indexed by their hash
 	orphans: RwLock<HashMap<Hash, Orphan>>,
 	// additional index of height -> hash
 	// so we can efficiently identify a child block (ex-orphan) after processing a block
 	height_idx: RwLock<HashMap<u64, Vec<Hash>>>,
-	// accumulated number of evicted block because of MAX_ORPHAN_SIZE limitation
+	// accumulated number of evicted block because of the max_orphans limitation
 	evicted: AtomicUsize,
+	// chain db the orphans are kept in, when persisted
+	store: Option<Arc<store::ChainStore>>,
 }
 
 impl OrphanBlockPool {
-	fn new() -> OrphanBlockPool {
-		OrphanBlockPool {
+	/// Builds the orphan pool, reloading the orphans kept in the chain db when
+	/// persisted. Orphans left over from a run with persistence are dropped
+	/// otherwise.
+	fn new(config: OrphanConfig, store: Arc<store::ChainStore>) -> Result<OrphanBlockPool, Error> {
+		let persisted = store.orphans()?;
+		let pool = OrphanBlockPool {
+			config: config.clone(),
 			orphans: RwLock::new(HashMap::new()),
 			height_idx: RwLock::new(HashMap::new()),
 			evicted: AtomicUsize::new(0),
+			store: if config.persist_orphans {
+				Some(store.clone())
+			} else {
+				None
+			},
+		};
+		if config.persist_orphans {
+			let mut orphans = pool.orphans.write();
+			let mut height_idx = pool.height_idx.write();
+			for orphan in persisted {
+				height_idx
+					.entry(orphan.block.header.height)
+					.or_insert(vec![])
+					.push(orphan.block.hash());
+				orphans.insert(orphan.block.hash(), orphan);
+			}
+			if !orphans.is_empty() {
+				debug!("orphans: reloaded {} orphans from the db", orphans.len());
+			}
+		} else if !persisted.is_empty() {
+			let batch = store.batch()?;
+			for orphan in persisted {
+				batch.delete_orphan(&orphan.block.hash())?;
+			}
+			batch.commit()?;
 		}
+		Ok(pool)
 	}
 
 	fn len(&self) -> usize {
@@ -82,40 +177,84 @@ impl OrphanBlockPool {
 		self.evicted.load(Ordering::Relaxed)
 	}
 
+	fn max_size(&self) -> usize {
+		self.config.max_orphans
+	}
+
 	fn add(&self, orphan: Orphan) {
 		let mut orphans = self.orphans.write();
 		let mut height_idx = self.height_idx.write();
+		let hash = orphan.block.hash();
+		self.persist(|batch| batch.save_orphan(&orphan));
 		{
 			let height_hashes = height_idx
 				.entry(orphan.block.header.height)
 				.or_insert(vec![]);
-			height_hashes.push(orphan.block.hash());
-			orphans.insert(orphan.block.hash(), orphan);
+			height_hashes.push(hash);
+			orphans.insert(hash, orphan);
 		}
 
-		if orphans.len() > MAX_ORPHAN_SIZE {
+		let max_orphans = self.config.max_orphans;
+		if orphans.len() > max_orphans {
 			let old_len = orphans.len();
+			let mut evicted = vec![];
 
 			// evict too old
-			orphans.retain(|_, ref mut x| {
-				x.added.elapsed() < Duration::from_secs(MAX_ORPHAN_AGE_SECS)
+			let max_age = Duration::from_secs(self.config.max_orphan_age_secs);
+			orphans.retain(|h, ref mut x| {
+				let keep = x.age() < max_age;
+				if !keep {
+					evicted.push(*h);
+				}
+				keep
 			});
-			// evict too far ahead
-			let mut heights = height_idx.keys().cloned().collect::<Vec<u64>>();
-			heights.sort_unstable();
-			for h in heights.iter().rev() {
-				if let Some(hs) = height_idx.remove(h) {
-					for h in hs {
-						let _ = orphans.remove(&h);
+
+			match self.config.orphan_eviction {
+				// evict too far ahead
+				OrphanEviction::HighestFirst => {
+					let mut heights = height_idx.keys().cloned().collect::<Vec<u64>>();
+					heights.sort_unstable();
+					for h in heights.iter().rev() {
+						if orphans.len() < max_orphans {
+							break;
+						}
+						if let Some(hs) = height_idx.remove(h) {
+							for h in hs {
+								if orphans.remove(&h).is_some() {
+									evicted.push(h);
+								}
+							}
+						}
 					}
 				}
-				if orphans.len() < MAX_ORPHAN_SIZE {
-					break;
+				// evict received first
+				OrphanEviction::OldestFirst => {
+					let mut by_age = orphans
+						.values()
+						.map(|x| (x.received, x.block.hash()))
+						.collect::<Vec<_>>();
+					by_age.sort_unstable();
+					for (_, h) in by_age {
+						if orphans.len() < max_orphans {
+							break;
+						}
+						orphans.remove(&h);
+						evicted.push(h);
+					}
 				}
 			}
 			// cleanup index
-			height_idx.retain(|_, ref mut xs| xs.iter().any(|x| orphans.contains_key(&x)));
+			height_idx.retain(|_, ref mut xs| {
+				xs.retain(|x| orphans.contains_key(&x));
+				!xs.is_empty()
+			});
 
+			self.persist(|batch| {
+				for h in &evicted {
+					batch.delete_orphan(h)?;
+				}
+				Ok(())
+			});
 			self.evicted
 				.fetch_add(old_len - orphans.len(), Ordering::Relaxed);
 		}
@@ -126,15 +265,50 @@ impl OrphanBlockPool {
 	fn remove_by_height(&self, height: &u64) -> Option<Vec<Orphan>> {
 		let mut orphans = self.orphans.write();
 		let mut height_idx = self.height_idx.write();
-		height_idx
+		let removed: Option<Vec<Orphan>> = height_idx
 			.remove(height)
-			.map(|hs| hs.iter().filter_map(|h| orphans.remove(h)).collect())
+			.map(|hs| hs.iter().filter_map(|h| orphans.remove(h)).collect());
+		if let Some(ref removed) = removed {
+			self.persist(|batch| {
+				for orphan in removed {
+					batch.delete_orphan(&orphan.block.hash())?;
+				}
+				Ok(())
+			});
+		}
+		removed
 	}
 
 	pub fn contains(&self, hash: &Hash) -> bool {
 		let orphans = self.orphans.read();
 		orphans.contains_key(hash)
 	}
+
+	/// Summary of the orphans in the pool, ordered by height.
+	fn infos(&self) -> Vec<OrphanInfo> {
+		let orphans = self.orphans.read();
+		let mut infos = orphans.values().map(|x| x.info()).collect::<Vec<_>>();
+		infos.sort_by_key(|x| (x.height, x.received));
+		infos
+	}
+
+	// Applies the provided changes to the orphans kept in the chain db, if
+	// persisted. Failing to do so only costs us a refetch after a restart, so
+	// we just log it.
+	fn persist<F>(&self, f: F)
+	where
+		F: FnOnce(&store::Batch<'_>) -> Result<(), grin_store::Error>,
+	{
+		if let Some(ref store) = self.store {
+			let res = store.batch().and_then(|batch| {
+				f(&batch)?;
+				batch.commit()
+			});
+			if let Err(e) = res {
+				warn!("orphans: failed to update the persisted orphans: {:?}", e);
+			}
+		}
+	}
 }
 
 /// Facade to the blockchain block processing pipeline and storage. Provides
@@ -148,10 +322,19 @@ pub struct Chain {
 	txhashset: Arc<RwLock<txhashset::TxHashSet>>,
 	verifier_cache: Arc<RwLock<dyn VerifierCache>>,
 	// POW verification function
-	pow_verifier: fn(&BlockHeader) -> Result<(), pow::Error>,
+	pow_verifier: fn(&ChainParams, &BlockHeader) -> Result<(), pow::Error>,
 	archive_mode: bool,
 	stop_state: Arc<Mutex<StopState>>,
 	genesis: BlockHeader,
+	params: ChainParams,
+	// segmented download (PIBD) of the txhashset in progress, if any
+	desegmenter: Arc<RwLock<Option<txhashset::Desegmenter>>>,
+	// output leaf set at the last block we served output bitmap segments for
+	segmenter_leaf_set: Arc<RwLock<Option<(Hash, Bitmap)>>>,
+	// txhashset archives built for our syncing peers
+	txhashset_archives: Arc<RwLock<txhashset::ArchiveCache>>,
+	// held while building a txhashset archive, so we only build one at a time
+	txhashset_archive_build: Arc<Mutex<()>>,
 }
 
 impl Chain {
@@ -161,10 +344,12 @@ impl Chain {
 	pub fn init(
 		db_root: String,
 		adapter: Arc<dyn ChainAdapter + Send + Sync>,
+		params: ChainParams,
 		genesis: Block,
-		pow_verifier: fn(&BlockHeader) -> Result<(), pow::Error>,
+		pow_verifier: fn(&ChainParams, &BlockHeader) -> Result<(), pow::Error>,
 		verifier_cache: Arc<RwLock<dyn VerifierCache>>,
 		archive_mode: bool,
+		orphan_config: OrphanConfig,
 		stop_state: Arc<Mutex<StopState>>,
 	) -> Result<Chain, Error> {
 		// Note: We take a lock on the stop_state here and do not release it until
@@ -175,28 +360,48 @@ impl Chain {
 			return Err(ErrorKind::Stopped.into());
 		}
 
-		let store = Arc::new(store::ChainStore::new(&db_root)?);
+		let store = Arc::new(store::ChainStore::new(&db_root, params.clone())?);
 
 		// open the txhashset, creating a new one if necessary
 		let mut txhashset = txhashset::TxHashSet::open(db_root.clone(), store.clone(), None)?;
 
+		// Bring the db up to our current schema version before anything else
+		// reads from it (refusing to run against a newer schema). Opening the
+		// txhashset doesn't, and some migrations need its kernel MMR.
+		store
+			.migrate(txhashset.kernel_pmmr())
+			.map_err(|e| ErrorKind::StoreErr(e, "chain db migration".to_owned()))?;
+
 		setup_head(&genesis, &store, &mut txhashset)?;
 		Chain::log_heads(&store)?;
 
+		let txhashset_archives = txhashset::ArchiveCache::open(&db_root)?;
+		let orphans = OrphanBlockPool::new(orphan_config, store.clone())?;
+
 		Ok(Chain {
 			db_root,
 			store,
 			adapter,
-			orphans: Arc::new(OrphanBlockPool::new()),
+			orphans: Arc::new(orphans),
 			txhashset: Arc::new(RwLock::new(txhashset)),
 			pow_verifier,
 			verifier_cache,
 			archive_mode,
 			stop_state,
 			genesis: genesis.header.clone(),
+			params,
+			desegmenter: Arc::new(RwLock::new(None)),
+			segmenter_leaf_set: Arc::new(RwLock::new(None)),
+			txhashset_archives: Arc::new(RwLock::new(txhashset_archives)),
+			txhashset_archive_build: Arc::new(Mutex::new(())),
 		})
 	}
 
+	/// The parameters of the chain we run.
+	pub fn params(&self) -> &ChainParams {
+		&self.params
+	}
+
 	/// Return our shared txhashset instance.
 	pub fn txhashset(&self) -> Arc<RwLock<TxHashSet>> {
 		self.txhashset.clone()
@@ -250,8 +455,20 @@ impl Chain {
 	/// Processes a single block, then checks for orphans, processing
 	/// those as well if they're found
 	pub fn process_block(&self, b: Block, opts: Options) -> Result<Option<Tip>, Error> {
+		self.process_block_from(b, opts, None)
+	}
+
+	/// Processes a single block received from the provided peer, then checks
+	/// for orphans. The peer is recorded if the block turns out to be an
+	/// orphan.
+	pub fn process_block_from(
+		&self,
+		b: Block,
+		opts: Options,
+		peer: Option<String>,
+	) -> Result<Option<Tip>, Error> {
 		let height = b.header.height;
-		let res = self.process_block_single(b, opts);
+		let res = self.process_block_single(b, opts, peer);
 		if res.is_ok() {
 			self.check_orphans(height + 1);
 		}
@@ -279,7 +496,12 @@ impl Chain {
 	/// Attempt to add a new block to the chain.
 	/// Returns true if it has been added to the longest chain
 	/// or false if it has added to a fork (or orphan?).
-	fn process_block_single(&self, b: Block, opts: Options) -> Result<Option<Tip>, Error> {
+	fn process_block_single(
+		&self,
+		b: Block,
+		opts: Options,
+		peer: Option<String>,
+	) -> Result<Option<Tip>, Error> {
 		let (maybe_new_head, prev_head) = {
 			// Note: We take a lock on the stop_state here and do not release it until
 			// we have finished processing this single block.
@@ -289,6 +511,10 @@ impl Chain {
 			if stop_lock.is_stopped() {
 				return Err(ErrorKind::Stopped.into());
 			}
+			// Our chain state is partially rebuilt until a reindex completes.
+			if self.reindex_in_progress() {
+				return Err(ErrorKind::ReindexInProgress.into());
+			}
 
 			let mut txhashset = self.txhashset.write();
 			let batch = self.store.batch()?;
@@ -325,7 +551,8 @@ impl Chain {
 					let orphan = Orphan {
 						block: b,
 						opts: opts,
-						added: Instant::now(),
+						peer,
+						received: Utc::now(),
 					};
 
 					&self.orphans.add(orphan);
@@ -366,6 +593,9 @@ impl Chain {
 
 	/// Process a block header received during "header first" propagation.
 	pub fn process_block_header(&self, bh: &BlockHeader, opts: Options) -> Result<(), Error> {
+		if self.reindex_in_progress() {
+			return Err(ErrorKind::ReindexInProgress.into());
+		}
 		// We take a write lock on the txhashset and create a new batch
 		// but this is strictly readonly so we do not commit the batch.
 		let mut txhashset = self.txhashset.write();
@@ -387,6 +617,9 @@ impl Chain {
 		if stop_lock.is_stopped() {
 			return Err(ErrorKind::Stopped.into());
 		}
+		if self.reindex_in_progress() {
+			return Err(ErrorKind::ReindexInProgress.into());
+		}
 
 		let mut txhashset = self.txhashset.write();
 		let batch = self.store.batch()?;
@@ -398,6 +631,43 @@ impl Chain {
 		Ok(())
 	}
 
+	/// Header-only counterpart of sync_block_headers, for nodes following the
+	/// header chain without ever processing full blocks. The header MMR is
+	/// brought to the new header_head here, as it otherwise only moves along
+	/// with the full blocks we process.
+	pub fn sync_header_chain(&self, headers: &[BlockHeader], opts: Options) -> Result<(), Error> {
+		let stop_lock = self.stop_state.lock();
+		if stop_lock.is_stopped() {
+			return Err(ErrorKind::Stopped.into());
+		}
+		if self.reindex_in_progress() {
+			return Err(ErrorKind::ReindexInProgress.into());
+		}
+
+		let mut txhashset = self.txhashset.write();
+		let batch = self.store.batch()?;
+		let mut ctx = self.new_ctx(opts, batch, &mut txhashset)?;
+
+		pipe::sync_block_headers(headers, &mut ctx)?;
+		pipe::update_header_mmr(&mut ctx)?;
+		ctx.batch.commit()?;
+
+		Ok(())
+	}
+
+	/// Brings the header MMR to the current header_head (on header-only
+	/// nodes, where it is rewound to the head of the full chain on startup).
+	pub fn update_header_mmr(&self) -> Result<(), Error> {
+		let mut txhashset = self.txhashset.write();
+		let batch = self.store.batch()?;
+		let mut ctx = self.new_ctx(Options::NONE, batch, &mut txhashset)?;
+
+		pipe::update_header_mmr(&mut ctx)?;
+		ctx.batch.commit()?;
+
+		Ok(())
+	}
+
 	fn new_ctx<'a>(
 		&self,
 		opts: Options,
@@ -411,6 +681,7 @@ impl Chain {
 			txhashset,
 			batch,
 			orphans: self.orphans.clone(),
+			params: &self.params,
 		})
 	}
 
@@ -453,7 +724,7 @@ impl Chain {
 						},
 					);
 					let height = orphan.block.header.height;
-					let res = self.process_block_single(orphan.block, orphan.opts);
+					let res = self.process_block_single(orphan.block, orphan.opts, orphan.peer);
 					if res.is_ok() {
 						orphan_accepted = true;
 						height_accepted = height;
@@ -514,7 +785,7 @@ impl Chain {
 		let height = self.next_block_height()?;
 		let txhashset = self.txhashset.read();
 		txhashset::utxo_view(&txhashset, |utxo| {
-			utxo.verify_coinbase_maturity(&tx.inputs(), height)?;
+			utxo.verify_coinbase_maturity(&tx.inputs(), height, self.params.coinbase_maturity)?;
 			Ok(())
 		})
 	}
@@ -530,6 +801,28 @@ impl Chain {
 		}
 	}
 
+	/// Provides a readonly view of the UTXO set as of the block at the given
+	/// height on the current chain, by rewinding a readonly txhashset extension.
+	/// We can only rewind back as far as our tail (the oldest full block we have),
+	/// heights beyond this (or beyond our head) result in an error.
+	pub fn utxo_view_at<F, T>(&self, height: u64, inner: F) -> Result<T, Error>
+	where
+		F: FnOnce(&UTXOView<'_>) -> Result<T, Error>,
+	{
+		let head = self.head()?;
+		let tail_height = self.tail().map(|t| t.height).unwrap_or(0);
+		if height < tail_height || height > head.height {
+			return Err(ErrorKind::RewindOutOfRange(height, tail_height, head.height).into());
+		}
+
+		let header = self.get_header_by_height(height)?;
+		let mut txhashset = self.txhashset.write();
+		txhashset::extending_readonly(&mut txhashset, |extension| {
+			extension.rewind_with_output_pos(&header)?;
+			inner(&extension.utxo_view())
+		})
+	}
+
 	/// Validate the current chain state.
 	pub fn validate(&self, fast_validation: bool) -> Result<(), Error> {
 		let header = self.store.head_header()?;
@@ -566,7 +859,7 @@ impl Chain {
 				// Put the txhashset in the correct state as of the previous block.
 				// We cannot use the new block to do this because we have no
 				// explicit previous linkage (and prev_root not yet setup).
-				pipe::rewind_and_apply_fork(&prev_block, extension)?;
+				pipe::rewind_and_apply_fork(&prev_block, extension, &self.params)?;
 				extension.apply_block(&prev_block)?;
 
 				// Retrieve the header root before we apply the new block
@@ -654,44 +947,161 @@ impl Chain {
 		txhashset.merkle_proof(commit)
 	}
 
+	/// Merkle proof of the unspent output with the provided commitment against
+	/// the output root of our current head, for header-only peers.
+	pub fn get_output_proof(&self, commit: Commitment) -> Result<OutputProof, Error> {
+		let mut txhashset = self.txhashset.write();
+		// Read the head under the txhashset lock so both are consistent.
+		let header = self.head_header()?;
+		let (output, pos, proof) = txhashset.output_proof(commit)?;
+		Ok(OutputProof {
+			block_hash: header.hash(),
+			output,
+			pos,
+			proof,
+		})
+	}
+
+	/// Verifies an output proof obtained from a peer against our header chain
+	/// only: the block it refers to must be on the header chain (as per the
+	/// header MMR) and the proof must hash up to the block output root.
+	pub fn verify_output_proof(&self, output_proof: &OutputProof) -> Result<(), Error> {
+		let header = self.get_block_header(&output_proof.block_hash)?;
+		self.is_on_current_chain(&header)?;
+
+		let (proof, pos) = (&output_proof.proof, output_proof.pos);
+		if proof.mmr_size != header.output_mmr_size || pos > proof.mmr_size || !pmmr::is_leaf(pos) {
+			return Err(ErrorKind::MerkleProof.into());
+		}
+		proof
+			.verify(header.output_root, &output_proof.output, pos)
+			.map_err(|_| ErrorKind::MerkleProof)?;
+		Ok(())
+	}
+
 	/// Returns current txhashset roots.
 	pub fn get_txhashset_roots(&self) -> TxHashSetRoots {
 		self.txhashset.read().roots()
 	}
 
-	/// Provides a reading view into the current txhashset state as well as
-	/// the required indexes for a consumer to rewind to a consistent state
-	/// at the provided block hash.
+	/// Provides a reading view into the txhashset state at the provided block
+	/// hash as well as the required indexes for a consumer to rewind to it.
+	/// Any block of our current chain within the cut-through horizon is
+	/// served, the archive being built first if we don't have it cached (peers
+	/// still requesting at a height not aligned to the archive interval).
+	/// Callers are expected to cap how many archives they serve at the same
+	/// time, building one rewinds and zips the whole txhashset.
 	pub fn txhashset_read(&self, h: Hash) -> Result<(u64, u64, File), Error> {
+		let header = self.get_block_header(&h)?;
+		let cached = self.txhashset_archives.read().get(&h)?;
+		let txhashset_reader = match cached {
+			Some(zip) => zip,
+			None => {
+				self.is_on_current_chain(&header)?;
+				let horizon = self.params.cut_through_horizon as u64;
+				if self.head()?.height.saturating_sub(header.height) > horizon {
+					return Err(ErrorKind::TxHashSetErr(format!(
+						"no txhashset archive at {}, beyond the horizon",
+						h
+					))
+					.into());
+				}
+				self.txhashset_archive(&header)?
+			}
+		};
+		Ok((
+			header.output_mmr_size,
+			header.kernel_mmr_size,
+			txhashset_reader,
+		))
+	}
+
+	/// Header of the block we build the txhashset archive at for syncing
+	/// peers, given our current head.
+	pub fn txhashset_archive_header(&self) -> Result<BlockHeader, Error> {
+		let height = self.params.txhashset_archive_height(self.head()?.height);
+		self.get_header_by_height(height)
+	}
+
+	/// Whether the txhashset archive for our current head still has to be
+	/// built (and isn't being built already).
+	pub fn txhashset_archive_needed(&self) -> Result<bool, Error> {
+		let header = self.txhashset_archive_header()?;
+		Ok(!self.txhashset_archives.read().contains(&header.hash())
+			&& self.txhashset_archive_build.try_lock().is_some())
+	}
+
+	/// Builds the txhashset archive for our current head and adds it to our
+	/// cache, unless already there. This is slow (rewinds and zips the whole
+	/// txhashset), to be called in the background.
+	pub fn build_txhashset_archive(&self) -> Result<(), Error> {
+		let header = self.txhashset_archive_header()?;
+		self.txhashset_archive(&header)?;
+		Ok(())
+	}
+
+	/// Txhashset archive at any block of our current chain, built first
+	/// (rewinding and zipping the whole txhashset) if not cached. For local
+	/// exports only, unlike `txhashset_read` this doesn't restrict the block.
+	/// A pruned node can't rewind past its cut-through horizon.
+	pub fn txhashset_export(&self, h: Hash) -> Result<(BlockHeader, File), Error> {
+		let header = self.get_block_header(&h)?;
+		self.is_on_current_chain(&header)?;
+		let zip = self.txhashset_archive(&header)?;
+		Ok((header, zip))
+	}
+
+	// Cached txhashset archive at the provided block, built first if we
+	// don't have it yet.
+	fn txhashset_archive(&self, header: &BlockHeader) -> Result<File, Error> {
+		if let Some(zip) = self.txhashset_archives.read().get(&header.hash())? {
+			return Ok(zip);
+		}
+		let _building = self.txhashset_archive_build.lock();
+		// Someone else may have built it while we were waiting.
+		if let Some(zip) = self.txhashset_archives.read().get(&header.hash())? {
+			return Ok(zip);
+		}
+
+		let now = Instant::now();
+
 		// now we want to rewind the txhashset extension and
 		// sync a "rewound" copy of the leaf_set files to disk
 		// so we can send these across as part of the zip file.
 		// The fast sync client does *not* have the necessary data
 		// to rewind after receiving the txhashset zip.
-		let header = self.get_block_header(&h)?;
 		{
 			let mut txhashset = self.txhashset.write();
 			txhashset::extending_readonly(&mut txhashset, |extension| {
-				extension.rewind(&header)?;
+				extension.rewind(header)?;
 				extension.snapshot()?;
 				Ok(())
 			})?;
 		}
 
-		// prepares the zip and return the corresponding Read
-		let txhashset_reader = txhashset::zip_read(self.db_root.clone(), &header)?;
-		Ok((
-			header.output_mmr_size,
-			header.kernel_mmr_size,
-			txhashset_reader,
-		))
+		// prepares the zip and moves it to our cache
+		txhashset::zip_read(self.db_root.clone(), header)?;
+		let mut archives = self.txhashset_archives.write();
+		archives.add(header, &txhashset::zip_path(&self.db_root, header))?;
+
+		info!(
+			"txhashset_archive: built archive at {} {} in {}s",
+			header.height,
+			header.hash(),
+			now.elapsed().as_secs(),
+		);
+
+		archives.get(&header.hash())?.ok_or_else(|| {
+			ErrorKind::TxHashSetErr("txhashset archive missing from cache".to_owned()).into()
+		})
 	}
 
 	// Special handling to make sure the whole kernel set matches each of its
 	// roots in each block header, without truncation. We go back header by
 	// header, rewind and check each root. This fixes a potential weakness in
 	// fast sync where a reorg past the horizon could allow a whole rewrite of
-	// the kernel set.
+	// the kernel set. The kernel signatures get batch verified along the way,
+	// block by block (genesis included), kernels are never pruned.
 	fn validate_kernel_history(
 		&self,
 		header: &BlockHeader,
@@ -702,8 +1112,12 @@ impl Chain {
 		let mut count = 0;
 		let mut current = header.clone();
 		txhashset::rewindable_kernel_view(&txhashset, |view| {
-			while current.height > 0 {
+			loop {
 				view.rewind(&current)?;
+				view.verify_kernel_sigs(self.verifier_cache.clone())?;
+				if current.height == 0 {
+					break;
+				}
 				view.validate_root()?;
 				current = view.batch().get_previous_header(&current)?;
 				count += 1;
@@ -712,7 +1126,7 @@ impl Chain {
 		})?;
 
 		debug!(
-			"validate_kernel_history: validated kernel root on {} headers",
+			"validate_kernel_history: validated kernel root and signatures on {} headers",
 			count,
 		);
 
@@ -759,7 +1173,7 @@ impl Chain {
 		caller: String,
 		hashes: &mut Option<Vec<Hash>>,
 	) -> Result<bool, Error> {
-		let horizon = global::cut_through_horizon() as u64;
+		let horizon = self.params.cut_through_horizon as u64;
 		let body_head = self.head()?;
 		let header_head = self.header_head()?;
 		let sync_head = self.get_sync_head()?;
@@ -901,6 +1315,17 @@ impl Chain {
 		txhashset::clean_header_folder(&sandbox_dir);
 		txhashset::zip_write(sandbox_dir.clone(), txhashset_data.try_clone()?, &header)?;
 
+		self.validate_and_replace_txhashset(header, sandbox_dir, status)
+	}
+
+	// Fully validates the txhashset at the provided header extracted or
+	// rebuilt in the sandbox dir and, if valid, replaces ours with it.
+	fn validate_and_replace_txhashset(
+		&self,
+		header: BlockHeader,
+		sandbox_dir: PathBuf,
+		status: &dyn TxHashsetWriteStatus,
+	) -> Result<(), Error> {
 		let mut txhashset = txhashset::TxHashSet::open(
 			sandbox_dir
 				.to_str()
@@ -910,11 +1335,12 @@ impl Chain {
 			Some(&header),
 		)?;
 
-		// The txhashset.zip contains the output, rangeproof and kernel MMRs.
+		// The txhashset contains the output, rangeproof and kernel MMRs.
 		// We must rebuild the header MMR ourselves based on the headers in our db.
 		self.rebuild_header_mmr(&Tip::from_header(&header), &mut txhashset)?;
 
-		// Validate the full kernel history (kernel MMR root for every block header).
+		// Validate the full kernel history (kernel MMR root for every block header)
+		// and the kernel signatures.
 		self.validate_kernel_history(&header, &txhashset)?;
 
 		// all good, prepare a new batch and update all the required records
@@ -926,8 +1352,10 @@ impl Chain {
 			extension.rewind(&header)?;
 
 			// Validate the extension, generating the utxo_sum and kernel_sum.
-			// Full validation, including rangeproofs and kernel signature verification.
-			let (utxo_sum, kernel_sum) = extension.validate(false, status)?;
+			// Full validation, including rangeproofs. The kernel signatures
+			// were verified with the kernel history already.
+			let (utxo_sum, kernel_sum) = extension.validate(true, status)?;
+			extension.verify_rangeproofs(status)?;
 
 			// Save the block_sums (utxo_sum, kernel_sum) to the db for use later.
 			extension.batch.save_block_sums(
@@ -994,6 +1422,221 @@ impl Chain {
 		Ok(())
 	}
 
+	// Header of the block we're asked txhashset segments for. We only provide
+	// them for blocks on our current chain within the horizon, as we do for
+	// txhashset archives.
+	fn segment_header(&self, h: &Hash) -> Result<BlockHeader, Error> {
+		let header = self.get_block_header(h)?;
+		self.is_on_current_chain(&header)?;
+		let head = self.head()?;
+		let horizon = self.params.cut_through_horizon as u64;
+		if header.height < head.height.saturating_sub(horizon) {
+			return Err(ErrorKind::TxHashSetErr("block beyond horizon".to_owned()).into());
+		}
+		Ok(header)
+	}
+
+	/// Segment of the output MMR at the provided block, for PIBD.
+	pub fn get_output_segment(
+		&self,
+		h: Hash,
+		id: SegmentIdentifier,
+	) -> Result<Segment<OutputIdentifier>, Error> {
+		let header = self.segment_header(&h)?;
+		self.txhashset.read().output_segment(&header, id)
+	}
+
+	/// Segment of the rangeproof MMR at the provided block, for PIBD.
+	pub fn get_rangeproof_segment(
+		&self,
+		h: Hash,
+		id: SegmentIdentifier,
+	) -> Result<Segment<RangeProof>, Error> {
+		let header = self.segment_header(&h)?;
+		self.txhashset.read().rangeproof_segment(&header, id)
+	}
+
+	/// Segment of the kernel MMR at the provided block, for PIBD.
+	pub fn get_kernel_segment(
+		&self,
+		h: Hash,
+		id: SegmentIdentifier,
+	) -> Result<Segment<TxKernelEntry>, Error> {
+		let header = self.segment_header(&h)?;
+		self.txhashset.read().kernel_segment(&header, id)
+	}
+
+	/// Segment of the bitmap of unspent outputs at the provided block, for
+	/// PIBD. The bitmap is computed once per block and cached.
+	pub fn get_output_bitmap_segment(
+		&self,
+		h: Hash,
+		id: SegmentIdentifier,
+	) -> Result<BitmapSegment, Error> {
+		let header = self.segment_header(&h)?;
+		{
+			let leaf_set = self.segmenter_leaf_set.read();
+			if let Some((ref hash, ref bitmap)) = *leaf_set {
+				if *hash == h {
+					return Ok(BitmapSegment::from_bitmap(
+						id,
+						header.output_mmr_size,
+						bitmap,
+					)?);
+				}
+			}
+		}
+
+		let bitmap = {
+			let txhashset = self.txhashset.read();
+			let batch = self.store.batch()?;
+			txhashset.output_leaf_set_at(&header, &batch)?
+		};
+		let segment = BitmapSegment::from_bitmap(id, header.output_mmr_size, &bitmap)?;
+		*self.segmenter_leaf_set.write() = Some((h, bitmap));
+		Ok(segment)
+	}
+
+	/// Starts downloading the txhashset at the provided block in segments
+	/// (PIBD), keeping the download in progress if it's for the same block.
+	pub fn pibd_start(&self, h: Hash) -> Result<(), Error> {
+		let mut desegmenter = self.desegmenter.write();
+		if let Some(ref d) = *desegmenter {
+			if d.header().hash() == h {
+				return Ok(());
+			}
+		}
+		// Drop any previous download (and its open files) before cleaning up.
+		*desegmenter = None;
+		let header = self.get_block_header(&h)?;
+		self.clean_txhashset_sandbox();
+		*desegmenter = Some(txhashset::Desegmenter::new(header, &self.get_tmp_dir())?);
+		Ok(())
+	}
+
+	/// Block hash of the segmented txhashset download in progress, if any.
+	pub fn pibd_block_hash(&self) -> Option<Hash> {
+		self.desegmenter.read().as_ref().map(|d| d.header().hash())
+	}
+
+	/// Up to max segments the download in progress still needs.
+	pub fn pibd_missing_segments(&self, max: usize) -> Vec<(SegmentType, SegmentIdentifier)> {
+		match *self.desegmenter.read() {
+			Some(ref d) => d.missing_segments(max),
+			None => vec![],
+		}
+	}
+
+	/// Progress of the download in progress, segments received and total.
+	pub fn pibd_progress(&self) -> Option<(u64, u64)> {
+		self.desegmenter.read().as_ref().map(|d| d.progress())
+	}
+
+	/// Whether all the segments of the download in progress were received.
+	pub fn pibd_is_complete(&self) -> bool {
+		match *self.desegmenter.read() {
+			Some(ref d) => d.is_complete(),
+			None => false,
+		}
+	}
+
+	// Adds a segment to the download in progress, returning whether it's the
+	// one that completed the download (true only once per download).
+	fn pibd_receive<F>(&self, h: Hash, f: F) -> Result<bool, Error>
+	where
+		F: FnOnce(&mut txhashset::Desegmenter) -> Result<(), Error>,
+	{
+		match *self.desegmenter.write() {
+			Some(ref mut d) if d.header().hash() == h => {
+				let was_complete = d.is_complete();
+				f(d)?;
+				Ok(!was_complete && d.is_complete())
+			}
+			_ => Err(ErrorKind::TxHashSetErr("segment not requested".to_owned()).into()),
+		}
+	}
+
+	/// Adds a received segment of the output bitmap to the download, true if
+	/// it completed the download.
+	pub fn pibd_receive_bitmap_segment(
+		&self,
+		h: Hash,
+		segment: BitmapSegment,
+	) -> Result<bool, Error> {
+		self.pibd_receive(h, |d| d.add_bitmap_segment(segment))
+	}
+
+	/// Adds a received segment of the output MMR to the download, true if it
+	/// completed the download.
+	pub fn pibd_receive_output_segment(
+		&self,
+		h: Hash,
+		segment: Segment<OutputIdentifier>,
+	) -> Result<bool, Error> {
+		self.pibd_receive(h, |d| d.add_output_segment(segment))
+	}
+
+	/// Adds a received segment of the rangeproof MMR to the download, true if
+	/// it completed the download.
+	pub fn pibd_receive_rangeproof_segment(
+		&self,
+		h: Hash,
+		segment: Segment<RangeProof>,
+	) -> Result<bool, Error> {
+		self.pibd_receive(h, |d| d.add_rangeproof_segment(segment))
+	}
+
+	/// Adds a received segment of the kernel MMR to the download, true if it
+	/// completed the download.
+	pub fn pibd_receive_kernel_segment(
+		&self,
+		h: Hash,
+		segment: Segment<TxKernelEntry>,
+	) -> Result<bool, Error> {
+		self.pibd_receive(h, |d| d.add_kernel_segment(segment))
+	}
+
+	/// Completes the segmented download once all segments were received. The
+	/// rebuilt txhashset goes through the same full validation as one from a
+	/// txhashset archive before replacing ours.
+	pub fn pibd_finalize(&self, status: &dyn TxHashsetWriteStatus) -> Result<(), Error> {
+		status.on_setup();
+
+		let desegmenter = {
+			let mut desegmenter = self.desegmenter.write();
+			match desegmenter.take() {
+				Some(d) => {
+					if !d.is_complete() {
+						*desegmenter = Some(d);
+						return Err(ErrorKind::TxHashSetErr("missing segments".to_owned()).into());
+					}
+					d
+				}
+				None => {
+					return Err(
+						ErrorKind::TxHashSetErr("no download in progress".to_owned()).into(),
+					);
+				}
+			}
+		};
+
+		let mut hashes: Option<Vec<Hash>> = None;
+		if !self.check_txhashset_needed("pibd_finalize".to_owned(), &mut hashes)? {
+			warn!("pibd_finalize: txhashset segments received but not needed! ignored.");
+			return Err(ErrorKind::InvalidTxHashSet("not needed".to_owned()).into());
+		}
+
+		let header = desegmenter.header().clone();
+		desegmenter.finalize()?;
+		self.validate_and_replace_txhashset(header, self.get_tmp_dir(), status)
+	}
+
+	/// Abandons the segmented download in progress, if any.
+	pub fn pibd_reset(&self) {
+		*self.desegmenter.write() = None;
+		self.clean_txhashset_sandbox();
+	}
+
 	/// Cleanup old blocks from the db.
 	/// Determine the cutoff height from the horizon and the current block height.
 	/// *Only* runs if we are not in archive mode.
@@ -1006,7 +1649,7 @@ impl Chain {
 			return Ok(());
 		}
 
-		let horizon = global::cut_through_horizon() as u64;
+		let horizon = self.params.cut_through_horizon as u64;
 		let head = batch.head()?;
 
 		let tail = match batch.tail() {
@@ -1060,9 +1703,33 @@ impl Chain {
 		}
 		batch.save_body_tail(&Tip::from_header(&tail))?;
 
+		// Fork blocks below the tail won't ever make it to our main chain,
+		// remove them along with their filters (the ones of main chain blocks
+		// are kept for wallets). Every block we saved has a filter, which is
+		// much cheaper to go through than the blocks themselves.
+		let mut fork_count = 0;
+		for hash in batch.block_filter_hashes()? {
+			match batch.get_block_header(&hash) {
+				Ok(header) => {
+					if header.height >= tail.height
+						|| txhashset.get_header_hash_by_height(header.height)? == hash
+					{
+						continue;
+					}
+				}
+				Err(NotFoundErr(_)) => {}
+				Err(e) => return Err(From::from(e)),
+			}
+			if batch.block_exists(&hash)? {
+				batch.delete_block(&hash)?;
+			}
+			batch.delete_block_filter(&hash)?;
+			fork_count += 1;
+		}
+
 		debug!(
-			"remove_historical_blocks: removed {} blocks. tail height: {}",
-			count, tail.height
+			"remove_historical_blocks: removed {} blocks and {} fork blocks. tail height: {}",
+			count, fork_count, tail.height
 		);
 
 		Ok(())
@@ -1079,7 +1746,7 @@ impl Chain {
 		// current "head" and "tail" height to our cut-through horizon and
 		// allowing an additional 60 blocks in height before allowing a further compaction.
 		if let (Ok(tail), Ok(head)) = (self.tail(), self.head()) {
-			let horizon = global::cut_through_horizon() as u64;
+			let horizon = self.params.cut_through_horizon as u64;
 			let threshold = horizon.saturating_add(60);
 			debug!(
 				"compact: head: {}, tail: {}, diff: {}, horizon: {}",
@@ -1107,7 +1774,7 @@ impl Chain {
 		let mut batch = self.store.batch()?;
 
 		// Compact the txhashset itself (rewriting the pruned backend files).
-		txhashset.compact(&mut batch)?;
+		txhashset.compact(self.params.cut_through_horizon, &mut batch)?;
 
 		// Rebuild our output_pos index in the db based on current UTXO set.
 		txhashset::extending(&mut txhashset, &mut batch, |extension| {
@@ -1126,6 +1793,155 @@ impl Chain {
 		Ok(())
 	}
 
+	/// Is there a reindex in progress (started previously but not yet completed)?
+	pub fn reindex_in_progress(&self) -> bool {
+		self.store.reindex_head().is_ok()
+	}
+
+	/// Rebuild the full chain state from the blocks in our db.
+	///
+	/// * rebuilds the output, rangeproof, kernel and header MMRs from genesis
+	/// * rebuilds the output_pos index, the block_sums and the block input bitmaps
+	/// * replays every block up to the current head through full validation
+	///
+	/// Requires archive mode as we need every block since genesis.
+	/// The target (the chain head when the reindex started) is saved in the db
+	/// and the chain head is updated as each block is replayed, so an interrupted
+	/// reindex will resume from where it left off.
+	pub fn reindex(&self, status: &dyn ReindexStatus) -> Result<(), Error> {
+		if !self.archive_mode {
+			return Err(ErrorKind::Other("reindex requires archive mode".to_owned()).into());
+		}
+
+		let target = match self.store.reindex_head() {
+			Ok(target) => {
+				info!(
+					"reindex: resuming reindex to {} at {}",
+					target.last_block_h, target.height
+				);
+				// Interrupted before any block got replayed, the txhashset may
+				// not have been fully reset yet so do it again.
+				if self.head()?.height == 0 {
+					self.reset_for_reindex(None)?;
+				}
+				target
+			}
+			Err(NotFoundErr(_)) => {
+				// We can only reindex if we have every full block back to genesis.
+				if let Ok(tail) = self.tail() {
+					if tail.height > 1 {
+						return Err(ErrorKind::Other(format!(
+							"reindex requires all blocks, tail at {}",
+							tail.height
+						))
+						.into());
+					}
+				}
+				let head = self.head()?;
+				info!(
+					"reindex: starting reindex to {} at {}",
+					head.last_block_h, head.height
+				);
+				self.reset_for_reindex(Some(&head))?;
+				head
+			}
+			Err(e) => return Err(ErrorKind::StoreErr(e, "reindex head".to_owned()).into()),
+		};
+
+		// Find the blocks to replay, walking back from the target to our current head.
+		let head = self.head()?;
+		let mut hashes = vec![];
+		let mut current = self.get_block_header(&target.last_block_h)?;
+		while current.height > head.height {
+			hashes.push(current.hash());
+			current = self.get_previous_header(&current)?;
+		}
+		if current.hash() != head.last_block_h {
+			return Err(ErrorKind::Other("reindex target not on current chain".to_owned()).into());
+		}
+		hashes.reverse();
+
+		debug!(
+			"reindex: replaying {} blocks from {} to {}",
+			hashes.len(),
+			head.height,
+			target.height
+		);
+
+		for h in hashes {
+			// Note: We take a lock on the stop_state here for each block so we can
+			// stop cleanly between blocks and resume later.
+			let stop_lock = self.stop_state.lock();
+			if stop_lock.is_stopped() {
+				return Err(ErrorKind::Stopped.into());
+			}
+
+			let b = self.get_block(&h)?;
+			let mut txhashset = self.txhashset.write();
+			let batch = self.store.batch()?;
+			let mut ctx = self.new_ctx(Options::NONE, batch, &mut txhashset)?;
+			pipe::replay_block(&b, &mut ctx)?;
+			ctx.batch.commit()?;
+
+			status.on_reindex(b.header.height, target.height);
+		}
+
+		// All done, our header MMR now tracks the body head so
+		// reset header_head and sync_head to match.
+		let batch = self.store.batch()?;
+		batch.delete_reindex_head()?;
+		batch.reset_header_head()?;
+		batch.reset_sync_head()?;
+		batch.commit()?;
+
+		info!(
+			"reindex: done, head {} at {}",
+			target.last_block_h, target.height
+		);
+
+		Ok(())
+	}
+
+	/// Discard the txhashset and the output_pos and kernel_pos indexes and apply the genesis block
+	/// to a brand new (empty) txhashset, ready to replay all blocks on top of it.
+	/// When starting a reindex, its target is saved along with the reset head.
+	fn reset_for_reindex(&self, target: Option<&Tip>) -> Result<(), Error> {
+		let genesis = self.get_block(&self.genesis.hash())?;
+
+		let mut txhashset_ref = self.txhashset.write();
+
+		// Commit the reset head before touching the txhashset files so we never
+		// end up with a head that the txhashset on disk does not support.
+		{
+			let batch = self.store.batch()?;
+			if let Some(target) = target {
+				batch.save_reindex_head(target)?;
+			}
+			batch.save_body_head(&Tip::from_header(&genesis.header))?;
+			batch.clear_output_pos()?;
+			batch.clear_kernel_pos()?;
+			batch.commit()?;
+		}
+
+		// Before deleting, drop file handlers in underlying txhashset.
+		txhashset_ref.release_backend_files();
+		let db_root = PathBuf::from(self.db_root.clone());
+		txhashset::clean_txhashset_folder(&db_root);
+		txhashset::clean_header_folder(&db_root);
+
+		let mut txhashset =
+			txhashset::TxHashSet::open(self.db_root.clone(), self.store.clone(), None)?;
+		let mut batch = self.store.batch()?;
+		setup_genesis(&genesis, &mut batch, &mut txhashset)?;
+		batch.commit()?;
+
+		*txhashset_ref = txhashset;
+
+		debug!("reindex: reset txhashset to genesis {}", genesis.hash());
+
+		Ok(())
+	}
+
 	/// returns the last n nodes inserted into the output sum tree
 	pub fn get_last_n_output(&self, distance: u64) -> Vec<(Hash, OutputIdentifier)> {
 		self.txhashset.read().last_n_output(distance)
@@ -1178,6 +1994,16 @@ impl Chain {
 		self.orphans.len()
 	}
 
+	/// Maximum number of orphans in the orphan pool.
+	pub fn orphans_max_size(&self) -> usize {
+		self.orphans.max_size()
+	}
+
+	/// Summary of the orphan blocks in our orphan pool, ordered by height.
+	pub fn orphans(&self) -> Vec<OrphanInfo> {
+		self.orphans.infos()
+	}
+
 	/// Tip (head) of the block chain.
 	pub fn head(&self) -> Result<Tip, Error> {
 		self.store
@@ -1234,6 +2060,26 @@ impl Chain {
 			.map_err(|e| ErrorKind::StoreErr(e, "chain get block_sums".to_owned()).into())
 	}
 
+	/// Get the stats for a block on our main chain by header hash.
+	/// These are kept even after the full block has been compacted away.
+	pub fn get_block_stats(&self, h: &Hash) -> Result<BlockStats, Error> {
+		self.store
+			.get_block_stats(h)
+			.map_err(|e| ErrorKind::StoreErr(e, "chain get block_stats".to_owned()).into())
+	}
+
+	/// Get the compact filter of a block by header hash, for wallets to test
+	/// which blocks may be relevant to them. Kept for the blocks of our main
+	/// chain even after the full block has been compacted away. Built from
+	/// the block if it was stored before we kept filters.
+	pub fn get_block_filter(&self, h: &Hash) -> Result<BlockFilter, Error> {
+		match self.store.get_block_filter(h) {
+			Ok(filter) => Ok(filter),
+			Err(NotFoundErr(_)) => Ok(BlockFilter::from_block(&self.get_block(h)?)),
+			Err(e) => Err(ErrorKind::StoreErr(e, "chain get block_filter".to_owned()).into()),
+		}
+	}
+
 	/// Gets the block header at the provided height.
 	/// Note: Takes a read lock on the txhashset.
 	/// Take care not to call this repeatedly in a tight loop.
@@ -1286,6 +2132,130 @@ impl Chain {
 		}
 	}
 
+	/// Merkle proof of the kernel with the provided excess against the kernel
+	/// root of the block it was included in, for light clients (SPV) to check
+	/// a payment made it to the chain with the headers alone.
+	pub fn get_kernel_merkle_proof(&self, excess: &Commitment) -> Result<KernelProof, Error> {
+		let mut txhashset = self.txhashset.write();
+		let (kernel, pos) = txhashset.find_kernel(excess)?;
+		let header = self.header_for_kernel_pos(&txhashset, pos)?;
+		let proof = txhashset.kernel_merkle_proof(&header, pos)?;
+		Ok(KernelProof {
+			block_hash: header.hash(),
+			kernel,
+			pos,
+			proof,
+		})
+	}
+
+	/// Checks a payment proof: the signature of the receiver, and the kernel
+	/// the proof is for being on our chain. Returns the merkle proof of the
+	/// kernel against the block it was included in, evidence of the payment
+	/// for anyone with the headers.
+	pub fn verify_payment_proof(&self, proof: &PaymentProof) -> Result<KernelProof, Error> {
+		{
+			let secp = static_secp_instance();
+			let secp = secp.lock();
+			proof
+				.verify(&secp)
+				.map_err(|e| ErrorKind::InvalidPaymentProof(e.to_string()))?;
+		}
+		let kernel_proof = self.get_kernel_merkle_proof(&proof.excess)?;
+		proof
+			.verify_kernel(&kernel_proof.kernel)
+			.map_err(|e| ErrorKind::InvalidPaymentProof(e.to_string()))?;
+		Ok(kernel_proof)
+	}
+
+	// Header of the block on our chain which included the kernel at the
+	// provided kernel MMR position: the first one with a larger kernel MMR.
+	fn header_for_kernel_pos(
+		&self,
+		txhashset: &txhashset::TxHashSet,
+		pos: u64,
+	) -> Result<BlockHeader, Error> {
+		let mut min = 0;
+		let mut max = self.head()?.height;
+		while min < max {
+			let height = min + (max - min) / 2;
+			if txhashset.get_header_by_height(height)?.kernel_mmr_size < pos {
+				min = height + 1;
+			} else {
+				max = height;
+			}
+		}
+		let header = txhashset.get_header_by_height(min)?;
+		if header.kernel_mmr_size < pos {
+			return Err(ErrorKind::TxKernelNotFound.into());
+		}
+		Ok(header)
+	}
+
+	/// Succinct proof of the work behind the last header of our header MMR,
+	/// sampling headers by cumulative difficulty (see `core::chain_proof`).
+	/// Lets light clients check our chain has the most work with only a
+	/// logarithmic number of headers.
+	pub fn get_chain_proof(&self) -> Result<ChainProof, Error> {
+		let mut txhashset = self.txhashset.write();
+		let tip_height = match txhashset.header_mmr_height() {
+			Some(height) if height > 0 => height,
+			_ => return Err(ErrorKind::Other("no chain proof without blocks".to_owned()).into()),
+		};
+		let tip = txhashset.get_header_by_height(tip_height)?;
+		let genesis = txhashset.get_header_by_height(0)?;
+		let last = txhashset.get_header_by_height(tip_height - 1)?;
+
+		let points = chain_proof::sample_points(
+			&tip,
+			genesis.total_difficulty().to_num(),
+			last.total_difficulty().to_num(),
+		);
+		let mut heights = vec![0, last.height];
+		for point in points {
+			heights.push(self.height_for_work(&txhashset, point, last.height)?);
+		}
+		heights.sort_unstable();
+		heights.dedup();
+
+		let mmr_size = chain_proof::header_mmr_size(tip.height);
+		let mut headers = Vec::with_capacity(heights.len());
+		for height in heights {
+			let header = txhashset.get_header_by_height(height)?;
+			let proof = txhashset.header_merkle_proof(height, mmr_size)?;
+			headers.push(SampledHeader { header, proof });
+		}
+		Ok(ChainProof { tip, headers })
+	}
+
+	/// Verifies a chain proof against our genesis and proof of work.
+	pub fn verify_chain_proof(&self, proof: &ChainProof) -> Result<(), Error> {
+		proof
+			.verify(&self.params, &self.genesis.hash(), self.pow_verifier)
+			.map_err(|e| ErrorKind::InvalidChainProof(e).into())
+	}
+
+	// Height of the first header with at least the provided total difficulty,
+	// up to the provided max height.
+	fn height_for_work(
+		&self,
+		txhashset: &txhashset::TxHashSet,
+		work: u64,
+		max: u64,
+	) -> Result<u64, Error> {
+		let mut min = 0;
+		let mut max = max;
+		while min < max {
+			let height = min + (max - min) / 2;
+			let header = txhashset.get_header_by_height(height)?;
+			if header.total_difficulty().to_num() < work {
+				min = height + 1;
+			} else {
+				max = height;
+			}
+		}
+		Ok(min)
+	}
+
 	/// Verifies the given block header is actually on the current chain.
 	/// Checks the header_by_height index to verify the header is where we say
 	/// it is
@@ -1409,36 +2379,7 @@ fn setup_head(
 			}
 		}
 		Err(NotFoundErr(_)) => {
-			let mut sums = BlockSums::default();
-
-			// Save the genesis header with a "zero" header_root.
-			// We will update this later once we have the correct header_root.
-			batch.save_block_header(&genesis.header)?;
-			batch.save_block(&genesis)?;
-
-			let tip = Tip::from_header(&genesis.header);
-			batch.save_head(&tip)?;
-
-			if genesis.kernels().len() > 0 {
-				let (utxo_sum, kernel_sum) = (sums, genesis as &Committed).verify_kernel_sums(
-					genesis.header.overage(),
-					genesis.header.total_kernel_offset(),
-				)?;
-				sums = BlockSums {
-					utxo_sum,
-					kernel_sum,
-				};
-			}
-			txhashset::extending(txhashset, &mut batch, |extension| {
-				extension.apply_block(&genesis)?;
-				extension.validate_roots()?;
-				extension.validate_sizes()?;
-				Ok(())
-			})?;
-
-			// Save the block_sums to the db for use later.
-			batch.save_block_sums(&genesis.hash(), &sums)?;
-
+			setup_genesis(genesis, &mut batch, txhashset)?;
 			info!("init: saved genesis: {:?}", genesis.hash());
 		}
 		Err(e) => return Err(ErrorKind::StoreErr(e, "chain init load head".to_owned()))?,
@@ -1469,3 +2410,45 @@ fn setup_head(
 
 	Ok(())
 }
+
+/// Save the genesis block and apply it to an empty txhashset.
+/// Used both when initializing a brand new chain and when rebuilding
+/// the chain state from genesis during a reindex.
+fn setup_genesis(
+	genesis: &Block,
+	batch: &mut store::Batch<'_>,
+	txhashset: &mut txhashset::TxHashSet,
+) -> Result<(), Error> {
+	let mut sums = BlockSums::default();
+
+	// Save the genesis header with a "zero" header_root.
+	// We will update this later once we have the correct header_root.
+	batch.save_block_header(&genesis.header)?;
+	batch.save_block(&genesis)?;
+
+	let tip = Tip::from_header(&genesis.header);
+	batch.save_head(&tip)?;
+
+	if genesis.kernels().len() > 0 {
+		let (utxo_sum, kernel_sum) = (sums, genesis as &Committed).verify_kernel_sums(
+			genesis.header.overage(),
+			genesis.header.total_kernel_offset(),
+		)?;
+		sums = BlockSums {
+			utxo_sum,
+			kernel_sum,
+		};
+	}
+	txhashset::extending(txhashset, batch, |extension| {
+		extension.apply_block(&genesis)?;
+		extension.validate_roots()?;
+		extension.validate_sizes()?;
+		Ok(())
+	})?;
+
+	// Save the block_sums to the db for use later.
+	batch.save_block_sums(&genesis.hash(), &sums)?;
+	batch.save_block_stats(&genesis.hash(), &BlockStats::from_block(&genesis, None))?;
+
+	Ok(())
+}
@@ -13,6 +13,8 @@
 // limitations under the License.
 
 //! Error types for chain
+use crate::core::core::chain_proof::ChainProofError;
+use crate::core::core::pmmr::SegmentError;
 use crate::core::core::{block, committed, transaction};
 use crate::core::ser;
 use crate::keychain;
@@ -107,6 +109,10 @@ pub enum ErrorKind {
 	/// We've been provided a bad txhashset
 	#[fail(display = "Invalid TxHashSet: {}", _0)]
 	InvalidTxHashSet(String),
+	/// A file of the txhashset archive we've been provided doesn't match its
+	/// manifest
+	#[fail(display = "Invalid TxHashSet file {}: {}", _0, _1)]
+	InvalidTxHashSetFile(String, String),
 	/// Internal issue when trying to save or load data from store
 	#[fail(display = "Store Error: {}, reason: {}", _1, _0)]
 	StoreErr(store::Error, String),
@@ -119,6 +125,13 @@ pub enum ErrorKind {
 	/// Error with the txhashset
 	#[fail(display = "TxHashSetErr: {}", _0)]
 	TxHashSetErr(String),
+	/// The txhashset dir was written with a newer layout than the one we
+	/// support
+	#[fail(
+		display = "TxHashSet layout version {} newer than supported version {}",
+		_0, _1
+	)]
+	TxHashSetVersion(u32, u32),
 	/// Tx not valid based on lock_height.
 	#[fail(display = "Transaction Lock Height")]
 	TxLockHeight,
@@ -137,9 +150,24 @@ pub enum ErrorKind {
 	/// We cannot process data once the Grin server has been stopped.
 	#[fail(display = "Stopped (Grin Shutting Down)")]
 	Stopped,
+	/// We cannot process blocks or headers until a reindex completes.
+	#[fail(display = "Reindex in progress")]
+	ReindexInProgress,
+	/// Attempt to rewind to a height outside of our tail to head range
+	#[fail(display = "Height {} outside rewindable range {} to {}", _0, _1, _2)]
+	RewindOutOfRange(u64, u64, u64),
 	/// Internal Roaring Bitmap error
 	#[fail(display = "Roaring Bitmap error")]
 	Bitmap,
+	/// We've been provided a txhashset segment not matching the header roots
+	#[fail(display = "Invalid Segment: {}", _0)]
+	InvalidSegment(String),
+	/// We've been provided a chain proof that doesn't check out
+	#[fail(display = "Invalid Chain Proof: {:?}", _0)]
+	InvalidChainProof(ChainProofError),
+	/// We've been provided a payment proof that doesn't check out
+	#[fail(display = "Invalid Payment Proof: {}", _0)]
+	InvalidPaymentProof(String),
 }
 
 impl Display for Error {
@@ -183,7 +211,10 @@ impl Error {
 			| ErrorKind::StoreErr(_, _)
 			| ErrorKind::SerErr(_)
 			| ErrorKind::TxHashSetErr(_)
+			| ErrorKind::TxHashSetVersion(_, _)
 			| ErrorKind::GenesisBlockRequired
+			| ErrorKind::RewindOutOfRange(_, _, _)
+			| ErrorKind::ReindexInProgress
 			| ErrorKind::Other(_) => false,
 			_ => true,
 		}
@@ -256,6 +287,14 @@ impl From<io::Error> for Error {
 	}
 }
 
+impl From<SegmentError> for Error {
+	fn from(e: SegmentError) -> Error {
+		Error {
+			inner: Context::new(ErrorKind::InvalidSegment(format!("{:?}", e))),
+		}
+	}
+}
+
 impl From<secp::Error> for Error {
 	fn from(e: secp::Error) -> Error {
 		Error {
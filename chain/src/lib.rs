@@ -42,7 +42,10 @@ pub mod types;
 
 // Re-export the base interface
 
-pub use crate::chain::{Chain, MAX_ORPHAN_SIZE};
+pub use crate::chain::{Chain, MAX_ORPHAN_AGE_SECS, MAX_ORPHAN_SIZE};
 pub use crate::error::{Error, ErrorKind};
 pub use crate::store::ChainStore;
-pub use crate::types::{BlockStatus, ChainAdapter, Options, Tip, TxHashsetWriteStatus};
+pub use crate::types::{
+	BlockStats, BlockStatus, ChainAdapter, KernelProof, Options, OrphanConfig, OrphanEviction,
+	OrphanInfo, OutputProof, ReindexStatus, SegmentType, Tip, TxHashsetWriteStatus,
+};
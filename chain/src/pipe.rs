@@ -20,17 +20,17 @@ use crate::core::core::hash::Hashed;
 use crate::core::core::verifier_cache::VerifierCache;
 use crate::core::core::Committed;
 use crate::core::core::{Block, BlockHeader, BlockSums};
-use crate::core::global;
+use crate::core::global::ChainParams;
 use crate::core::pow;
 use crate::error::{Error, ErrorKind};
 use crate::store;
 use crate::txhashset;
-use crate::types::{Options, Tip};
+use crate::types::{BlockStats, Options, Tip};
 use crate::util::RwLock;
 use chrono::prelude::Utc;
 use chrono::Duration;
 use grin_store;
-use std::sync::Arc;
+use std::sync::{mpsc, Arc};
 
 /// Contextual information required to process a new block and either reject or
 /// accept it.
@@ -38,7 +38,7 @@ pub struct BlockContext<'a> {
 	/// The options
 	pub opts: Options,
 	/// The pow verifier to use when processing a block.
-	pub pow_verifier: fn(&BlockHeader) -> Result<(), pow::Error>,
+	pub pow_verifier: fn(&ChainParams, &BlockHeader) -> Result<(), pow::Error>,
 	/// The active txhashset (rewindable MMRs) to use for block processing.
 	pub txhashset: &'a mut txhashset::TxHashSet,
 	/// The active batch to use for block processing.
@@ -47,6 +47,8 @@ pub struct BlockContext<'a> {
 	pub verifier_cache: Arc<RwLock<dyn VerifierCache>>,
 	/// Recent orphan blocks to avoid double-processing
 	pub orphans: Arc<OrphanBlockPool>,
+	/// The parameters of the chain the block is processed for
+	pub params: &'a ChainParams,
 }
 
 /// Process a block header as part of processing a full block.
@@ -124,34 +126,22 @@ pub fn process_block(b: &Block, ctx: &mut BlockContext<'_>) -> Result<Option<Tip
 
 	// Validate the block itself, make sure it is internally consistent.
 	// Use the verifier_cache for verifying rangeproofs and kernel signatures.
-	validate_block(b, ctx)?;
+	// This is by far the most expensive part of processing a block so we run it
+	// on the thread pool while we validate the block against the txhashset.
+	let block_validation = spawn_validate_block(b, ctx)?;
 
 	// Start a chain extension unit of work dependent on the success of the
 	// internal validation and saving operations
-	txhashset::extending(&mut ctx.txhashset, &mut ctx.batch, |mut extension| {
-		if is_fork {
-			rewind_and_apply_fork(b, extension)?;
-		}
-
-		// Check any coinbase being spent have matured sufficiently.
-		// This needs to be done within the context of a potentially
-		// rewound txhashset extension to reflect chain state prior
-		// to applying the new block.
-		verify_coinbase_maturity(b, &mut extension)?;
+	let params = ctx.params;
+	txhashset::extending(&mut ctx.txhashset, &mut ctx.batch, |extension| {
+		let res = apply_block_to_extension(b, is_fork, extension, params);
 
-		// Validate the block against the UTXO set.
-		validate_utxo(b, &mut extension)?;
-
-		// Using block_sums (utxo_sum, kernel_sum) for the previous block from the db
-		// we can verify_kernel_sums across the full UTXO sum and full kernel sum
-		// accounting for inputs/outputs/kernels in this new block.
-		// We know there are no double-spends etc. if this verifies successfully.
-		verify_block_sums(b, &mut extension)?;
-
-		// Apply the block to the txhashset state.
-		// Validate the txhashset roots and sizes against the block header.
-		// Block is invalid if there are any discrepencies.
-		apply_block_to_txhashset(b, &mut extension)?;
+		// The block must be internally valid before we commit anything.
+		// Internal validation errors take precedence over the txhashset ones
+		// so a block with bad proofs or signatures is always reported (and its
+		// sender banned) as such, whatever its inputs.
+		block_validation.wait()?;
+		res?;
 
 		// If applying this block does not increase the work on the chain then
 		// we know we have not yet updated the chain to produce a new chain head.
@@ -177,6 +167,83 @@ pub fn process_block(b: &Block, ctx: &mut BlockContext<'_>) -> Result<Option<Tip
 	Ok(res)
 }
 
+/// Validate the block against the txhashset, rewound to its parent if on a
+/// fork, and apply it.
+fn apply_block_to_extension(
+	b: &Block,
+	is_fork: bool,
+	extension: &mut txhashset::Extension<'_>,
+	params: &ChainParams,
+) -> Result<(), Error> {
+	if is_fork {
+		rewind_and_apply_fork(b, extension, params)?;
+	}
+
+	// Check any coinbase being spent have matured sufficiently.
+	// This needs to be done within the context of a potentially
+	// rewound txhashset extension to reflect chain state prior
+	// to applying the new block.
+	verify_coinbase_maturity(b, extension, params)?;
+
+	// Validate the block against the UTXO set.
+	validate_utxo(b, extension)?;
+
+	// Using block_sums (utxo_sum, kernel_sum) for the previous block from the db
+	// we can verify_kernel_sums across the full UTXO sum and full kernel sum
+	// accounting for inputs/outputs/kernels in this new block.
+	// We know there are no double-spends etc. if this verifies successfully.
+	verify_block_sums(b, extension)?;
+
+	// Apply the block to the txhashset state.
+	// Validate the txhashset roots and sizes against the block header.
+	// Block is invalid if there are any discrepencies.
+	apply_block_to_txhashset(b, extension)?;
+	Ok(())
+}
+
+/// Replay a block we already have in the db on top of the current chain head,
+/// running it through full validation again.
+/// This is only ever used when reindexing the chain from genesis, the block
+/// must immediately follow the current chain head.
+pub fn replay_block(b: &Block, ctx: &mut BlockContext<'_>) -> Result<Tip, Error> {
+	debug!(
+		"pipe: replay_block {} at {} [in/out/kern: {}/{}/{}]",
+		b.hash(),
+		b.header.height,
+		b.inputs().len(),
+		b.outputs().len(),
+		b.kernels().len(),
+	);
+
+	let head = ctx.batch.head()?;
+	if b.header.prev_hash != head.last_block_h {
+		return Err(ErrorKind::Unfit("replayed block does not follow head".to_string()).into());
+	}
+
+	validate_header(&b.header, ctx)?;
+	validate_block(b, ctx)?;
+
+	let params = ctx.params;
+	txhashset::extending(&mut ctx.txhashset, &mut ctx.batch, |mut extension| {
+		verify_coinbase_maturity(b, &mut extension, params)?;
+		validate_utxo(b, &mut extension)?;
+		verify_block_sums(b, &mut extension)?;
+		apply_block_to_txhashset(b, &mut extension)?;
+		Ok(())
+	})?;
+
+	// Saving the block again rebuilds the input bitmap for this block
+	// based on the (rebuilt) output_pos index.
+	add_block(b, &ctx.batch)?;
+
+	let tip = Tip::from_header(&b.header);
+	ctx.batch
+		.save_body_head(&tip)
+		.map_err(|e| ErrorKind::StoreErr(e, "pipe save body".to_owned()))?;
+
+	Ok(tip)
+}
+
 /// Process the block header.
 /// This is only ever used during sync and uses a context based on sync_head.
 pub fn sync_block_headers(
@@ -249,6 +316,23 @@ pub fn sync_block_headers(
 	}
 }
 
+/// Brings the header MMR to the header_head, rewinding it first if the
+/// header_head is on a fork. Only used by header-only nodes, as the header
+/// MMR otherwise follows the full blocks we process.
+pub fn update_header_mmr(ctx: &mut BlockContext<'_>) -> Result<(), Error> {
+	let header_head = ctx.batch.header_head()?;
+	let header = ctx.batch.get_block_header(&header_head.last_block_h)?;
+	txhashset::header_extending(&mut ctx.txhashset, &mut ctx.batch, |extension| {
+		if extension.is_on_current_chain(&header).is_ok() {
+			extension.rewind(&header)?;
+		} else {
+			rewind_and_apply_header_fork(&header, extension)?;
+			extension.apply_header(&header)?;
+		}
+		Ok(())
+	})
+}
+
 /// Process block header as part of "header first" block propagation.
 /// We validate the header but we do not store it or update header head based
 /// on this. We will update these once we get the block back after requesting
@@ -348,8 +432,8 @@ fn validate_header(header: &BlockHeader, ctx: &mut BlockContext<'_>) -> Result<(
 	}
 
 	// TODO: remove CI check from here somehow
-	if header.timestamp > Utc::now() + Duration::seconds(12 * (consensus::BLOCK_TIME_SEC as i64))
-		&& !global::is_automated_testing_mode()
+	if header.timestamp > Utc::now() + Duration::seconds(12 * (ctx.params.block_time_sec as i64))
+		&& !ctx.params.is_automated_testing_mode()
 	{
 		// refuse blocks more than 12 blocks intervals in future (as in bitcoin)
 		// TODO add warning in p2p code if local time is too different from peers
@@ -357,11 +441,11 @@ fn validate_header(header: &BlockHeader, ctx: &mut BlockContext<'_>) -> Result<(
 	}
 
 	if !ctx.opts.contains(Options::SKIP_POW) {
-		if !header.pow.is_primary() && !header.pow.is_secondary() {
+		if !header.pow.is_primary(ctx.params) && !header.pow.is_secondary() {
 			return Err(ErrorKind::LowEdgebits.into());
 		}
 		let edge_bits = header.pow.edge_bits();
-		if !(ctx.pow_verifier)(header).is_ok() {
+		if !(ctx.pow_verifier)(ctx.params, header).is_ok() {
 			error!(
 				"pipe: error validating header with cuckoo edge_bits {}",
 				edge_bits
@@ -380,7 +464,7 @@ fn validate_header(header: &BlockHeader, ctx: &mut BlockContext<'_>) -> Result<(
 	}
 
 	// TODO - get rid of the automated testing mode check here somehow
-	if header.timestamp <= prev.timestamp && !global::is_automated_testing_mode() {
+	if header.timestamp <= prev.timestamp && !ctx.params.is_automated_testing_mode() {
 		// prevent time warp attacks and some timestamp manipulations by forcing strict
 		// time progression (but not in CI mode)
 		return Err(ErrorKind::InvalidBlockTime.into());
@@ -399,7 +483,7 @@ fn validate_header(header: &BlockHeader, ctx: &mut BlockContext<'_>) -> Result<(
 
 		let target_difficulty = header.total_difficulty() - prev.total_difficulty();
 
-		if header.pow.to_difficulty(header.height) < target_difficulty {
+		if header.pow.to_difficulty(ctx.params, header.height) < target_difficulty {
 			return Err(ErrorKind::DifficultyTooLow.into());
 		}
 
@@ -408,7 +492,8 @@ fn validate_header(header: &BlockHeader, ctx: &mut BlockContext<'_>) -> Result<(
 		// (during testnet1 we use _block_ difficulty here)
 		let child_batch = ctx.batch.child()?;
 		let diff_iter = store::DifficultyIter::from_batch(prev.hash(), child_batch);
-		let next_header_info = consensus::next_difficulty(header.height, diff_iter);
+		let next_header_info =
+			consensus::next_difficulty_with_params(ctx.params, header.height, diff_iter);
 		if target_difficulty != next_header_info.difficulty {
 			info!(
 				"validate_header: header target difficulty {} != {}",
@@ -433,15 +518,65 @@ fn validate_header(header: &BlockHeader, ctx: &mut BlockContext<'_>) -> Result<(
 fn validate_block(block: &Block, ctx: &mut BlockContext<'_>) -> Result<(), Error> {
 	let prev = ctx.batch.get_previous_header(&block.header)?;
 	block
-		.validate(&prev.total_kernel_offset, ctx.verifier_cache.clone())
+		.validate(
+			ctx.params,
+			&prev.total_kernel_offset,
+			ctx.verifier_cache.clone(),
+		)
 		.map_err(|e| ErrorKind::InvalidBlockProof(e))?;
 	Ok(())
 }
 
+/// Handle on the internal validation of a block running on the thread pool.
+struct BlockValidation {
+	rx: mpsc::Receiver<Result<(), Error>>,
+}
+
+impl BlockValidation {
+	/// Block until the validation completes and return the result.
+	fn wait(self) -> Result<(), Error> {
+		match self.rx.recv() {
+			Ok(res) => res,
+			Err(_) => Err(ErrorKind::Other("block validation did not complete".to_owned()).into()),
+		}
+	}
+}
+
+/// Start validating the block (see `validate_block`) on the thread pool.
+/// We need to clone the block here as the validation may outlive our reference
+/// to it, this is cheap compared to the cost of the validation itself. The
+/// chain parameters go along, the thread pool being shared by all chains.
+fn spawn_validate_block(
+	block: &Block,
+	ctx: &mut BlockContext<'_>,
+) -> Result<BlockValidation, Error> {
+	let prev = ctx.batch.get_previous_header(&block.header)?;
+	let block = block.clone();
+	let verifier_cache = ctx.verifier_cache.clone();
+	let params = ctx.params.clone();
+	let (tx, rx) = mpsc::sync_channel(1);
+	rayon::spawn(move || {
+		let res = block
+			.validate(&params, &prev.total_kernel_offset, verifier_cache)
+			.map(|_| ())
+			.map_err(|e| ErrorKind::InvalidBlockProof(e).into());
+		// Nothing to do if nobody is waiting for the result any more.
+		let _ = tx.send(res);
+	});
+	Ok(BlockValidation { rx })
+}
+
 /// Verify the block is not spending coinbase outputs before they have sufficiently matured.
-fn verify_coinbase_maturity(block: &Block, ext: &txhashset::Extension<'_>) -> Result<(), Error> {
-	ext.utxo_view()
-		.verify_coinbase_maturity(&block.inputs(), block.header.height)
+fn verify_coinbase_maturity(
+	block: &Block,
+	ext: &txhashset::Extension<'_>,
+	params: &ChainParams,
+) -> Result<(), Error> {
+	ext.utxo_view().verify_coinbase_maturity(
+		&block.inputs(),
+		block.header.height,
+		params.coinbase_maturity,
+	)
 }
 
 /// Some "real magick" verification logic.
@@ -488,6 +623,36 @@ fn apply_block_to_txhashset(
 	ext.apply_block(block)?;
 	ext.validate_roots()?;
 	ext.validate_sizes()?;
+	save_block_stats(block, ext)?;
+	Ok(())
+}
+
+/// Save the stats for a block now on our main chain.
+/// These are saved as part of the extension so they are discarded along with
+/// any txhashset changes if the block does not end up on the main chain.
+fn save_block_stats(block: &Block, ext: &txhashset::Extension<'_>) -> Result<(), Error> {
+	let prev = ext.batch.get_previous_header(&block.header)?;
+	let stats = BlockStats::from_block(block, Some(&prev));
+	ext.batch
+		.save_block_stats(&block.hash(), &stats)
+		.map_err(|e| ErrorKind::StoreErr(e, "pipe save block stats".to_owned()))?;
+	Ok(())
+}
+
+/// Delete the stats for all blocks on our main chain beyond the fork point,
+/// as we are about to rewind them.
+fn delete_block_stats(
+	forked_header: &BlockHeader,
+	ext: &txhashset::Extension<'_>,
+) -> Result<(), Error> {
+	let head = ext.batch.head()?;
+	let mut current = ext.batch.get_block_header(&head.last_block_h)?;
+	while current.height > forked_header.height {
+		ext.batch
+			.delete_block_stats(&current.hash())
+			.map_err(|e| ErrorKind::StoreErr(e, "pipe delete block stats".to_owned()))?;
+		current = ext.batch.get_previous_header(&current)?;
+	}
 	Ok(())
 }
 
@@ -611,7 +776,11 @@ pub fn rewind_and_apply_header_fork(
 /// to find to fork root. Rewind the txhashset to the root and apply all the
 /// forked blocks prior to the one being processed to set the txhashset in
 /// the expected state.
-pub fn rewind_and_apply_fork(b: &Block, ext: &mut txhashset::Extension<'_>) -> Result<(), Error> {
+pub fn rewind_and_apply_fork(
+	b: &Block,
+	ext: &mut txhashset::Extension<'_>,
+	params: &ChainParams,
+) -> Result<(), Error> {
 	// extending a fork, first identify the block where forking occurred
 	// keeping the hashes of blocks along the fork
 	let mut fork_hashes = vec![];
@@ -624,6 +793,9 @@ pub fn rewind_and_apply_fork(b: &Block, ext: &mut txhashset::Extension<'_>) -> R
 
 	let forked_header = current;
 
+	// The blocks we rewind are no longer on our main chain.
+	delete_block_stats(&forked_header, ext)?;
+
 	// Rewind the txhashset state back to the block where we forked from the most work chain.
 	ext.rewind(&forked_header)?;
 
@@ -635,7 +807,7 @@ pub fn rewind_and_apply_fork(b: &Block, ext: &mut txhashset::Extension<'_>) -> R
 			.map_err(|e| ErrorKind::StoreErr(e, format!("getting forked blocks")))?;
 
 		// Re-verify coinbase maturity along this fork.
-		verify_coinbase_maturity(&fb, ext)?;
+		verify_coinbase_maturity(&fb, ext, params)?;
 		// Validate the block against the UTXO set.
 		validate_utxo(&fb, ext)?;
 		// Re-verify block_sums to set the block_sums up on this fork correctly.
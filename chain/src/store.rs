@@ -14,14 +14,20 @@
 
 //! Implements storage primitives required by the chain
 
+use crate::chain::Orphan;
 use crate::core::consensus::HeaderInfo;
+use crate::core::core::block_filter::BlockFilter;
 use crate::core::core::hash::{Hash, Hashed};
-use crate::core::core::{Block, BlockHeader, BlockSums};
+use crate::core::core::pmmr::{self, ReadonlyPMMR};
+use crate::core::core::{Block, BlockHeader, BlockSums, TxKernel};
+use crate::core::global::ChainParams;
 use crate::core::pow::Difficulty;
-use crate::types::Tip;
+use crate::types::{BlockStats, Tip};
 use crate::util::secp::pedersen::Commitment;
 use croaring::Bitmap;
 use grin_store as store;
+use grin_store::migration::{self, Migration};
+use grin_store::pmmr::PMMRBackend;
 use grin_store::{option_to_not_found, to_key, Error};
 use std::sync::Arc;
 
@@ -36,6 +42,18 @@ const SYNC_HEAD_PREFIX: u8 = 's' as u8;
 const COMMIT_POS_PREFIX: u8 = 'c' as u8;
 const BLOCK_INPUT_BITMAP_PREFIX: u8 = 'B' as u8;
 const BLOCK_SUMS_PREFIX: u8 = 'M' as u8;
+const REINDEX_HEAD_PREFIX: u8 = 'R' as u8;
+const BLOCK_STATS_PREFIX: u8 = 'S' as u8;
+const ORPHAN_PREFIX: u8 = 'o' as u8;
+const KERNEL_POS_PREFIX: u8 = 'k' as u8;
+const BLOCK_FILTER_PREFIX: u8 = 'f' as u8;
+
+/// Current schema version of the chain db.
+/// Bump this (and add a migration) whenever the format of the data changes.
+pub const SCHEMA_VERSION: u32 = 2;
+
+/// Number of blocks processed per (committed) step of a migration.
+const MIGRATION_STEP_SIZE: usize = 1000;
 
 /// All chain-related database operations
 pub struct ChainStore {
@@ -43,11 +61,31 @@ pub struct ChainStore {
 }
 
 impl ChainStore {
-	/// Create new chain store
-	pub fn new(db_root: &str) -> Result<ChainStore, Error> {
-		let db = store::Store::new(db_root, None, Some(STORE_SUBPATH.clone()), None)?;
+	/// Create new chain store, for the chain with the provided parameters
+	pub fn new(db_root: &str, params: ChainParams) -> Result<ChainStore, Error> {
+		let db = store::Store::new(db_root, None, Some(STORE_SUBPATH.clone()), None)?
+			.with_chain_params(params);
 		Ok(ChainStore { db })
 	}
+
+	/// The schema version of the chain db.
+	pub fn schema_version(&self) -> Result<u32, Error> {
+		migration::schema_version(&self.db)
+	}
+
+	/// Runs any migration required to bring the chain db up to our current
+	/// schema version, given our (whole) kernel MMR. Fails if the db schema
+	/// is newer than what we support.
+	pub fn migrate(
+		&self,
+		kernel_pmmr: ReadonlyPMMR<'_, TxKernel, PMMRBackend<TxKernel>>,
+	) -> Result<(), Error> {
+		let migrations: Vec<Box<dyn Migration + '_>> = vec![
+			Box::new(BlockStatsMigration),
+			Box::new(KernelIndexMigration { kernel_pmmr }),
+		];
+		migration::migrate(&self.db, SCHEMA_VERSION, &migrations)
+	}
 }
 
 impl ChainStore {
@@ -76,6 +114,11 @@ impl ChainStore {
 		option_to_not_found(self.db.get_ser(&vec![SYNC_HEAD_PREFIX]), "SYNC_HEAD")
 	}
 
+	/// The target of an in progress (possibly interrupted) reindex.
+	pub fn reindex_head(&self) -> Result<Tip, Error> {
+		option_to_not_found(self.db.get_ser(&vec![REINDEX_HEAD_PREFIX]), "REINDEX_HEAD")
+	}
+
 	/// Get full block.
 	pub fn get_block(&self, h: &Hash) -> Result<Block, Error> {
 		option_to_not_found(
@@ -97,6 +140,24 @@ impl ChainStore {
 		)
 	}
 
+	/// Get the stats for a block on our main chain.
+	pub fn get_block_stats(&self, h: &Hash) -> Result<BlockStats, Error> {
+		option_to_not_found(
+			self.db
+				.get_ser(&to_key(BLOCK_STATS_PREFIX, &mut h.to_vec())),
+			&format!("Block stats for block: {}", h),
+		)
+	}
+
+	/// Get the compact filter of a block.
+	pub fn get_block_filter(&self, h: &Hash) -> Result<BlockFilter, Error> {
+		option_to_not_found(
+			self.db
+				.get_ser(&to_key(BLOCK_FILTER_PREFIX, &mut h.to_vec())),
+			&format!("Block filter for block: {}", h),
+		)
+	}
+
 	/// Get previous header.
 	pub fn get_previous_header(&self, header: &BlockHeader) -> Result<BlockHeader, Error> {
 		self.get_block_header(&header.prev_hash)
@@ -111,6 +172,12 @@ impl ChainStore {
 		)
 	}
 
+	/// The orphan blocks kept in the db (see `OrphanConfig::persist_orphans`).
+	pub(crate) fn orphans(&self) -> Result<Vec<Orphan>, Error> {
+		let key = to_key(ORPHAN_PREFIX, &mut "".to_string().into_bytes());
+		Ok(self.db.iter::<Orphan>(&key)?.map(|(_, x)| x).collect())
+	}
+
 	/// Get PMMR pos for the given output commitment.
 	pub fn get_output_pos(&self, commit: &Commitment) -> Result<u64, Error> {
 		option_to_not_found(
@@ -120,6 +187,15 @@ impl ChainStore {
 		)
 	}
 
+	/// Get kernel MMR pos for the given kernel excess.
+	pub fn get_kernel_pos(&self, excess: &Commitment) -> Result<u64, Error> {
+		option_to_not_found(
+			self.db
+				.get_ser(&to_key(KERNEL_POS_PREFIX, &mut excess.as_ref().to_vec())),
+			&format!("Kernel position for: {:?}", excess),
+		)
+	}
+
 	/// Builds a new batch to be used with this store.
 	pub fn batch(&self) -> Result<Batch<'_>, Error> {
 		Ok(Batch {
@@ -186,6 +262,21 @@ impl<'a> Batch<'a> {
 		self.db.put_ser(&vec![SYNC_HEAD_PREFIX], t)
 	}
 
+	/// The target of an in progress (possibly interrupted) reindex.
+	pub fn reindex_head(&self) -> Result<Tip, Error> {
+		option_to_not_found(self.db.get_ser(&vec![REINDEX_HEAD_PREFIX]), "REINDEX_HEAD")
+	}
+
+	/// Save the target of a reindex to db so an interrupted reindex can be resumed.
+	pub fn save_reindex_head(&self, t: &Tip) -> Result<(), Error> {
+		self.db.put_ser(&vec![REINDEX_HEAD_PREFIX], t)
+	}
+
+	/// Delete the reindex target once the reindex has completed.
+	pub fn delete_reindex_head(&self) -> Result<(), Error> {
+		self.db.delete(&vec![REINDEX_HEAD_PREFIX])
+	}
+
 	/// Reset sync_head to the current head of the header chain.
 	pub fn reset_sync_head(&self) -> Result<(), Error> {
 		let head = self.header_head()?;
@@ -217,6 +308,12 @@ impl<'a> Batch<'a> {
 		// Build the "input bitmap" for this new block and store it in the db.
 		self.build_and_store_block_input_bitmap(&b)?;
 
+		// Build the compact filter of the block for wallets. Unlike the block
+		// itself, the filter of a main chain block is not removed when the
+		// block is pruned during compaction, so wallets can scan the full
+		// history. Fork blocks take their filter with them.
+		self.save_block_filter(&BlockFilter::from_block(b))?;
+
 		// Save the block itself to the db.
 		self.db
 			.put_ser(&to_key(BLOCK_PREFIX, &mut b.hash().to_vec())[..], b)?;
@@ -277,6 +374,23 @@ impl<'a> Batch<'a> {
 		Ok(())
 	}
 
+	/// Save kernel_pos to index.
+	pub fn save_kernel_pos(&self, excess: &Commitment, pos: u64) -> Result<(), Error> {
+		self.db.put_ser(
+			&to_key(KERNEL_POS_PREFIX, &mut excess.as_ref().to_vec())[..],
+			&pos,
+		)
+	}
+
+	/// Clear all entries from the kernel_pos index (must be rebuilt after).
+	pub fn clear_kernel_pos(&self) -> Result<(), Error> {
+		let key = to_key(KERNEL_POS_PREFIX, &mut "".to_string().into_bytes());
+		for (k, _) in self.db.iter::<u64>(&key)? {
+			self.db.delete(&k)?;
+		}
+		Ok(())
+	}
+
 	/// Get the previous header.
 	pub fn get_previous_header(&self, header: &BlockHeader) -> Result<BlockHeader, Error> {
 		self.get_block_header(&header.prev_hash)
@@ -324,6 +438,66 @@ impl<'a> Batch<'a> {
 		self.db.delete(&to_key(BLOCK_SUMS_PREFIX, &mut bh.to_vec()))
 	}
 
+	/// Save the compact filter of a block.
+	pub fn save_block_filter(&self, filter: &BlockFilter) -> Result<(), Error> {
+		self.db.put_ser(
+			&to_key(BLOCK_FILTER_PREFIX, &mut filter.block_hash.to_vec())[..],
+			filter,
+		)
+	}
+
+	/// Hashes of all the blocks we have a compact filter for.
+	pub fn block_filter_hashes(&self) -> Result<Vec<Hash>, Error> {
+		let key = to_key(BLOCK_FILTER_PREFIX, &mut "".to_string().into_bytes());
+		Ok(self
+			.db
+			.iter::<BlockFilter>(&key)?
+			.map(|(_, filter)| filter.block_hash)
+			.collect())
+	}
+
+	/// Delete the compact filter of a block, once the block is off our main
+	/// chain for good.
+	pub fn delete_block_filter(&self, h: &Hash) -> Result<(), Error> {
+		self.db
+			.delete(&to_key(BLOCK_FILTER_PREFIX, &mut h.to_vec()))
+	}
+
+	/// Save the stats for a block on our main chain.
+	pub fn save_block_stats(&self, h: &Hash, stats: &BlockStats) -> Result<(), Error> {
+		self.db
+			.put_ser(&to_key(BLOCK_STATS_PREFIX, &mut h.to_vec())[..], stats)
+	}
+
+	/// Get the stats for a block on our main chain.
+	pub fn get_block_stats(&self, h: &Hash) -> Result<BlockStats, Error> {
+		option_to_not_found(
+			self.db
+				.get_ser(&to_key(BLOCK_STATS_PREFIX, &mut h.to_vec())),
+			&format!("Block stats for block: {}", h),
+		)
+	}
+
+	/// Delete the stats for a block, when it is rewound off our main chain.
+	/// Note: unlike block_sums these are not removed when the block is
+	/// pruned during compaction.
+	pub fn delete_block_stats(&self, h: &Hash) -> Result<(), Error> {
+		self.db.delete(&to_key(BLOCK_STATS_PREFIX, &mut h.to_vec()))
+	}
+
+	/// Save an orphan block, to reload it on restart.
+	pub(crate) fn save_orphan(&self, orphan: &Orphan) -> Result<(), Error> {
+		self.db.put_ser(
+			&to_key(ORPHAN_PREFIX, &mut orphan.hash().to_vec())[..],
+			orphan,
+		)
+	}
+
+	/// Delete an orphan block once processed or evicted.
+	pub fn delete_orphan(&self, h: &Hash) -> Result<(), Error> {
+		self.db.delete(&to_key(ORPHAN_PREFIX, &mut h.to_vec()))
+	}
+
 	/// Build the input bitmap for the given block.
 	fn build_block_input_bitmap(&self, block: &Block) -> Result<Bitmap, Error> {
 		let bitmap = block
@@ -474,3 +648,111 @@ impl<'a> Iterator for DifficultyIter<'a> {
 		}
 	}
 }
+
+/// Schema version 1: backfills the stats for all blocks on our main chain we
+/// still have the full block for, walking back from the head.
+/// The progress marker is the hash of the next block to process.
+struct BlockStatsMigration;
+
+impl Migration for BlockStatsMigration {
+	fn version(&self) -> u32 {
+		1
+	}
+
+	fn description(&self) -> &str {
+		"build block stats"
+	}
+
+	fn step(
+		&self,
+		batch: &store::Batch<'_>,
+		progress: Option<Vec<u8>>,
+	) -> Result<Option<Vec<u8>>, Error> {
+		let mut next = match progress {
+			Some(marker) => Hash::from_vec(&marker),
+			None => match batch.get_ser::<Tip>(&vec![HEAD_PREFIX])? {
+				Some(head) => head.last_block_h,
+				// Brand new db, nothing to migrate.
+				None => return Ok(None),
+			},
+		};
+
+		for _ in 0..MIGRATION_STEP_SIZE {
+			let block: Block = match batch.get_ser(&to_key(BLOCK_PREFIX, &mut next.to_vec()))? {
+				Some(block) => block,
+				// Reached our tail, earlier blocks have been compacted away.
+				None => return Ok(None),
+			};
+			let stats_key = to_key(BLOCK_STATS_PREFIX, &mut next.to_vec());
+			let prev: Option<BlockHeader> = if block.header.height > 0 {
+				batch.get_ser(&to_key(
+					BLOCK_HEADER_PREFIX,
+					&mut block.header.prev_hash.to_vec(),
+				))?
+			} else {
+				None
+			};
+			if !batch.exists(&stats_key)? {
+				batch.put_ser(&stats_key, &BlockStats::from_block(&block, prev.as_ref()))?;
+			}
+			if block.header.height == 0 {
+				return Ok(None);
+			}
+			next = block.header.prev_hash;
+		}
+		Ok(Some(next.to_vec()))
+	}
+}
+
+/// Schema version 2: indexes the kernels of dbs predating the kernel_pos
+/// index, going through the whole kernel MMR (kernels are never pruned).
+/// The progress marker is the MMR position to resume from.
+struct KernelIndexMigration<'a> {
+	kernel_pmmr: ReadonlyPMMR<'a, TxKernel, PMMRBackend<TxKernel>>,
+}
+
+impl<'a> Migration for KernelIndexMigration<'a> {
+	fn version(&self) -> u32 {
+		2
+	}
+
+	fn description(&self) -> &str {
+		"build kernel index"
+	}
+
+	fn step(
+		&self,
+		batch: &store::Batch<'_>,
+		progress: Option<Vec<u8>>,
+	) -> Result<Option<Vec<u8>>, Error> {
+		let mut pos = match progress {
+			Some(marker) => {
+				let mut bytes = [0; 8];
+				bytes.copy_from_slice(&marker);
+				u64::from_be_bytes(bytes)
+			}
+			None => 1,
+		};
+
+		let last_pos = self.kernel_pmmr.unpruned_size();
+		let mut count = 0;
+		while pos <= last_pos && count < MIGRATION_STEP_SIZE {
+			if pmmr::is_leaf(pos) {
+				if let Some(entry) = self.kernel_pmmr.get_data(pos) {
+					let key = to_key(
+						KERNEL_POS_PREFIX,
+						&mut entry.kernel.excess.as_ref().to_vec(),
+					);
+					batch.put_ser(&key, &pos)?;
+				}
+				count += 1;
+			}
+			pos += 1;
+		}
+		if pos > last_pos {
+			Ok(None)
+		} else {
+			Ok(Some(pos.to_be_bytes().to_vec()))
+		}
+	}
+}
@@ -15,10 +15,16 @@
 //! Utility structs to handle the 3 hashtrees (output, range proof,
 //! kernel) more conveniently and transactionally.
 
+mod archives;
+mod desegmenter;
+mod manifest;
 mod rewindable_kernel_view;
 mod txhashset;
 mod utxo_view;
 
+pub use self::archives::*;
+pub use self::desegmenter::*;
+pub use self::manifest::*;
 pub use self::rewindable_kernel_view::*;
 pub use self::txhashset::*;
 pub use self::utxo_view::*;
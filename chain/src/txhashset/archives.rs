@@ -0,0 +1,176 @@
+// Copyright 2018 The Grin Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Cache of the txhashset archives we serve to syncing peers, built once
+//! per archive height (see `global::txhashset_archive_height`) and kept on
+//! disk along with a manifest listing them.
+
+use crate::core::core::hash::{Hash, Hashed};
+use crate::core::core::BlockHeader;
+use crate::core::ser::{self, Readable, Reader, Writeable, Writer};
+use crate::error::{Error, ErrorKind};
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+
+/// Directory the archives are cached in, in the chain data dir.
+pub const TXHASHSET_ARCHIVES_SUBDIR: &'static str = "txhashset_archives";
+
+const MANIFEST_FILE: &'static str = "manifest.bin";
+
+/// Number of archives we keep. Syncing peers which started just before we
+/// built the latest one still get the one before.
+const MAX_ARCHIVES: usize = 2;
+
+/// Manifest entry of a cached archive.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ArchiveEntry {
+	/// Hash of the block the archive is at
+	pub hash: Hash,
+	/// Height of the block the archive is at
+	pub height: u64,
+	/// Size of the archive in bytes
+	pub size: u64,
+}
+
+impl ArchiveEntry {
+	fn file_name(&self) -> String {
+		format!("txhashset_{}_{}.zip", self.height, self.hash.to_hex())
+	}
+}
+
+impl Writeable for ArchiveEntry {
+	fn write<W: Writer>(&self, writer: &mut W) -> Result<(), ser::Error> {
+		self.hash.write(writer)?;
+		writer.write_u64(self.height)?;
+		writer.write_u64(self.size)
+	}
+}
+
+impl Readable for ArchiveEntry {
+	fn read(reader: &mut dyn Reader) -> Result<ArchiveEntry, ser::Error> {
+		let hash = Hash::read(reader)?;
+		let height = reader.read_u64()?;
+		let size = reader.read_u64()?;
+		Ok(ArchiveEntry { hash, height, size })
+	}
+}
+
+/// The cached archives, most recent last.
+pub struct ArchiveCache {
+	dir: PathBuf,
+	entries: Vec<ArchiveEntry>,
+}
+
+impl ArchiveCache {
+	/// Opens the cache in the provided chain data dir. Entries of the
+	/// manifest whose archive is missing or doesn't have the expected size
+	/// are dropped, as well as any file the manifest doesn't list (leftovers
+	/// of an interrupted build).
+	pub fn open(root_dir: &str) -> Result<ArchiveCache, Error> {
+		let dir = Path::new(root_dir).join(TXHASHSET_ARCHIVES_SUBDIR);
+		fs::create_dir_all(&dir)?;
+
+		let entries: Vec<ArchiveEntry> = match fs::read(dir.join(MANIFEST_FILE)) {
+			Ok(data) => ser::deserialize_default(&mut &data[..]).unwrap_or_else(|e| {
+				warn!("txhashset archives: invalid manifest, ignoring it: {:?}", e);
+				vec![]
+			}),
+			Err(_) => vec![],
+		};
+		let entries: Vec<ArchiveEntry> = entries
+			.into_iter()
+			.filter(|entry| match fs::metadata(dir.join(entry.file_name())) {
+				Ok(metadata) => metadata.len() == entry.size,
+				Err(_) => false,
+			})
+			.collect();
+
+		let cache = ArchiveCache { dir, entries };
+		cache.write_manifest()?;
+		cache.remove_unlisted();
+		Ok(cache)
+	}
+
+	/// The cached archives, most recent last.
+	pub fn entries(&self) -> &[ArchiveEntry] {
+		&self.entries
+	}
+
+	/// Whether we have an archive at the provided block.
+	pub fn contains(&self, hash: &Hash) -> bool {
+		self.entries.iter().any(|entry| entry.hash == *hash)
+	}
+
+	/// Opens the archive at the provided block, if cached.
+	pub fn get(&self, hash: &Hash) -> Result<Option<File>, Error> {
+		match self.entries.iter().find(|entry| entry.hash == *hash) {
+			Some(entry) => Ok(Some(File::open(self.dir.join(entry.file_name()))?)),
+			None => Ok(None),
+		}
+	}
+
+	/// Moves the archive at the provided block into the cache, evicting the
+	/// oldest ones beyond our limit.
+	pub fn add(&mut self, header: &BlockHeader, zip_path: &Path) -> Result<(), Error> {
+		let entry = ArchiveEntry {
+			hash: header.hash(),
+			height: header.height,
+			size: fs::metadata(zip_path)?.len(),
+		};
+		if self.contains(&entry.hash) {
+			return Err(ErrorKind::TxHashSetErr(format!(
+				"txhashset archive at {} already cached",
+				entry.hash
+			))
+			.into());
+		}
+		fs::rename(zip_path, self.dir.join(entry.file_name()))?;
+
+		self.entries.push(entry);
+		self.entries.sort_by_key(|entry| entry.height);
+		while self.entries.len() > MAX_ARCHIVES {
+			self.entries.remove(0);
+		}
+		self.write_manifest()?;
+		self.remove_unlisted();
+		Ok(())
+	}
+
+	// Writes the manifest in a tmp file first, so we never end up with a
+	// partially written one.
+	fn write_manifest(&self) -> Result<(), Error> {
+		let data = ser::ser_vec(&self.entries, ser::ProtocolVersion::local())
+			.map_err(ErrorKind::SerErr)?;
+		let tmp_path = self.dir.join(format!("{}.tmp", MANIFEST_FILE));
+		fs::write(&tmp_path, data)?;
+		fs::rename(tmp_path, self.dir.join(MANIFEST_FILE))?;
+		Ok(())
+	}
+
+	fn remove_unlisted(&self) {
+		let listed: Vec<String> = self.entries.iter().map(|e| e.file_name()).collect();
+		let dir_entries = match fs::read_dir(&self.dir) {
+			Ok(dir_entries) => dir_entries,
+			Err(_) => return,
+		};
+		for dir_entry in dir_entries.filter_map(|e| e.ok()) {
+			let name = dir_entry.file_name().to_string_lossy().into_owned();
+			if name != MANIFEST_FILE && !listed.contains(&name) {
+				if let Err(e) = fs::remove_file(dir_entry.path()) {
+					warn!("txhashset archives: failed to remove {}: {}", name, e);
+				}
+			}
+		}
+	}
+}
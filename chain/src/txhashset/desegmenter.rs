@@ -0,0 +1,421 @@
+// Copyright 2018 The Grin Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Rebuilds the txhashset from the segments of its MMRs received from our
+//! peers (PIBD), validating each segment against the roots of the header
+//! as it comes in.
+
+use crate::core::core::hash::Hash;
+use crate::core::core::pmmr::{self, Backend, BitmapSegment, Segment, SegmentIdentifier};
+use crate::core::core::{BlockHeader, Output, OutputIdentifier, TxKernel, TxKernelEntry};
+use crate::core::ser::{PMMRIndexHashable, PMMRable};
+use crate::error::{Error, ErrorKind};
+use crate::txhashset::{KERNEL_SUBDIR, OUTPUT_SUBDIR, RANGE_PROOF_SUBDIR, TXHASHSET_SUBDIR};
+use crate::types::SegmentType;
+use crate::util::secp::pedersen::RangeProof;
+use croaring::Bitmap;
+use grin_store::pmmr::PMMRBackend;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Height of the output and output bitmap segments we request (2048 leaves).
+pub const OUTPUT_SEGMENT_HEIGHT: u8 = 11;
+/// Height of the rangeproof segments we request (512 leaves).
+pub const RANGEPROOF_SEGMENT_HEIGHT: u8 = 9;
+/// Height of the kernel segments we request (512 leaves).
+pub const KERNEL_SEGMENT_HEIGHT: u8 = 9;
+
+/// Segments of an MMR are applied strictly in order, this is the number of
+/// segments past the next one to apply we're willing to request and hold
+/// in memory.
+const SEGMENT_WINDOW: u64 = 32;
+
+/// One of the txhashset MMRs, rebuilt segment by segment in its backend.
+struct SegmentedMMR<T: PMMRable> {
+	backend: PMMRBackend<T>,
+	mmr_size: u64,
+	root: Hash,
+	height: u8,
+	next_idx: u64,
+	pending: BTreeMap<u64, Segment<T::E>>,
+	// Position and hash (if not compacted) of the subtree roots not yet
+	// merged into their parent, the peaks of the MMR rebuilt so far.
+	peaks: Vec<(u64, Option<Hash>)>,
+	// Positions of the leaves we received the data of.
+	data_leaves: Bitmap,
+}
+
+impl<T> SegmentedMMR<T>
+where
+	T: PMMRable,
+	T::E: PMMRIndexHashable + Clone,
+{
+	fn new(
+		dir: PathBuf,
+		prunable: bool,
+		fixed_size: bool,
+		mmr_size: u64,
+		root: Hash,
+		height: u8,
+	) -> Result<SegmentedMMR<T>, Error> {
+		fs::create_dir_all(&dir)?;
+		let backend = PMMRBackend::new(dir, prunable, fixed_size, None)?;
+		Ok(SegmentedMMR {
+			backend,
+			mmr_size,
+			root,
+			height,
+			next_idx: 0,
+			pending: BTreeMap::new(),
+			peaks: vec![],
+			data_leaves: Bitmap::create(),
+		})
+	}
+
+	fn segment_count(&self) -> u64 {
+		SegmentIdentifier::count(self.height, self.mmr_size)
+	}
+
+	fn is_complete(&self) -> bool {
+		self.next_idx >= self.segment_count()
+	}
+
+	fn missing_segments(&self) -> Vec<SegmentIdentifier> {
+		let end = self.segment_count().min(self.next_idx + SEGMENT_WINDOW);
+		(self.next_idx..end)
+			.filter(|idx| !self.pending.contains_key(idx))
+			.map(|idx| SegmentIdentifier {
+				height: self.height,
+				idx,
+			})
+			.collect()
+	}
+
+	fn add_segment(&mut self, segment: Segment<T::E>) -> Result<(), Error> {
+		let id = segment.identifier();
+		if id.height != self.height {
+			return Err(ErrorKind::InvalidSegment(format!(
+				"unexpected segment height {}",
+				id.height
+			))
+			.into());
+		}
+		// Already applied, already pending or not requested, nothing to do.
+		if id.idx < self.next_idx
+			|| id.idx >= self.next_idx + SEGMENT_WINDOW
+			|| self.pending.contains_key(&id.idx)
+		{
+			return Ok(());
+		}
+		segment.validate(self.mmr_size, self.root)?;
+		self.pending.insert(id.idx, segment);
+
+		while let Some(segment) = self.pending.remove(&self.next_idx) {
+			self.apply_segment(segment)?;
+			self.next_idx += 1;
+		}
+		Ok(())
+	}
+
+	// Appends all the nodes of a (validated) segment to the backend, up to
+	// the first leaf of the next segment. That includes the parents of the
+	// segment root whose right child is this segment root, merged with the
+	// peaks left by the previous segments. Nothing is written if the segment
+	// turns out to be inconsistent with them.
+	fn apply_segment(&mut self, segment: Segment<T::E>) -> Result<(), Error> {
+		let (id, hashes, leaf_data) = segment.parts();
+		let (first_pos, _, _) = id.bounds(self.mmr_size).ok_or_else(|| {
+			Error::from(ErrorKind::InvalidSegment("non-existent segment".to_owned()))
+		})?;
+		let last_pos = if id.idx + 1 < self.segment_count() {
+			pmmr::insertion_to_pmmr_index((id.idx + 1) * id.capacity() + 1) - 1
+		} else {
+			self.mmr_size
+		};
+		let hashes: HashMap<u64, Hash> = hashes.into_iter().collect();
+		let mut leaf_data = leaf_data.into_iter().peekable();
+
+		let mut peaks = self.peaks.clone();
+		let mut nodes = vec![];
+		let mut pruned_roots = vec![];
+		for pos in first_pos..=last_pos {
+			let height = pmmr::bintree_postorder_height(pos);
+			let (hash, data) = if height == 0 {
+				match leaf_data.peek() {
+					Some((leaf_pos, _)) if *leaf_pos == pos => match leaf_data.next() {
+						Some((_, data)) => (Some(data.hash_with_index(pos - 1)), Some(data)),
+						None => (None, None),
+					},
+					_ => (None, None),
+				}
+			} else {
+				let right = peaks.pop();
+				let left = peaks.pop();
+				let (left, right) = match (left, right) {
+					(Some(left), Some(right))
+						if left.0 == pos - (1 << height) && right.0 == pos - 1 =>
+					{
+						(left.1, right.1)
+					}
+					_ => {
+						return Err(ErrorKind::InvalidSegment(format!(
+							"missing children of {}",
+							pos
+						))
+						.into());
+					}
+				};
+				let hash = match (hashes.get(&pos), left, right) {
+					(Some(hash), None, None) => {
+						pruned_roots.push(pos);
+						Some(*hash)
+					}
+					(None, Some(left), Some(right)) => Some((left, right).hash_with_index(pos - 1)),
+					(None, None, None) => None,
+					_ => {
+						return Err(ErrorKind::InvalidSegment(format!(
+							"inconsistent compaction at {}",
+							pos
+						))
+						.into());
+					}
+				};
+				(hash, None)
+			};
+			peaks.push((pos, hash));
+			nodes.push((pos, hash, data));
+		}
+
+		for (pos, hash, data) in nodes {
+			if let Some(data) = data {
+				self.backend.append_data(&data)?;
+				self.data_leaves.add(pos as u32);
+			}
+			if let Some(hash) = hash {
+				self.backend.append_hash(hash)?;
+			}
+		}
+		for pos in pruned_roots {
+			self.backend.append_pruned_root(pos);
+		}
+		self.peaks = peaks;
+		self.backend.sync()?;
+		Ok(())
+	}
+
+	fn finalize(&mut self) -> Result<(), Error> {
+		self.backend.sync()?;
+		self.backend.sync_prune_list()?;
+		self.backend.release_files();
+		Ok(())
+	}
+}
+
+/// Rebuilds the txhashset at a given header in a sandbox directory from
+/// segments of its MMRs and of the output bitmap (the unspent outputs at
+/// that header). Segments can be received in any order, they're validated
+/// as soon as they're added and the MMRs rebuilt as they become complete
+/// from their beginning.
+pub struct Desegmenter {
+	header: BlockHeader,
+	output: SegmentedMMR<Output>,
+	rangeproof: SegmentedMMR<RangeProof>,
+	kernel: SegmentedMMR<TxKernel>,
+	bitmap_segments: HashSet<u64>,
+	leaf_set: Bitmap,
+}
+
+impl Desegmenter {
+	/// Creates a new desegmenter for the txhashset at the provided header,
+	/// rebuilt in the txhashset folder of the provided (clean) sandbox
+	/// directory.
+	pub fn new(header: BlockHeader, sandbox_dir: &Path) -> Result<Desegmenter, Error> {
+		let dir = sandbox_dir.join(TXHASHSET_SUBDIR);
+		let output = SegmentedMMR::new(
+			dir.join(OUTPUT_SUBDIR),
+			true,
+			true,
+			header.output_mmr_size,
+			header.output_root,
+			OUTPUT_SEGMENT_HEIGHT,
+		)?;
+		let rangeproof = SegmentedMMR::new(
+			dir.join(RANGE_PROOF_SUBDIR),
+			true,
+			true,
+			header.output_mmr_size,
+			header.range_proof_root,
+			RANGEPROOF_SEGMENT_HEIGHT,
+		)?;
+		let kernel = SegmentedMMR::new(
+			dir.join(KERNEL_SUBDIR),
+			false,
+			false,
+			header.kernel_mmr_size,
+			header.kernel_root,
+			KERNEL_SEGMENT_HEIGHT,
+		)?;
+		Ok(Desegmenter {
+			header,
+			output,
+			rangeproof,
+			kernel,
+			bitmap_segments: HashSet::new(),
+			leaf_set: Bitmap::create(),
+		})
+	}
+
+	/// Header of the txhashset being rebuilt.
+	pub fn header(&self) -> &BlockHeader {
+		&self.header
+	}
+
+	fn bitmap_segment_count(&self) -> u64 {
+		SegmentIdentifier::count(OUTPUT_SEGMENT_HEIGHT, self.header.output_mmr_size)
+	}
+
+	/// Up to max segments we still need, bitmap segments first, then
+	/// kernel, output and rangeproof segments interleaved. A bitmap segment
+	/// is only needed once we have the output segment it covers, to check it
+	/// against.
+	pub fn missing_segments(&self, max: usize) -> Vec<(SegmentType, SegmentIdentifier)> {
+		let mut missing: Vec<_> = (0..self.bitmap_segment_count().min(self.output.next_idx))
+			.filter(|idx| !self.bitmap_segments.contains(idx))
+			.map(|idx| {
+				let id = SegmentIdentifier {
+					height: OUTPUT_SEGMENT_HEIGHT,
+					idx,
+				};
+				(SegmentType::Bitmap, id)
+			})
+			.take(max)
+			.collect();
+
+		let mut kernel = self.kernel.missing_segments().into_iter();
+		let mut output = self.output.missing_segments().into_iter();
+		let mut rangeproof = self.rangeproof.missing_segments().into_iter();
+		while missing.len() < max {
+			let next = vec![
+				kernel.next().map(|id| (SegmentType::Kernel, id)),
+				output.next().map(|id| (SegmentType::Output, id)),
+				rangeproof.next().map(|id| (SegmentType::RangeProof, id)),
+			];
+			if next.iter().all(|x| x.is_none()) {
+				break;
+			}
+			missing.extend(next.into_iter().filter_map(|x| x));
+		}
+		missing.truncate(max);
+		missing
+	}
+
+	/// Adds a segment of the output bitmap. The bitmap isn't committed to in
+	/// the header, each output marked unspent has to be one the (validated)
+	/// output segment of the same identifier holds the data of. An unspent
+	/// output missing from the bitmap is only caught by the full validation
+	/// of the txhashset, its commitment missing from the UTXO sum.
+	pub fn add_bitmap_segment(&mut self, segment: BitmapSegment) -> Result<(), Error> {
+		let id = segment.identifier();
+		if id.height != OUTPUT_SEGMENT_HEIGHT {
+			return Err(ErrorKind::InvalidSegment(format!(
+				"unexpected segment height {}",
+				id.height
+			))
+			.into());
+		}
+		// Already added or not requested yet, nothing to do.
+		if self.bitmap_segments.contains(&id.idx) || id.idx >= self.output.next_idx {
+			return Ok(());
+		}
+		segment.validate(self.header.output_mmr_size)?;
+		let positions = segment.leaf_positions();
+		if let Some(pos) = positions
+			.iter()
+			.find(|pos| !self.output.data_leaves.contains(**pos as u32))
+		{
+			return Err(ErrorKind::InvalidSegment(format!(
+				"no unspent output at {} in the output segment",
+				pos
+			))
+			.into());
+		}
+		for pos in positions {
+			self.leaf_set.add(pos as u32);
+		}
+		self.bitmap_segments.insert(id.idx);
+		Ok(())
+	}
+
+	/// Adds a segment of the output MMR.
+	pub fn add_output_segment(&mut self, segment: Segment<OutputIdentifier>) -> Result<(), Error> {
+		self.output.add_segment(segment)
+	}
+
+	/// Adds a segment of the rangeproof MMR.
+	pub fn add_rangeproof_segment(&mut self, segment: Segment<RangeProof>) -> Result<(), Error> {
+		self.rangeproof.add_segment(segment)
+	}
+
+	/// Adds a segment of the kernel MMR.
+	pub fn add_kernel_segment(&mut self, segment: Segment<TxKernelEntry>) -> Result<(), Error> {
+		self.kernel.add_segment(segment)
+	}
+
+	/// Number of segments received and applied so far, along with the total
+	/// number of segments.
+	pub fn progress(&self) -> (u64, u64) {
+		let received = self.bitmap_segments.len() as u64
+			+ self.output.next_idx
+			+ self.rangeproof.next_idx
+			+ self.kernel.next_idx;
+		let total = self.bitmap_segment_count()
+			+ self.output.segment_count()
+			+ self.rangeproof.segment_count()
+			+ self.kernel.segment_count();
+		(received, total)
+	}
+
+	/// Whether we have received all the segments.
+	pub fn is_complete(&self) -> bool {
+		self.bitmap_segments.len() as u64 == self.bitmap_segment_count()
+			&& self.output.is_complete()
+			&& self.rangeproof.is_complete()
+			&& self.kernel.is_complete()
+	}
+
+	/// Writes the leaf sets of the output and rangeproof MMRs and all the
+	/// prune lists, leaving a txhashset in the sandbox that can be opened and
+	/// fully validated like one extracted from an archive.
+	pub fn finalize(mut self) -> Result<(), Error> {
+		if !self.is_complete() {
+			return Err(ErrorKind::TxHashSetErr("missing segments".to_owned()).into());
+		}
+		for pos in self.leaf_set.iter() {
+			if !self.rangeproof.data_leaves.contains(pos) {
+				return Err(ErrorKind::InvalidSegment(format!(
+					"missing unspent output at {}",
+					pos
+				))
+				.into());
+			}
+			self.output.backend.add_leaf(pos as u64);
+			self.rangeproof.backend.add_leaf(pos as u64);
+		}
+		self.output.finalize()?;
+		self.rangeproof.finalize()?;
+		self.kernel.finalize()?;
+		Ok(())
+	}
+}
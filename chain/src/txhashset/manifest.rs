@@ -0,0 +1,203 @@
+// Copyright 2018 The Grin Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Manifest of the files in a txhashset archive with their size and blake2b
+//! hash, to check an archive we received is intact right after extracting
+//! it, before going through the (much more expensive) full validation.
+
+use crate::core::core::hash::{Hash, HashWriter};
+use crate::core::ser::{self, Readable, Reader, Writeable, Writer};
+use crate::error::{Error, ErrorKind};
+use crate::txhashset::{KERNEL_SUBDIR, OUTPUT_SUBDIR, RANGE_PROOF_SUBDIR};
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::Path;
+
+/// Name of the manifest file, at the root of the archive.
+pub const MANIFEST_FILE: &'static str = "manifest.bin";
+
+/// Size and hash of a file in the archive.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ManifestEntry {
+	/// Path of the file relative to the root of the archive, '/' separated
+	pub path: String,
+	/// Size of the file in bytes
+	pub size: u64,
+	/// Blake2b hash of the file content
+	pub hash: Hash,
+}
+
+impl Writeable for ManifestEntry {
+	fn write<W: Writer>(&self, writer: &mut W) -> Result<(), ser::Error> {
+		writer.write_bytes(&self.path.as_bytes())?;
+		writer.write_u64(self.size)?;
+		self.hash.write(writer)
+	}
+}
+
+impl Readable for ManifestEntry {
+	fn read(reader: &mut dyn Reader) -> Result<ManifestEntry, ser::Error> {
+		let path = String::from_utf8(reader.read_bytes_len_prefix()?)
+			.map_err(|_| ser::Error::CorruptedData)?;
+		let size = reader.read_u64()?;
+		let hash = Hash::read(reader)?;
+		Ok(ManifestEntry { path, size, hash })
+	}
+}
+
+/// Manifest of the files in a txhashset archive, sorted by path. It
+/// carries a hash of its entries, to tell a corrupted manifest apart from
+/// a corrupted file.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Manifest {
+	/// Files in the archive
+	pub entries: Vec<ManifestEntry>,
+}
+
+impl Writeable for Manifest {
+	fn write<W: Writer>(&self, writer: &mut W) -> Result<(), ser::Error> {
+		writer.write_u64(self.entries.len() as u64)?;
+		for entry in &self.entries {
+			entry.write(writer)?;
+		}
+		self.checksum()?.write(writer)
+	}
+}
+
+impl Readable for Manifest {
+	fn read(reader: &mut dyn Reader) -> Result<Manifest, ser::Error> {
+		let len = reader.read_u64()?;
+		let mut entries = Vec::with_capacity(len.min(1024) as usize);
+		for _ in 0..len {
+			entries.push(ManifestEntry::read(reader)?);
+		}
+		let manifest = Manifest { entries };
+		if Hash::read(reader)? != manifest.checksum()? {
+			return Err(ser::Error::CorruptedData);
+		}
+		Ok(manifest)
+	}
+}
+
+impl Manifest {
+	/// Builds the manifest of the files in the provided txhashset dir.
+	pub fn build(txhashset_path: &Path) -> Result<Manifest, Error> {
+		let entries = manifest_files(txhashset_path)?
+			.into_iter()
+			.map(|(path, size)| {
+				let hash = file_hash(&txhashset_path.join(&path))?;
+				Ok(ManifestEntry { path, size, hash })
+			})
+			.collect::<Result<Vec<_>, Error>>()?;
+		Ok(Manifest { entries })
+	}
+
+	/// Writes the manifest in the provided txhashset dir.
+	pub fn save(&self, txhashset_path: &Path) -> Result<(), Error> {
+		let data = ser::ser_vec(self, ser::ProtocolVersion::local()).map_err(ErrorKind::SerErr)?;
+		fs::write(txhashset_path.join(MANIFEST_FILE), data)?;
+		Ok(())
+	}
+
+	/// Reads the manifest in the provided txhashset dir, if any.
+	pub fn load(txhashset_path: &Path) -> Result<Option<Manifest>, Error> {
+		let path = txhashset_path.join(MANIFEST_FILE);
+		if !path.exists() {
+			return Ok(None);
+		}
+		let data = fs::read(path)?;
+		let manifest = ser::deserialize_default(&mut &data[..]).map_err(|e| {
+			ErrorKind::InvalidTxHashSetFile(MANIFEST_FILE.to_owned(), format!("{:?}", e))
+		})?;
+		Ok(Some(manifest))
+	}
+
+	/// Checks the files in the provided txhashset dir are exactly the ones
+	/// listed, with the expected sizes and hashes. The error names the first
+	/// file that isn't.
+	pub fn verify(&self, txhashset_path: &Path) -> Result<(), Error> {
+		let mut found = manifest_files(txhashset_path)?;
+		for entry in &self.entries {
+			let bad_file = |reason: &str| -> Error {
+				ErrorKind::InvalidTxHashSetFile(entry.path.clone(), reason.to_owned()).into()
+			};
+			match found.remove(&entry.path) {
+				None => return Err(bad_file("missing")),
+				Some(size) if size != entry.size => {
+					return Err(bad_file(&format!(
+						"size {} instead of {}",
+						size, entry.size
+					)));
+				}
+				Some(_) => {}
+			}
+			if file_hash(&txhashset_path.join(&entry.path))? != entry.hash {
+				return Err(bad_file("hash mismatch"));
+			}
+		}
+		if let Some(path) = found.keys().next() {
+			return Err(ErrorKind::InvalidTxHashSetFile(
+				path.clone(),
+				"not in manifest".to_owned(),
+			)
+			.into());
+		}
+		Ok(())
+	}
+
+	fn checksum(&self) -> Result<Hash, ser::Error> {
+		let mut hasher = HashWriter::default();
+		for entry in &self.entries {
+			entry.write(&mut hasher)?;
+		}
+		Ok(hasher.into_hash())
+	}
+}
+
+// Files in the MMR subdirs of the provided txhashset dir, with their size.
+fn manifest_files(txhashset_path: &Path) -> Result<BTreeMap<String, u64>, Error> {
+	let mut files = BTreeMap::new();
+	for subdir in &[OUTPUT_SUBDIR, RANGE_PROOF_SUBDIR, KERNEL_SUBDIR] {
+		let dir = txhashset_path.join(subdir);
+		if !dir.exists() {
+			continue;
+		}
+		for entry in fs::read_dir(dir)? {
+			let entry = entry?;
+			let metadata = entry.metadata()?;
+			if metadata.is_file() {
+				let name = entry.file_name().to_string_lossy().into_owned();
+				files.insert(format!("{}/{}", subdir, name), metadata.len());
+			}
+		}
+	}
+	Ok(files)
+}
+
+fn file_hash(path: &Path) -> Result<Hash, Error> {
+	let mut file = File::open(path)?;
+	let mut hasher = HashWriter::default();
+	let mut buf = vec![0; 64 * 1024];
+	loop {
+		let n = file.read(&mut buf)?;
+		if n == 0 {
+			break;
+		}
+		hasher
+			.write_fixed_bytes(&&buf[..n])
+			.map_err(ErrorKind::SerErr)?;
+	}
+	Ok(hasher.into_hash())
+}
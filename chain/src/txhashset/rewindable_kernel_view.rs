@@ -14,11 +14,14 @@
 
 //! Lightweight readonly view into kernel MMR for convenience.
 
-use crate::core::core::pmmr::RewindablePMMR;
-use crate::core::core::{BlockHeader, TxKernel};
+use crate::core::core::pmmr::{self, RewindablePMMR};
+use crate::core::core::verifier_cache::VerifierCache;
+use crate::core::core::{BlockHeader, TransactionBody, TxKernel};
 use crate::error::{Error, ErrorKind};
 use crate::store::Batch;
+use crate::util::RwLock;
 use grin_store::pmmr::PMMRBackend;
+use std::sync::Arc;
 
 /// Rewindable (but readonly) view of the kernel set (based on kernel MMR).
 pub struct RewindableKernelView<'a> {
@@ -78,4 +81,33 @@ impl<'a> RewindableKernelView<'a> {
 		}
 		Ok(())
 	}
+
+	/// Batch verify the signatures of the kernels added by the block at the
+	/// current header of this view. Kernels already in the verifier cache are
+	/// skipped and the newly verified ones get added to it.
+	pub fn verify_kernel_sigs(
+		&self,
+		verifier_cache: Arc<RwLock<dyn VerifierCache>>,
+	) -> Result<(), Error> {
+		let start_pos = if self.header.height == 0 {
+			0
+		} else {
+			let prev_header = self.batch.get_previous_header(&self.header)?;
+			prev_header.kernel_mmr_size
+		};
+
+		let mut kernels = vec![];
+		for pos in (start_pos + 1)..=self.header.kernel_mmr_size {
+			if pmmr::is_leaf(pos) {
+				let kernel = self
+					.pmmr
+					.get_data(pos)
+					.ok_or::<Error>(ErrorKind::TxKernelNotFound.into())?;
+				kernels.push(kernel.kernel);
+			}
+		}
+
+		TransactionBody::verify_kernel_sigs_cached(&kernels, verifier_cache)?;
+		Ok(())
+	}
 }
@@ -18,15 +18,16 @@
 use crate::core::core::committed::Committed;
 use crate::core::core::hash::{Hash, Hashed};
 use crate::core::core::merkle_proof::MerkleProof;
-use crate::core::core::pmmr::{self, Backend, ReadonlyPMMR, RewindablePMMR, PMMR};
+use crate::core::core::pmmr::{
+	self, Backend, ReadonlyPMMR, RewindablePMMR, Segment, SegmentIdentifier, PMMR,
+};
 use crate::core::core::{
-	Block, BlockHeader, Input, Output, OutputIdentifier, TxKernel, TxKernelEntry,
+	Block, BlockHeader, Input, Output, OutputIdentifier, TransactionBody, TxKernel, TxKernelEntry,
 };
-use crate::core::global;
-use crate::core::ser::{PMMRIndexHashable, PMMRable};
+use crate::core::ser::{self, PMMRIndexHashable, PMMRable};
 use crate::error::{Error, ErrorKind};
 use crate::store::{Batch, ChainStore};
-use crate::txhashset::{RewindableKernelView, UTXOView};
+use crate::txhashset::{Manifest, RewindableKernelView, UTXOView, MANIFEST_FILE};
 use crate::types::{Tip, TxHashSetRoots, TxHashsetWriteStatus};
 use crate::util::secp::pedersen::{Commitment, RangeProof};
 use crate::util::{file, secp_static, zip};
@@ -35,22 +36,35 @@ use grin_store;
 use grin_store::pmmr::{clean_files_by_prefix, PMMRBackend, PMMR_FILES};
 use std::collections::HashSet;
 use std::fs::{self, File};
+use std::io;
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use std::time::Instant;
 
 const HEADERHASHSET_SUBDIR: &'static str = "header";
-const TXHASHSET_SUBDIR: &'static str = "txhashset";
+pub(crate) const TXHASHSET_SUBDIR: &'static str = "txhashset";
 
 const HEADER_HEAD_SUBDIR: &'static str = "header_head";
 const SYNC_HEAD_SUBDIR: &'static str = "sync_head";
 
-const OUTPUT_SUBDIR: &'static str = "output";
-const RANGE_PROOF_SUBDIR: &'static str = "rangeproof";
-const KERNEL_SUBDIR: &'static str = "kernel";
+pub(crate) const OUTPUT_SUBDIR: &'static str = "output";
+pub(crate) const RANGE_PROOF_SUBDIR: &'static str = "rangeproof";
+pub(crate) const KERNEL_SUBDIR: &'static str = "kernel";
 
 const TXHASHSET_ZIP: &'static str = "txhashset_snapshot";
 
+/// Version of the layout of the txhashset dir (pmmr hash, data, leaf and
+/// prune files). Bump this (and add a step to `migrate_layout`) whenever it
+/// changes.
+pub const TXHASHSET_VERSION: u32 = 1;
+
+/// File in the txhashset dir holding its layout version.
+pub const TXHASHSET_VERSION_FILE: &'static str = "version.bin";
+
+/// Number of kernels read from the kernel MMR before batch verifying their
+/// signatures, when validating the whole txhashset.
+const KERNEL_VERIFY_CHUNK_SIZE: usize = 1_000;
+
 struct PMMRHandle<T: PMMRable> {
 	backend: PMMRBackend<T>,
 	last_pos: u64,
@@ -116,6 +130,7 @@ impl TxHashSet {
 		commit_index: Arc<ChainStore>,
 		header: Option<&BlockHeader>,
 	) -> Result<TxHashSet, Error> {
+		migrate_layout(Path::new(&root_dir))?;
 		Ok(TxHashSet {
 			header_pmmr_h: PMMRHandle::new(
 				&root_dir,
@@ -208,6 +223,11 @@ impl TxHashSet {
 			.get_last_n_insertions(distance)
 	}
 
+	/// Readonly view of the whole kernel MMR.
+	pub fn kernel_pmmr(&self) -> ReadonlyPMMR<'_, TxKernel, PMMRBackend<TxKernel>> {
+		ReadonlyPMMR::at(&self.kernel_pmmr_h.backend, self.kernel_pmmr_h.last_pos)
+	}
+
 	/// as above, for kernels
 	pub fn last_n_kernel(&self, distance: u64) -> Vec<(Hash, TxKernelEntry)> {
 		ReadonlyPMMR::at(&self.kernel_pmmr_h.backend, self.kernel_pmmr_h.last_pos)
@@ -293,15 +313,141 @@ impl TxHashSet {
 			.map_err(|_| ErrorKind::MerkleProof.into())
 	}
 
-	/// Compact the MMR data files and flush the rm logs
-	pub fn compact(&mut self, batch: &mut Batch<'_>) -> Result<(), Error> {
+	/// The kernel with the provided excess, along with its position in the
+	/// kernel MMR. We look in the index to find the kernel MMR pos, then
+	/// check the kernel there has the expected excess (the index is not
+	/// cleaned up on rewind).
+	pub fn find_kernel(&self, excess: &Commitment) -> Result<(TxKernel, u64), Error> {
+		let pos = match self.commit_index.get_kernel_pos(excess) {
+			Ok(pos) => pos,
+			Err(grin_store::Error::NotFoundErr(_)) => {
+				return Err(ErrorKind::TxKernelNotFound.into())
+			}
+			Err(e) => return Err(ErrorKind::StoreErr(e, format!("txhashset find kernel")).into()),
+		};
+		let kernel_pmmr =
+			ReadonlyPMMR::at(&self.kernel_pmmr_h.backend, self.kernel_pmmr_h.last_pos);
+		match kernel_pmmr.get_data(pos) {
+			Some(entry) if entry.kernel.excess == *excess => Ok((entry.kernel, pos)),
+			_ => Err(ErrorKind::TxKernelNotFound.into()),
+		}
+	}
+
+	/// Merkle proof of the kernel at the provided position against the kernel
+	/// root of the provided header, built on the kernel MMR as of this header.
+	pub fn kernel_merkle_proof(
+		&mut self,
+		header: &BlockHeader,
+		pos: u64,
+	) -> Result<MerkleProof, Error> {
+		if pos > header.kernel_mmr_size || header.kernel_mmr_size > self.kernel_pmmr_h.last_pos {
+			return Err(ErrorKind::MerkleProof.into());
+		}
+		PMMR::at(&mut self.kernel_pmmr_h.backend, header.kernel_mmr_size)
+			.merkle_proof(pos)
+			.map_err(|_| ErrorKind::MerkleProof.into())
+	}
+
+	/// Height of the last header in the header MMR.
+	pub fn header_mmr_height(&self) -> Option<u64> {
+		pmmr::n_leaves(self.header_pmmr_h.last_pos).checked_sub(1)
+	}
+
+	/// Merkle proof of the header at the provided height against the root of
+	/// the header MMR at the provided size, as committed to by the prev_root
+	/// of a later header.
+	pub fn header_merkle_proof(
+		&mut self,
+		height: u64,
+		mmr_size: u64,
+	) -> Result<MerkleProof, Error> {
+		let pos = pmmr::insertion_to_pmmr_index(height + 1);
+		if pos > mmr_size || mmr_size > self.header_pmmr_h.last_pos {
+			return Err(ErrorKind::MerkleProof.into());
+		}
+		PMMR::at(&mut self.header_pmmr_h.backend, mmr_size)
+			.merkle_proof(pos)
+			.map_err(|_| ErrorKind::MerkleProof.into())
+	}
+
+	/// The unspent output with the provided commitment, along with its
+	/// position and a merkle proof valid for the current output pmmr state.
+	pub fn output_proof(
+		&mut self,
+		commit: Commitment,
+	) -> Result<(OutputIdentifier, u64, MerkleProof), Error> {
+		let pos = self.commit_index.get_output_pos(&commit)?;
+		let pmmr = PMMR::at(&mut self.output_pmmr_h.backend, self.output_pmmr_h.last_pos);
+		let output = match pmmr.get_data(pos) {
+			Some(output) if output.commit == commit => output,
+			_ => return Err(ErrorKind::OutputNotFound.into()),
+		};
+		let proof = pmmr.merkle_proof(pos).map_err(|_| ErrorKind::MerkleProof)?;
+		Ok((output, pos, proof))
+	}
+
+	/// Segment of the output MMR as of the provided header, for PIBD.
+	pub fn output_segment(
+		&self,
+		header: &BlockHeader,
+		id: SegmentIdentifier,
+	) -> Result<Segment<OutputIdentifier>, Error> {
+		let pmmr = ReadonlyPMMR::at(&self.output_pmmr_h.backend, header.output_mmr_size);
+		Ok(Segment::from_pmmr(id, &pmmr)?)
+	}
+
+	/// Segment of the rangeproof MMR as of the provided header, for PIBD.
+	pub fn rangeproof_segment(
+		&self,
+		header: &BlockHeader,
+		id: SegmentIdentifier,
+	) -> Result<Segment<RangeProof>, Error> {
+		let pmmr = ReadonlyPMMR::at(&self.rproof_pmmr_h.backend, header.output_mmr_size);
+		Ok(Segment::from_pmmr(id, &pmmr)?)
+	}
+
+	/// Segment of the kernel MMR as of the provided header, for PIBD.
+	pub fn kernel_segment(
+		&self,
+		header: &BlockHeader,
+		id: SegmentIdentifier,
+	) -> Result<Segment<TxKernelEntry>, Error> {
+		let pmmr = ReadonlyPMMR::at(&self.kernel_pmmr_h.backend, header.kernel_mmr_size);
+		Ok(Segment::from_pmmr(id, &pmmr)?)
+	}
+
+	/// The output leaf set as it was at the provided header: our current
+	/// leaf set (up to the header) plus all the outputs spent since.
+	pub fn output_leaf_set_at(
+		&self,
+		header: &BlockHeader,
+		batch: &Batch<'_>,
+	) -> Result<Bitmap, Error> {
+		let size = header.output_mmr_size;
+		let head_header = batch.head_header()?;
+		let spent = input_pos_to_rewind(header, &head_header, batch)?;
+
+		let mut leaf_set: Bitmap = self
+			.output_pmmr_h
+			.backend
+			.leaf_pos_iter()
+			.filter(|x| *x <= size)
+			.map(|x| x as u32)
+			.collect();
+		leaf_set.or_inplace(&spent.iter().filter(|x| *x as u64 <= size).collect());
+		Ok(leaf_set)
+	}
+
+	/// Compact the MMR data files and flush the rm logs, up to the horizon
+	/// (in blocks from the head).
+	pub fn compact(&mut self, horizon: u32, batch: &mut Batch<'_>) -> Result<(), Error> {
 		debug!("txhashset: starting compaction...");
 
 		let head_header = batch.head_header()?;
 		let current_height = head_header.height;
 
 		// horizon for compacting is based on current_height
-		let horizon_height = current_height.saturating_sub(global::cut_through_horizon().into());
+		let horizon_height = current_height.saturating_sub(horizon.into());
 		let horizon_hash = self.get_header_hash_by_height(horizon_height)?;
 		let horizon_header = batch.get_block_header(&horizon_hash)?;
 
@@ -958,9 +1104,12 @@ impl<'a> Extension<'a> {
 
 	/// Push kernel onto MMR (hash and data files).
 	fn apply_kernel(&mut self, kernel: &TxKernel) -> Result<(), Error> {
-		self.kernel_pmmr
+		let pos = self
+			.kernel_pmmr
 			.push(kernel)
 			.map_err(&ErrorKind::TxHashSetErr)?;
+		// Update the kernel_pos index for the new kernel.
+		self.batch.save_kernel_pos(&kernel.excess, pos)?;
 		Ok(())
 	}
 
@@ -1035,6 +1184,29 @@ impl<'a> Extension<'a> {
 	/// Rewinds the MMRs to the provided block, rewinding to the last output pos
 	/// and last kernel pos of that block.
 	pub fn rewind(&mut self, header: &BlockHeader) -> Result<(), Error> {
+		self.rewind_spent(header)?;
+		Ok(())
+	}
+
+	/// Rewinds the MMRs to the provided block (see `rewind`) and restores the
+	/// output_pos index entries for all outputs spent since that block.
+	/// The index is rebuilt from the UTXO set on compaction so these entries may
+	/// be missing, and we need them to look up outputs in the rewound UTXO set.
+	/// Only makes sense in a readonly extension as the batch is discarded.
+	pub fn rewind_with_output_pos(&mut self, header: &BlockHeader) -> Result<(), Error> {
+		let spent_pos = self.rewind_spent(header)?;
+		for pos in spent_pos.iter() {
+			let pos = pos as u64;
+			if let Some(out) = self.output_pmmr.get_data(pos) {
+				self.batch.save_output_pos(&out.commitment(), pos)?;
+			}
+		}
+		Ok(())
+	}
+
+	/// Rewinds the MMRs to the provided block, returning the positions of the
+	/// outputs that were spent since (and are now unspent again).
+	fn rewind_spent(&mut self, header: &BlockHeader) -> Result<Bitmap, Error> {
 		debug!("Rewind to header {} at {}", header.hash(), header.height,);
 
 		// We need to build bitmaps of added and removed output positions
@@ -1057,7 +1229,7 @@ impl<'a> Extension<'a> {
 		// Update our header to reflect the one we rewound to.
 		self.header = header.clone();
 
-		Ok(())
+		Ok(rewind_rm_pos)
 	}
 
 	/// Rewinds the MMRs to the provided positions, given the output and
@@ -1276,6 +1448,31 @@ impl<'a> Extension<'a> {
 			now.elapsed().as_secs(),
 		);
 
+		self.rebuild_kernel_index()
+	}
+
+	/// Rebuild the index of kernel MMR positions to the corresponding kernels.
+	/// Kernels are never pruned so this goes through all of them.
+	pub fn rebuild_kernel_index(&self) -> Result<(), Error> {
+		let now = Instant::now();
+
+		self.batch.clear_kernel_pos()?;
+
+		let mut count = 0;
+		let last_pos = self.kernel_pmmr.unpruned_size();
+		for pos in (1..=last_pos).filter(|x| pmmr::is_leaf(*x)) {
+			if let Some(entry) = self.kernel_pmmr.get_data(pos) {
+				self.batch.save_kernel_pos(&entry.kernel.excess, pos)?;
+				count += 1;
+			}
+		}
+
+		debug!(
+			"txhashset: rebuild_kernel_index: {} kernels, took {}s",
+			count,
+			now.elapsed().as_secs(),
+		);
+
 		Ok(())
 	}
 
@@ -1320,27 +1517,30 @@ impl<'a> Extension<'a> {
 	fn verify_kernel_signatures(&self, status: &dyn TxHashsetWriteStatus) -> Result<(), Error> {
 		let now = Instant::now();
 
+		// Kernels are batch verified, a chunk at a time. Most of them are long
+		// gone from the verifier cache (if they ever were in it), so we don't
+		// go through it.
 		let mut kern_count = 0;
 		let total_kernels = pmmr::n_leaves(self.kernel_pmmr.unpruned_size());
+		let mut kernels = Vec::with_capacity(KERNEL_VERIFY_CHUNK_SIZE);
 		for n in 1..self.kernel_pmmr.unpruned_size() + 1 {
 			if pmmr::is_leaf(n) {
 				let kernel = self
 					.kernel_pmmr
 					.get_data(n)
 					.ok_or::<Error>(ErrorKind::TxKernelNotFound.into())?;
+				kernels.push(kernel.kernel);
+			}
+			if kernels.len() == KERNEL_VERIFY_CHUNK_SIZE || n == self.kernel_pmmr.unpruned_size() {
+				TransactionBody::verify_kernel_sigs(&kernels)?;
+				kern_count += kernels.len() as u64;
+				kernels.clear();
 
-				kernel.verify()?;
-				kern_count += 1;
-
-				if kern_count % 20 == 0 {
-					status.on_validation(kern_count, total_kernels, 0, 0);
-				}
-				if kern_count % 1_000 == 0 {
-					debug!(
-						"txhashset: verify_kernel_signatures: verified {} signatures",
-						kern_count,
-					);
-				}
+				status.on_validation(kern_count, total_kernels, 0, 0);
+				debug!(
+					"txhashset: verify_kernel_signatures: verified {} signatures",
+					kern_count,
+				);
 			}
 		}
 
@@ -1354,7 +1554,8 @@ impl<'a> Extension<'a> {
 		Ok(())
 	}
 
-	fn verify_rangeproofs(&self, status: &dyn TxHashsetWriteStatus) -> Result<(), Error> {
+	/// Verify the rangeproof of each unspent output, in batches.
+	pub fn verify_rangeproofs(&self, status: &dyn TxHashsetWriteStatus) -> Result<(), Error> {
 		let now = Instant::now();
 
 		let mut commits: Vec<Commitment> = vec![];
@@ -1415,13 +1616,18 @@ impl<'a> Extension<'a> {
 	}
 }
 
+/// Path of the zip `zip_read` packages the txhashset data files at the
+/// provided block into.
+pub fn zip_path(root_dir: &str, header: &BlockHeader) -> PathBuf {
+	let txhashset_zip = format!("{}_{}.zip", TXHASHSET_ZIP, header.hash().to_string());
+	Path::new(root_dir).join(txhashset_zip)
+}
+
 /// Packages the txhashset data files into a zip and returns a Read to the
 /// resulting file
 pub fn zip_read(root_dir: String, header: &BlockHeader) -> Result<File, Error> {
-	let txhashset_zip = format!("{}_{}.zip", TXHASHSET_ZIP, header.hash().to_string());
-
 	let txhashset_path = Path::new(&root_dir).join(TXHASHSET_SUBDIR);
-	let zip_path = Path::new(&root_dir).join(txhashset_zip);
+	let zip_path = zip_path(&root_dir, header);
 
 	// if file exist, just re-use it
 	let zip_file = File::open(zip_path.clone());
@@ -1457,6 +1663,9 @@ pub fn zip_read(root_dir: String, header: &BlockHeader) -> Result<File, Error> {
 		file::copy_dir_to(&txhashset_path, &temp_txhashset_path)?;
 		// Check and remove file that are not supposed to be there
 		check_and_remove_files(&temp_txhashset_path, header)?;
+		// List every file with its size and hash, for the receiver to check
+		// the archive is intact before validating it
+		Manifest::build(&temp_txhashset_path)?.save(&temp_txhashset_path)?;
 		// Compress zip
 		zip::compress(&temp_txhashset_path, &File::create(zip_path.clone())?)
 			.map_err(|ze| ErrorKind::Other(ze.to_string()))?;
@@ -1490,7 +1699,67 @@ pub fn zip_write(
 	fs::create_dir_all(txhashset_path.clone())?;
 	zip::decompress(txhashset_data, &txhashset_path, expected_file)
 		.map_err(|ze| ErrorKind::Other(ze.to_string()))?;
-	check_and_remove_files(&txhashset_path, header)
+
+	// Catch a corrupted archive before going through the full validation
+	// (archives from older nodes don't have a manifest).
+	match Manifest::load(&txhashset_path)? {
+		Some(manifest) => {
+			manifest.verify(&txhashset_path)?;
+			fs::remove_file(txhashset_path.join(MANIFEST_FILE))?;
+		}
+		None => debug!("zip_write: no manifest in txhashset archive"),
+	}
+	check_and_remove_files(&txhashset_path, header)?;
+
+	// Archives don't carry the version file, they're always in the layout of
+	// the protocol version we speak, our current one.
+	write_layout_version(&txhashset_path)
+}
+
+/// Layout version of the txhashset dir under the provided root, 0 for a dir
+/// without a version file (written before the layout was versioned).
+pub fn layout_version(root_dir: &Path) -> Result<u32, Error> {
+	let path = root_dir.join(TXHASHSET_SUBDIR).join(TXHASHSET_VERSION_FILE);
+	match fs::read(&path) {
+		Ok(data) => Ok(ser::deserialize_default(&mut &data[..]).map_err(ErrorKind::SerErr)?),
+		Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(0),
+		Err(e) => Err(e.into()),
+	}
+}
+
+/// Brings the txhashset dir under the provided root up to our current layout
+/// version, refusing to touch one written with a newer layout. The version
+/// is only saved once all the steps are done, so steps must be safe to run
+/// again after a crash.
+fn migrate_layout(root_dir: &Path) -> Result<(), Error> {
+	let version = layout_version(root_dir)?;
+	if version > TXHASHSET_VERSION {
+		return Err(ErrorKind::TxHashSetVersion(version, TXHASHSET_VERSION).into());
+	}
+	if version == TXHASHSET_VERSION {
+		return Ok(());
+	}
+
+	// Steps from each version to the next go here, in order. Version 1 only
+	// introduced the version file, the pmmr files are left as they are.
+	debug!(
+		"migrate_layout: txhashset from version {} to {}",
+		version, TXHASHSET_VERSION
+	);
+	let txhashset_path = root_dir.join(TXHASHSET_SUBDIR);
+	fs::create_dir_all(&txhashset_path)?;
+	write_layout_version(&txhashset_path)
+}
+
+// Writes the version file in a tmp file first, so we never end up with a
+// partially written one.
+fn write_layout_version(txhashset_path: &Path) -> Result<(), Error> {
+	let data = ser::ser_vec(&TXHASHSET_VERSION, ser::ProtocolVersion::local())
+		.map_err(ErrorKind::SerErr)?;
+	let tmp_path = txhashset_path.join(format!("{}.tmp", TXHASHSET_VERSION_FILE));
+	fs::write(&tmp_path, data)?;
+	fs::rename(tmp_path, txhashset_path.join(TXHASHSET_VERSION_FILE))?;
+	Ok(())
 }
 
 /// Overwrite txhashset folders in "to" folder with "from" folder
@@ -1542,8 +1811,11 @@ fn expected_file(path: &Path) -> bool {
 	lazy_static! {
 		static ref RE: Regex = Regex::new(
 			format!(
-				r#"^({}|{}|{})((/|\\)pmmr_(hash|data|leaf|prun)\.bin(\.\w*)?)?$"#,
-				OUTPUT_SUBDIR, KERNEL_SUBDIR, RANGE_PROOF_SUBDIR
+				r#"^(({}|{}|{})((/|\\)pmmr_(hash|data|leaf|prun)\.bin(\.\w*)?)?|{})$"#,
+				OUTPUT_SUBDIR,
+				KERNEL_SUBDIR,
+				RANGE_PROOF_SUBDIR,
+				regex::escape(MANIFEST_FILE)
 			)
 			.as_str()
 		)
@@ -16,11 +16,11 @@
 
 use crate::core::core::hash::{Hash, Hashed};
 use crate::core::core::pmmr::{self, ReadonlyPMMR};
-use crate::core::core::{Block, BlockHeader, Input, Output, Transaction};
-use crate::core::global;
+use crate::core::core::{Block, BlockHeader, Input, Output, OutputIdentifier, Transaction};
 use crate::core::ser::PMMRIndexHashable;
 use crate::error::{Error, ErrorKind};
 use crate::store::Batch;
+use crate::util::secp::pedersen::Commitment;
 use grin_store::pmmr::PMMRBackend;
 
 /// Readonly view of the UTXO set (based on output MMR).
@@ -98,9 +98,49 @@ impl<'a> UTXOView<'a> {
 		Ok(())
 	}
 
+	/// Lookup the output with the given commitment in the UTXO set.
+	/// Returns the output along with its pos in the output MMR if unspent.
+	pub fn get_unspent(&self, commit: &Commitment) -> Option<(OutputIdentifier, u64)> {
+		if let Ok(pos) = self.batch.get_output_pos(commit) {
+			if let Some(out) = self.output_pmmr.get_data(pos) {
+				if out.commitment() == *commit {
+					return Some((out, pos));
+				}
+			}
+		}
+		None
+	}
+
+	/// Find the header of the block that added the output at the given pos
+	/// in the output MMR, searching the header MMR of this view.
+	pub fn get_header_for_output_pos(&self, pos: u64) -> Result<BlockHeader, Error> {
+		// Find the first header where the output MMR includes the pos.
+		let mut min = 0;
+		let mut max = pmmr::n_leaves(self.header_pmmr.unpruned_size()).saturating_sub(1);
+		while min < max {
+			let mid = min + (max - min) / 2;
+			if self.get_header_by_height(mid)?.output_mmr_size >= pos {
+				max = mid;
+			} else {
+				min = mid + 1;
+			}
+		}
+
+		let header = self.get_header_by_height(min)?;
+		if header.output_mmr_size < pos {
+			return Err(ErrorKind::OutputNotFound.into());
+		}
+		Ok(header)
+	}
+
 	/// Verify we are not attempting to spend any coinbase outputs
-	/// that have not sufficiently matured.
-	pub fn verify_coinbase_maturity(&self, inputs: &Vec<Input>, height: u64) -> Result<(), Error> {
+	/// that have not sufficiently matured (coinbase_maturity blocks).
+	pub fn verify_coinbase_maturity(
+		&self,
+		inputs: &Vec<Input>,
+		height: u64,
+		coinbase_maturity: u64,
+	) -> Result<(), Error> {
 		// Find the greatest output pos of any coinbase
 		// outputs we are attempting to spend.
 		let pos = inputs
@@ -113,13 +153,13 @@ impl<'a> UTXOView<'a> {
 		if pos > 0 {
 			// If we have not yet reached 1,000 / 1,440 blocks then
 			// we can fail immediately as coinbase cannot be mature.
-			if height < global::coinbase_maturity() {
+			if height < coinbase_maturity {
 				return Err(ErrorKind::ImmatureCoinbase.into());
 			}
 
 			// Find the "cutoff" pos in the output MMR based on the
 			// header from 1,000 blocks ago.
-			let cutoff_height = height.checked_sub(global::coinbase_maturity()).unwrap_or(0);
+			let cutoff_height = height.checked_sub(coinbase_maturity).unwrap_or(0);
 			let cutoff_header = self.get_header_by_height(cutoff_height)?;
 			let cutoff_pos = cutoff_header.output_mmr_size;
 
@@ -14,10 +14,13 @@
 
 //! Base types that the block chain pipeline requires.
 
+use crate::chain::{MAX_ORPHAN_AGE_SECS, MAX_ORPHAN_SIZE};
 use crate::core::core::hash::{Hash, Hashed, ZERO_HASH};
-use crate::core::core::{Block, BlockHeader};
+use crate::core::core::merkle_proof::MerkleProof;
+use crate::core::core::{Block, BlockHeader, OutputIdentifier, TransactionBody, TxKernel};
 use crate::core::pow::Difficulty;
-use crate::core::ser;
+use crate::core::ser::{self, Readable, Writeable};
+use chrono::prelude::{DateTime, Utc};
 
 bitflags! {
 /// Options for block validation
@@ -118,6 +121,216 @@ impl ser::Readable for Tip {
 	}
 }
 
+/// Summary statistics for a block on our main chain. Kept independently of
+/// the full block so they survive compaction.
+///
+/// There is no cut-through count: transactions are cut-through when the block
+/// is built and a valid block can't spend an output it creates (it would be
+/// rejected with `transaction::Error::CutThrough`). The outputs cut through
+/// are gone by the time the block is accepted, there is nothing to count.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct BlockStats {
+	/// Height of the block
+	pub height: u64,
+	/// Total fees paid by the transactions in the block
+	pub fees: u64,
+	/// Number of transactions, counted as non-coinbase kernels
+	pub tx_count: u64,
+	/// Block weight, as used to enforce the max block weight
+	pub weight: u64,
+	/// Number of inputs of the block, the outputs it spends
+	pub num_inputs: u64,
+	/// Number of outputs created by the block
+	pub num_outputs: u64,
+	/// Number of kernels in the block
+	pub num_kernels: u64,
+	/// Seconds elapsed since the previous block
+	pub block_time: u64,
+}
+
+impl BlockStats {
+	/// Builds the stats for a block given its previous header (none for
+	/// genesis).
+	pub fn from_block(block: &Block, prev: Option<&BlockHeader>) -> BlockStats {
+		let block_time = match prev {
+			Some(prev) => (block.header.timestamp - prev.timestamp).num_seconds() as u64,
+			None => 0,
+		};
+		let weight = TransactionBody::weight_as_block(
+			block.inputs().len(),
+			block.outputs().len(),
+			block.kernels().len(),
+		) as u64;
+		BlockStats {
+			height: block.header.height,
+			fees: block.total_fees(),
+			tx_count: block.kernels().iter().filter(|k| !k.is_coinbase()).count() as u64,
+			weight,
+			num_inputs: block.inputs().len() as u64,
+			num_outputs: block.outputs().len() as u64,
+			num_kernels: block.kernels().len() as u64,
+			block_time,
+		}
+	}
+}
+
+impl ser::Writeable for BlockStats {
+	fn write<W: ser::Writer>(&self, writer: &mut W) -> Result<(), ser::Error> {
+		writer.write_u64(self.height)?;
+		writer.write_u64(self.fees)?;
+		writer.write_u64(self.tx_count)?;
+		writer.write_u64(self.weight)?;
+		writer.write_u64(self.num_inputs)?;
+		writer.write_u64(self.num_outputs)?;
+		writer.write_u64(self.num_kernels)?;
+		writer.write_u64(self.block_time)?;
+		Ok(())
+	}
+}
+
+impl ser::Readable for BlockStats {
+	fn read(reader: &mut dyn ser::Reader) -> Result<BlockStats, ser::Error> {
+		let height = reader.read_u64()?;
+		let fees = reader.read_u64()?;
+		let tx_count = reader.read_u64()?;
+		let weight = reader.read_u64()?;
+		let num_inputs = reader.read_u64()?;
+		let num_outputs = reader.read_u64()?;
+		let num_kernels = reader.read_u64()?;
+		let block_time = reader.read_u64()?;
+		Ok(BlockStats {
+			height,
+			fees,
+			tx_count,
+			weight,
+			num_inputs,
+			num_outputs,
+			num_kernels,
+			block_time,
+		})
+	}
+}
+
+/// The parts of the txhashset that can be downloaded in segments (PIBD): the
+/// output, rangeproof and kernel MMRs, and the output leaf set bitmap.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum SegmentType {
+	/// Output leaf set bitmap
+	Bitmap,
+	/// Output MMR
+	Output,
+	/// Rangeproof MMR
+	RangeProof,
+	/// Kernel MMR
+	Kernel,
+}
+
+/// Merkle proof of an output against the output root of a block header, as
+/// served by full nodes to the header-only nodes following the header chain.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OutputProof {
+	/// Hash of the block header whose output root the proof is against
+	pub block_hash: Hash,
+	/// The output being proven
+	pub output: OutputIdentifier,
+	/// Position of the output in the output MMR
+	pub pos: u64,
+	/// Merkle proof of the output, of the size of the output MMR at the block
+	pub proof: MerkleProof,
+}
+
+impl ser::Writeable for OutputProof {
+	fn write<W: ser::Writer>(&self, writer: &mut W) -> Result<(), ser::Error> {
+		self.block_hash.write(writer)?;
+		self.output.write(writer)?;
+		writer.write_u64(self.pos)?;
+		self.proof.write(writer)
+	}
+}
+
+impl ser::Readable for OutputProof {
+	fn read(reader: &mut dyn ser::Reader) -> Result<OutputProof, ser::Error> {
+		Ok(OutputProof {
+			block_hash: Hash::read(reader)?,
+			output: OutputIdentifier::read(reader)?,
+			pos: reader.read_u64()?,
+			proof: MerkleProof::read(reader)?,
+		})
+	}
+}
+
+/// Merkle proof of a kernel against the kernel root of the header of the
+/// block it was included in, for light clients verifying payments.
+#[derive(Debug, Clone, PartialEq)]
+pub struct KernelProof {
+	/// Hash of the block header whose kernel root the proof is against
+	pub block_hash: Hash,
+	/// The kernel being proven
+	pub kernel: TxKernel,
+	/// Position of the kernel in the kernel MMR
+	pub pos: u64,
+	/// Merkle proof of the kernel, of the size of the kernel MMR at the block
+	pub proof: MerkleProof,
+}
+
+/// Which orphans to evict first when the orphan pool is full. Orphans older
+/// than the max orphan age always go first.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum OrphanEviction {
+	/// Evict the orphans furthest ahead of our chain, the last we'll be able
+	/// to process.
+	HighestFirst,
+	/// Evict the orphans we received first.
+	OldestFirst,
+}
+
+impl Default for OrphanEviction {
+	fn default() -> OrphanEviction {
+		OrphanEviction::HighestFirst
+	}
+}
+
+/// Orphan block pool configuration
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct OrphanConfig {
+	/// Maximum number of orphan blocks kept in the pool
+	pub max_orphans: usize,
+	/// Orphans older than this (in seconds) are evicted first
+	pub max_orphan_age_secs: u64,
+	/// Which orphans to evict when the pool is full
+	pub orphan_eviction: OrphanEviction,
+	/// Whether to keep the orphans in the chain db, so we don't have to
+	/// fetch them again after a restart
+	pub persist_orphans: bool,
+}
+
+impl Default for OrphanConfig {
+	fn default() -> OrphanConfig {
+		OrphanConfig {
+			max_orphans: MAX_ORPHAN_SIZE,
+			max_orphan_age_secs: MAX_ORPHAN_AGE_SECS,
+			orphan_eviction: OrphanEviction::default(),
+			persist_orphans: false,
+		}
+	}
+}
+
+/// Summary of an orphan block in the orphan pool.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OrphanInfo {
+	/// Hash of the block
+	pub hash: Hash,
+	/// Height of the block
+	pub height: u64,
+	/// Hash of the (missing) previous block
+	pub prev_hash: Hash,
+	/// Address of the peer we received the block from, if any
+	pub peer: Option<String>,
+	/// When we received the block
+	pub received: DateTime<Utc>,
+}
+
 /// Bridge between the chain pipeline and the rest of the system. Handles
 /// downstream processing of valid blocks by the rest of the system, most
 /// importantly the broadcasting of blocks to our peers.
@@ -143,6 +356,13 @@ pub trait TxHashsetWriteStatus {
 	fn on_done(&self);
 }
 
+/// Inform the caller of the progress of a full chain reindex, as replaying
+/// every block from genesis can take a (very) long time.
+pub trait ReindexStatus {
+	/// Block at current_height has been replayed and fully validated.
+	fn on_reindex(&self, current_height: u64, highest_height: u64);
+}
+
 /// Do-nothing implementation of TxHashsetWriteStatus
 pub struct NoStatus;
 
@@ -153,6 +373,10 @@ impl TxHashsetWriteStatus for NoStatus {
 	fn on_done(&self) {}
 }
 
+impl ReindexStatus for NoStatus {
+	fn on_reindex(&self, _ch: u64, _hh: u64) {}
+}
+
 /// Dummy adapter used as a placeholder for real implementations
 pub struct NoopAdapter {}
 
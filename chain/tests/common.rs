@@ -0,0 +1,115 @@
+// Copyright 2018 The Grin Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Common test functions
+
+use self::chain::types::NoopAdapter;
+use self::chain::{Chain, OrphanConfig};
+use self::core::core::verifier_cache::LruVerifierCache;
+use self::core::core::{Block, Transaction};
+use self::core::global::{ChainParams, ChainTypes};
+use self::core::{consensus, libtx, pow};
+use self::keychain::{ExtKeychainPath, Keychain};
+use self::util::{Mutex, RwLock, StopState};
+use chrono::Duration;
+use grin_chain as chain;
+use grin_core as core;
+use grin_keychain as keychain;
+use grin_util as util;
+use std::fs;
+use std::sync::Arc;
+
+pub fn clean_output_dir(dir_name: &str) {
+	let _ = fs::remove_dir_all(dir_name);
+}
+
+pub fn init_chain(
+	dir_name: &str,
+	params: ChainParams,
+	genesis: Block,
+	archive_mode: bool,
+	orphan_config: OrphanConfig,
+) -> Chain {
+	let verifier_cache = Arc::new(RwLock::new(LruVerifierCache::new()));
+	chain::Chain::init(
+		dir_name.to_string(),
+		Arc::new(NoopAdapter {}),
+		params,
+		genesis,
+		pow::verify_size,
+		verifier_cache,
+		archive_mode,
+		orphan_config,
+		Arc::new(Mutex::new(StopState::new())),
+	)
+	.unwrap()
+}
+
+// Pruned automated testing chain, with the default orphan config.
+pub fn setup(dir_name: &str, genesis: Block) -> Chain {
+	init_chain(
+		dir_name,
+		ChainTypes::AutomatedTesting.params(),
+		genesis,
+		false,
+		OrphanConfig::default(),
+	)
+}
+
+// Builds the next block on top of the chain head, with a reward derived
+// from n and the provided transactions, mined with the chain parameters.
+pub fn mine_block<K>(chain: &Chain, keychain: &K, n: u32, txs: Vec<Transaction>) -> Block
+where
+	K: Keychain,
+{
+	let params = chain.params();
+	let prev = chain.head_header().unwrap();
+	let next_header_info =
+		consensus::next_difficulty_with_params(params, 1, chain.difficulty_iter().unwrap());
+	let key_id = ExtKeychainPath::new(1, n, 0, 0, 0).to_identifier();
+	let fees = txs.iter().map(|tx| tx.fee()).sum();
+	let reward = libtx::reward::output(keychain, &key_id, fees, false).unwrap();
+	let mut b =
+		core::core::Block::new(&prev, txs, next_header_info.clone().difficulty, reward).unwrap();
+	b.header.timestamp = prev.timestamp + Duration::seconds(60);
+	b.header.pow.secondary_scaling = next_header_info.secondary_scaling;
+
+	chain.set_txhashset_roots(&mut b).unwrap();
+
+	pow::pow_size_with_params(
+		params,
+		&mut b.header,
+		next_header_info.difficulty,
+		params.proofsize,
+		params.min_edge_bits,
+	)
+	.unwrap();
+	b
+}
+
+// Mines and processes count empty blocks, returning them.
+pub fn mine_blocks<K>(chain: &Chain, keychain: &K, count: u32) -> Vec<Block>
+where
+	K: Keychain,
+{
+	(1..=count)
+		.map(|n| {
+			let b = mine_block(chain, keychain, n, vec![]);
+			chain
+				.process_block(b.clone(), chain::Options::MINE)
+				.unwrap();
+			b
+		})
+		.collect()
+}
@@ -43,10 +43,12 @@ fn setup(dir_name: &str) -> Chain {
 	chain::Chain::init(
 		dir_name.to_string(),
 		Arc::new(NoopAdapter {}),
+		ChainTypes::AutomatedTesting.params(),
 		genesis_block,
 		pow::verify_size,
 		verifier_cache,
 		false,
+		chain::OrphanConfig::default(),
 		Arc::new(Mutex::new(StopState::new())),
 	)
 	.unwrap()
@@ -57,10 +59,12 @@ fn reload_chain(dir_name: &str) -> Chain {
 	chain::Chain::init(
 		dir_name.to_string(),
 		Arc::new(NoopAdapter {}),
+		ChainTypes::AutomatedTesting.params(),
 		genesis::genesis_dev(),
 		pow::verify_size,
 		verifier_cache,
 		false,
+		chain::OrphanConfig::default(),
 		Arc::new(Mutex::new(StopState::new())),
 	)
 	.unwrap()
@@ -16,9 +16,9 @@ use self::chain::types::NoopAdapter;
 use self::chain::Chain;
 use self::core::core::hash::Hashed;
 use self::core::core::verifier_cache::LruVerifierCache;
-use self::core::core::{Block, BlockHeader, OutputIdentifier, Transaction};
+use self::core::core::{Block, BlockHeader, OutputIdentifier, Transaction, TransactionBody};
 use self::core::genesis;
-use self::core::global::ChainTypes;
+use self::core::global::{ChainParams, ChainTypes};
 use self::core::libtx::{self, build, reward};
 use self::core::pow::Difficulty;
 use self::core::{consensus, global, pow};
@@ -37,16 +37,22 @@ fn clean_output_dir(dir_name: &str) {
 }
 
 fn setup(dir_name: &str, genesis: Block) -> Chain {
+	setup_with_params(dir_name, ChainTypes::AutomatedTesting.params(), genesis)
+}
+
+fn setup_with_params(dir_name: &str, params: ChainParams, genesis: Block) -> Chain {
 	util::init_test_logger();
 	clean_output_dir(dir_name);
 	let verifier_cache = Arc::new(RwLock::new(LruVerifierCache::new()));
 	chain::Chain::init(
 		dir_name.to_string(),
 		Arc::new(NoopAdapter {}),
+		params,
 		genesis,
 		pow::verify_size,
 		verifier_cache,
 		false,
+		chain::OrphanConfig::default(),
 		Arc::new(Mutex::new(StopState::new())),
 	)
 	.unwrap()
@@ -417,6 +423,55 @@ fn spend_in_fork_and_compact() {
 	clean_output_dir(".grin6");
 }
 
+#[test]
+fn compact_removes_fork_block_filters() {
+	global::set_mining_mode(ChainTypes::AutomatedTesting);
+	let kc = ExtKeychain::from_random_seed(false).unwrap();
+	{
+		let mut params = ChainTypes::AutomatedTesting.params();
+		params.cut_through_horizon = 5;
+		let chain = setup_with_params(
+			".grin_compact_filters",
+			params,
+			pow::mine_genesis_block().unwrap(),
+		);
+
+		let prev = chain.head_header().unwrap();
+		let b1 = prepare_block(&kc, &prev, &chain, 2);
+		let b1head = b1.header.clone();
+		chain.process_block(b1, chain::Options::SKIP_POW).unwrap();
+
+		// A main chain block and a losing sibling, both saved with a filter.
+		let b2 = prepare_block(&kc, &b1head, &chain, 4);
+		let mut prev = b2.header.clone();
+		chain.process_block(b2, chain::Options::SKIP_POW).unwrap();
+		let bfork = prepare_fork_block(&kc, &b1head, &chain, 3);
+		let fork_hash = bfork.hash();
+		chain
+			.process_block(bfork, chain::Options::SKIP_POW)
+			.unwrap();
+		assert_eq!(chain.head_header().unwrap().hash(), prev.hash());
+		assert!(chain.get_block_filter(&fork_hash).is_ok());
+
+		// Go past the horizon and compact.
+		for n in 5..15 {
+			let next = prepare_block(&kc, &prev, &chain, n);
+			prev = next.header.clone();
+			chain.process_block(next, chain::Options::SKIP_POW).unwrap();
+		}
+		chain.compact().unwrap();
+
+		// Both blocks are gone, only the filter of the main chain one is kept.
+		let b2_hash = chain.get_header_by_height(2).unwrap().hash();
+		assert!(chain.get_block(&b2_hash).is_err());
+		assert!(chain.get_block_filter(&b2_hash).is_ok());
+		assert!(chain.get_block(&fork_hash).is_err());
+		assert!(chain.get_block_filter(&fork_hash).is_err());
+	}
+	// Cleanup chain directory
+	clean_output_dir(".grin_compact_filters");
+}
+
 /// Test ability to retrieve block headers for a given output
 #[test]
 fn output_header_mappings() {
@@ -480,6 +535,188 @@ fn output_header_mappings() {
 	clean_output_dir(".grin_header_for_output");
 }
 
+/// Test the UTXO set can be queried as of a previous block.
+#[test]
+fn utxo_view_at_height() {
+	global::set_mining_mode(ChainTypes::AutomatedTesting);
+	{
+		let chain = setup(".grin_utxo_view_at", pow::mine_genesis_block().unwrap());
+		let kc = ExtKeychain::from_random_seed(false).unwrap();
+
+		// Mine the first block and keep track of the coinbase output.
+		let prev = chain.head_header().unwrap();
+		let b = prepare_block(&kc, &prev, &chain, 2);
+		let coinbase = b.outputs()[0].commitment();
+		chain.process_block(b, chain::Options::SKIP_POW).unwrap();
+
+		// Mine a few more blocks so the coinbase output matures.
+		for n in 3..6 {
+			let prev = chain.head_header().unwrap();
+			let b = prepare_block(&kc, &prev, &chain, n);
+			chain.process_block(b, chain::Options::SKIP_POW).unwrap();
+		}
+
+		// Spend the coinbase output in the block at height 5.
+		let key_id2 = ExtKeychainPath::new(1, 2, 0, 0, 0).to_identifier();
+		let key_id30 = ExtKeychainPath::new(1, 30, 0, 0, 0).to_identifier();
+		let tx = build::transaction(
+			vec![
+				build::coinbase_input(consensus::REWARD, key_id2),
+				build::output(consensus::REWARD - 20000, key_id30),
+				build::with_fee(20000),
+			],
+			&kc,
+		)
+		.unwrap();
+		let prev = chain.head_header().unwrap();
+		let b = prepare_block_tx(&kc, &prev, &chain, 6, vec![&tx]);
+		chain.process_block(b, chain::Options::SKIP_POW).unwrap();
+
+		let prev = chain.head_header().unwrap();
+		let b = prepare_block(&kc, &prev, &chain, 7);
+		chain.process_block(b, chain::Options::SKIP_POW).unwrap();
+		assert_eq!(chain.head().unwrap().height, 6);
+
+		let output = tx.outputs()[0].commitment();
+
+		// The coinbase output was unspent as of height 4, the new output did not exist yet.
+		chain
+			.utxo_view_at(4, |utxo| {
+				let (_, pos) = utxo.get_unspent(&coinbase).unwrap();
+				assert_eq!(utxo.get_header_for_output_pos(pos)?.height, 1);
+				assert!(utxo.get_unspent(&output).is_none());
+				Ok(())
+			})
+			.unwrap();
+
+		// The coinbase output is spent as of height 5 (and still spent at the head).
+		for height in 5..7 {
+			chain
+				.utxo_view_at(height, |utxo| {
+					assert!(utxo.get_unspent(&coinbase).is_none());
+					let (_, pos) = utxo.get_unspent(&output).unwrap();
+					assert_eq!(utxo.get_header_for_output_pos(pos)?.height, 5);
+					Ok(())
+				})
+				.unwrap();
+		}
+
+		// Nothing to see beyond our head.
+		assert!(chain.utxo_view_at(7, |_| Ok(())).is_err());
+
+		// The current chain state is left untouched.
+		assert!(chain
+			.is_unspent(&OutputIdentifier::from_output(&tx.outputs()[0]))
+			.is_ok());
+		chain.validate(false).unwrap();
+	}
+	// Cleanup chain directory
+	clean_output_dir(".grin_utxo_view_at");
+}
+
+/// Test block stats are recorded for the current chain, removed on rewind
+/// and kept once the full blocks have been compacted away.
+#[test]
+fn block_stats() {
+	global::set_mining_mode(ChainTypes::AutomatedTesting);
+	{
+		let chain = setup(".grin_block_stats", pow::mine_genesis_block().unwrap());
+		let kc = ExtKeychain::from_random_seed(false).unwrap();
+
+		// A brand new db is created at our current schema version.
+		assert_eq!(
+			chain.store().schema_version().unwrap(),
+			chain::store::SCHEMA_VERSION
+		);
+
+		let genesis = chain.head_header().unwrap();
+		let stats = chain.get_block_stats(&genesis.hash()).unwrap();
+		assert_eq!(stats.height, 0);
+		assert_eq!(stats.block_time, 0);
+
+		// Mine the first block so we can spend the coinbase later.
+		let b = prepare_block(&kc, &genesis, &chain, 2);
+		let b1 = b.header.clone();
+		chain.process_block(b, chain::Options::SKIP_POW).unwrap();
+
+		let stats = chain.get_block_stats(&b1.hash()).unwrap();
+		assert_eq!(stats.height, 1);
+		assert_eq!(stats.fees, 0);
+		assert_eq!(stats.tx_count, 0);
+		assert_eq!(stats.num_inputs, 0);
+		assert_eq!(stats.num_outputs, 1);
+		assert_eq!(stats.num_kernels, 1);
+		assert_eq!(stats.block_time, 60);
+
+		for n in 3..6 {
+			let prev = chain.head_header().unwrap();
+			let b = prepare_block(&kc, &prev, &chain, n);
+			chain.process_block(b, chain::Options::SKIP_POW).unwrap();
+		}
+		let fork_head = chain.head_header().unwrap();
+		assert_eq!(fork_head.height, 4);
+
+		// Spend the first coinbase output in the block at height 5.
+		let key_id2 = ExtKeychainPath::new(1, 2, 0, 0, 0).to_identifier();
+		let key_id30 = ExtKeychainPath::new(1, 30, 0, 0, 0).to_identifier();
+		let tx = build::transaction(
+			vec![
+				build::coinbase_input(consensus::REWARD, key_id2),
+				build::output(consensus::REWARD - 20000, key_id30),
+				build::with_fee(20000),
+			],
+			&kc,
+		)
+		.unwrap();
+		let b = prepare_block_tx(&kc, &fork_head, &chain, 6, vec![&tx]);
+		let b5 = b.clone();
+		chain.process_block(b, chain::Options::SKIP_POW).unwrap();
+
+		let stats = chain.get_block_stats(&b5.hash()).unwrap();
+		assert_eq!(stats.height, 5);
+		assert_eq!(stats.fees, 20000);
+		assert_eq!(stats.tx_count, 1);
+		assert_eq!(stats.num_inputs, 1);
+		assert_eq!(stats.num_outputs, 2);
+		assert_eq!(stats.num_kernels, 2);
+		assert_eq!(
+			stats.weight,
+			TransactionBody::weight_as_block(1, 2, 2) as u64
+		);
+
+		// A losing fork block is not on our chain so has no stats.
+		let fork = prepare_fork_block(&kc, &fork_head, &chain, 1);
+		let fork1 = fork.header.clone();
+		chain.process_block(fork, chain::Options::SKIP_POW).unwrap();
+		assert!(chain.get_block_stats(&fork1.hash()).is_err());
+
+		// Make the fork win, rewinding the block at height 5.
+		let fork = prepare_fork_block(&kc, &fork1, &chain, 10);
+		let fork2 = fork.header.clone();
+		chain.process_block(fork, chain::Options::SKIP_POW).unwrap();
+		assert_eq!(chain.head_header().unwrap().hash(), fork2.hash());
+
+		assert!(chain.get_block_stats(&b5.hash()).is_err());
+		assert_eq!(chain.get_block_stats(&fork1.hash()).unwrap().height, 5);
+		assert_eq!(chain.get_block_stats(&fork2.hash()).unwrap().height, 6);
+
+		// Mine enough blocks for compaction to remove the early full blocks.
+		for n in 0..130 {
+			let prev = chain.head_header().unwrap();
+			let b = prepare_block(&kc, &prev, &chain, 11 + n);
+			chain.process_block(b, chain::Options::SKIP_POW).unwrap();
+		}
+		chain.compact().unwrap();
+
+		assert!(chain.get_block(&b1.hash()).is_err());
+		let stats = chain.get_block_stats(&b1.hash()).unwrap();
+		assert_eq!(stats.height, 1);
+		assert_eq!(stats.num_outputs, 1);
+	}
+	// Cleanup chain directory
+	clean_output_dir(".grin_block_stats");
+}
+
 fn prepare_block<K>(kc: &K, prev: &BlockHeader, chain: &Chain, diff: u64) -> Block
 where
 	K: Keychain,
@@ -561,10 +798,12 @@ fn actual_diff_iter_output() {
 	let chain = chain::Chain::init(
 		"../.grin".to_string(),
 		Arc::new(NoopAdapter {}),
+		ChainTypes::AutomatedTesting.params(),
 		genesis_block,
 		pow::verify_size,
 		verifier_cache,
 		false,
+		chain::OrphanConfig::default(),
 		Arc::new(Mutex::new(StopState::new())),
 	)
 	.unwrap();
@@ -53,7 +53,9 @@ fn test_various_store_indices() {
 	let keychain = ExtKeychain::from_random_seed(false).unwrap();
 	let key_id = ExtKeychainPath::new(1, 1, 0, 0, 0).to_identifier();
 
-	let chain_store = Arc::new(chain::store::ChainStore::new(chain_dir).unwrap());
+	let chain_store = Arc::new(
+		chain::store::ChainStore::new(chain_dir, ChainTypes::AutomatedTesting.params()).unwrap(),
+	);
 
 	global::set_mining_mode(ChainTypes::AutomatedTesting);
 	let genesis = pow::mine_genesis_block().unwrap();
@@ -0,0 +1,112 @@
+// Copyright 2018 The Grin Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+pub mod common;
+
+use self::chain::{Chain, ErrorKind};
+use self::core::core::{Block, BlockHeader, Transaction};
+use self::core::global::{self, ChainTypes};
+use self::core::libtx::{self, build};
+use self::core::pow::{self, Difficulty};
+use self::keychain::{ExtKeychain, ExtKeychainPath, Keychain};
+use crate::common::{clean_output_dir, mine_block, mine_blocks, setup};
+use grin_chain as chain;
+use grin_core as core;
+use grin_keychain as keychain;
+use grin_util as util;
+
+// Puts the transaction in a block on top of the chain head, taking the
+// header of a valid block (the txhashset roots won't match), mines it and
+// processes it.
+fn process_with_tx<K>(chain: &Chain, keychain: &K, tx: Transaction) -> ErrorKind
+where
+	K: Keychain,
+{
+	let params = chain.params();
+	let prev = chain.head_header().unwrap();
+	let header = mine_block(chain, keychain, 10, vec![]).header;
+	let difficulty = header.total_difficulty() - prev.total_difficulty();
+
+	let key_id = ExtKeychainPath::new(1, 10, 0, 0, 0).to_identifier();
+	let reward = libtx::reward::output(keychain, &key_id, tx.fee(), false).unwrap();
+	let mut b = Block::new(&prev, vec![tx], Difficulty::min(), reward).unwrap();
+	b.header = BlockHeader {
+		total_kernel_offset: b.header.total_kernel_offset.clone(),
+		..header
+	};
+	pow::pow_size(
+		&mut b.header,
+		difficulty,
+		params.proofsize,
+		params.min_edge_bits,
+	)
+	.unwrap();
+
+	chain
+		.process_block(b, chain::Options::NONE)
+		.unwrap_err()
+		.kind()
+}
+
+// A transaction spending an output that isn't in the UTXO set.
+fn tx_spending_unknown_output<K>(keychain: &K) -> Transaction
+where
+	K: Keychain,
+{
+	build::transaction(
+		vec![
+			build::input(
+				10_000,
+				ExtKeychainPath::new(1, 100, 0, 0, 0).to_identifier(),
+			),
+			build::output(8_000, ExtKeychainPath::new(1, 101, 0, 0, 0).to_identifier()),
+			build::with_fee(2_000),
+		],
+		keychain,
+	)
+	.unwrap()
+}
+
+// The internal validation of a block takes precedence over its validation
+// against the UTXO set, a block with bad signatures is reported as such
+// whatever its inputs.
+#[test]
+fn block_validation_error_precedence() {
+	util::init_test_logger();
+	global::set_mining_mode(ChainTypes::AutomatedTesting);
+	let chain_dir = ".grin_block_validation";
+	clean_output_dir(chain_dir);
+
+	let keychain = ExtKeychain::from_random_seed(false).unwrap();
+	{
+		let chain = setup(chain_dir, pow::mine_genesis_block().unwrap());
+		mine_blocks(&chain, &keychain, 1);
+
+		// Only the input is bad.
+		let tx = tx_spending_unknown_output(&keychain);
+		if let ErrorKind::InvalidBlockProof(e) = process_with_tx(&chain, &keychain, tx) {
+			panic!("expected a txhashset error, got {:?}", e);
+		}
+
+		// Both the input and the kernel signature are bad.
+		let other = tx_spending_unknown_output(&keychain);
+		let mut tx = tx_spending_unknown_output(&keychain);
+		tx.kernels_mut()[0].excess_sig = other.kernels()[0].excess_sig;
+		match process_with_tx(&chain, &keychain, tx) {
+			ErrorKind::InvalidBlockProof(_) => {}
+			e => panic!("expected an invalid block proof, got {:?}", e),
+		}
+	}
+	clean_output_dir(chain_dir);
+}
@@ -0,0 +1,147 @@
+// Copyright 2018 The Grin Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+pub mod common;
+
+use self::chain::{Chain, ErrorKind, OrphanConfig};
+use self::core::core::{block, transaction, Transaction};
+use self::core::global::{self, ChainParams};
+use self::core::libtx::build;
+use self::core::{consensus, pow};
+use self::keychain::{ExtKeychain, ExtKeychainPath, Keychain};
+use crate::common::{clean_output_dir, init_chain, mine_block};
+use grin_chain as chain;
+use grin_core as core;
+use grin_keychain as keychain;
+use std::thread;
+
+// Opens a chain with the provided parameters, passed explicitly everywhere,
+// and mines a few empty blocks on it.
+fn setup_chain<K>(dir_name: &str, params: ChainParams, keychain: &K) -> Chain
+where
+	K: Keychain,
+{
+	clean_output_dir(dir_name);
+
+	let chain = init_chain(
+		dir_name,
+		params.clone(),
+		pow::mine_genesis_block_with_params(&params).unwrap(),
+		false,
+		OrphanConfig::default(),
+	);
+	assert_eq!(chain.params(), &params);
+
+	for n in 1..5 {
+		let b = mine_block(&chain, keychain, n, vec![]);
+		chain.process_block(b, chain::Options::MINE).unwrap();
+	}
+	chain
+}
+
+// A transaction spending the coinbase of the first block.
+fn spend_first_coinbase<K>(keychain: &K) -> Transaction
+where
+	K: Keychain,
+{
+	let key_id1 = ExtKeychainPath::new(1, 1, 0, 0, 0).to_identifier();
+	let key_id2 = ExtKeychainPath::new(1, 100, 0, 0, 0).to_identifier();
+	build::transaction(
+		vec![
+			build::coinbase_input(consensus::REWARD, key_id1),
+			build::output(consensus::REWARD - 2, key_id2),
+			build::with_fee(2),
+		],
+		keychain,
+	)
+	.unwrap()
+}
+
+// Mines a few blocks on a chain with the provided parameters and checks
+// whether the first coinbase can be spent in the next one.
+fn coinbase_spendable(dir_name: &str, params: ChainParams) -> bool {
+	let keychain = ExtKeychain::from_random_seed(false).unwrap();
+	let chain = setup_chain(dir_name, params, &keychain);
+	let tx = spend_first_coinbase(&keychain);
+
+	let res = match chain.verify_coinbase_maturity(&tx) {
+		Ok(_) => true,
+		Err(e) => match e.kind() {
+			ErrorKind::ImmatureCoinbase => false,
+			_ => panic!("Expected ok or immature coinbase, got {:?}", e),
+		},
+	};
+	clean_output_dir(dir_name);
+	res
+}
+
+// Mines a few blocks on a chain with the provided parameters and checks
+// whether a block spending the first coinbase is accepted, blocks being
+// validated on the thread pool shared by both chains.
+fn tx_block_accepted(dir_name: &str, params: ChainParams) -> bool {
+	let keychain = ExtKeychain::from_random_seed(false).unwrap();
+	let chain = setup_chain(dir_name, params, &keychain);
+	let tx = spend_first_coinbase(&keychain);
+
+	let b = mine_block(&chain, &keychain, 5, vec![tx]);
+	let res = match chain.process_block(b, chain::Options::MINE) {
+		Ok(_) => true,
+		Err(e) => match e.kind() {
+			ErrorKind::InvalidBlockProof(block::Error::Transaction(
+				transaction::Error::TooHeavy,
+			)) => false,
+			_ => panic!("Expected ok or too heavy block, got {:?}", e),
+		},
+	};
+	clean_output_dir(dir_name);
+	res
+}
+
+// Chains with different parameters run concurrently in the same process, the
+// process wide chain type doesn't matter.
+#[test]
+fn concurrent_chain_params() {
+	let _ = env_logger::init();
+	let short_maturity = ChainParams::automated_testing();
+	let long_maturity = ChainParams {
+		coinbase_maturity: 10,
+		..ChainParams::automated_testing()
+	};
+
+	let short = thread::spawn(move || coinbase_spendable(".grin_params_short", short_maturity));
+	let long = thread::spawn(move || coinbase_spendable(".grin_params_long", long_maturity));
+
+	assert!(short.join().unwrap());
+	assert!(!long.join().unwrap());
+	assert!(global::is_mainnet());
+}
+
+// Same for the max block weight, a block with a transaction (on top of the
+// coinbase) being too heavy for one of the chains.
+#[test]
+fn concurrent_max_block_weight() {
+	let _ = env_logger::init();
+	let heavy = ChainParams::automated_testing();
+	let light = ChainParams {
+		max_block_weight: 40,
+		..ChainParams::automated_testing()
+	};
+
+	let heavy = thread::spawn(move || tx_block_accepted(".grin_params_heavy", heavy));
+	let light = thread::spawn(move || tx_block_accepted(".grin_params_light", light));
+
+	assert!(heavy.join().unwrap());
+	assert!(!light.join().unwrap());
+	assert!(global::is_mainnet());
+}
@@ -0,0 +1,103 @@
+// Copyright 2018 The Grin Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+pub mod common;
+
+use self::chain::{Chain, ErrorKind};
+use self::core::core::chain_proof::{ChainProof, ChainProofError};
+use self::core::core::hash::Hashed;
+use self::core::global::{self, ChainTypes};
+use self::core::{pow, ser};
+use self::keychain::{ExtKeychain, Keychain};
+use crate::common::{clean_output_dir, mine_blocks, setup};
+use grin_chain as chain;
+use grin_core as core;
+use grin_keychain as keychain;
+use grin_util as util;
+
+fn proof_error(chain: &Chain, proof: &ChainProof) -> ChainProofError {
+	match chain.verify_chain_proof(proof).unwrap_err().kind() {
+		ErrorKind::InvalidChainProof(e) => e,
+		e => panic!("expected an invalid chain proof, got {:?}", e),
+	}
+}
+
+#[test]
+fn chain_proofs() {
+	util::init_test_logger();
+	global::set_mining_mode(ChainTypes::AutomatedTesting);
+	let chain_dir = ".grin_chain_proof";
+	clean_output_dir(chain_dir);
+
+	let keychain = ExtKeychain::from_random_seed(false).unwrap();
+	let chain = setup(chain_dir, pow::mine_genesis_block().unwrap());
+	assert!(chain.get_chain_proof().is_err());
+
+	mine_blocks(&chain, &keychain, 24);
+	let head = chain.head_header().unwrap();
+
+	let proof = chain.get_chain_proof().unwrap();
+	assert_eq!(proof.tip, head);
+	assert_eq!(proof.headers.first().unwrap().header.height, 0);
+	assert_eq!(proof.headers.last().unwrap().header.height, head.height - 1);
+	chain.verify_chain_proof(&proof).unwrap();
+
+	// The proof survives a serialization roundtrip.
+	let bytes = ser::ser_vec(&proof, ser::ProtocolVersion::local()).unwrap();
+	let proof2: ChainProof = ser::deserialize_default(&mut &bytes[..]).unwrap();
+	assert_eq!(proof, proof2);
+	chain.verify_chain_proof(&proof2).unwrap();
+
+	// The proof is for a different genesis.
+	let mut bad = proof.clone();
+	bad.headers.remove(0);
+	assert_eq!(proof_error(&chain, &bad), ChainProofError::GenesisMismatch);
+	let genesis = proof.headers[0].header.hash();
+	assert_eq!(
+		proof.verify(chain.params(), &head.hash(), pow::verify_size),
+		Err(ChainProofError::GenesisMismatch)
+	);
+	assert_eq!(
+		proof.verify(chain.params(), &genesis, pow::verify_size),
+		Ok(())
+	);
+
+	// The last sampled header isn't the one before the tip.
+	let mut bad = proof.clone();
+	bad.headers.pop();
+	assert_eq!(proof_error(&chain, &bad), ChainProofError::TipMismatch);
+
+	// The sampled headers are out of order.
+	let mut bad = proof.clone();
+	bad.headers.swap(1, 2);
+	assert_eq!(proof_error(&chain, &bad), ChainProofError::InvalidOrder);
+
+	// A sampled header with the Merkle proof of another one.
+	let mut bad = proof.clone();
+	bad.headers[1].proof = proof.headers[2].proof.clone();
+	assert_eq!(
+		proof_error(&chain, &bad),
+		ChainProofError::MerkleProof(proof.headers[1].header.height)
+	);
+
+	// The tip proof of work doesn't check out.
+	let mut bad = proof.clone();
+	bad.tip.pow.nonce += 1;
+	assert_eq!(
+		proof_error(&chain, &bad),
+		ChainProofError::InvalidPoW(head.height)
+	);
+
+	clean_output_dir(chain_dir);
+}
@@ -49,10 +49,12 @@ fn test_coinbase_maturity() {
 		let chain = chain::Chain::init(
 			".grin".to_string(),
 			Arc::new(NoopAdapter {}),
+			ChainTypes::AutomatedTesting.params(),
 			genesis_block,
 			pow::verify_size,
 			verifier_cache,
 			false,
+			chain::OrphanConfig::default(),
 			Arc::new(Mutex::new(StopState::new())),
 		)
 		.unwrap();
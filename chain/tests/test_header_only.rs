@@ -0,0 +1,93 @@
+// Copyright 2018 The Grin Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+pub mod common;
+
+use self::core::core::hash::Hashed;
+use self::core::global::{self, ChainTypes};
+use self::core::pow;
+use self::keychain::{ExtKeychain, Keychain};
+use crate::common::{clean_output_dir, mine_block, setup};
+use grin_chain as chain;
+use grin_core as core;
+use grin_keychain as keychain;
+use grin_util as util;
+
+#[test]
+fn header_only_output_proofs() {
+	util::init_test_logger();
+	global::set_mining_mode(ChainTypes::AutomatedTesting);
+	let full_dir = ".grin_header_only/full";
+	let light_dir = ".grin_header_only/light";
+	clean_output_dir(".grin_header_only");
+
+	let keychain = ExtKeychain::from_random_seed(false).unwrap();
+	let genesis = pow::mine_genesis_block().unwrap();
+	{
+		let full = setup(full_dir, genesis.clone());
+		let light = setup(light_dir, genesis.clone());
+
+		let mut blocks = vec![];
+		for n in 1..10 {
+			let b = mine_block(&full, &keychain, n, vec![]);
+			full.process_block(b.clone(), chain::Options::MINE).unwrap();
+			blocks.push(b);
+		}
+
+		// The light chain only follows the headers, in 2 batches.
+		let headers: Vec<_> = blocks.iter().map(|b| b.header.clone()).collect();
+		light
+			.sync_header_chain(&headers[..4], chain::Options::SYNC)
+			.unwrap();
+
+		// A proof against a block past our header head can't be verified yet.
+		let commit = blocks[2].outputs()[0].commitment();
+		let proof = full.get_output_proof(commit).unwrap();
+		assert_eq!(proof.block_hash, blocks[8].hash());
+		assert!(light.verify_output_proof(&proof).is_err());
+
+		light
+			.sync_header_chain(&headers[4..], chain::Options::SYNC)
+			.unwrap();
+		assert_eq!(light.header_head().unwrap().last_block_h, blocks[8].hash());
+		assert_eq!(light.head().unwrap().height, 0);
+		assert_eq!(light.get_header_by_height(5).unwrap(), blocks[4].header);
+		light.verify_output_proof(&proof).unwrap();
+
+		// The proof only holds for the output and position it was built for.
+		let other = full
+			.get_output_proof(blocks[3].outputs()[0].commitment())
+			.unwrap();
+		let mut bad_proof = proof.clone();
+		bad_proof.output = other.output;
+		assert!(light
+			.verify_output_proof(&bad_proof)
+			.unwrap_err()
+			.is_bad_data());
+		let mut bad_proof = proof.clone();
+		bad_proof.pos = other.pos;
+		assert!(light
+			.verify_output_proof(&bad_proof)
+			.unwrap_err()
+			.is_bad_data());
+
+		// The header MMR is rewound to the head of the (empty) full chain on
+		// restart, and brought back to the header head on request.
+		drop(light);
+		let light = setup(light_dir, genesis.clone());
+		light.update_header_mmr().unwrap();
+		light.verify_output_proof(&proof).unwrap();
+	}
+	clean_output_dir(".grin_header_only");
+}
@@ -0,0 +1,139 @@
+// Copyright 2018 The Grin Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+pub mod common;
+
+use self::core::core::hash::Hashed;
+use self::core::core::merkle_proof::MerkleProofError;
+use self::core::global::{self, ChainTypes};
+use self::core::libtx::payment_proof::PaymentProof;
+use self::core::pow;
+use self::keychain::{ExtKeychain, Keychain};
+use self::util::secp::key::{PublicKey, SecretKey};
+use crate::common::{clean_output_dir, mine_block, setup};
+use grin_chain as chain;
+use grin_core as core;
+use grin_keychain as keychain;
+use grin_util as util;
+
+#[test]
+fn kernel_merkle_proofs() {
+	util::init_test_logger();
+	global::set_mining_mode(ChainTypes::AutomatedTesting);
+	let chain_dir = ".grin_kernel_proof";
+	clean_output_dir(chain_dir);
+
+	let keychain = ExtKeychain::from_random_seed(false).unwrap();
+	let genesis = pow::mine_genesis_block().unwrap();
+	{
+		let chain = setup(chain_dir, genesis.clone());
+		let mut blocks = vec![];
+		for n in 1..8 {
+			let b = mine_block(&chain, &keychain, n, vec![]);
+			chain
+				.process_block(b.clone(), chain::Options::MINE)
+				.unwrap();
+			blocks.push(b);
+		}
+		let head_header = chain.head_header().unwrap();
+
+		for b in &blocks {
+			let kernel = &b.kernels()[0];
+			let proof = chain.get_kernel_merkle_proof(&kernel.excess).unwrap();
+			assert_eq!(proof.block_hash, b.hash());
+			assert_eq!(proof.kernel, *kernel);
+
+			// The proof is against the kernel root of the block the kernel
+			// was included in, not our current head.
+			proof
+				.proof
+				.verify_kernel(&b.header, kernel, proof.pos)
+				.unwrap();
+			if b.header.height != head_header.height {
+				assert_eq!(
+					proof.proof.verify_kernel(&head_header, kernel, proof.pos),
+					Err(MerkleProofError::SizeMismatch)
+				);
+			}
+		}
+
+		// No proof for a kernel we don't know about.
+		let other = mine_block(&chain, &keychain, 100, vec![]);
+		assert!(chain
+			.get_kernel_merkle_proof(&other.kernels()[0].excess)
+			.is_err());
+	}
+	{
+		// Proofs are still found after a restart.
+		let chain = setup(chain_dir, genesis.clone());
+		let head_header = chain.head_header().unwrap();
+		let block = chain.get_block(&head_header.prev_hash).unwrap();
+		let kernel = &block.kernels()[0];
+		let proof = chain.get_kernel_merkle_proof(&kernel.excess).unwrap();
+		assert_eq!(proof.block_hash, block.hash());
+		proof
+			.proof
+			.verify_kernel(&block.header, kernel, proof.pos)
+			.unwrap();
+	}
+	clean_output_dir(chain_dir);
+}
+
+#[test]
+fn payment_proof_kernel_on_chain() {
+	util::init_test_logger();
+	global::set_mining_mode(ChainTypes::AutomatedTesting);
+	let chain_dir = ".grin_payment_proof";
+	clean_output_dir(chain_dir);
+
+	let keychain = ExtKeychain::from_random_seed(false).unwrap();
+	let secp = keychain.secp();
+	let sender_key = SecretKey::new(secp, &mut rand::thread_rng());
+	let sender = PublicKey::from_secret_key(secp, &sender_key).unwrap();
+	let receiver_key = SecretKey::new(secp, &mut rand::thread_rng());
+
+	let genesis = pow::mine_genesis_block().unwrap();
+	{
+		let chain = setup(chain_dir, genesis);
+		let b = mine_block(&chain, &keychain, 1, vec![]);
+		chain
+			.process_block(b.clone(), chain::Options::MINE)
+			.unwrap();
+		let kernel = &b.kernels()[0];
+
+		let proof = PaymentProof::create(secp, 100, kernel.excess, sender, &receiver_key).unwrap();
+		let kernel_proof = chain.verify_payment_proof(&proof).unwrap();
+		assert_eq!(kernel_proof.block_hash, b.hash());
+		kernel_proof
+			.proof
+			.verify_kernel(&b.header, kernel, kernel_proof.pos)
+			.unwrap();
+
+		// A proof with a bad signature is rejected before any lookup.
+		let mut bad = proof.clone();
+		bad.amount = 200;
+		match chain.verify_payment_proof(&bad).map_err(|e| e.kind()) {
+			Err(chain::ErrorKind::InvalidPaymentProof(_)) => {}
+			res => panic!("unexpected result: {:?}", res.map(|_| ())),
+		}
+
+		// A valid proof for a kernel not on chain.
+		let other = mine_block(&chain, &keychain, 2, vec![]);
+		let proof =
+			PaymentProof::create(secp, 100, other.kernels()[0].excess, sender, &receiver_key)
+				.unwrap();
+		assert!(chain.verify_payment_proof(&proof).is_err());
+	}
+	clean_output_dir(chain_dir);
+}
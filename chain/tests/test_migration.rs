@@ -0,0 +1,81 @@
+// Copyright 2018 The Grin Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+pub mod common;
+
+use self::core::global::{self, ChainTypes};
+use self::core::pow;
+use self::keychain::ExtKeychain;
+use self::store::migration::SchemaVersion;
+use crate::common::{clean_output_dir, mine_blocks, setup};
+use grin_chain as chain;
+use grin_core as core;
+use grin_keychain as keychain;
+use grin_store as store;
+use grin_util as util;
+
+// Records the schema version in the chain db, as an older node would have.
+fn set_schema_version(dir_name: &str, version: u32) {
+	let db = store::Store::new(dir_name, None, Some("chain"), None).unwrap();
+	let batch = db.batch().unwrap();
+	batch
+		.put_ser(
+			&store::to_key(b'#', &mut b"version".to_vec()),
+			&SchemaVersion(version),
+		)
+		.unwrap();
+	batch.commit().unwrap();
+}
+
+// A db at schema version 1 predates the kernel_pos index, which gets built
+// from the kernel MMR on the next start.
+#[test]
+fn kernel_index_migration() {
+	util::init_test_logger();
+	global::set_mining_mode(ChainTypes::AutomatedTesting);
+	let chain_dir = ".grin_kernel_index_migration";
+	clean_output_dir(chain_dir);
+
+	let keychain = ExtKeychain::from_random_seed(false).unwrap();
+	let genesis = pow::mine_genesis_block().unwrap();
+	let kernels = {
+		let chain = setup(chain_dir, genesis.clone());
+		let blocks = mine_blocks(&chain, &keychain, 3);
+		let kernels: Vec<_> = blocks
+			.iter()
+			.map(|b| {
+				let excess = b.kernels()[0].excess;
+				(excess, chain.store().get_kernel_pos(&excess).unwrap())
+			})
+			.collect();
+
+		let store = chain.store();
+		let batch = store.batch().unwrap();
+		batch.clear_kernel_pos().unwrap();
+		batch.commit().unwrap();
+		kernels
+	};
+	set_schema_version(chain_dir, 1);
+
+	let chain = setup(chain_dir, genesis);
+	assert_eq!(
+		chain.store().schema_version().unwrap(),
+		chain::store::SCHEMA_VERSION
+	);
+	for (excess, pos) in kernels {
+		assert_eq!(chain.store().get_kernel_pos(&excess).unwrap(), pos);
+	}
+	drop(chain);
+	clean_output_dir(chain_dir);
+}
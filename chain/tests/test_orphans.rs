@@ -0,0 +1,177 @@
+// Copyright 2018 The Grin Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+pub mod common;
+
+use self::chain::{Chain, ErrorKind, OrphanConfig, OrphanEviction};
+use self::core::core::hash::Hashed;
+use self::core::core::Block;
+use self::core::global::{self, ChainTypes};
+use self::core::pow;
+use self::keychain::{ExtKeychain, Keychain};
+use crate::common::{clean_output_dir, init_chain, mine_blocks, setup};
+use grin_chain as chain;
+use grin_core as core;
+use grin_keychain as keychain;
+use grin_util as util;
+
+// Mines a few blocks on a fresh chain, to feed them to other chains.
+fn mine_fresh_blocks<K>(dir_name: &str, genesis: Block, keychain: &K, count: u32) -> Vec<Block>
+where
+	K: Keychain,
+{
+	let chain = setup(dir_name, genesis);
+	mine_blocks(&chain, keychain, count)
+}
+
+fn setup_orphans(dir_name: &str, genesis: Block, orphan_config: OrphanConfig) -> Chain {
+	init_chain(
+		dir_name,
+		ChainTypes::AutomatedTesting.params(),
+		genesis,
+		false,
+		orphan_config,
+	)
+}
+
+fn add_orphan(chain: &Chain, b: &Block) {
+	let res = chain.process_block_from(
+		b.clone(),
+		chain::Options::NONE,
+		Some("10.0.0.1:3414".to_owned()),
+	);
+	match res.unwrap_err().kind() {
+		ErrorKind::Orphan => {}
+		e => panic!("expected an orphan, got {:?}", e),
+	}
+}
+
+#[test]
+fn persisted_orphans() {
+	util::init_test_logger();
+	global::set_mining_mode(ChainTypes::AutomatedTesting);
+	let chain_dir = ".grin_orphans_persist/chain";
+	clean_output_dir(".grin_orphans_persist");
+
+	let keychain = ExtKeychain::from_random_seed(false).unwrap();
+	let genesis = pow::mine_genesis_block().unwrap();
+	let blocks = mine_fresh_blocks(".grin_orphans_persist/miner", genesis.clone(), &keychain, 3);
+	let config = OrphanConfig {
+		persist_orphans: true,
+		..OrphanConfig::default()
+	};
+	{
+		let chain = setup_orphans(chain_dir, genesis.clone(), config.clone());
+		add_orphan(&chain, &blocks[2]);
+		add_orphan(&chain, &blocks[1]);
+
+		let orphans = chain.orphans();
+		assert_eq!(orphans.len(), 2);
+		assert_eq!(orphans[0].hash, blocks[1].hash());
+		assert_eq!(orphans[0].height, 2);
+		assert_eq!(orphans[0].prev_hash, blocks[0].hash());
+		assert_eq!(orphans[0].peer, Some("10.0.0.1:3414".to_owned()));
+		assert_eq!(orphans[1].hash, blocks[2].hash());
+	}
+	{
+		// The orphans survive a restart and get processed once their parent
+		// shows up.
+		let chain = setup_orphans(chain_dir, genesis.clone(), config.clone());
+		assert_eq!(chain.orphans_len(), 2);
+		assert!(chain.is_orphan(&blocks[2].hash()));
+		chain
+			.process_block(blocks[0].clone(), chain::Options::NONE)
+			.unwrap();
+		assert_eq!(chain.head().unwrap().last_block_h, blocks[2].hash());
+		assert_eq!(chain.orphans_len(), 0);
+	}
+	{
+		let chain = setup_orphans(chain_dir, genesis.clone(), config.clone());
+		assert_eq!(chain.orphans_len(), 0);
+	}
+	clean_output_dir(".grin_orphans_persist");
+}
+
+#[test]
+fn unpersisted_orphans_dropped() {
+	util::init_test_logger();
+	global::set_mining_mode(ChainTypes::AutomatedTesting);
+	let chain_dir = ".grin_orphans_drop/chain";
+	clean_output_dir(".grin_orphans_drop");
+
+	let keychain = ExtKeychain::from_random_seed(false).unwrap();
+	let genesis = pow::mine_genesis_block().unwrap();
+	let blocks = mine_fresh_blocks(".grin_orphans_drop/miner", genesis.clone(), &keychain, 2);
+	let config = OrphanConfig {
+		persist_orphans: true,
+		..OrphanConfig::default()
+	};
+	{
+		let chain = setup_orphans(chain_dir, genesis.clone(), config.clone());
+		add_orphan(&chain, &blocks[1]);
+	}
+	{
+		// Restarting without persistence drops the orphans kept in the db.
+		let chain = setup_orphans(chain_dir, genesis.clone(), OrphanConfig::default());
+		assert_eq!(chain.orphans_len(), 0);
+	}
+	{
+		let chain = setup_orphans(chain_dir, genesis.clone(), config.clone());
+		assert_eq!(chain.orphans_len(), 0);
+	}
+	clean_output_dir(".grin_orphans_drop");
+}
+
+#[test]
+fn orphan_eviction() {
+	util::init_test_logger();
+	global::set_mining_mode(ChainTypes::AutomatedTesting);
+	clean_output_dir(".grin_orphans_evict");
+
+	let keychain = ExtKeychain::from_random_seed(false).unwrap();
+	let genesis = pow::mine_genesis_block().unwrap();
+	let blocks = mine_fresh_blocks(".grin_orphans_evict/miner", genesis.clone(), &keychain, 4);
+	{
+		// The orphans furthest ahead go first.
+		let config = OrphanConfig {
+			max_orphans: 2,
+			orphan_eviction: OrphanEviction::HighestFirst,
+			..OrphanConfig::default()
+		};
+		let chain = setup_orphans(".grin_orphans_evict/highest", genesis.clone(), config);
+		add_orphan(&chain, &blocks[1]);
+		add_orphan(&chain, &blocks[3]);
+		add_orphan(&chain, &blocks[2]);
+		assert_eq!(chain.orphans_max_size(), 2);
+		let heights: Vec<_> = chain.orphans().iter().map(|x| x.height).collect();
+		assert_eq!(heights, vec![2]);
+		assert_eq!(chain.orphans_evicted_len(), 2);
+	}
+	{
+		// The orphans received first go first.
+		let config = OrphanConfig {
+			max_orphans: 2,
+			orphan_eviction: OrphanEviction::OldestFirst,
+			..OrphanConfig::default()
+		};
+		let chain = setup_orphans(".grin_orphans_evict/oldest", genesis.clone(), config);
+		add_orphan(&chain, &blocks[1]);
+		add_orphan(&chain, &blocks[3]);
+		add_orphan(&chain, &blocks[2]);
+		let heights: Vec<_> = chain.orphans().iter().map(|x| x.height).collect();
+		assert_eq!(heights, vec![3]);
+		assert_eq!(chain.orphans_evicted_len(), 2);
+	}
+	clean_output_dir(".grin_orphans_evict");
+}
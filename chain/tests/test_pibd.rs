@@ -0,0 +1,224 @@
+// Copyright 2018 The Grin Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use self::chain::types::{NoStatus, NoopAdapter};
+use self::chain::{Chain, SegmentType};
+use self::core::core::hash::Hashed;
+use self::core::core::pmmr::BitmapSegment;
+use self::core::core::verifier_cache::LruVerifierCache;
+use self::core::core::{Block, OutputIdentifier, Transaction};
+use self::core::global::{self, ChainTypes};
+use self::core::libtx::{self, build};
+use self::core::{consensus, pow};
+use self::keychain::{ExtKeychain, ExtKeychainPath, Keychain};
+use self::util::{Mutex, RwLock, StopState};
+use chrono::Duration;
+use croaring::Bitmap;
+use grin_chain as chain;
+use grin_core as core;
+use grin_keychain as keychain;
+use grin_util as util;
+use std::fs;
+use std::sync::Arc;
+
+fn clean_output_dir(dir_name: &str) {
+	let _ = fs::remove_dir_all(dir_name);
+}
+
+fn setup(dir_name: &str, genesis: Block) -> Chain {
+	let verifier_cache = Arc::new(RwLock::new(LruVerifierCache::new()));
+	chain::Chain::init(
+		dir_name.to_string(),
+		Arc::new(NoopAdapter {}),
+		ChainTypes::AutomatedTesting.params(),
+		genesis,
+		pow::verify_size,
+		verifier_cache,
+		false,
+		chain::OrphanConfig::default(),
+		Arc::new(Mutex::new(StopState::new())),
+	)
+	.unwrap()
+}
+
+fn mine_block<K>(chain: &Chain, keychain: &K, n: u32, txs: Vec<Transaction>) -> Block
+where
+	K: Keychain,
+{
+	let prev = chain.head_header().unwrap();
+	let next_header_info = consensus::next_difficulty(1, chain.difficulty_iter().unwrap());
+	let key_id = ExtKeychainPath::new(1, n, 0, 0, 0).to_identifier();
+	let fees = txs.iter().map(|tx| tx.fee()).sum();
+	let reward = libtx::reward::output(keychain, &key_id, fees, false).unwrap();
+	let mut b =
+		core::core::Block::new(&prev, txs, next_header_info.clone().difficulty, reward).unwrap();
+	b.header.timestamp = prev.timestamp + Duration::seconds(60);
+	b.header.pow.secondary_scaling = next_header_info.secondary_scaling;
+
+	chain.set_txhashset_roots(&mut b).unwrap();
+
+	pow::pow_size(
+		&mut b.header,
+		next_header_info.difficulty,
+		global::proofsize(),
+		global::min_edge_bits(),
+	)
+	.unwrap();
+	b
+}
+
+// Feeds all the segments the destination chain asks for, taken from the
+// source chain.
+fn transfer_segments(src: &Chain, dest: &Chain) {
+	let h = dest.pibd_block_hash().unwrap();
+	loop {
+		let missing = dest.pibd_missing_segments(16);
+		if missing.is_empty() {
+			break;
+		}
+		for (segment_type, id) in missing {
+			match segment_type {
+				SegmentType::Bitmap => {
+					let segment = src.get_output_bitmap_segment(h, id).unwrap();
+					dest.pibd_receive_bitmap_segment(h, segment).unwrap();
+				}
+				SegmentType::Output => {
+					let segment = src.get_output_segment(h, id).unwrap();
+					dest.pibd_receive_output_segment(h, segment).unwrap();
+				}
+				SegmentType::RangeProof => {
+					let segment = src.get_rangeproof_segment(h, id).unwrap();
+					dest.pibd_receive_rangeproof_segment(h, segment).unwrap();
+				}
+				SegmentType::Kernel => {
+					let segment = src.get_kernel_segment(h, id).unwrap();
+					dest.pibd_receive_kernel_segment(h, segment).unwrap();
+				}
+			}
+		}
+	}
+}
+
+#[test]
+fn pibd_compacted_chain() {
+	util::init_test_logger();
+	global::set_mining_mode(ChainTypes::AutomatedTesting);
+	let src_dir = ".grin_pibd/src";
+	let dest_dir = ".grin_pibd/dest";
+	clean_output_dir(".grin_pibd");
+
+	let keychain = ExtKeychain::from_random_seed(false).unwrap();
+	let genesis = pow::mine_genesis_block().unwrap();
+	{
+		let src = setup(src_dir, genesis.clone());
+
+		for n in 1..5 {
+			let b = mine_block(&src, &keychain, n, vec![]);
+			src.process_block(b, chain::Options::MINE).unwrap();
+		}
+
+		// Spend the first 2 coinbase outputs (siblings in the output MMR) so
+		// compaction removes them entirely.
+		let key_id1 = ExtKeychainPath::new(1, 1, 0, 0, 0).to_identifier();
+		let key_id2 = ExtKeychainPath::new(1, 2, 0, 0, 0).to_identifier();
+		let key_id30 = ExtKeychainPath::new(1, 30, 0, 0, 0).to_identifier();
+		let tx = build::transaction(
+			vec![
+				build::coinbase_input(consensus::REWARD, key_id1),
+				build::coinbase_input(consensus::REWARD, key_id2),
+				build::output(2 * consensus::REWARD - 20000, key_id30),
+				build::with_fee(20000),
+			],
+			&keychain,
+		)
+		.unwrap();
+		let b = mine_block(&src, &keychain, 5, vec![tx.clone()]);
+		src.process_block(b, chain::Options::MINE).unwrap();
+
+		for n in 6..150 {
+			let b = mine_block(&src, &keychain, n, vec![]);
+			src.process_block(b, chain::Options::MINE).unwrap();
+		}
+		src.compact().unwrap();
+
+		let head = src.head_header().unwrap();
+		let headers: Vec<_> = (1..=head.height)
+			.map(|height| src.get_header_by_height(height).unwrap())
+			.collect();
+
+		let dest = setup(dest_dir, genesis.clone());
+		dest.sync_block_headers(&headers, chain::Options::SYNC)
+			.unwrap();
+
+		dest.pibd_start(head.hash()).unwrap();
+		assert_eq!(dest.pibd_block_hash(), Some(head.hash()));
+		assert!(!dest.pibd_is_complete());
+
+		// Bitmap segments are only asked for once we have the output segment
+		// they are checked against.
+		assert!(dest
+			.pibd_missing_segments(16)
+			.iter()
+			.all(|x| x.0 != SegmentType::Bitmap));
+
+		// A segment of the output MMR at another block is rejected as invalid.
+		let prev = src.get_previous_header(&head).unwrap();
+		let id = dest
+			.pibd_missing_segments(16)
+			.into_iter()
+			.find(|x| x.0 == SegmentType::Output)
+			.unwrap()
+			.1;
+		let segment = src.get_output_segment(prev.hash(), id).unwrap();
+		let res = dest.pibd_receive_output_segment(head.hash(), segment);
+		assert!(res.unwrap_err().is_bad_data());
+
+		// A bitmap segment flagging the compacted outputs as unspent is
+		// rejected as well.
+		let segment = src.get_output_segment(head.hash(), id).unwrap();
+		assert!(!dest
+			.pibd_receive_output_segment(head.hash(), segment)
+			.unwrap());
+		assert!(dest
+			.pibd_missing_segments(16)
+			.contains(&(SegmentType::Bitmap, id)));
+		let bitmap = Bitmap::of(&[1, 2, 4]);
+		let segment = BitmapSegment::from_bitmap(id, head.output_mmr_size, &bitmap).unwrap();
+		let res = dest.pibd_receive_bitmap_segment(head.hash(), segment);
+		assert!(res.unwrap_err().is_bad_data());
+
+		// Segments are only served for blocks within the horizon.
+		let old = src.get_header_by_height(1).unwrap();
+		assert!(src.get_kernel_segment(old.hash(), id).is_err());
+
+		transfer_segments(&src, &dest);
+		assert!(dest.pibd_is_complete());
+		assert_eq!(dest.pibd_progress().map(|p| p.0 == p.1), Some(true));
+		dest.pibd_finalize(&NoStatus).unwrap();
+		assert_eq!(dest.pibd_block_hash(), None);
+
+		// We end up with the state of the source chain.
+		assert_eq!(dest.head().unwrap(), src.head().unwrap());
+		let roots = src.get_txhashset_roots();
+		let dest_roots = dest.get_txhashset_roots();
+		assert_eq!(dest_roots.output_root, roots.output_root);
+		assert_eq!(dest_roots.rproof_root, roots.rproof_root);
+		assert_eq!(dest_roots.kernel_root, roots.kernel_root);
+		assert!(dest
+			.is_unspent(&OutputIdentifier::from_output(&tx.outputs()[0]))
+			.is_ok());
+		dest.validate(false).unwrap();
+	}
+	clean_output_dir(".grin_pibd");
+}
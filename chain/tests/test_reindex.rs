@@ -0,0 +1,198 @@
+// Copyright 2018 The Grin Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+pub mod common;
+
+use self::chain::types::NoStatus;
+use self::chain::{Chain, ErrorKind, OrphanConfig};
+use self::core::core::hash::Hashed;
+use self::core::core::{Block, OutputIdentifier};
+use self::core::global::{self, ChainTypes};
+use self::core::libtx::build;
+use self::core::{consensus, pow};
+use self::keychain::{ExtKeychain, ExtKeychainPath, Keychain};
+use crate::common::{clean_output_dir, init_chain, mine_block, setup};
+use grin_chain as chain;
+use grin_core as core;
+use grin_keychain as keychain;
+use grin_util as util;
+
+fn setup_archive(dir_name: &str, genesis: Block) -> Chain {
+	init_chain(
+		dir_name,
+		ChainTypes::AutomatedTesting.params(),
+		genesis,
+		true,
+		OrphanConfig::default(),
+	)
+}
+
+#[test]
+fn reindex_archive_chain() {
+	util::init_test_logger();
+	global::set_mining_mode(ChainTypes::AutomatedTesting);
+	let chain_dir = ".grin_reindex";
+	clean_output_dir(chain_dir);
+
+	let keychain = ExtKeychain::from_random_seed(false).unwrap();
+	{
+		let chain = setup_archive(chain_dir, pow::mine_genesis_block().unwrap());
+
+		// Mine enough blocks for the first coinbase output to mature.
+		let mut coinbase = None;
+		for n in 1..5 {
+			let b = mine_block(&chain, &keychain, n, vec![]);
+			if n == 1 {
+				coinbase = Some(b.outputs()[0]);
+			}
+			chain.process_block(b, chain::Options::MINE).unwrap();
+		}
+
+		// Spend the first coinbase output so we have an input to rebuild.
+		let key_id1 = ExtKeychainPath::new(1, 1, 0, 0, 0).to_identifier();
+		let key_id30 = ExtKeychainPath::new(1, 30, 0, 0, 0).to_identifier();
+		let tx = build::transaction(
+			vec![
+				build::coinbase_input(consensus::REWARD, key_id1),
+				build::output(consensus::REWARD - 20000, key_id30),
+				build::with_fee(20000),
+			],
+			&keychain,
+		)
+		.unwrap();
+		let b = mine_block(&chain, &keychain, 5, vec![tx.clone()]);
+		chain.process_block(b, chain::Options::MINE).unwrap();
+
+		for n in 6..8 {
+			let b = mine_block(&chain, &keychain, n, vec![]);
+			chain.process_block(b, chain::Options::MINE).unwrap();
+		}
+
+		let head = chain.head().unwrap();
+		let roots = chain.get_txhashset_roots();
+		let sums = chain.get_block_sums(&head.last_block_h).unwrap();
+		let out_commit = tx.outputs()[0].commitment();
+		let out_pos = chain.get_output_pos(&out_commit).unwrap();
+		let excess = tx.kernels()[0].excess;
+		let kernel_pos = chain.store().get_kernel_pos(&excess).unwrap();
+
+		// Drop the output_pos and kernel_pos indexes, the reindex rebuilds them.
+		{
+			let store = chain.store();
+			let batch = store.batch().unwrap();
+			batch.clear_output_pos().unwrap();
+			batch.clear_kernel_pos().unwrap();
+			batch.commit().unwrap();
+		}
+		assert!(chain.get_output_pos(&out_commit).is_err());
+		assert!(chain.store().get_kernel_pos(&excess).is_err());
+
+		assert!(!chain.reindex_in_progress());
+		chain.reindex(&NoStatus).unwrap();
+		assert!(!chain.reindex_in_progress());
+		assert_eq!(chain.get_output_pos(&out_commit).unwrap(), out_pos);
+		assert_eq!(chain.store().get_kernel_pos(&excess).unwrap(), kernel_pos);
+
+		// We should end up exactly where we started.
+		assert_eq!(chain.head().unwrap(), head);
+		assert_eq!(chain.header_head().unwrap(), head);
+		let new_roots = chain.get_txhashset_roots();
+		assert_eq!(new_roots.output_root, roots.output_root);
+		assert_eq!(new_roots.rproof_root, roots.rproof_root);
+		assert_eq!(new_roots.kernel_root, roots.kernel_root);
+		assert_eq!(new_roots.header_root, roots.header_root);
+		let new_sums = chain.get_block_sums(&head.last_block_h).unwrap();
+		assert_eq!(new_sums.utxo_sum, sums.utxo_sum);
+		assert_eq!(new_sums.kernel_sum, sums.kernel_sum);
+		assert_eq!(
+			chain.get_header_by_height(head.height).unwrap().hash(),
+			head.last_block_h
+		);
+
+		// The rebuilt output_pos index and UTXO set reflect the spend.
+		assert!(chain
+			.is_unspent(&OutputIdentifier::from_output(&coinbase.unwrap()))
+			.is_err());
+		assert!(chain
+			.is_unspent(&OutputIdentifier::from_output(&tx.outputs()[0]))
+			.is_ok());
+
+		chain.validate(false).unwrap();
+
+		// And we can keep extending the chain afterwards.
+		let b = mine_block(&chain, &keychain, 8, vec![]);
+		chain.process_block(b, chain::Options::MINE).unwrap();
+		assert_eq!(chain.head().unwrap().height, head.height + 1);
+	}
+	clean_output_dir(chain_dir);
+}
+
+// Blocks and headers are refused while a reindex is pending, our chain state
+// being only partially rebuilt, and accepted again once it completes.
+#[test]
+fn no_sync_during_reindex() {
+	util::init_test_logger();
+	global::set_mining_mode(ChainTypes::AutomatedTesting);
+	let chain_dir = ".grin_reindex_pending";
+	clean_output_dir(chain_dir);
+
+	let keychain = ExtKeychain::from_random_seed(false).unwrap();
+	{
+		let chain = setup_archive(chain_dir, pow::mine_genesis_block().unwrap());
+		for n in 1..4 {
+			let b = mine_block(&chain, &keychain, n, vec![]);
+			chain.process_block(b, chain::Options::MINE).unwrap();
+		}
+
+		// A reindex interrupted right after it started.
+		let head = chain.head().unwrap();
+		{
+			let batch = chain.store().batch().unwrap();
+			batch.save_reindex_head(&head).unwrap();
+			batch.commit().unwrap();
+		}
+		assert!(chain.reindex_in_progress());
+
+		let b = mine_block(&chain, &keychain, 4, vec![]);
+		match chain.process_block(b.clone(), chain::Options::MINE) {
+			Err(e) => assert_eq!(e.kind(), ErrorKind::ReindexInProgress),
+			Ok(_) => panic!("block accepted during reindex"),
+		}
+		match chain.sync_block_headers(&[b.header.clone()], chain::Options::SYNC) {
+			Err(e) => assert_eq!(e.kind(), ErrorKind::ReindexInProgress),
+			Ok(_) => panic!("header accepted during reindex"),
+		}
+		assert_eq!(chain.head().unwrap(), head);
+
+		chain.reindex(&NoStatus).unwrap();
+		assert!(!chain.reindex_in_progress());
+		chain.process_block(b, chain::Options::MINE).unwrap();
+		assert_eq!(chain.head().unwrap().height, head.height + 1);
+	}
+	clean_output_dir(chain_dir);
+}
+
+#[test]
+fn reindex_requires_archive_mode() {
+	util::init_test_logger();
+	global::set_mining_mode(ChainTypes::AutomatedTesting);
+	let chain_dir = ".grin_reindex_pruned";
+	clean_output_dir(chain_dir);
+	{
+		let chain = setup(chain_dir, pow::mine_genesis_block().unwrap());
+		assert!(chain.reindex(&NoStatus).is_err());
+		assert!(!chain.reindex_in_progress());
+	}
+	clean_output_dir(chain_dir);
+}
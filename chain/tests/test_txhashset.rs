@@ -12,9 +12,11 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+pub mod common;
+
 use grin_chain as chain;
 use grin_core as core;
-
+use grin_keychain as keychain;
 use grin_util as util;
 
 use std::collections::HashSet;
@@ -25,20 +27,22 @@ use std::sync::Arc;
 
 use crate::chain::store::ChainStore;
 use crate::chain::txhashset;
+use crate::common::{clean_output_dir, mine_blocks, setup};
+use crate::core::core::verifier_cache::{LruVerifierCache, VerifierCache};
 use crate::core::core::BlockHeader;
-use crate::util::file;
+use crate::core::global::{self, ChainTypes};
+use crate::core::pow;
+use crate::core::ser;
+use crate::keychain::ExtKeychain;
+use crate::util::{file, RwLock};
 use grin_core::core::hash::Hashed;
 
-fn clean_output_dir(dir_name: &str) {
-	let _ = fs::remove_dir_all(dir_name);
-}
-
 #[test]
 fn test_unexpected_zip() {
 	let db_root = format!(".grin_txhashset_zip");
 	clean_output_dir(&db_root);
 	{
-		let chain_store = ChainStore::new(&db_root).unwrap();
+		let chain_store = ChainStore::new(&db_root, ChainTypes::AutomatedTesting.params()).unwrap();
 		let store = Arc::new(chain_store);
 		txhashset::TxHashSet::open(db_root.clone(), store.clone(), None).unwrap();
 		let head = BlockHeader::default();
@@ -82,6 +86,233 @@ fn test_unexpected_zip() {
 	clean_output_dir(&db_root);
 }
 
+#[test]
+fn test_archive_cache() {
+	let db_root = format!(".grin_txhashset_archives");
+	clean_output_dir(&db_root);
+	{
+		let mut cache = txhashset::ArchiveCache::open(&db_root).unwrap();
+		assert!(cache.entries().is_empty());
+
+		let mut headers = vec![];
+		for height in 1..4 {
+			let header = BlockHeader {
+				height: height * 10,
+				..BlockHeader::default()
+			};
+			let zip_path = Path::new(&db_root).join(format!("archive_{}.zip", height));
+			fs::write(&zip_path, vec![0; height as usize]).unwrap();
+			cache.add(&header, &zip_path).unwrap();
+			assert!(!zip_path.exists());
+			headers.push(header);
+		}
+
+		// Only the 2 most recent archives are kept.
+		let heights: Vec<_> = cache.entries().iter().map(|e| e.height).collect();
+		assert_eq!(heights, vec![20, 30]);
+		assert!(!cache.contains(&headers[0].hash()));
+		assert!(cache.get(&headers[0].hash()).unwrap().is_none());
+		let zip = cache.get(&headers[2].hash()).unwrap().unwrap();
+		assert_eq!(zip.metadata().unwrap().len(), 3);
+
+		// The manifest is read back, dropping the archives that don't match it
+		// and any leftover file.
+		let archives_dir = Path::new(&db_root).join(txhashset::TXHASHSET_ARCHIVES_SUBDIR);
+		let entry = &cache.entries()[0];
+		let zip_name = format!("txhashset_{}_{}.zip", entry.height, entry.hash.to_hex());
+		fs::write(archives_dir.join(zip_name), vec![0; 5]).unwrap();
+		fs::write(archives_dir.join("leftover.zip"), vec![0; 5]).unwrap();
+
+		let cache = txhashset::ArchiveCache::open(&db_root).unwrap();
+		let heights: Vec<_> = cache.entries().iter().map(|e| e.height).collect();
+		assert_eq!(heights, vec![30]);
+		assert!(cache.contains(&headers[2].hash()));
+		assert_eq!(fs::read_dir(&archives_dir).unwrap().count(), 2);
+	}
+	clean_output_dir(&db_root);
+}
+
+// The kernel view batch verifies the signatures of the kernels of each block
+// it's rewound to, as done when validating the kernel history of a txhashset
+// we receive.
+#[test]
+fn test_kernel_view_sigs() {
+	util::init_test_logger();
+	global::set_mining_mode(ChainTypes::AutomatedTesting);
+	let db_root = ".grin_kernel_view_sigs";
+	clean_output_dir(db_root);
+	{
+		let chain = setup(db_root, pow::mine_genesis_block().unwrap());
+		let keychain = ExtKeychain::from_random_seed(false).unwrap();
+		let blocks = mine_blocks(&chain, &keychain, 4);
+
+		let verifier_cache = Arc::new(RwLock::new(LruVerifierCache::new()));
+		let txhashset = chain.txhashset();
+		let txhashset = txhashset.read();
+		txhashset::rewindable_kernel_view(&txhashset, |view| {
+			for b in blocks.iter().rev() {
+				view.rewind(&b.header)?;
+				view.verify_kernel_sigs(verifier_cache.clone())?;
+			}
+			Ok(())
+		})
+		.unwrap();
+
+		// All the kernels were verified, and cached.
+		let kernels: Vec<_> = blocks.iter().flat_map(|b| b.kernels().to_vec()).collect();
+		assert_eq!(kernels.len(), 4);
+		assert!(verifier_cache
+			.write()
+			.filter_kernel_sig_unverified(&kernels)
+			.is_empty());
+	}
+	clean_output_dir(db_root);
+}
+
+// Archives are served at any block within the horizon, not only at the
+// archive height, built on demand.
+#[test]
+fn test_txhashset_read_any_block() {
+	util::init_test_logger();
+	global::set_mining_mode(ChainTypes::AutomatedTesting);
+	let db_root = ".grin_txhashset_read";
+	clean_output_dir(db_root);
+	{
+		let chain = setup(db_root, pow::mine_genesis_block().unwrap());
+		let keychain = ExtKeychain::from_random_seed(false).unwrap();
+		let blocks = mine_blocks(&chain, &keychain, 4);
+
+		let header = &blocks[2].header;
+		assert_ne!(
+			chain.txhashset_archive_header().unwrap().hash(),
+			header.hash()
+		);
+		let (output_index, kernel_index, zip) = chain.txhashset_read(header.hash()).unwrap();
+		assert_eq!(output_index, header.output_mmr_size);
+		assert_eq!(kernel_index, header.kernel_mmr_size);
+		assert!(zip.metadata().unwrap().len() > 0);
+
+		assert!(chain.txhashset_read(BlockHeader::default().hash()).is_err());
+	}
+	clean_output_dir(db_root);
+}
+
+#[test]
+fn test_archive_manifest() {
+	let dir_name = ".grin_txhashset_manifest";
+	clean_output_dir(dir_name);
+	let dir = Path::new(dir_name);
+	for subdir in &["output", "rangeproof", "kernel"] {
+		fs::create_dir_all(dir.join(subdir)).unwrap();
+		fs::write(dir.join(subdir).join("pmmr_hash.bin"), subdir.as_bytes()).unwrap();
+		fs::write(dir.join(subdir).join("pmmr_data.bin"), vec![1; 100]).unwrap();
+	}
+
+	let manifest = txhashset::Manifest::build(dir).unwrap();
+	assert_eq!(manifest.entries.len(), 6);
+	manifest.save(dir).unwrap();
+	let manifest = txhashset::Manifest::load(dir).unwrap().unwrap();
+	assert!(manifest.verify(dir).is_ok());
+
+	let bad_file = |res: Result<(), chain::Error>| match res.unwrap_err().kind() {
+		chain::ErrorKind::InvalidTxHashSetFile(path, _) => path,
+		e => panic!("unexpected error {:?}", e),
+	};
+
+	// Same size, different content.
+	fs::write(dir.join("kernel").join("pmmr_data.bin"), vec![2; 100]).unwrap();
+	assert_eq!(bad_file(manifest.verify(dir)), "kernel/pmmr_data.bin");
+	fs::write(dir.join("kernel").join("pmmr_data.bin"), vec![1; 100]).unwrap();
+
+	// Truncated.
+	fs::write(dir.join("output").join("pmmr_data.bin"), vec![1; 99]).unwrap();
+	assert_eq!(bad_file(manifest.verify(dir)), "output/pmmr_data.bin");
+	fs::write(dir.join("output").join("pmmr_data.bin"), vec![1; 100]).unwrap();
+
+	// Missing and unlisted files.
+	fs::rename(
+		dir.join("rangeproof").join("pmmr_hash.bin"),
+		dir.join("rangeproof").join("pmmr_prun.bin"),
+	)
+	.unwrap();
+	assert_eq!(bad_file(manifest.verify(dir)), "rangeproof/pmmr_hash.bin");
+	fs::rename(
+		dir.join("rangeproof").join("pmmr_prun.bin"),
+		dir.join("rangeproof").join("pmmr_hash.bin"),
+	)
+	.unwrap();
+	fs::write(dir.join("rangeproof").join("pmmr_prun.bin"), vec![]).unwrap();
+	assert_eq!(bad_file(manifest.verify(dir)), "rangeproof/pmmr_prun.bin");
+
+	// A corrupted manifest is reported as such.
+	let manifest_path = dir.join(txhashset::MANIFEST_FILE);
+	let mut data = fs::read(&manifest_path).unwrap();
+	let last = data.len() - 40;
+	data[last] ^= 1;
+	fs::write(&manifest_path, data).unwrap();
+	match txhashset::Manifest::load(dir).unwrap_err().kind() {
+		chain::ErrorKind::InvalidTxHashSetFile(path, _) => {
+			assert_eq!(path, txhashset::MANIFEST_FILE)
+		}
+		e => panic!("unexpected error {:?}", e),
+	}
+
+	clean_output_dir(dir_name);
+}
+
+#[test]
+fn test_layout_version() {
+	let db_root = format!(".grin_txhashset_version");
+	clean_output_dir(&db_root);
+	{
+		let store =
+			Arc::new(ChainStore::new(&db_root, ChainTypes::AutomatedTesting.params()).unwrap());
+		let root = Path::new(&db_root);
+		let version_path = root
+			.join("txhashset")
+			.join(txhashset::TXHASHSET_VERSION_FILE);
+
+		// A new txhashset is created at our current layout version.
+		let mut txhashset =
+			txhashset::TxHashSet::open(db_root.clone(), store.clone(), None).unwrap();
+		txhashset.release_backend_files();
+		assert_eq!(
+			txhashset::layout_version(root).unwrap(),
+			txhashset::TXHASHSET_VERSION
+		);
+
+		// One written before the layout was versioned is brought up to it.
+		fs::remove_file(&version_path).unwrap();
+		assert_eq!(txhashset::layout_version(root).unwrap(), 0);
+		let mut txhashset =
+			txhashset::TxHashSet::open(db_root.clone(), store.clone(), None).unwrap();
+		txhashset.release_backend_files();
+		assert_eq!(
+			txhashset::layout_version(root).unwrap(),
+			txhashset::TXHASHSET_VERSION
+		);
+
+		// One written with a newer layout is left alone.
+		let newer = txhashset::TXHASHSET_VERSION + 1;
+		fs::write(
+			&version_path,
+			ser::ser_vec(&newer, ser::ProtocolVersion::local()).unwrap(),
+		)
+		.unwrap();
+		match txhashset::TxHashSet::open(db_root.clone(), store.clone(), None) {
+			Err(e) => match e.kind() {
+				chain::ErrorKind::TxHashSetVersion(found, supported) => {
+					assert_eq!((found, supported), (newer, txhashset::TXHASHSET_VERSION))
+				}
+				e => panic!("unexpected error {:?}", e),
+			},
+			Ok(_) => panic!("opened a txhashset with a newer layout"),
+		}
+		assert_eq!(txhashset::layout_version(root).unwrap(), newer);
+	}
+	clean_output_dir(&db_root);
+}
+
 fn write_file(db_root: String) {
 	OpenOptions::new()
 		.create(true)
@@ -139,6 +370,8 @@ fn txhashset_contains_expected_files(dirname: String, path_buf: PathBuf) -> bool
 		"kernel".to_string(),
 		"pmmr_hash.bin".to_string(),
 		"pmmr_data.bin".to_string(),
+		txhashset::MANIFEST_FILE.to_string(),
+		txhashset::TXHASHSET_VERSION_FILE.to_string(),
 	];
 	let expected_files_hashset = HashSet::from_iter(expected_files.iter().cloned());
 	let intersection: HashSet<_> = zip_files_hashset
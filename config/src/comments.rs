@@ -80,6 +80,17 @@ fn comments() -> HashMap<String, String> {
 #UserTesting - For regular user testing (cuckoo 16)
 #Floonet - For the long term floonet test network
 #Mainnet - For mainnet
+#Custom - For a custom local network, see custom_chain_file
+"
+		.to_string(),
+	);
+
+	retval.insert(
+		"custom_chain_file".to_string(),
+		"
+#Path of the chain file of a custom network (chain_type = \"Custom\"), with its
+#genesis block, magic bytes, coinbase maturity, block time, initial difficulty,
+#edge bits and seeds. \"grin server genesis\" builds and solves its genesis.
 "
 		.to_string(),
 	);
@@ -103,6 +114,15 @@ fn comments() -> HashMap<String, String> {
 		.to_string(),
 	);
 
+	retval.insert(
+		"header_only".to_string(),
+		"
+#run a header-only node, only following the header chain (no txhashset
+#nor full blocks) and checking outputs with merkle proofs from full nodes
+"
+		.to_string(),
+	);
+
 	retval.insert(
 		"skip_sync_wait".to_string(),
 		"
@@ -177,6 +197,10 @@ fn comments() -> HashMap<String, String> {
 		"#test miner wallet URL (burns if this doesn't exist)
 #test_miner_wallet_url = \"http://127.0.0.1:3415\"
 
+#test miner PoW solver, either \"Reference\" (default) or the faster but
+#memory heavy multi-threaded \"Mean\"
+#test_miner_solver = \"Mean\"
+
 #########################################
 ### SERVER P2P CONFIGURATION          ###
 #########################################
@@ -235,12 +259,59 @@ fn comments() -> HashMap<String, String> {
 #until we get to at least this number
 #peer_min_preferred_count = 8
 
-# 15 = Bit flags for FULL_NODE
+#maximum number of txhashset archives uploaded to syncing peers at the same time
+#txhashset_max_uploads = 4
+
+# 511 = Bit flags for FULL_NODE
 #This structure needs to be changed internally, to make it more configurable
 
 # A preferred dandelion_peer, mainly used for testing dandelion
 # dandelion_peer = \"10.0.0.1:13144\"
 
+"
+		.to_string(),
+	);
+
+	retval.insert(
+		"[server.orphan_config]".to_string(),
+		"
+#########################################
+### ORPHAN BLOCKS CONFIGURATION       ###
+#########################################
+"
+		.to_string(),
+	);
+
+	retval.insert(
+		"max_orphans".to_string(),
+		"
+#maximum number of orphan blocks (received before their parent) kept around
+"
+		.to_string(),
+	);
+
+	retval.insert(
+		"max_orphan_age_secs".to_string(),
+		"
+#orphans older than this (in seconds) are the first evicted when full
+"
+		.to_string(),
+	);
+
+	retval.insert(
+		"orphan_eviction".to_string(),
+		"
+#which orphans to evict next when full, either the furthest ahead of our
+#chain (\"HighestFirst\") or the first received (\"OldestFirst\")
+"
+		.to_string(),
+	);
+
+	retval.insert(
+		"persist_orphans".to_string(),
+		"
+#whether to keep the orphans in the chain db, so we don't fetch them again
+#after a restart (during sync)
 "
 		.to_string(),
 	);
@@ -282,7 +353,8 @@ fn comments() -> HashMap<String, String> {
 	retval.insert(
 		"mineable_max_weight".to_string(),
 		"
-#maximum total weight of transactions that can get selected to build a block
+#maximum total weight of transactions that can get selected to build a block,
+#defaults to the max block weight of the chain
 "
 		.to_string(),
 	);
@@ -194,6 +194,9 @@ impl GlobalConfig {
 			global::ChainTypes::AutomatedTesting => {
 				panic!("Can't run automated testing directly");
 			}
+			global::ChainTypes::Custom => {
+				defaults.custom_chain_file = Some("grin-custom-chain.toml".to_owned());
+			}
 		}
 		defaults_conf
 	}
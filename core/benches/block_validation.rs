@@ -0,0 +1,117 @@
+// Copyright 2018 The Grin Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Benchmark full validation of a block close to the max block weight,
+//! on a single thread vs. across the full thread pool.
+//! Run with `cargo bench -p grin_core --bench block_validation`.
+
+#[macro_use]
+extern crate criterion;
+
+use self::core::consensus::{BLOCK_INPUT_WEIGHT, BLOCK_KERNEL_WEIGHT, BLOCK_OUTPUT_WEIGHT};
+use self::core::core::verifier_cache::{LruVerifierCache, VerifierCache};
+use self::core::core::{Block, BlockHeader, Transaction};
+use self::core::global::{self, ChainTypes};
+use self::core::libtx::build::{self, input, output, with_fee};
+use self::core::libtx::reward;
+use self::core::pow::Difficulty;
+use self::keychain::{BlindingFactor, ExtKeychain, Keychain};
+use self::util::RwLock;
+use criterion::Criterion;
+use grin_core as core;
+use grin_keychain as keychain;
+use grin_util as util;
+use std::sync::Arc;
+
+fn verifier_cache() -> Arc<RwLock<dyn VerifierCache>> {
+	Arc::new(RwLock::new(LruVerifierCache::new()))
+}
+
+// Build a block filled with 1 input, 1 output txs up to (just under) the max
+// block weight. Building all the rangeproofs takes a while.
+fn full_block() -> Block {
+	global::set_mining_mode(ChainTypes::Mainnet);
+
+	let keychain = ExtKeychain::from_random_seed(false).unwrap();
+	let tx_weight = BLOCK_INPUT_WEIGHT + BLOCK_OUTPUT_WEIGHT + BLOCK_KERNEL_WEIGHT;
+	let coinbase_weight = BLOCK_OUTPUT_WEIGHT + BLOCK_KERNEL_WEIGHT;
+	let num_txs = (global::max_block_weight() - coinbase_weight) / tx_weight;
+
+	let txs: Vec<Transaction> = (0..num_txs as u32)
+		.map(|n| {
+			build::transaction(
+				vec![
+					input(1_000, ExtKeychain::derive_key_id(1, n, 0, 0, 0)),
+					output(990, ExtKeychain::derive_key_id(2, n, 0, 0, 0)),
+					with_fee(10),
+				],
+				&keychain,
+			)
+			.unwrap()
+		})
+		.collect();
+
+	let fees = txs.iter().map(|tx| tx.fee()).sum();
+	let key_id = ExtKeychain::derive_key_id(3, 0, 0, 0, 0);
+	let reward_output = reward::output(&keychain, &key_id, fees, false).unwrap();
+	Block::new(
+		&BlockHeader::default(),
+		txs,
+		Difficulty::min(),
+		reward_output,
+	)
+	.unwrap()
+}
+
+fn bench_block_validation(c: &mut Criterion) {
+	let block = Arc::new(full_block());
+	let single_thread = rayon::ThreadPoolBuilder::new()
+		.num_threads(1)
+		.build()
+		.unwrap();
+
+	// We use a fresh verifier cache each time so nothing is skipped.
+	let b = block.clone();
+	c.bench_function("validate full block (single thread)", move |bencher| {
+		bencher.iter(|| {
+			single_thread.install(|| {
+				b.validate(
+					&global::chain_params(),
+					&BlindingFactor::zero(),
+					verifier_cache(),
+				)
+				.unwrap()
+			})
+		})
+	});
+
+	let b = block.clone();
+	c.bench_function("validate full block (thread pool)", move |bencher| {
+		bencher.iter(|| {
+			b.validate(
+				&global::chain_params(),
+				&BlindingFactor::zero(),
+				verifier_cache(),
+			)
+			.unwrap()
+		})
+	});
+}
+
+criterion_group! {
+	name = benches;
+	config = Criterion::default().sample_size(10);
+	targets = bench_block_validation
+}
+criterion_main!(benches);
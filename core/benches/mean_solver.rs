@@ -0,0 +1,97 @@
+// Copyright 2018 The Grin Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Benchmark the mean Cuckatoo and Cuckaroo solvers on edge_bits 19 to 29,
+//! on a single thread vs. across the full thread pool. Solutions found are
+//! checked against the reference verifier.
+//! Run with `cargo bench -p grin_core --bench mean_solver`, the larger graphs
+//! take a while (and a few GB of memory), filter them with e.g.
+//! `cargo bench -p grin_core --bench mean_solver -- 'cuckatoo2[0-3]'`.
+
+#[macro_use]
+extern crate criterion;
+
+use self::core::global::PoWContextTypes;
+use self::core::pow::{new_cuckaroo_ctx, new_cuckatoo_ctx, new_mean_ctx, PoWContext};
+use criterion::Criterion;
+use grin_core as core;
+
+const PROOF_SIZE: usize = 42;
+
+// Solves a graph with the mean solver and checks the solutions it finds with
+// a fresh reference verifier.
+fn solve(variant: &PoWContextTypes, edge_bits: u8, nonce: u32) {
+	let header = vec![0u8; 80];
+	let mut ctx = new_mean_ctx::<u64>(variant.clone(), edge_bits, PROOF_SIZE, 10).unwrap();
+	ctx.set_header_nonce(header.clone(), Some(nonce), true)
+		.unwrap();
+	let sols = match ctx.find_cycles() {
+		Ok(sols) => sols,
+		Err(_) => return,
+	};
+
+	let mut verifier = match variant {
+		PoWContextTypes::Cuckaroo => new_cuckaroo_ctx::<u64>(edge_bits, PROOF_SIZE).unwrap(),
+		_ => new_cuckatoo_ctx::<u64>(edge_bits, PROOF_SIZE, 10).unwrap(),
+	};
+	verifier
+		.set_header_nonce(header, Some(nonce), false)
+		.unwrap();
+	for sol in sols {
+		assert_eq!(sol.edge_bits, edge_bits);
+		verifier.verify(&sol).unwrap();
+	}
+}
+
+fn bench_mean_solver(c: &mut Criterion) {
+	for variant in &[PoWContextTypes::Cuckatoo, PoWContextTypes::Cuckaroo] {
+		let name = format!("{:?}", variant).to_lowercase();
+		for edge_bits in 19..30 {
+			let single_thread = rayon::ThreadPoolBuilder::new()
+				.num_threads(1)
+				.build()
+				.unwrap();
+			let v = variant.clone();
+			c.bench_function(
+				&format!("mean {}{} (single thread)", name, edge_bits),
+				move |bencher| {
+					let mut nonce = 0;
+					bencher.iter(|| {
+						nonce += 1;
+						single_thread.install(|| solve(&v, edge_bits, nonce))
+					})
+				},
+			);
+
+			let v = variant.clone();
+			c.bench_function(
+				&format!("mean {}{} (thread pool)", name, edge_bits),
+				move |bencher| {
+					let mut nonce = 0;
+					bencher.iter(|| {
+						nonce += 1;
+						solve(&v, edge_bits, nonce)
+					})
+				},
+			);
+		}
+	}
+}
+
+criterion_group! {
+	name = benches;
+	config = Criterion::default().sample_size(10);
+	targets = bench_mean_solver
+}
+criterion_main!(benches);
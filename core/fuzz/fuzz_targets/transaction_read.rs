@@ -8,5 +8,5 @@ use grin_core::ser;
 
 fuzz_target!(|data: &[u8]| {
 	let mut d = data.clone();
-	let _t: Result<Transaction, ser::Error> = ser::deserialize(&mut d);
+	let _t: Result<Transaction, ser::Error> = ser::deserialize_default(&mut d);
 });
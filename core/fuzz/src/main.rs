@@ -25,7 +25,7 @@ fn generate<W: ser::Writeable>(target: &str, obj: W) -> Result<(), ser::Error> {
 	if !pattern_path.exists() {
 		let mut file = File::create(&pattern_path)
 			.map_err(|e| ser::Error::IOErr("can't create a pattern file".to_owned(), e.kind()))?;
-		ser::serialize(&mut file, &obj)
+		ser::serialize_default(&mut file, &obj)
 	} else {
 		Ok(())
 	}
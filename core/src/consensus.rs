@@ -20,7 +20,7 @@
 
 use std::cmp::{max, min};
 
-use crate::global;
+use crate::global::{self, ChainParams};
 use crate::pow::Difficulty;
 
 /// A grin is divisible to 10^9, following the SI prefixes
@@ -164,16 +164,23 @@ pub const AR_SCALE_DAMP_FACTOR: u64 = 13;
 /// Compute weight of a graph as number of siphash bits defining the graph
 /// Must be made dependent on height to phase out smaller size over the years
 /// This can wait until end of 2019 at latest
+/// Uses the process wide chain parameters, for tests and tools only.
 pub fn graph_weight(height: u64, edge_bits: u8) -> u64 {
+	graph_weight_with_params(&global::chain_params(), height, edge_bits)
+}
+
+/// Compute weight of a graph (see `graph_weight`) with explicit chain
+/// parameters
+pub fn graph_weight_with_params(params: &ChainParams, height: u64, edge_bits: u8) -> u64 {
 	let mut xpr_edge_bits = edge_bits as u64;
 
-	let bits_over_min = edge_bits.saturating_sub(global::min_edge_bits());
+	let bits_over_min = edge_bits.saturating_sub(params.min_edge_bits);
 	let expiry_height = (1 << bits_over_min) * YEAR_HEIGHT;
 	if height >= expiry_height {
 		xpr_edge_bits = xpr_edge_bits.saturating_sub(1 + (height - expiry_height) / WEEK_HEIGHT);
 	}
 
-	(2 << (edge_bits - global::base_edge_bits()) as u64) * xpr_edge_bits
+	(2 << (edge_bits - params.base_edge_bits) as u64) * xpr_edge_bits
 }
 
 /// Minimum difficulty, enforced in diff retargetting
@@ -271,7 +278,18 @@ pub fn clamp(actual: u64, goal: u64, clamp_factor: u64) -> u64 {
 ///
 /// The secondary proof-of-work factor is calculated along the same lines, as
 /// an adjustment on the deviation against the ideal value.
+///
+/// Uses the process wide chain parameters, for tests and tools only.
 pub fn next_difficulty<T>(height: u64, cursor: T) -> HeaderInfo
+where
+	T: IntoIterator<Item = HeaderInfo>,
+{
+	next_difficulty_with_params(&global::chain_params(), height, cursor)
+}
+
+/// Computes the proof-of-work difficulty that the next block should comply
+/// with (see `next_difficulty`), with explicit chain parameters.
+pub fn next_difficulty_with_params<T>(params: &ChainParams, height: u64, cursor: T) -> HeaderInfo
 where
 	T: IntoIterator<Item = HeaderInfo>,
 {
@@ -279,7 +297,7 @@ where
 	// to latest, and pad with simulated pre-genesis data to allow earlier
 	// adjustment if there isn't enough window data length will be
 	// DIFFICULTY_ADJUST_WINDOW + 1 (for initial block time bound)
-	let diff_data = global::difficulty_data_to_vector(cursor);
+	let diff_data = global::difficulty_data_to_vector_with_params(params, cursor);
 
 	// First, get the ratio of secondary PoW vs primary, skipping initial header
 	let sec_pow_scaling = secondary_pow_scaling(height, &diff_data[1..]);
@@ -296,13 +314,14 @@ where
 		.sum();
 
 	// adjust time delta toward goal subject to dampening and clamping
+	let block_time_window = DIFFICULTY_ADJUST_WINDOW * params.block_time_sec;
 	let adj_ts = clamp(
-		damp(ts_delta, BLOCK_TIME_WINDOW, DIFFICULTY_DAMP_FACTOR),
-		BLOCK_TIME_WINDOW,
+		damp(ts_delta, block_time_window, DIFFICULTY_DAMP_FACTOR),
+		block_time_window,
 		CLAMP_FACTOR,
 	);
 	// minimum difficulty avoids getting stuck due to dampening
-	let difficulty = max(MIN_DIFFICULTY, diff_sum * BLOCK_TIME_SEC / adj_ts);
+	let difficulty = max(MIN_DIFFICULTY, diff_sum * params.block_time_sec / adj_ts);
 
 	HeaderInfo::from_diff_scaling(Difficulty::from_num(difficulty), sec_pow_scaling)
 }
@@ -15,9 +15,12 @@
 //! Core types
 
 pub mod block;
+pub mod block_filter;
 pub mod block_sums;
+pub mod chain_proof;
 pub mod committed;
 pub mod compact_block;
+pub mod compact_transaction;
 pub mod hash;
 pub mod id;
 pub mod merkle_proof;
@@ -33,6 +36,7 @@ pub use self::block::*;
 pub use self::block_sums::*;
 pub use self::committed::Committed;
 pub use self::compact_block::*;
+pub use self::compact_transaction::*;
 pub use self::id::ShortId;
 pub use self::transaction::*;
 
@@ -30,7 +30,7 @@ use crate::core::verifier_cache::VerifierCache;
 use crate::core::{
 	transaction, Commitment, Input, Output, Transaction, TransactionBody, TxKernel, Weighting,
 };
-use crate::global;
+use crate::global::{self, ChainParams};
 use crate::keychain::{self, BlindingFactor};
 use crate::pow::{Difficulty, Proof, ProofOfWork};
 use crate::ser::{self, FixedLength, PMMRable, Readable, Reader, Writeable, Writer};
@@ -307,7 +307,7 @@ impl BlockHeader {
 	pub fn pre_pow(&self) -> Vec<u8> {
 		let mut header_buf = vec![];
 		{
-			let mut writer = ser::BinWriter::new(&mut header_buf);
+			let mut writer = ser::BinWriter::default(&mut header_buf);
 			self.write_pre_pow(&mut writer).unwrap();
 			self.pow.write_pre_pow(self.version, &mut writer).unwrap();
 			writer.write_u64(self.pow.nonce).unwrap();
@@ -388,7 +388,8 @@ impl Readable for Block {
 		// Treat any validation issues as data corruption.
 		// An example of this would be reading a block
 		// that exceeded the allowed number of inputs.
-		body.validate_read(Weighting::AsBlock)
+		let params = reader.chain_params().ok_or(ser::Error::NoChainParams)?;
+		body.validate_read(params, Weighting::AsBlock)
 			.map_err(|_| ser::Error::CorruptedData)?;
 
 		Ok(Block { header, body })
@@ -613,8 +614,8 @@ impl Block {
 	/// * kernel signature verification (on the body)
 	/// * coinbase sum verification
 	/// * kernel sum verification
-	pub fn validate_read(&self) -> Result<(), Error> {
-		self.body.validate_read(Weighting::AsBlock)?;
+	pub fn validate_read(&self, params: &ChainParams) -> Result<(), Error> {
+		self.body.validate_read(params, Weighting::AsBlock)?;
 		self.verify_kernel_lock_heights()?;
 		Ok(())
 	}
@@ -638,14 +639,27 @@ impl Block {
 
 	/// Validates all the elements in a block that can be checked without
 	/// additional data. Includes commitment sums and kernels, Merkle
-	/// trees, reward, etc.
+	/// trees, reward, etc. The block weight is checked against the provided
+	/// chain parameters, the body being verified on the rayon thread pool.
 	pub fn validate(
 		&self,
+		params: &ChainParams,
 		prev_kernel_offset: &BlindingFactor,
 		verifier: Arc<RwLock<dyn VerifierCache>>,
 	) -> Result<Commitment, Error> {
-		self.body.validate(Weighting::AsBlock, verifier)?;
+		// Verify the body (rangeproofs and kernel signatures) in parallel with
+		// the kernel sums, the two are independent of each other.
+		let (body_res, sums_res) = rayon::join(
+			|| self.body.validate(params, Weighting::AsBlock, verifier),
+			|| self.verify_sums(prev_kernel_offset),
+		);
+		body_res?;
+		sums_res
+	}
 
+	// Verify the coinbase and kernel lock heights and that the block sums
+	// correctly, returning the kernel sum.
+	fn verify_sums(&self, prev_kernel_offset: &BlindingFactor) -> Result<Commitment, Error> {
 		self.verify_kernel_lock_heights()?;
 		self.verify_coinbase()?;
 
@@ -0,0 +1,249 @@
+// Copyright 2018 The Grin Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Compact probabilistic block filters (Golomb-coded sets) over the output
+//! commitments a block creates and the ones its inputs spend.
+//!
+//! Wallets test the commitments they know about against the filter of each
+//! block to find the blocks that may be relevant to them, without having to
+//! download every block or page through the whole UTXO set. A filter never
+//! misses a commitment of its block, and false positives happen at a rate of
+//! about 1 in 2^19 per tested commitment (parameters as in BIP158).
+//!
+//! Filters aren't committed to in the block header: one can only be checked
+//! by building it again from the block itself.
+
+use crate::core::hash::{Hash, Hashed};
+use crate::core::Block;
+use crate::ser::{self, Readable, Reader, Writeable, Writer};
+use crate::util::secp::pedersen::Commitment;
+
+/// Number of bits of the remainder of the Golomb-Rice coding.
+pub const FILTER_P: u8 = 19;
+
+/// Inverse of the false positive rate of the filters.
+pub const FILTER_M: u64 = 784_931;
+
+/// Largest filter we're willing to read, comfortably above the filter of a
+/// block full of inputs.
+const MAX_FILTER_SIZE: u64 = 1_000_000;
+
+// Reads are limited in size by the underlying reader, so large filters are
+// read in chunks.
+const READ_CHUNK_SIZE: u64 = 100_000;
+
+/// A Golomb-coded set over the output commitments created and spent by a
+/// block. Hashing is keyed by the block hash, so false positives differ from
+/// block to block.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BlockFilter {
+	/// Hash of the block the filter is for
+	pub block_hash: Hash,
+	/// Number of commitments in the filter
+	pub n: u64,
+	/// The Golomb-Rice coded, sorted hashes of the commitments
+	pub data: Vec<u8>,
+}
+
+impl Writeable for BlockFilter {
+	fn write<W: Writer>(&self, writer: &mut W) -> Result<(), ser::Error> {
+		self.block_hash.write(writer)?;
+		writer.write_u64(self.n)?;
+		writer.write_bytes(&self.data)
+	}
+}
+
+impl Readable for BlockFilter {
+	fn read(reader: &mut dyn Reader) -> Result<BlockFilter, ser::Error> {
+		let block_hash = Hash::read(reader)?;
+		let n = reader.read_u64()?;
+		let len = reader.read_u64()?;
+		if len > MAX_FILTER_SIZE {
+			return Err(ser::Error::TooLargeReadErr);
+		}
+		let mut data = Vec::with_capacity(len as usize);
+		let mut remaining = len;
+		while remaining > 0 {
+			let chunk = remaining.min(READ_CHUNK_SIZE);
+			data.extend(reader.read_fixed_bytes(chunk as usize)?);
+			remaining -= chunk;
+		}
+		Ok(BlockFilter {
+			block_hash,
+			n,
+			data,
+		})
+	}
+}
+
+impl BlockFilter {
+	/// Builds the filter of the provided commitments for the block with the
+	/// provided hash.
+	pub fn new(block_hash: Hash, commits: &[Commitment]) -> BlockFilter {
+		let mut items: Vec<_> = commits.iter().map(|c| c.0.to_vec()).collect();
+		items.sort_unstable();
+		items.dedup();
+
+		let n = items.len() as u64;
+		let mut values: Vec<_> = items
+			.into_iter()
+			.map(|item| hash_to_range(&block_hash, item, n))
+			.collect();
+		values.sort_unstable();
+
+		let mut writer = BitWriter::default();
+		let mut last = 0;
+		for value in values {
+			let delta = value - last;
+			writer.write_unary(delta >> FILTER_P);
+			writer.write_bits(delta, FILTER_P);
+			last = value;
+		}
+		BlockFilter {
+			block_hash,
+			n,
+			data: writer.bytes,
+		}
+	}
+
+	/// Builds the filter of a block, over the commitments of its outputs and
+	/// inputs.
+	pub fn from_block(block: &Block) -> BlockFilter {
+		let commits: Vec<_> = block
+			.outputs()
+			.iter()
+			.map(|x| x.commitment())
+			.chain(block.inputs().iter().map(|x| x.commitment()))
+			.collect();
+		BlockFilter::new(block.hash(), &commits)
+	}
+
+	/// Whether the provided commitment may have been created or spent by the
+	/// block.
+	pub fn matches(&self, commit: &Commitment) -> bool {
+		self.matches_any(&[*commit])
+	}
+
+	/// Whether any of the provided commitments may have been created or spent
+	/// by the block. A malformed filter matches everything, so the block gets
+	/// looked at rather than missed.
+	pub fn matches_any(&self, commits: &[Commitment]) -> bool {
+		if self.n == 0 || commits.is_empty() {
+			return false;
+		}
+		let mut queries: Vec<_> = commits
+			.iter()
+			.map(|c| hash_to_range(&self.block_hash, c.0.to_vec(), self.n))
+			.collect();
+		queries.sort_unstable();
+
+		let mut reader = BitReader::new(&self.data);
+		let mut queries = queries.into_iter().peekable();
+		let mut value = 0;
+		for _ in 0..self.n {
+			let delta = match (reader.read_unary(), reader.read_bits(FILTER_P)) {
+				(Some(q), Some(r)) => (q << FILTER_P) + r,
+				_ => return true,
+			};
+			value += delta;
+			while let Some(query) = queries.peek() {
+				if *query == value {
+					return true;
+				} else if *query < value {
+					queries.next();
+				} else {
+					break;
+				}
+			}
+			if queries.peek().is_none() {
+				return false;
+			}
+		}
+		false
+	}
+}
+
+// Maps a commitment uniformly to [0, n * FILTER_M), keyed by the block hash.
+fn hash_to_range(block_hash: &Hash, item: Vec<u8>, n: u64) -> u64 {
+	let h = (*block_hash, item).hash().to_u64();
+	((h as u128 * (n * FILTER_M) as u128) >> 64) as u64
+}
+
+#[derive(Default)]
+struct BitWriter {
+	bytes: Vec<u8>,
+	// number of bits used in the last byte
+	used: u8,
+}
+
+impl BitWriter {
+	fn write_bit(&mut self, bit: bool) {
+		if self.used == 0 {
+			self.bytes.push(0);
+		}
+		if bit {
+			let last = self.bytes.len() - 1;
+			self.bytes[last] |= 0x80 >> self.used;
+		}
+		self.used = (self.used + 1) % 8;
+	}
+
+	// Writes the n lowest bits of the value, most significant first.
+	fn write_bits(&mut self, value: u64, n: u8) {
+		for i in (0..n).rev() {
+			self.write_bit((value >> i) & 1 == 1);
+		}
+	}
+
+	fn write_unary(&mut self, value: u64) {
+		for _ in 0..value {
+			self.write_bit(true);
+		}
+		self.write_bit(false);
+	}
+}
+
+struct BitReader<'a> {
+	bytes: &'a [u8],
+	pos: usize,
+}
+
+impl<'a> BitReader<'a> {
+	fn new(bytes: &'a [u8]) -> BitReader<'a> {
+		BitReader { bytes, pos: 0 }
+	}
+
+	fn read_bit(&mut self) -> Option<bool> {
+		let byte = self.bytes.get(self.pos / 8)?;
+		let bit = byte & (0x80 >> (self.pos % 8)) != 0;
+		self.pos += 1;
+		Some(bit)
+	}
+
+	fn read_bits(&mut self, n: u8) -> Option<u64> {
+		let mut value = 0;
+		for _ in 0..n {
+			value = (value << 1) | self.read_bit()? as u64;
+		}
+		Some(value)
+	}
+
+	fn read_unary(&mut self) -> Option<u64> {
+		let mut value = 0;
+		while self.read_bit()? {
+			value += 1;
+		}
+		Some(value)
+	}
+}
@@ -0,0 +1,227 @@
+// Copyright 2018 The Grin Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Succinct (FlyClient-style) proofs of the work behind a chain tip.
+//!
+//! A proof samples headers weighted by cumulative difficulty, more densely
+//! towards the tip, each with a Merkle proof against the root of the header
+//! MMR the tip commits to (its `prev_root`). The samples are derived from the
+//! tip hash, so a prover can't pick them. Checking a proof takes a number of
+//! headers logarithmic in the length of the chain.
+
+use crate::core::hash::{Hash, Hashed};
+use crate::core::merkle_proof::MerkleProof;
+use crate::core::pmmr;
+use crate::core::BlockHeader;
+use crate::global::ChainParams;
+use crate::pow;
+use crate::ser::{self, Readable, Reader, Writeable, Writer};
+use std::cmp::min;
+
+/// Number of headers sampled in each layer of the chain work. Layer i covers
+/// the most recent 1/2^i of the work, and there are log2(height) + 1 layers.
+pub const SAMPLES_PER_LAYER: u64 = 8;
+
+/// Chain proof errors.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ChainProofError {
+	/// The tip is the genesis block, there is no work to prove.
+	EmptyChain,
+	/// The first header of the proof is not the expected genesis.
+	GenesisMismatch,
+	/// The last header of the proof is not the one right before the tip.
+	TipMismatch,
+	/// The headers of the proof are not ordered by height and work.
+	InvalidOrder,
+	/// The Merkle proof of the header at this height is invalid.
+	MerkleProof(u64),
+	/// The proof of work of the header at this height is invalid.
+	InvalidPoW(u64),
+	/// No header of the proof covers this amount of cumulative work.
+	MissingSample(u64),
+}
+
+/// A header sampled by a chain proof, with its Merkle proof against the root
+/// of the header MMR committed to by the tip.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SampledHeader {
+	/// The sampled header
+	pub header: BlockHeader,
+	/// Merkle proof of the header against the tip prev_root
+	pub proof: MerkleProof,
+}
+
+impl Writeable for SampledHeader {
+	fn write<W: Writer>(&self, writer: &mut W) -> Result<(), ser::Error> {
+		self.header.write(writer)?;
+		self.proof.write(writer)
+	}
+}
+
+impl Readable for SampledHeader {
+	fn read(reader: &mut dyn Reader) -> Result<SampledHeader, ser::Error> {
+		let header = BlockHeader::read(reader)?;
+		let proof = MerkleProof::read(reader)?;
+		Ok(SampledHeader { header, proof })
+	}
+}
+
+/// Succinct proof of the work behind a chain tip. Always includes the
+/// genesis header and the header right before the tip, the other headers
+/// being the ones covering the sampled amounts of work, ordered by height.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChainProof {
+	/// The tip the proof is for
+	pub tip: BlockHeader,
+	/// The sampled headers, ordered by height
+	pub headers: Vec<SampledHeader>,
+}
+
+impl Writeable for ChainProof {
+	fn write<W: Writer>(&self, writer: &mut W) -> Result<(), ser::Error> {
+		self.tip.write(writer)?;
+		writer.write_u64(self.headers.len() as u64)?;
+		for header in &self.headers {
+			header.write(writer)?;
+		}
+		Ok(())
+	}
+}
+
+impl Readable for ChainProof {
+	fn read(reader: &mut dyn Reader) -> Result<ChainProof, ser::Error> {
+		let tip = BlockHeader::read(reader)?;
+		let len = reader.read_u64()?;
+		let mut headers = Vec::with_capacity(len.min(1024) as usize);
+		for _ in 0..len {
+			headers.push(SampledHeader::read(reader)?);
+		}
+		Ok(ChainProof { tip, headers })
+	}
+}
+
+impl ChainProof {
+	/// Verifies the proof against the expected genesis hash, checking the
+	/// proof of work of the tip and of every sampled header with the provided
+	/// verifier for the chain with the provided parameters. The work of a
+	/// sampled header is bounded by the difficulty its proof achieves (see
+	/// `proven_work`).
+	pub fn verify(
+		&self,
+		params: &ChainParams,
+		genesis: &Hash,
+		pow_verifier: fn(&ChainParams, &BlockHeader) -> Result<(), pow::Error>,
+	) -> Result<(), ChainProofError> {
+		let tip = &self.tip;
+		if tip.height == 0 {
+			return Err(ChainProofError::EmptyChain);
+		}
+		let first = match self.headers.first() {
+			Some(first) if first.header.height == 0 && first.header.hash() == *genesis => first,
+			_ => return Err(ChainProofError::GenesisMismatch),
+		};
+		let last = self.headers.last().expect("at least genesis");
+		if last.header.height + 1 != tip.height
+			|| last.header.hash() != tip.prev_hash
+			|| last.header.total_difficulty() >= tip.total_difficulty()
+		{
+			return Err(ChainProofError::TipMismatch);
+		}
+		pow_verifier(params, tip).map_err(|_| ChainProofError::InvalidPoW(tip.height))?;
+
+		let mmr_size = header_mmr_size(tip.height);
+		for (i, sampled) in self.headers.iter().enumerate() {
+			let header = &sampled.header;
+			if i > 0 {
+				let prev = &self.headers[i - 1].header;
+				if header.height <= prev.height
+					|| header.total_difficulty() <= prev.total_difficulty()
+				{
+					return Err(ChainProofError::InvalidOrder);
+				}
+			}
+			let pos = pmmr::insertion_to_pmmr_index(header.height + 1);
+			sampled
+				.proof
+				.verify_leaf(tip.prev_root, mmr_size, header, pos)
+				.map_err(|_| ChainProofError::MerkleProof(header.height))?;
+			if header.height > 0 {
+				pow_verifier(params, header)
+					.map_err(|_| ChainProofError::InvalidPoW(header.height))?;
+			}
+		}
+
+		// Every sampled amount of work must fall within the work of one of
+		// our headers, bounded by the difficulty its proof of work achieves.
+		let genesis_work = first.header.total_difficulty().to_num();
+		let last_work = last.header.total_difficulty().to_num();
+		for point in sample_points(tip, genesis_work, last_work) {
+			let idx = match self
+				.headers
+				.binary_search_by_key(&point, |x| x.header.total_difficulty().to_num())
+			{
+				Ok(idx) => idx,
+				Err(idx) => idx,
+			};
+			let header = &self.headers[idx].header;
+			let work = header.total_difficulty().to_num();
+			let own_work = proven_work(params, header);
+			if work.saturating_sub(own_work) >= point {
+				return Err(ChainProofError::MissingSample(point));
+			}
+		}
+		Ok(())
+	}
+}
+
+/// The work the proof of work of a sampled header proves on its own. The
+/// secondary scaling of a header can't be checked without the headers of the
+/// difficulty window before it, so it's capped at the initial graph weight:
+/// a secondary proof never counts for more than at genesis, whatever scaling
+/// the header declares.
+pub fn proven_work(params: &ChainParams, header: &BlockHeader) -> u64 {
+	let mut pow = header.pow.clone();
+	pow.secondary_scaling = min(pow.secondary_scaling, params.initial_graph_weight);
+	pow.to_difficulty(params, header.height).to_num()
+}
+
+/// Size of the header MMR committed to by the prev_root of a header at the
+/// provided height, holding all the headers before it.
+pub fn header_mmr_size(height: u64) -> u64 {
+	pmmr::insertion_to_pmmr_index(height + 1).saturating_sub(1)
+}
+
+/// The amounts of cumulative work at which to sample headers for the
+/// provided tip, given the total difficulty of the genesis and of the header
+/// right before the tip. Sorted, and all above the genesis work.
+pub fn sample_points(tip: &BlockHeader, genesis_work: u64, last_work: u64) -> Vec<u64> {
+	let span = last_work.saturating_sub(genesis_work);
+	let layers = 64 - tip.height.leading_zeros() as u64;
+	let seed = tip.hash();
+
+	let mut points = vec![];
+	for layer in 0..layers {
+		let window = span >> layer;
+		if window == 0 {
+			break;
+		}
+		for i in 0..SAMPLES_PER_LAYER {
+			let rand = (seed, layer * SAMPLES_PER_LAYER + i).hash().to_u64();
+			points.push(last_work - rand % window);
+		}
+	}
+	points.sort_unstable();
+	points.dedup();
+	points
+}
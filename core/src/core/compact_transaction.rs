@@ -0,0 +1,290 @@
+// Copyright 2018 The Grin Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Compact Transactions.
+
+use rand::{thread_rng, Rng};
+use std::collections::HashSet;
+
+use crate::core::hash::{DefaultHashable, Hashed};
+use crate::core::id::ShortIdentifiable;
+use crate::core::transaction::{Error, Transaction, TransactionBody};
+use crate::core::{Input, Output, ShortId, TxKernel};
+use crate::keychain::BlindingFactor;
+use crate::ser::{self, read_multi, Readable, Reader, VerifySortedAndUnique, Writeable, Writer};
+
+/// Container for full inputs, outputs and kernels and the inp_ids and
+/// out_ids of a compact transaction.
+#[derive(Debug, Clone)]
+pub struct CompactTransactionBody {
+	/// List of full inputs, the ones the peer is not expected to know about
+	pub inp_full: Vec<Input>,
+	/// List of full outputs, the ones the peer is not expected to know about
+	pub out_full: Vec<Output>,
+	/// List of full kernels, always sent in full
+	pub kern_full: Vec<TxKernel>,
+	/// List of inputs, excluding those in the full list (short_ids)
+	pub inp_ids: Vec<ShortId>,
+	/// List of outputs, excluding those in the full list (short_ids)
+	pub out_ids: Vec<ShortId>,
+}
+
+impl CompactTransactionBody {
+	fn init(
+		inp_full: Vec<Input>,
+		out_full: Vec<Output>,
+		kern_full: Vec<TxKernel>,
+		inp_ids: Vec<ShortId>,
+		out_ids: Vec<ShortId>,
+		verify_sorted: bool,
+	) -> Result<Self, Error> {
+		let body = CompactTransactionBody {
+			inp_full,
+			out_full,
+			kern_full,
+			inp_ids,
+			out_ids,
+		};
+
+		if verify_sorted {
+			// If we are verifying sort order then verify and
+			// return an error if not sorted lexicographically.
+			body.verify_sorted()?;
+			Ok(body)
+		} else {
+			// If we are not verifying sort order then sort in place and return.
+			let mut body = body;
+			body.sort();
+			Ok(body)
+		}
+	}
+
+	/// Sort everything.
+	fn sort(&mut self) {
+		self.inp_full.sort_unstable();
+		self.out_full.sort_unstable();
+		self.kern_full.sort_unstable();
+		self.inp_ids.sort_unstable();
+		self.out_ids.sort_unstable();
+	}
+
+	/// "Lightweight" validation.
+	fn validate_read(&self) -> Result<(), Error> {
+		self.verify_sorted()?;
+		Ok(())
+	}
+
+	// Verify everything is sorted in lexicographical order and no duplicates present.
+	fn verify_sorted(&self) -> Result<(), Error> {
+		self.inp_full.verify_sorted_and_unique()?;
+		self.out_full.verify_sorted_and_unique()?;
+		self.kern_full.verify_sorted_and_unique()?;
+		self.inp_ids.verify_sorted_and_unique()?;
+		self.out_ids.verify_sorted_and_unique()?;
+		Ok(())
+	}
+}
+
+impl Readable for CompactTransactionBody {
+	fn read(reader: &mut dyn Reader) -> Result<CompactTransactionBody, ser::Error> {
+		let (inp_full_len, out_full_len, kern_full_len, inp_id_len, out_id_len) =
+			ser_multiread!(reader, read_u64, read_u64, read_u64, read_u64, read_u64);
+
+		let inp_full = read_multi(reader, inp_full_len)?;
+		let out_full = read_multi(reader, out_full_len)?;
+		let kern_full = read_multi(reader, kern_full_len)?;
+		let inp_ids = read_multi(reader, inp_id_len)?;
+		let out_ids = read_multi(reader, out_id_len)?;
+
+		// Initialize compact transaction body, verifying sort order.
+		let body =
+			CompactTransactionBody::init(inp_full, out_full, kern_full, inp_ids, out_ids, true)
+				.map_err(|_| ser::Error::CorruptedData)?;
+
+		Ok(body)
+	}
+}
+
+impl Writeable for CompactTransactionBody {
+	fn write<W: Writer>(&self, writer: &mut W) -> Result<(), ser::Error> {
+		ser_multiwrite!(
+			writer,
+			[write_u64, self.inp_full.len() as u64],
+			[write_u64, self.out_full.len() as u64],
+			[write_u64, self.kern_full.len() as u64],
+			[write_u64, self.inp_ids.len() as u64],
+			[write_u64, self.out_ids.len() as u64]
+		);
+
+		self.inp_full.write(writer)?;
+		self.out_full.write(writer)?;
+		self.kern_full.write(writer)?;
+		self.inp_ids.write(writer)?;
+		self.out_ids.write(writer)?;
+
+		Ok(())
+	}
+}
+
+impl Into<CompactTransactionBody> for CompactTransaction {
+	fn into(self) -> CompactTransactionBody {
+		self.body
+	}
+}
+
+/// Compact representation of a full transaction, for relay.
+/// Kernels are always sent in full but the inputs and outputs a peer has
+/// reasonably likely already seen (as part of other transactions in its pool,
+/// before they got aggregated for example) are represented as short_ids.
+/// The short_ids are keyed on the hash of the kernels and offset (which
+/// uniquely identify the transaction) and a connection specific nonce.
+/// A peer missing some of them can go request the full transaction.
+#[derive(Debug, Clone)]
+pub struct CompactTransaction {
+	/// The kernel "offset" of the full transaction
+	pub offset: BlindingFactor,
+	/// Nonce for connection specific short_ids
+	pub nonce: u64,
+	/// Container for the full inputs, outputs and kernels and the
+	/// inp_ids and out_ids in the compact transaction.
+	body: CompactTransactionBody,
+}
+
+impl DefaultHashable for CompactTransaction {}
+
+impl CompactTransaction {
+	/// Build a compact transaction from a full transaction. Inputs and
+	/// outputs also found in the provided (known) transactions are replaced
+	/// by their short_id, as well as inputs spending one of their outputs.
+	/// Everything else is sent in full.
+	pub fn new(tx: Transaction, known: &[Transaction]) -> CompactTransaction {
+		let nonce = thread_rng().gen();
+
+		// An input and the output it spends hash the same (features and
+		// commitment), so a single set covers both cases for inputs.
+		let mut known_inputs = HashSet::new();
+		let mut known_outputs = HashSet::new();
+		for x in known {
+			known_inputs.extend(x.inputs().iter().map(|inp| inp.hash()));
+			for out in x.outputs() {
+				known_inputs.insert(out.hash());
+				known_outputs.insert(out.hash());
+			}
+		}
+
+		let offset = tx.offset.clone();
+		let body: TransactionBody = tx.into();
+
+		// Kernels (and offset) first, the short_ids are keyed on them.
+		let mut ct = CompactTransaction {
+			offset,
+			nonce,
+			body: CompactTransactionBody::init(vec![], vec![], body.kernels, vec![], vec![], false)
+				.expect("sorting, not verifying"),
+		};
+		let hash = ct.hash();
+
+		for inp in body.inputs {
+			if known_inputs.contains(&inp.hash()) {
+				ct.body.inp_ids.push(inp.short_id(&hash, nonce));
+			} else {
+				ct.body.inp_full.push(inp);
+			}
+		}
+		for out in body.outputs {
+			if known_outputs.contains(&out.hash()) {
+				ct.body.out_ids.push(out.short_id(&hash, nonce));
+			} else {
+				ct.body.out_full.push(out);
+			}
+		}
+		ct.body.sort();
+		ct
+	}
+
+	/// "Lightweight" validation.
+	fn validate_read(&self) -> Result<(), Error> {
+		self.body.validate_read()?;
+		Ok(())
+	}
+
+	/// Get full inputs
+	pub fn inp_full(&self) -> &Vec<Input> {
+		&self.body.inp_full
+	}
+
+	/// Get full outputs
+	pub fn out_full(&self) -> &Vec<Output> {
+		&self.body.out_full
+	}
+
+	/// Get full kernels
+	pub fn kern_full(&self) -> &Vec<TxKernel> {
+		&self.body.kern_full
+	}
+
+	/// Get inp_ids
+	pub fn inp_ids(&self) -> &Vec<ShortId> {
+		&self.body.inp_ids
+	}
+
+	/// Get out_ids
+	pub fn out_ids(&self) -> &Vec<ShortId> {
+		&self.body.out_ids
+	}
+}
+
+/// Implementation of Writeable for a compact transaction. When hashing only
+/// the offset and kernels are written, they are enough to identify the
+/// transaction and key the short_ids.
+impl Writeable for CompactTransaction {
+	fn write<W: Writer>(&self, writer: &mut W) -> Result<(), ser::Error> {
+		self.offset.write(writer)?;
+
+		if writer.serialization_mode() == ser::SerializationMode::Hash {
+			self.body.kern_full.write(writer)?;
+		} else {
+			writer.write_u64(self.nonce)?;
+			self.body.write(writer)?;
+		}
+
+		Ok(())
+	}
+}
+
+/// Implementation of Readable for a compact transaction, defines how to read
+/// a compact transaction from a binary stream.
+impl Readable for CompactTransaction {
+	fn read(reader: &mut dyn Reader) -> Result<CompactTransaction, ser::Error> {
+		let offset = BlindingFactor::read(reader)?;
+		let nonce = reader.read_u64()?;
+		let body = CompactTransactionBody::read(reader)?;
+
+		// A transaction always has at least one kernel, relay relies on it.
+		if body.kern_full.is_empty() {
+			return Err(ser::Error::CorruptedData);
+		}
+
+		let ct = CompactTransaction {
+			offset,
+			nonce,
+			body,
+		};
+
+		// Now validate the compact transaction and treat any validation error as corrupted data.
+		ct.validate_read().map_err(|_| ser::Error::CorruptedData)?;
+
+		Ok(ct)
+	}
+}
@@ -16,6 +16,7 @@
 
 use crate::core::hash::Hash;
 use crate::core::pmmr;
+use crate::core::{BlockHeader, TxKernel};
 use crate::ser;
 use crate::ser::{PMMRIndexHashable, Readable, Reader, Writeable, Writer};
 use crate::util;
@@ -25,6 +26,10 @@ use crate::util;
 pub enum MerkleProofError {
 	/// Merkle proof root hash does not match when attempting to verify.
 	RootMismatch,
+	/// Merkle proof was not built for an MMR of the expected size.
+	SizeMismatch,
+	/// Position is not a leaf of the MMR the proof was built for.
+	InvalidPosition,
 }
 
 /// A Merkle proof that proves a particular element exists in the MMR.
@@ -78,14 +83,14 @@ impl MerkleProof {
 	/// Serialize the Merkle proof as a hex string (for api json endpoints)
 	pub fn to_hex(&self) -> String {
 		let mut vec = Vec::new();
-		ser::serialize(&mut vec, &self).expect("serialization failed");
+		ser::serialize_default(&mut vec, &self).expect("serialization failed");
 		util::to_hex(vec)
 	}
 
 	/// Convert hex string representation back to a Merkle proof instance
 	pub fn from_hex(hex: &str) -> Result<MerkleProof, String> {
 		let bytes = util::from_hex(hex.to_string()).unwrap();
-		let res = ser::deserialize(&mut &bytes[..])
+		let res = ser::deserialize_default(&mut &bytes[..])
 			.map_err(|_| "failed to deserialize a Merkle Proof".to_string())?;
 		Ok(res)
 	}
@@ -97,12 +102,60 @@ impl MerkleProof {
 		root: Hash,
 		element: &dyn PMMRIndexHashable,
 		node_pos: u64,
+	) -> Result<(), MerkleProofError> {
+		let node_hash = if node_pos > self.mmr_size {
+			element.hash_with_index(self.mmr_size)
+		} else {
+			element.hash_with_index(node_pos - 1)
+		};
+		self.verify_hash(root, node_hash, node_pos)
+	}
+
+	/// Verifies the Merkle proof of the leaf element at the provided position
+	/// against the root of an MMR of the provided size, checking the proof
+	/// was built for an MMR of this size.
+	pub fn verify_leaf(
+		&self,
+		root: Hash,
+		mmr_size: u64,
+		element: &dyn PMMRIndexHashable,
+		pos: u64,
+	) -> Result<(), MerkleProofError> {
+		if self.mmr_size != mmr_size {
+			return Err(MerkleProofError::SizeMismatch);
+		}
+		if pos == 0 || pos > mmr_size || !pmmr::is_leaf(pos) {
+			return Err(MerkleProofError::InvalidPosition);
+		}
+		self.verify(root, element, pos)
+	}
+
+	/// Verifies the Merkle proof of the kernel at the provided position
+	/// against the kernel root of the provided header. The proof must have
+	/// been built for the kernel MMR as of this header (rewound to its kernel
+	/// MMR size).
+	pub fn verify_kernel(
+		&self,
+		header: &BlockHeader,
+		kernel: &TxKernel,
+		pos: u64,
+	) -> Result<(), MerkleProofError> {
+		self.verify_leaf(header.kernel_root, header.kernel_mmr_size, kernel, pos)
+	}
+
+	/// Verifies the Merkle proof against the provided root hash, given the
+	/// hash of the node (leaf or subtree root) at the provided position.
+	pub fn verify_hash(
+		&self,
+		root: Hash,
+		node_hash: Hash,
+		node_pos: u64,
 	) -> Result<(), MerkleProofError> {
 		let mut proof = self.clone();
 		// calculate the peaks once as these are based on overall MMR size
 		// (and will not change)
 		let peaks_pos = pmmr::peaks(self.mmr_size);
-		proof.verify_consume(root, element, node_pos, &peaks_pos)
+		proof.verify_consume(root, node_hash, node_pos, &peaks_pos)
 	}
 
 	/// Consumes the Merkle proof while verifying it.
@@ -111,16 +164,10 @@ impl MerkleProof {
 	fn verify_consume(
 		&mut self,
 		root: Hash,
-		element: &dyn PMMRIndexHashable,
+		node_hash: Hash,
 		node_pos: u64,
 		peaks_pos: &[u64],
 	) -> Result<(), MerkleProofError> {
-		let node_hash = if node_pos > self.mmr_size {
-			element.hash_with_index(self.mmr_size)
-		} else {
-			element.hash_with_index(node_pos - 1)
-		};
-
 		// handle special case of only a single entry in the MMR
 		// (no siblings to hash together)
 		if self.path.is_empty() {
@@ -134,23 +181,23 @@ impl MerkleProof {
 		let sibling = self.path.remove(0);
 		let (parent_pos, sibling_pos) = pmmr::family(node_pos);
 
-		if let Ok(x) = peaks_pos.binary_search(&node_pos) {
-			let parent = if x == peaks_pos.len() - 1 {
+		let parent = if let Ok(x) = peaks_pos.binary_search(&node_pos) {
+			if x == peaks_pos.len() - 1 {
 				(sibling, node_hash)
 			} else {
 				(node_hash, sibling)
-			};
-			self.verify(root, &parent, parent_pos)
-		} else if parent_pos > self.mmr_size {
-			let parent = (sibling, node_hash);
-			self.verify(root, &parent, parent_pos)
+			}
+		} else if parent_pos > self.mmr_size || pmmr::is_left_sibling(sibling_pos) {
+			(sibling, node_hash)
 		} else {
-			let parent = if pmmr::is_left_sibling(sibling_pos) {
-				(sibling, node_hash)
-			} else {
-				(node_hash, sibling)
-			};
-			self.verify(root, &parent, parent_pos)
-		}
+			(node_hash, sibling)
+		};
+
+		let parent_hash = if parent_pos > self.mmr_size {
+			parent.hash_with_index(self.mmr_size)
+		} else {
+			parent.hash_with_index(parent_pos - 1)
+		};
+		self.verify_consume(root, parent_hash, parent_pos, peaks_pos)
 	}
 }
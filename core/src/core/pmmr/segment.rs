@@ -0,0 +1,545 @@
+// Copyright 2018 The Grin Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Segments of a (pruned) MMR, allowing a MMR to be transferred and verified
+//! piece by piece against its root rather than all at once.
+//!
+//! A segment of height `h` and index `i` covers the leaves with insertion
+//! index in `[i * 2^h, (i + 1) * 2^h)` and all the nodes above them up to the
+//! root of their subtree (or up to the end of the MMR for the last, partial,
+//! segment). It carries the data of every leaf still available in the MMR
+//! and the hashes of the roots of the compacted subtrees, everything else
+//! being recomputed by the receiver. A full segment comes with the Merkle
+//! proof of its root (or of the compacted subtree root above it), the last
+//! partial segment with the peaks on its left.
+
+use croaring::Bitmap;
+
+use crate::core::hash::Hash;
+use crate::core::merkle_proof::MerkleProof;
+use crate::core::pmmr::{self, Backend, ReadonlyPMMR};
+use crate::ser::{self, PMMRIndexHashable, PMMRable, Readable, Reader, Writeable, Writer};
+use std::collections::HashMap;
+
+/// Segments higher than this are refused, keeping segment messages to a
+/// reasonable size.
+pub const MAX_SEGMENT_HEIGHT: u8 = 12;
+
+/// Segment errors.
+#[derive(Clone, Debug, PartialEq)]
+pub enum SegmentError {
+	/// The segment starts beyond the last leaf of the MMR.
+	NonExistent,
+	/// The segment height is above MAX_SEGMENT_HEIGHT.
+	HeightTooLarge,
+	/// The hash at this position is required but missing.
+	MissingHash(u64),
+	/// The leaf at this position has a hash but its data is missing.
+	MissingLeaf(u64),
+	/// The node at this position is not expected in the segment.
+	UnexpectedNode(u64),
+	/// The segment does not hash to the expected root.
+	RootMismatch,
+}
+
+/// Identifies a segment by its height (a segment covers 2^height leaves)
+/// and its index.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct SegmentIdentifier {
+	/// Height of the segment.
+	pub height: u8,
+	/// Index of the segment, from 0.
+	pub idx: u64,
+}
+
+impl Writeable for SegmentIdentifier {
+	fn write<W: Writer>(&self, writer: &mut W) -> Result<(), ser::Error> {
+		writer.write_u8(self.height)?;
+		writer.write_u64(self.idx)
+	}
+}
+
+impl Readable for SegmentIdentifier {
+	fn read(reader: &mut dyn Reader) -> Result<SegmentIdentifier, ser::Error> {
+		let height = reader.read_u8()?;
+		let idx = reader.read_u64()?;
+		Ok(SegmentIdentifier { height, idx })
+	}
+}
+
+impl SegmentIdentifier {
+	/// Number of leaves covered by a full segment.
+	pub fn capacity(&self) -> u64 {
+		1 << self.height
+	}
+
+	/// Number of segments of the provided height covering a MMR of the
+	/// provided size.
+	pub fn count(height: u8, mmr_size: u64) -> u64 {
+		let capacity = 1 << height;
+		(pmmr::n_leaves(mmr_size) + capacity - 1) / capacity
+	}
+
+	/// First and last positions of the segment in a MMR of the provided size,
+	/// and whether this is a full segment (the last one may not be).
+	/// None if the segment lies beyond the end of the MMR.
+	pub fn bounds(&self, mmr_size: u64) -> Option<(u64, u64, bool)> {
+		if self.height > MAX_SEGMENT_HEIGHT {
+			return None;
+		}
+		let n_leaves = pmmr::n_leaves(mmr_size);
+		let offset = self.idx.checked_mul(self.capacity())?;
+		if offset >= n_leaves {
+			return None;
+		}
+		let first_pos = pmmr::insertion_to_pmmr_index(offset + 1);
+		if offset + self.capacity() <= n_leaves {
+			let last_leaf_pos = pmmr::insertion_to_pmmr_index(offset + self.capacity());
+			Some((first_pos, last_leaf_pos + self.height as u64, true))
+		} else {
+			Some((first_pos, mmr_size, false))
+		}
+	}
+}
+
+/// A segment of a MMR, see the module documentation.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Segment<T> {
+	identifier: SegmentIdentifier,
+	hashes: Vec<(u64, Hash)>,
+	leaf_data: Vec<(u64, T)>,
+	proof: Vec<Hash>,
+}
+
+impl<T> Segment<T>
+where
+	T: PMMRIndexHashable + Clone,
+{
+	/// Builds the segment from the provided (readonly view of a) MMR.
+	pub fn from_pmmr<U, B>(
+		identifier: SegmentIdentifier,
+		pmmr: &ReadonlyPMMR<'_, U, B>,
+	) -> Result<Segment<T>, SegmentError>
+	where
+		U: PMMRable<E = T>,
+		B: Backend<U>,
+	{
+		if identifier.height > MAX_SEGMENT_HEIGHT {
+			return Err(SegmentError::HeightTooLarge);
+		}
+		let mmr_size = pmmr.unpruned_size();
+		let (first_pos, last_pos, full) = identifier
+			.bounds(mmr_size)
+			.ok_or(SegmentError::NonExistent)?;
+
+		let mut hashes = vec![];
+		let mut leaf_data = vec![];
+		for pos in first_pos..=last_pos {
+			if pmmr::is_leaf(pos) {
+				if let Some(data) = pmmr.get_data_from_file(pos) {
+					leaf_data.push((pos, data));
+				} else if pmmr.get_from_file(pos).is_some() {
+					return Err(SegmentError::MissingLeaf(pos));
+				}
+			} else if let Some(hash) = pmmr.get_from_file(pos) {
+				// Only the roots of compacted subtrees are sent, all the other
+				// hashes can be recomputed from their children.
+				let left_child = pos - (1 << pmmr::bintree_postorder_height(pos));
+				if pmmr.get_from_file(left_child).is_none() {
+					hashes.push((pos, hash));
+				}
+			}
+		}
+
+		let proof = if full {
+			let mut pos = last_pos;
+			if hashes.is_empty() && leaf_data.is_empty() {
+				// The whole segment has been compacted away, we go up the tree
+				// to the root of the compacted subtree it belongs to.
+				loop {
+					pos = pmmr::family(pos).0;
+					if pos > mmr_size {
+						return Err(SegmentError::MissingHash(last_pos));
+					}
+					if let Some(hash) = pmmr.get_from_file(pos) {
+						hashes.push((pos, hash));
+						break;
+					}
+				}
+			}
+			node_proof(pmmr, pos)?
+		} else {
+			pmmr::peaks(mmr_size)
+				.into_iter()
+				.filter(|x| *x < first_pos)
+				.map(|x| pmmr.get_from_file(x).ok_or(SegmentError::MissingHash(x)))
+				.collect::<Result<Vec<_>, _>>()?
+		};
+
+		Ok(Segment {
+			identifier,
+			hashes,
+			leaf_data,
+			proof,
+		})
+	}
+
+	/// The segment identifier.
+	pub fn identifier(&self) -> SegmentIdentifier {
+		self.identifier
+	}
+
+	/// Positions and hashes of the roots of the compacted subtrees in the
+	/// segment.
+	pub fn hashes(&self) -> &[(u64, Hash)] {
+		&self.hashes
+	}
+
+	/// Positions and data of the leaves in the segment.
+	pub fn leaf_data(&self) -> &[(u64, T)] {
+		&self.leaf_data
+	}
+
+	/// Consumes the segment, returning its identifier, hashes and leaf data.
+	pub fn parts(self) -> (SegmentIdentifier, Vec<(u64, Hash)>, Vec<(u64, T)>) {
+		(self.identifier, self.hashes, self.leaf_data)
+	}
+
+	/// Verifies the segment against the root of a MMR of the provided size.
+	pub fn validate(&self, mmr_size: u64, root: Hash) -> Result<(), SegmentError> {
+		if self.identifier.height > MAX_SEGMENT_HEIGHT {
+			return Err(SegmentError::HeightTooLarge);
+		}
+		let (first_pos, last_pos, full) = self
+			.identifier
+			.bounds(mmr_size)
+			.ok_or(SegmentError::NonExistent)?;
+
+		// A fully compacted segment is proven by the root of the compacted
+		// subtree above it.
+		if full && self.leaf_data.is_empty() && self.hashes.len() == 1 {
+			let (pos, hash) = self.hashes[0];
+			if pos > last_pos {
+				if !pmmr::path(last_pos, mmr_size).contains(&pos) {
+					return Err(SegmentError::UnexpectedNode(pos));
+				}
+				return self.verify_proof(mmr_size, root, hash, pos);
+			}
+		}
+
+		let mut prev = 0;
+		for (pos, _) in &self.leaf_data {
+			if *pos <= prev || *pos < first_pos || *pos > last_pos || !pmmr::is_leaf(*pos) {
+				return Err(SegmentError::UnexpectedNode(*pos));
+			}
+			prev = *pos;
+		}
+		let mut hashes = HashMap::new();
+		prev = 0;
+		for (pos, hash) in &self.hashes {
+			if *pos <= prev || *pos < first_pos || *pos > last_pos || pmmr::is_leaf(*pos) {
+				return Err(SegmentError::UnexpectedNode(*pos));
+			}
+			hashes.insert(*pos, *hash);
+			prev = *pos;
+		}
+
+		// Rebuild the segment bottom up, keeping the subtree roots built so far
+		// on a stack (none for a compacted node).
+		let mut stack: Vec<(u64, Option<Hash>)> = vec![];
+		let mut leaves = self.leaf_data.iter().peekable();
+		for pos in first_pos..=last_pos {
+			let hash = if pmmr::is_leaf(pos) {
+				match leaves.peek() {
+					Some((leaf_pos, data)) if *leaf_pos == pos => {
+						let hash = data.hash_with_index(pos - 1);
+						leaves.next();
+						Some(hash)
+					}
+					_ => None,
+				}
+			} else {
+				let right = stack.pop().ok_or(SegmentError::MissingHash(pos - 1))?;
+				let left = stack.pop().ok_or(SegmentError::MissingHash(pos - 1))?;
+				match (hashes.get(&pos), left.1, right.1) {
+					(Some(hash), None, None) => Some(*hash),
+					(Some(_), _, _) => return Err(SegmentError::UnexpectedNode(pos)),
+					(None, Some(l), Some(r)) => Some((l, r).hash_with_index(pos - 1)),
+					(None, None, None) => None,
+					(None, None, Some(_)) => return Err(SegmentError::MissingHash(left.0)),
+					(None, Some(_), None) => return Err(SegmentError::MissingHash(right.0)),
+				}
+			};
+			stack.push((pos, hash));
+		}
+
+		if full {
+			let hash = match stack.pop() {
+				Some((_, Some(hash))) => hash,
+				_ => return Err(SegmentError::MissingHash(last_pos)),
+			};
+			self.verify_proof(mmr_size, root, hash, last_pos)
+		} else {
+			// The peaks on the left come with the segment, the ones on the right
+			// are the top nodes of the segment itself.
+			let left_peaks = pmmr::peaks(mmr_size)
+				.into_iter()
+				.filter(|x| *x < first_pos)
+				.count();
+			if self.proof.len() != left_peaks {
+				return Err(SegmentError::RootMismatch);
+			}
+			let mut peaks = self.proof.clone();
+			for (pos, hash) in stack {
+				peaks.push(hash.ok_or(SegmentError::MissingHash(pos))?);
+			}
+			let mut res = None;
+			for peak in peaks.iter().rev() {
+				res = match res {
+					None => Some(*peak),
+					Some(rhash) => Some((*peak, rhash).hash_with_index(mmr_size)),
+				}
+			}
+			if res == Some(root) {
+				Ok(())
+			} else {
+				Err(SegmentError::RootMismatch)
+			}
+		}
+	}
+
+	fn verify_proof(
+		&self,
+		mmr_size: u64,
+		root: Hash,
+		hash: Hash,
+		pos: u64,
+	) -> Result<(), SegmentError> {
+		let proof = MerkleProof {
+			mmr_size,
+			path: self.proof.clone(),
+		};
+		proof
+			.verify_hash(root, hash, pos)
+			.map_err(|_| SegmentError::RootMismatch)
+	}
+}
+
+/// Sibling path from the node at the provided position up to the root of the
+/// MMR, in the format expected by MerkleProof.
+fn node_proof<U, B>(pmmr: &ReadonlyPMMR<'_, U, B>, pos: u64) -> Result<Vec<Hash>, SegmentError>
+where
+	U: PMMRable,
+	B: Backend<U>,
+{
+	let mmr_size = pmmr.unpruned_size();
+	let get = |x: u64| pmmr.get_from_file(x).ok_or(SegmentError::MissingHash(x));
+
+	let family_branch = pmmr::family_branch(pos, mmr_size);
+	let mut path = family_branch
+		.iter()
+		.map(|x| get(x.1))
+		.collect::<Result<Vec<_>, _>>()?;
+
+	let peak_pos = match family_branch.last() {
+		Some(&(x, _)) => x,
+		None => pos,
+	};
+	let peaks = pmmr::peaks(mmr_size);
+
+	// bag the peaks on the right of our peak, this is our peak sibling
+	let mut rhs = None;
+	for peak in peaks.iter().filter(|x| **x > peak_pos).rev() {
+		let hash = get(*peak)?;
+		rhs = match rhs {
+			None => Some(hash),
+			Some(rhash) => Some((hash, rhash).hash_with_index(mmr_size)),
+		}
+	}
+	if let Some(rhs) = rhs {
+		path.push(rhs);
+	}
+	for peak in peaks.iter().filter(|x| **x < peak_pos).rev() {
+		path.push(get(*peak)?);
+	}
+	Ok(path)
+}
+
+impl<T: Writeable> Writeable for Segment<T> {
+	fn write<W: Writer>(&self, writer: &mut W) -> Result<(), ser::Error> {
+		self.identifier.write(writer)?;
+		writer.write_u64(self.hashes.len() as u64)?;
+		for (pos, hash) in &self.hashes {
+			writer.write_u64(*pos)?;
+			hash.write(writer)?;
+		}
+		writer.write_u64(self.leaf_data.len() as u64)?;
+		for (pos, data) in &self.leaf_data {
+			writer.write_u64(*pos)?;
+			data.write(writer)?;
+		}
+		writer.write_u64(self.proof.len() as u64)?;
+		self.proof.write(writer)?;
+		Ok(())
+	}
+}
+
+impl<T: Readable> Readable for Segment<T> {
+	fn read(reader: &mut dyn Reader) -> Result<Segment<T>, ser::Error> {
+		let identifier = SegmentIdentifier::read(reader)?;
+		if identifier.height > MAX_SEGMENT_HEIGHT {
+			return Err(ser::Error::TooLargeReadErr);
+		}
+		// A segment can't hold more nodes than twice its capacity, nor a proof
+		// longer than the 64 levels of a MMR (plus the peaks).
+		let max_nodes = 2 * identifier.capacity();
+
+		let count = reader.read_u64()?;
+		if count > max_nodes {
+			return Err(ser::Error::TooLargeReadErr);
+		}
+		let mut hashes = Vec::with_capacity(count as usize);
+		for _ in 0..count {
+			let pos = reader.read_u64()?;
+			hashes.push((pos, Hash::read(reader)?));
+		}
+
+		let count = reader.read_u64()?;
+		if count > identifier.capacity() {
+			return Err(ser::Error::TooLargeReadErr);
+		}
+		let mut leaf_data = Vec::with_capacity(count as usize);
+		for _ in 0..count {
+			let pos = reader.read_u64()?;
+			leaf_data.push((pos, T::read(reader)?));
+		}
+
+		let count = reader.read_u64()?;
+		if count > 128 {
+			return Err(ser::Error::TooLargeReadErr);
+		}
+		let mut proof = Vec::with_capacity(count as usize);
+		for _ in 0..count {
+			proof.push(Hash::read(reader)?);
+		}
+
+		Ok(Segment {
+			identifier,
+			hashes,
+			leaf_data,
+			proof,
+		})
+	}
+}
+
+/// A segment of a bitmap over the leaves of a MMR (typically the output leaf
+/// set), one bit per leaf covered by the segment of the same identifier.
+/// Bits are packed 8 per byte, lowest bit first.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BitmapSegment {
+	identifier: SegmentIdentifier,
+	bits: Vec<u8>,
+}
+
+impl BitmapSegment {
+	/// Builds the segment from a bitmap of leaf positions.
+	pub fn from_bitmap(
+		identifier: SegmentIdentifier,
+		mmr_size: u64,
+		bitmap: &Bitmap,
+	) -> Result<BitmapSegment, SegmentError> {
+		let n_leaves = BitmapSegment::n_leaves(identifier, mmr_size)?;
+		let offset = identifier.idx * identifier.capacity();
+		let mut bits = vec![0u8; ((n_leaves + 7) / 8) as usize];
+		for i in 0..n_leaves {
+			let pos = pmmr::insertion_to_pmmr_index(offset + i + 1);
+			if bitmap.contains(pos as u32) {
+				bits[(i / 8) as usize] |= 1 << (i % 8);
+			}
+		}
+		Ok(BitmapSegment { identifier, bits })
+	}
+
+	/// The segment identifier.
+	pub fn identifier(&self) -> SegmentIdentifier {
+		self.identifier
+	}
+
+	/// Checks the segment has the right number of bits for a MMR of the
+	/// provided size. The bits set are to be checked against the leaves of
+	/// the output segment of the same identifier, see `leaf_positions`.
+	pub fn validate(&self, mmr_size: u64) -> Result<(), SegmentError> {
+		let n_leaves = BitmapSegment::n_leaves(self.identifier, mmr_size)?;
+		if self.bits.len() as u64 != (n_leaves + 7) / 8 {
+			return Err(SegmentError::RootMismatch);
+		}
+		if n_leaves % 8 != 0 {
+			let last = self.bits[self.bits.len() - 1];
+			if last >> (n_leaves % 8) != 0 {
+				return Err(SegmentError::RootMismatch);
+			}
+		}
+		Ok(())
+	}
+
+	/// Leaf positions set in this segment.
+	pub fn leaf_positions(&self) -> Vec<u64> {
+		let offset = self.identifier.idx * self.identifier.capacity();
+		let mut positions = vec![];
+		for (i, byte) in self.bits.iter().enumerate() {
+			for bit in 0..8 {
+				if byte & (1 << bit) != 0 {
+					let n = offset + (i as u64) * 8 + bit;
+					positions.push(pmmr::insertion_to_pmmr_index(n + 1));
+				}
+			}
+		}
+		positions
+	}
+
+	fn n_leaves(identifier: SegmentIdentifier, mmr_size: u64) -> Result<u64, SegmentError> {
+		if identifier.height > MAX_SEGMENT_HEIGHT {
+			return Err(SegmentError::HeightTooLarge);
+		}
+		identifier
+			.bounds(mmr_size)
+			.ok_or(SegmentError::NonExistent)?;
+		let offset = identifier.idx * identifier.capacity();
+		Ok(std::cmp::min(
+			identifier.capacity(),
+			pmmr::n_leaves(mmr_size) - offset,
+		))
+	}
+}
+
+impl Writeable for BitmapSegment {
+	fn write<W: Writer>(&self, writer: &mut W) -> Result<(), ser::Error> {
+		self.identifier.write(writer)?;
+		writer.write_bytes(&self.bits)
+	}
+}
+
+impl Readable for BitmapSegment {
+	fn read(reader: &mut dyn Reader) -> Result<BitmapSegment, ser::Error> {
+		let identifier = SegmentIdentifier::read(reader)?;
+		if identifier.height > MAX_SEGMENT_HEIGHT {
+			return Err(ser::Error::TooLargeReadErr);
+		}
+		let bits = reader.read_bytes_len_prefix()?;
+		if bits.len() as u64 > (identifier.capacity() + 7) / 8 {
+			return Err(ser::Error::TooLargeReadErr);
+		}
+		Ok(BitmapSegment { identifier, bits })
+	}
+}
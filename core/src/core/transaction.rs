@@ -14,9 +14,12 @@
 
 //! Transactions
 
+use crate::core::compact_transaction::{CompactTransaction, CompactTransactionBody};
 use crate::core::hash::{DefaultHashable, Hashed};
+use crate::core::id::{ShortId, ShortIdentifiable};
 use crate::core::verifier_cache::VerifierCache;
 use crate::core::{committed, Committed};
+use crate::global::ChainParams;
 use crate::keychain::{self, BlindingFactor};
 use crate::libtx::secp_ser;
 use crate::ser::{
@@ -30,8 +33,11 @@ use crate::util::static_secp_instance;
 use crate::util::RwLock;
 use crate::{consensus, global};
 use enum_primitive::FromPrimitive;
+use rayon::prelude::*;
 use std::cmp::Ordering;
 use std::cmp::{max, min};
+use std::collections::HashSet;
+use std::iter::FromIterator;
 use std::sync::Arc;
 use std::{error, fmt};
 
@@ -185,7 +191,7 @@ hashable_ord!(TxKernel);
 impl ::std::hash::Hash for TxKernel {
 	fn hash<H: ::std::hash::Hasher>(&self, state: &mut H) {
 		let mut vec = Vec::new();
-		ser::serialize(&mut vec, &self).expect("serialization failed");
+		ser::serialize_default(&mut vec, &self).expect("serialization failed");
 		::std::hash::Hash::hash(&vec, state);
 	}
 }
@@ -270,17 +276,23 @@ impl TxKernel {
 	/// as a public key and checking the signature verifies with the fee as
 	/// message.
 	pub fn verify(&self) -> Result<(), Error> {
+		let secp = static_secp_instance();
+		let secp = secp.lock();
+		self.verify_with(&secp)
+	}
+
+	/// Verify the transaction proof validity (see `verify`) using the
+	/// provided secp instance.
+	pub fn verify_with(&self, secp: &secp::Secp256k1) -> Result<(), Error> {
 		if self.is_coinbase() && self.fee != 0 || !self.is_height_locked() && self.lock_height != 0
 		{
 			return Err(Error::InvalidKernelFeatures);
 		}
-		let secp = static_secp_instance();
-		let secp = secp.lock();
 		let sig = &self.excess_sig;
 		// Verify aggsig directly in libsecp
-		let pubkey = &self.excess.to_pubkey(&secp)?;
+		let pubkey = &self.excess.to_pubkey(secp)?;
 		if !secp::aggsig::verify_single(
-			&secp,
+			secp,
 			&sig,
 			&self.msg_to_sign()?,
 			None,
@@ -294,6 +306,40 @@ impl TxKernel {
 		Ok(())
 	}
 
+	/// Batch verifies the signatures of the provided kernels. Gives the same
+	/// result as verifying each one of them individually, in a fraction of
+	/// the time.
+	pub fn batch_sig_verify(kernels: &[TxKernel]) -> Result<(), Error> {
+		let secp = static_secp_instance();
+		let secp = secp.lock();
+		TxKernel::batch_sig_verify_with(&secp, kernels)
+	}
+
+	/// Batch verifies the signatures of the provided kernels (see
+	/// `batch_sig_verify`) using the provided secp instance.
+	pub fn batch_sig_verify_with(
+		secp: &secp::Secp256k1,
+		kernels: &[TxKernel],
+	) -> Result<(), Error> {
+		let mut sigs = Vec::with_capacity(kernels.len());
+		let mut msgs = Vec::with_capacity(kernels.len());
+		let mut pubkeys = Vec::with_capacity(kernels.len());
+		for kernel in kernels {
+			if kernel.is_coinbase() && kernel.fee != 0
+				|| !kernel.is_height_locked() && kernel.lock_height != 0
+			{
+				return Err(Error::InvalidKernelFeatures);
+			}
+			sigs.push(kernel.excess_sig);
+			msgs.push(kernel.msg_to_sign()?);
+			pubkeys.push(kernel.excess.to_pubkey(secp)?);
+		}
+		if !secp::aggsig::verify_batch(secp, &sigs, &msgs, &pubkeys) {
+			return Err(Error::IncorrectSignature);
+		}
+		Ok(())
+	}
+
 	/// Build an empty tx kernel with zero values.
 	pub fn empty() -> TxKernel {
 		TxKernel {
@@ -330,6 +376,10 @@ pub struct TxKernelEntry {
 	pub kernel: TxKernel,
 }
 
+// Hashes exactly as the underlying kernel, so the entries read from the
+// kernel MMR data file can be hashed back into the MMR.
+impl DefaultHashable for TxKernelEntry {}
+
 impl Writeable for TxKernelEntry {
 	fn write<W: Writer>(&self, writer: &mut W) -> Result<(), ser::Error> {
 		self.kernel.write(writer)?;
@@ -394,6 +444,15 @@ pub enum Weighting {
 	NoLimit,
 }
 
+/// Number of rangeproofs we batch verify together when verifying a body.
+/// Batches are verified in parallel so we trade off the efficiency of larger
+/// batches against spreading the work across more threads.
+const RANGEPROOF_BATCH_SIZE: usize = 64;
+
+/// Number of kernel signatures we batch verify together, batches being
+/// verified in parallel as for rangeproofs.
+pub const KERNEL_SIG_BATCH_SIZE: usize = 256;
+
 /// TransactionBody is a common abstraction for transaction and block
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct TransactionBody {
@@ -446,7 +505,11 @@ impl Readable for TransactionBody {
 			kernel_len as usize,
 		);
 
-		if tx_block_weight > global::max_block_weight() {
+		let max_block_weight = reader
+			.chain_params()
+			.ok_or(ser::Error::NoChainParams)?
+			.max_block_weight;
+		if tx_block_weight > max_block_weight {
 			return Err(ser::Error::TooLargeReadErr);
 		}
 
@@ -610,7 +673,7 @@ impl TransactionBody {
 
 	/// Verify the body is not too big in terms of number of inputs|outputs|kernels.
 	/// Weight rules vary depending on the "weight type" (block or tx or pool).
-	fn verify_weight(&self, weighting: Weighting) -> Result<(), Error> {
+	fn verify_weight(&self, params: &ChainParams, weighting: Weighting) -> Result<(), Error> {
 		// A coinbase reward is a single output and a single kernel (for now).
 		// We need to account for this when verifying max tx weights.
 		let coinbase_weight = consensus::BLOCK_OUTPUT_WEIGHT + consensus::BLOCK_KERNEL_WEIGHT;
@@ -624,11 +687,11 @@ impl TransactionBody {
 		// for the additional coinbase reward (1 output + 1 kernel).
 		//
 		let max_weight = match weighting {
-			Weighting::AsTransaction => global::max_block_weight().saturating_sub(coinbase_weight),
+			Weighting::AsTransaction => params.max_block_weight.saturating_sub(coinbase_weight),
 			Weighting::AsLimitedTransaction(max_weight) => {
-				min(global::max_block_weight(), max_weight).saturating_sub(coinbase_weight)
+				min(params.max_block_weight, max_weight).saturating_sub(coinbase_weight)
 			}
-			Weighting::AsBlock => global::max_block_weight(),
+			Weighting::AsBlock => params.max_block_weight,
 			Weighting::NoLimit => {
 				// We do not verify "tx as pool" weight so we are done here.
 				return Ok(());
@@ -696,12 +759,57 @@ impl TransactionBody {
 		Ok(())
 	}
 
+	// Batch verify the rangeproofs of the provided outputs.
+	// Outputs are split into fixed size batches, verified in parallel across the
+	// thread pool, each thread using its own secp instance.
+	fn verify_rangeproofs(outputs: &[Output]) -> Result<(), Error> {
+		outputs
+			.par_chunks(RANGEPROOF_BATCH_SIZE)
+			.try_for_each(|chunk| {
+				let commits = chunk.iter().map(|x| x.commit).collect();
+				let proofs = chunk.iter().map(|x| x.proof).collect();
+				util::with_thread_secp(|secp| {
+					secp.verify_bullet_proof_multi(commits, proofs, None)
+				})?;
+				Ok(())
+			})
+	}
+
+	/// Batch verify the signatures of the provided tx kernels.
+	/// Kernels are split into fixed size batches, verified in parallel across
+	/// the thread pool, each thread using its own secp instance.
+	pub fn verify_kernel_sigs(kernels: &[TxKernel]) -> Result<(), Error> {
+		kernels
+			.par_chunks(KERNEL_SIG_BATCH_SIZE)
+			.try_for_each(|chunk| {
+				util::with_thread_secp(|secp| TxKernel::batch_sig_verify_with(secp, chunk))
+			})
+	}
+
+	/// Batch verify the signatures of the provided tx kernels that the
+	/// verifier cache doesn't know about yet, caching the results.
+	pub fn verify_kernel_sigs_cached(
+		kernels: &[TxKernel],
+		verifier: Arc<RwLock<dyn VerifierCache>>,
+	) -> Result<(), Error> {
+		let kernels = {
+			let mut verifier = verifier.write();
+			verifier.filter_kernel_sig_unverified(kernels)
+		};
+		TransactionBody::verify_kernel_sigs(&kernels)?;
+		{
+			let mut verifier = verifier.write();
+			verifier.add_kernel_sig_verified(kernels);
+		}
+		Ok(())
+	}
+
 	/// "Lightweight" validation that we can perform quickly during read/deserialization.
 	/// Subset of full validation that skips expensive verification steps, specifically -
 	/// * rangeproof verification
 	/// * kernel signature verification
-	pub fn validate_read(&self, weighting: Weighting) -> Result<(), Error> {
-		self.verify_weight(weighting)?;
+	pub fn validate_read(&self, params: &ChainParams, weighting: Weighting) -> Result<(), Error> {
+		self.verify_weight(params, weighting)?;
 		self.verify_sorted()?;
 		self.verify_cut_through()?;
 		Ok(())
@@ -712,10 +820,11 @@ impl TransactionBody {
 	/// output.
 	pub fn validate(
 		&self,
+		params: &ChainParams,
 		weighting: Weighting,
 		verifier: Arc<RwLock<dyn VerifierCache>>,
 	) -> Result<(), Error> {
-		self.validate_read(weighting)?;
+		self.validate_read(params, weighting)?;
 
 		// Find all the outputs that have not had their rangeproofs verified.
 		let outputs = {
@@ -723,29 +832,20 @@ impl TransactionBody {
 			verifier.filter_rangeproof_unverified(&self.outputs)
 		};
 
-		// Now batch verify all those unverified rangeproofs
-		if !outputs.is_empty() {
-			let mut commits = vec![];
-			let mut proofs = vec![];
-			for x in &outputs {
-				commits.push(x.commit);
-				proofs.push(x.proof);
-			}
-			Output::batch_verify_proofs(&commits, &proofs)?;
-		}
-
 		// Find all the kernels that have not yet been verified.
 		let kernels = {
 			let mut verifier = verifier.write();
 			verifier.filter_kernel_sig_unverified(&self.kernels)
 		};
 
-		// Verify the unverified tx kernels.
-		// No ability to batch verify these right now
-		// so just do them individually.
-		for x in &kernels {
-			x.verify()?;
-		}
+		// Now verify all those unverified rangeproofs and tx kernels,
+		// these are independent of each other so we do this in parallel.
+		let (rproofs_res, kernels_res) = rayon::join(
+			|| TransactionBody::verify_rangeproofs(&outputs),
+			|| TransactionBody::verify_kernel_sigs(&kernels),
+		);
+		rproofs_res?;
+		kernels_res?;
 
 		// Cache the successful verification results for the new outputs and kernels.
 		{
@@ -808,7 +908,9 @@ impl Readable for Transaction {
 		// Treat any validation issues as data corruption.
 		// An example of this would be reading a tx
 		// that exceeded the allowed number of inputs.
-		tx.validate_read().map_err(|_| ser::Error::CorruptedData)?;
+		let params = reader.chain_params().ok_or(ser::Error::NoChainParams)?;
+		tx.validate_read(params)
+			.map_err(|_| ser::Error::CorruptedData)?;
 
 		Ok(tx)
 	}
@@ -861,6 +963,63 @@ impl Transaction {
 		Transaction { offset, ..self }
 	}
 
+	/// Hydrate a transaction from a compact transaction, looking up the
+	/// inputs and outputs referenced by short_id in the provided txs. An
+	/// input may also be found as the output of one of the txs it spends.
+	/// Note: caller must validate the transaction themselves, we do not
+	/// validate it here.
+	pub fn hydrate_from(
+		ct: CompactTransaction,
+		txs: Vec<Transaction>,
+	) -> Result<Transaction, Error> {
+		let hash = ct.hash();
+		trace!("transaction: hydrate_from: {}, {} txs", hash, txs.len());
+
+		let nonce = ct.nonce;
+		let offset = ct.offset.clone();
+		let body: CompactTransactionBody = ct.into();
+
+		let inp_ids: HashSet<ShortId> = HashSet::from_iter(body.inp_ids);
+		let out_ids: HashSet<ShortId> = HashSet::from_iter(body.out_ids);
+
+		// start from the full inputs and outputs of the compact transaction
+		let mut all_inputs: HashSet<Input> = HashSet::from_iter(body.inp_full);
+		let mut all_outputs: HashSet<Output> = HashSet::from_iter(body.out_full);
+
+		// collect the inputs and outputs from the txs matching the short_ids
+		for tx in txs {
+			let tb: TransactionBody = tx.into();
+			let spent = tb
+				.outputs
+				.iter()
+				.map(|out| Input::new(out.features, out.commit))
+				.collect::<Vec<_>>();
+			all_inputs.extend(
+				tb.inputs
+					.into_iter()
+					.chain(spent)
+					.filter(|x| inp_ids.contains(&x.short_id(&hash, nonce))),
+			);
+			all_outputs.extend(
+				tb.outputs
+					.into_iter()
+					.filter(|x| out_ids.contains(&x.short_id(&hash, nonce))),
+			);
+		}
+
+		// Initialize a tx body and sort everything.
+		let body = TransactionBody::init(
+			Vec::from_iter(all_inputs),
+			Vec::from_iter(all_outputs),
+			body.kern_full,
+			false,
+		)?;
+
+		// Note: we have not actually validated the tx here,
+		// caller must validate the tx.
+		Ok(Transaction { offset, body })
+	}
+
 	/// Builds a new transaction with the provided inputs added. Existing
 	/// inputs, if any, are kept intact.
 	/// Sort order is maintained.
@@ -941,21 +1100,33 @@ impl Transaction {
 	/// * rangeproof verification (on the body)
 	/// * kernel signature verification (on the body)
 	/// * kernel sum verification
-	pub fn validate_read(&self) -> Result<(), Error> {
-		self.body.validate_read(Weighting::AsTransaction)?;
+	pub fn validate_read(&self, params: &ChainParams) -> Result<(), Error> {
+		self.body.validate_read(params, Weighting::AsTransaction)?;
 		self.body.verify_features()?;
 		Ok(())
 	}
 
 	/// Validates all relevant parts of a fully built transaction. Checks the
 	/// excess value against the signature as well as range proofs for each
-	/// output.
+	/// output. Weights are checked against the process wide chain parameters,
+	/// see `validate_with_params` for a given chain.
 	pub fn validate(
 		&self,
 		weighting: Weighting,
 		verifier: Arc<RwLock<dyn VerifierCache>>,
 	) -> Result<(), Error> {
-		self.body.validate(weighting, verifier)?;
+		self.validate_with_params(&global::chain_params(), weighting, verifier)
+	}
+
+	/// Validates a fully built transaction for the chain with the provided
+	/// parameters.
+	pub fn validate_with_params(
+		&self,
+		params: &ChainParams,
+		weighting: Weighting,
+		verifier: Arc<RwLock<dyn VerifierCache>>,
+	) -> Result<(), Error> {
+		self.body.validate(params, weighting, verifier)?;
 		self.body.verify_features()?;
 		self.verify_kernel_sums(self.overage(), self.offset)?;
 		Ok(())
@@ -1159,7 +1330,7 @@ hashable_ord!(Input);
 impl ::std::hash::Hash for Input {
 	fn hash<H: ::std::hash::Hasher>(&self, state: &mut H) {
 		let mut vec = Vec::new();
-		ser::serialize(&mut vec, &self).expect("serialization failed");
+		ser::serialize_default(&mut vec, &self).expect("serialization failed");
 		::std::hash::Hash::hash(&vec, state);
 	}
 }
@@ -1270,7 +1441,7 @@ hashable_ord!(Output);
 impl ::std::hash::Hash for Output {
 	fn hash<H: ::std::hash::Hasher>(&self, state: &mut H) {
 		let mut vec = Vec::new();
-		ser::serialize(&mut vec, &self).expect("serialization failed");
+		ser::serialize_default(&mut vec, &self).expect("serialization failed");
 		::std::hash::Hash::hash(&vec, state);
 	}
 }
@@ -1520,8 +1691,8 @@ mod test {
 		};
 
 		let mut vec = vec![];
-		ser::serialize(&mut vec, &kernel).expect("serialized failed");
-		let kernel2: TxKernel = ser::deserialize(&mut &vec[..]).unwrap();
+		ser::serialize_default(&mut vec, &kernel).expect("serialized failed");
+		let kernel2: TxKernel = ser::deserialize_default(&mut &vec[..]).unwrap();
 		assert_eq!(kernel2.features, KernelFeatures::Plain);
 		assert_eq!(kernel2.lock_height, 0);
 		assert_eq!(kernel2.excess, commit);
@@ -1538,8 +1709,8 @@ mod test {
 		};
 
 		let mut vec = vec![];
-		ser::serialize(&mut vec, &kernel).expect("serialized failed");
-		let kernel2: TxKernel = ser::deserialize(&mut &vec[..]).unwrap();
+		ser::serialize_default(&mut vec, &kernel).expect("serialized failed");
+		let kernel2: TxKernel = ser::deserialize_default(&mut &vec[..]).unwrap();
 		assert_eq!(kernel2.features, KernelFeatures::HeightLocked);
 		assert_eq!(kernel2.lock_height, 100);
 		assert_eq!(kernel2.excess, commit);
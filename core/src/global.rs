@@ -18,11 +18,11 @@
 
 use crate::consensus::HeaderInfo;
 use crate::consensus::{
-	graph_weight, BASE_EDGE_BITS, BLOCK_TIME_SEC, COINBASE_MATURITY, CUT_THROUGH_HORIZON,
-	DAY_HEIGHT, DEFAULT_MIN_EDGE_BITS, DIFFICULTY_ADJUST_WINDOW, INITIAL_DIFFICULTY,
-	MAX_BLOCK_WEIGHT, PROOFSIZE, SECOND_POW_EDGE_BITS, STATE_SYNC_THRESHOLD,
+	graph_weight_with_params, BASE_EDGE_BITS, BLOCK_TIME_SEC, COINBASE_MATURITY,
+	CUT_THROUGH_HORIZON, DAY_HEIGHT, DEFAULT_MIN_EDGE_BITS, DIFFICULTY_ADJUST_WINDOW,
+	INITIAL_DIFFICULTY, MAX_BLOCK_WEIGHT, PROOFSIZE, SECOND_POW_EDGE_BITS, STATE_SYNC_THRESHOLD,
 };
-use crate::pow::{self, new_cuckaroo_ctx, new_cuckatoo_ctx, EdgeType, PoWContext};
+use crate::pow::{self, new_cuckaroo_ctx, new_cuckatoo_ctx, new_mean_ctx, EdgeType, PoWContext};
 /// An enum collecting sets of parameters used throughout the
 /// code wherever mining is needed. This should allow for
 /// different sets of parameters for different purposes,
@@ -56,6 +56,13 @@ pub const TESTING_CUT_THROUGH_HORIZON: u32 = 70;
 /// Testing state sync threshold in blocks
 pub const TESTING_STATE_SYNC_THRESHOLD: u32 = 20;
 
+/// Number of blocks between the heights txhashset archives are built at for
+/// syncing peers, 12 hours worth
+pub const TXHASHSET_ARCHIVE_INTERVAL: u64 = 12 * 60;
+
+/// Testing txhashset archive interval in blocks
+pub const TESTING_TXHASHSET_ARCHIVE_INTERVAL: u64 = 10;
+
 /// Testing initial graph weight
 pub const TESTING_INITIAL_GRAPH_WEIGHT: u32 = 1;
 
@@ -65,6 +72,15 @@ pub const TESTING_INITIAL_DIFFICULTY: u64 = 1;
 /// Testing max_block_weight (artifically low, just enough to support a few txs).
 pub const TESTING_MAX_BLOCK_WEIGHT: usize = 150;
 
+/// Magic bytes starting p2p messages on testing networks
+pub const OTHER_MAGIC: [u8; 2] = [73, 43];
+
+/// Magic bytes starting p2p messages on floonet
+pub const FLOONET_MAGIC: [u8; 2] = [83, 59];
+
+/// Magic bytes starting p2p messages on mainnet
+pub const MAINNET_MAGIC: [u8; 2] = [97, 61];
+
 /// If a peer's last updated difficulty is 2 hours ago and its difficulty's lower than ours,
 /// we're sure this peer is a stuck node, and we will kick out such kind of stuck peers.
 pub const STUCK_PEER_KICK_TIME: i64 = 2 * 3600 * 1000;
@@ -94,6 +110,9 @@ pub enum ChainTypes {
 	Floonet,
 	/// Main production network
 	Mainnet,
+	/// Custom local network, with its parameters and genesis block loaded
+	/// from a chain file
+	Custom,
 }
 
 impl ChainTypes {
@@ -104,6 +123,7 @@ impl ChainTypes {
 			ChainTypes::UserTesting => "user".to_owned(),
 			ChainTypes::Floonet => "floo".to_owned(),
 			ChainTypes::Mainnet => "main".to_owned(),
+			ChainTypes::Custom => "custom".to_owned(),
 		}
 	}
 }
@@ -125,26 +145,283 @@ pub enum PoWContextTypes {
 	Cuckaroo,
 }
 
+/// PoW solver used when mining
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub enum PoWSolverTypes {
+	/// Reference (lean, single threaded) solver, mostly for tests
+	Reference,
+	/// Multi-threaded mean solver, much faster but memory heavy
+	Mean,
+}
+
+impl Default for PoWSolverTypes {
+	fn default() -> PoWSolverTypes {
+		PoWSolverTypes::Reference
+	}
+}
+
+/// Parameters of a chain, everything that differs between the chain types.
+/// Presets exist for each of them (see `ChainTypes::params`). Components
+/// running a chain (`Chain`, the transaction pool, the p2p server) are given
+/// their parameters explicitly, only tests and tools fall back on the process
+/// wide ones (see `chain_params`).
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ChainParams {
+	/// The chain type these parameters are for
+	pub chain_type: ChainTypes,
+	/// Magic bytes starting every p2p message, so that peers of different
+	/// chains can't talk to each other
+	pub magic: [u8; 2],
+	/// Block interval the difficulty adjustment targets, in seconds
+	pub block_time_sec: u64,
+	/// The minimum acceptable edge_bits
+	pub min_edge_bits: u8,
+	/// Reference edge_bits used to compute factor on higher Cuck(at)oo graph
+	/// sizes
+	pub base_edge_bits: u8,
+	/// The proofsize
+	pub proofsize: usize,
+	/// Coinbase maturity for coinbases to be spent
+	pub coinbase_maturity: u64,
+	/// Initial mining difficulty
+	pub initial_block_difficulty: u64,
+	/// Initial mining secondary scale
+	pub initial_graph_weight: u32,
+	/// Maximum allowed block weight
+	pub max_block_weight: usize,
+	/// Horizon at which we can cut-through and do full local pruning
+	pub cut_through_horizon: u32,
+	/// Threshold at which we can request a txhashset (and full blocks from)
+	pub state_sync_threshold: u32,
+	/// Number of blocks between the heights txhashset archives are built at
+	pub txhashset_archive_interval: u64,
+	/// Nonce known to create a valid POW on the genesis block
+	pub genesis_nonce: u64,
+}
+
+impl ChainParams {
+	/// Parameters for CI testing
+	pub fn automated_testing() -> ChainParams {
+		ChainParams {
+			chain_type: ChainTypes::AutomatedTesting,
+			magic: OTHER_MAGIC,
+			block_time_sec: BLOCK_TIME_SEC,
+			min_edge_bits: AUTOMATED_TESTING_MIN_EDGE_BITS,
+			base_edge_bits: AUTOMATED_TESTING_MIN_EDGE_BITS,
+			proofsize: AUTOMATED_TESTING_PROOF_SIZE,
+			coinbase_maturity: AUTOMATED_TESTING_COINBASE_MATURITY,
+			initial_block_difficulty: TESTING_INITIAL_DIFFICULTY,
+			initial_graph_weight: TESTING_INITIAL_GRAPH_WEIGHT,
+			max_block_weight: TESTING_MAX_BLOCK_WEIGHT,
+			cut_through_horizon: TESTING_CUT_THROUGH_HORIZON,
+			state_sync_threshold: TESTING_STATE_SYNC_THRESHOLD,
+			txhashset_archive_interval: TESTING_TXHASHSET_ARCHIVE_INTERVAL,
+			// won't make a difference
+			genesis_nonce: 0,
+		}
+	}
+
+	/// Parameters for user testing
+	pub fn user_testing() -> ChainParams {
+		ChainParams {
+			chain_type: ChainTypes::UserTesting,
+			magic: OTHER_MAGIC,
+			block_time_sec: BLOCK_TIME_SEC,
+			min_edge_bits: USER_TESTING_MIN_EDGE_BITS,
+			base_edge_bits: USER_TESTING_MIN_EDGE_BITS,
+			proofsize: USER_TESTING_PROOF_SIZE,
+			coinbase_maturity: USER_TESTING_COINBASE_MATURITY,
+			initial_block_difficulty: TESTING_INITIAL_DIFFICULTY,
+			initial_graph_weight: TESTING_INITIAL_GRAPH_WEIGHT,
+			max_block_weight: TESTING_MAX_BLOCK_WEIGHT,
+			cut_through_horizon: TESTING_CUT_THROUGH_HORIZON,
+			state_sync_threshold: TESTING_STATE_SYNC_THRESHOLD,
+			txhashset_archive_interval: TESTING_TXHASHSET_ARCHIVE_INTERVAL,
+			// Magic nonce for current genesis block at cuckatoo15
+			genesis_nonce: 27944,
+		}
+	}
+
+	/// Parameters of the protocol testing network
+	pub fn floonet() -> ChainParams {
+		ChainParams {
+			chain_type: ChainTypes::Floonet,
+			magic: FLOONET_MAGIC,
+			..ChainParams::mainnet()
+		}
+	}
+
+	/// Parameters of the main production network
+	pub fn mainnet() -> ChainParams {
+		let mut params = ChainParams {
+			chain_type: ChainTypes::Mainnet,
+			magic: MAINNET_MAGIC,
+			block_time_sec: BLOCK_TIME_SEC,
+			min_edge_bits: DEFAULT_MIN_EDGE_BITS,
+			base_edge_bits: BASE_EDGE_BITS,
+			proofsize: PROOFSIZE,
+			coinbase_maturity: COINBASE_MATURITY,
+			initial_block_difficulty: INITIAL_DIFFICULTY,
+			initial_graph_weight: 0,
+			max_block_weight: MAX_BLOCK_WEIGHT,
+			cut_through_horizon: CUT_THROUGH_HORIZON,
+			state_sync_threshold: STATE_SYNC_THRESHOLD,
+			txhashset_archive_interval: TXHASHSET_ARCHIVE_INTERVAL,
+			// Placeholder, obviously not the right value
+			genesis_nonce: 0,
+		};
+		params.initial_graph_weight =
+			graph_weight_with_params(&params, 0, SECOND_POW_EDGE_BITS) as u32;
+		params
+	}
+
+	/// Default parameters of a custom network, the ones of a user testing
+	/// network with production horizons and block weight. Meant to be
+	/// overridden by the chain file of the network.
+	pub fn custom() -> ChainParams {
+		ChainParams {
+			chain_type: ChainTypes::Custom,
+			max_block_weight: MAX_BLOCK_WEIGHT,
+			cut_through_horizon: CUT_THROUGH_HORIZON,
+			state_sync_threshold: STATE_SYNC_THRESHOLD,
+			txhashset_archive_interval: TXHASHSET_ARCHIVE_INTERVAL,
+			genesis_nonce: 0,
+			..ChainParams::user_testing()
+		}
+	}
+
+	/// PoW variant of the graphs with the provided edge_bits
+	pub fn pow_context_type(&self, edge_bits: u8) -> PoWContextTypes {
+		match self.chain_type {
+			// Mainnet has Cuckaroo29 for AR and Cuckatoo30+ for AF
+			ChainTypes::Mainnet if edge_bits == 29 => PoWContextTypes::Cuckaroo,
+
+			// Same for Floonet
+			ChainTypes::Floonet if edge_bits == 29 => PoWContextTypes::Cuckaroo,
+
+			// Everything else is Cuckatoo only
+			_ => PoWContextTypes::Cuckatoo,
+		}
+	}
+
+	/// Return either a cuckoo context or a cuckatoo context
+	/// Single change point
+	pub fn create_pow_context<T>(
+		&self,
+		height: u64,
+		edge_bits: u8,
+		proof_size: usize,
+		max_sols: u32,
+	) -> Result<Box<dyn PoWContext<T>>, pow::Error>
+	where
+		T: EdgeType + 'static,
+	{
+		self.create_solver_pow_context(
+			PoWSolverTypes::Reference,
+			height,
+			edge_bits,
+			proof_size,
+			max_sols,
+		)
+	}
+
+	/// Same as `create_pow_context` with the provided solver finding the
+	/// cycles. All solvers verify proofs the same way.
+	pub fn create_solver_pow_context<T>(
+		&self,
+		solver: PoWSolverTypes,
+		_height: u64,
+		edge_bits: u8,
+		proof_size: usize,
+		max_sols: u32,
+	) -> Result<Box<dyn PoWContext<T>>, pow::Error>
+	where
+		T: EdgeType + 'static,
+	{
+		let variant = self.pow_context_type(edge_bits);
+		match solver {
+			PoWSolverTypes::Mean => new_mean_ctx(variant, edge_bits, proof_size, max_sols),
+			PoWSolverTypes::Reference if variant == PoWContextTypes::Cuckaroo => {
+				new_cuckaroo_ctx(edge_bits, proof_size)
+			}
+			PoWSolverTypes::Reference => new_cuckatoo_ctx(edge_bits, proof_size, max_sols),
+		}
+	}
+
+	/// Height of the txhashset to sync from given the height of the (header)
+	/// head: state_sync_threshold blocks behind it, rounded down to the
+	/// archive interval so that all syncing nodes ask for the same archive.
+	pub fn txhashset_archive_height(&self, head_height: u64) -> u64 {
+		let height = head_height.saturating_sub(self.state_sync_threshold as u64 + 1);
+		height - height % self.txhashset_archive_interval
+	}
+
+	/// Are these automated testing parameters?
+	pub fn is_automated_testing_mode(&self) -> bool {
+		ChainTypes::AutomatedTesting == self.chain_type
+	}
+
+	/// Are these user testing parameters?
+	pub fn is_user_testing_mode(&self) -> bool {
+		ChainTypes::UserTesting == self.chain_type
+	}
+
+	/// Are these parameters of a production network?
+	/// Production defined as a live public network, testnet[n] or mainnet.
+	pub fn is_production_mode(&self) -> bool {
+		ChainTypes::Floonet == self.chain_type || ChainTypes::Mainnet == self.chain_type
+	}
+
+	/// Are these floonet parameters?
+	pub fn is_floonet(&self) -> bool {
+		ChainTypes::Floonet == self.chain_type
+	}
+}
+
+impl ChainTypes {
+	/// Preset parameters of the chain type, only defaults for a custom chain
+	/// whose parameters come from its chain file
+	pub fn params(&self) -> ChainParams {
+		match *self {
+			ChainTypes::AutomatedTesting => ChainParams::automated_testing(),
+			ChainTypes::UserTesting => ChainParams::user_testing(),
+			ChainTypes::Floonet => ChainParams::floonet(),
+			ChainTypes::Mainnet => ChainParams::mainnet(),
+			ChainTypes::Custom => ChainParams::custom(),
+		}
+	}
+}
+
 lazy_static! {
-	/// The mining parameter mode
-	pub static ref CHAIN_TYPE: RwLock<ChainTypes> =
-			RwLock::new(ChainTypes::Mainnet);
+	/// The process wide chain parameters
+	pub static ref CHAIN_PARAMS: RwLock<ChainParams> =
+			RwLock::new(ChainParams::mainnet());
 
 	/// PoW context type to instantiate
 	pub static ref POW_CONTEXT_TYPE: RwLock<PoWContextTypes> =
 			RwLock::new(PoWContextTypes::Cuckoo);
 }
 
-/// Set the mining mode
+/// Set the mining mode, process wide
 pub fn set_mining_mode(mode: ChainTypes) {
-	let mut param_ref = CHAIN_TYPE.write();
-	*param_ref = mode;
+	set_chain_params(mode.params());
+}
+
+/// Set the chain parameters, process wide
+pub fn set_chain_params(params: ChainParams) {
+	let mut param_ref = CHAIN_PARAMS.write();
+	*param_ref = params;
+}
+
+/// A copy of the process wide chain parameters
+pub fn chain_params() -> ChainParams {
+	CHAIN_PARAMS.read().clone()
 }
 
 /// Return either a cuckoo context or a cuckatoo context
 /// Single change point
 pub fn create_pow_context<T>(
-	_height: u64,
+	height: u64,
 	edge_bits: u8,
 	proof_size: usize,
 	max_sols: u32,
@@ -152,132 +429,83 @@ pub fn create_pow_context<T>(
 where
 	T: EdgeType + 'static,
 {
-	let chain_type = CHAIN_TYPE.read().clone();
-	match chain_type {
-		// Mainnet has Cuckaroo29 for AR and Cuckatoo30+ for AF
-		ChainTypes::Mainnet if edge_bits == 29 => new_cuckaroo_ctx(edge_bits, proof_size),
-		ChainTypes::Mainnet => new_cuckatoo_ctx(edge_bits, proof_size, max_sols),
-
-		// Same for Floonet
-		ChainTypes::Floonet if edge_bits == 29 => new_cuckaroo_ctx(edge_bits, proof_size),
-		ChainTypes::Floonet => new_cuckatoo_ctx(edge_bits, proof_size, max_sols),
-
-		// Everything else is Cuckatoo only
-		_ => new_cuckatoo_ctx(edge_bits, proof_size, max_sols),
-	}
+	CHAIN_PARAMS
+		.read()
+		.create_pow_context(height, edge_bits, proof_size, max_sols)
 }
 
 /// The minimum acceptable edge_bits
 pub fn min_edge_bits() -> u8 {
-	let param_ref = CHAIN_TYPE.read();
-	match *param_ref {
-		ChainTypes::AutomatedTesting => AUTOMATED_TESTING_MIN_EDGE_BITS,
-		ChainTypes::UserTesting => USER_TESTING_MIN_EDGE_BITS,
-		_ => DEFAULT_MIN_EDGE_BITS,
-	}
+	CHAIN_PARAMS.read().min_edge_bits
 }
 
 /// Reference edge_bits used to compute factor on higher Cuck(at)oo graph sizes,
 /// while the min_edge_bits can be changed on a soft fork, changing
 /// base_edge_bits is a hard fork.
 pub fn base_edge_bits() -> u8 {
-	let param_ref = CHAIN_TYPE.read();
-	match *param_ref {
-		ChainTypes::AutomatedTesting => AUTOMATED_TESTING_MIN_EDGE_BITS,
-		ChainTypes::UserTesting => USER_TESTING_MIN_EDGE_BITS,
-		_ => BASE_EDGE_BITS,
-	}
+	CHAIN_PARAMS.read().base_edge_bits
 }
 
 /// The proofsize
 pub fn proofsize() -> usize {
-	let param_ref = CHAIN_TYPE.read();
-	match *param_ref {
-		ChainTypes::AutomatedTesting => AUTOMATED_TESTING_PROOF_SIZE,
-		ChainTypes::UserTesting => USER_TESTING_PROOF_SIZE,
-		_ => PROOFSIZE,
-	}
+	CHAIN_PARAMS.read().proofsize
 }
 
 /// Coinbase maturity for coinbases to be spent
 pub fn coinbase_maturity() -> u64 {
-	let param_ref = CHAIN_TYPE.read();
-	match *param_ref {
-		ChainTypes::AutomatedTesting => AUTOMATED_TESTING_COINBASE_MATURITY,
-		ChainTypes::UserTesting => USER_TESTING_COINBASE_MATURITY,
-		_ => COINBASE_MATURITY,
-	}
+	CHAIN_PARAMS.read().coinbase_maturity
 }
 
 /// Initial mining difficulty
 pub fn initial_block_difficulty() -> u64 {
-	let param_ref = CHAIN_TYPE.read();
-	match *param_ref {
-		ChainTypes::AutomatedTesting => TESTING_INITIAL_DIFFICULTY,
-		ChainTypes::UserTesting => TESTING_INITIAL_DIFFICULTY,
-		ChainTypes::Floonet => INITIAL_DIFFICULTY,
-		ChainTypes::Mainnet => INITIAL_DIFFICULTY,
-	}
+	CHAIN_PARAMS.read().initial_block_difficulty
 }
 /// Initial mining secondary scale
 pub fn initial_graph_weight() -> u32 {
-	let param_ref = CHAIN_TYPE.read();
-	match *param_ref {
-		ChainTypes::AutomatedTesting => TESTING_INITIAL_GRAPH_WEIGHT,
-		ChainTypes::UserTesting => TESTING_INITIAL_GRAPH_WEIGHT,
-		ChainTypes::Floonet => graph_weight(0, SECOND_POW_EDGE_BITS) as u32,
-		ChainTypes::Mainnet => graph_weight(0, SECOND_POW_EDGE_BITS) as u32,
-	}
+	CHAIN_PARAMS.read().initial_graph_weight
 }
 
 /// Maximum allowed block weight.
 pub fn max_block_weight() -> usize {
-	let param_ref = CHAIN_TYPE.read();
-	match *param_ref {
-		ChainTypes::AutomatedTesting => TESTING_MAX_BLOCK_WEIGHT,
-		ChainTypes::UserTesting => TESTING_MAX_BLOCK_WEIGHT,
-		ChainTypes::Floonet => MAX_BLOCK_WEIGHT,
-		ChainTypes::Mainnet => MAX_BLOCK_WEIGHT,
-	}
+	CHAIN_PARAMS.read().max_block_weight
 }
 
 /// Horizon at which we can cut-through and do full local pruning
 pub fn cut_through_horizon() -> u32 {
-	let param_ref = CHAIN_TYPE.read();
-	match *param_ref {
-		ChainTypes::AutomatedTesting => TESTING_CUT_THROUGH_HORIZON,
-		ChainTypes::UserTesting => TESTING_CUT_THROUGH_HORIZON,
-		_ => CUT_THROUGH_HORIZON,
-	}
+	CHAIN_PARAMS.read().cut_through_horizon
 }
 
 /// Threshold at which we can request a txhashset (and full blocks from)
 pub fn state_sync_threshold() -> u32 {
-	let param_ref = CHAIN_TYPE.read();
-	match *param_ref {
-		ChainTypes::AutomatedTesting => TESTING_STATE_SYNC_THRESHOLD,
-		ChainTypes::UserTesting => TESTING_STATE_SYNC_THRESHOLD,
-		_ => STATE_SYNC_THRESHOLD,
-	}
+	CHAIN_PARAMS.read().state_sync_threshold
+}
+
+/// Number of blocks between the heights txhashset archives are built at
+pub fn txhashset_archive_interval() -> u64 {
+	CHAIN_PARAMS.read().txhashset_archive_interval
+}
+
+/// Height of the txhashset to sync from given the height of the (header)
+/// head: state_sync_threshold blocks behind it, rounded down to the archive
+/// interval so that all syncing nodes ask for the same archive.
+pub fn txhashset_archive_height(head_height: u64) -> u64 {
+	CHAIN_PARAMS.read().txhashset_archive_height(head_height)
 }
 
 /// Are we in automated testing mode?
 pub fn is_automated_testing_mode() -> bool {
-	let param_ref = CHAIN_TYPE.read();
-	ChainTypes::AutomatedTesting == *param_ref
+	CHAIN_PARAMS.read().is_automated_testing_mode()
 }
 
 /// Are we in user testing mode?
 pub fn is_user_testing_mode() -> bool {
-	let param_ref = CHAIN_TYPE.read();
-	ChainTypes::UserTesting == *param_ref
+	CHAIN_PARAMS.read().is_user_testing_mode()
 }
 
 /// Are we in production mode?
 /// Production defined as a live public network, testnet[n] or mainnet.
 pub fn is_production_mode() -> bool {
-	let param_ref = CHAIN_TYPE.read();
-	ChainTypes::Floonet == *param_ref || ChainTypes::Mainnet == *param_ref
+	CHAIN_PARAMS.read().is_production_mode()
 }
 
 /// Are we in floonet?
@@ -285,14 +513,12 @@ pub fn is_production_mode() -> bool {
 /// as possible to "mainnet" configuration as possible.
 /// We want to avoid missing any mainnet only code paths.
 pub fn is_floonet() -> bool {
-	let param_ref = CHAIN_TYPE.read();
-	ChainTypes::Floonet == *param_ref
+	CHAIN_PARAMS.read().is_floonet()
 }
 
 /// Are we for real?
 pub fn is_mainnet() -> bool {
-	let param_ref = CHAIN_TYPE.read();
-	ChainTypes::Mainnet == *param_ref
+	ChainTypes::Mainnet == CHAIN_PARAMS.read().chain_type
 }
 
 /// Helper function to get a nonce known to create a valid POW on
@@ -300,30 +526,28 @@ pub fn is_mainnet() -> bool {
 /// as the genesis block POW solution turns out to be the same for every new
 /// block chain at the moment
 pub fn get_genesis_nonce() -> u64 {
-	let param_ref = CHAIN_TYPE.read();
-	match *param_ref {
-		// won't make a difference
-		ChainTypes::AutomatedTesting => 0,
-		// Magic nonce for current genesis block at cuckatoo15
-		ChainTypes::UserTesting => 27944,
-		// Placeholder, obviously not the right value
-		ChainTypes::Floonet => 0,
-		// Placeholder, obviously not the right value
-		ChainTypes::Mainnet => 0,
-	}
+	CHAIN_PARAMS.read().genesis_nonce
 }
 
 /// Short name representing the current chain type ("floo", "main", etc.)
 pub fn chain_shortname() -> String {
-	let param_ref = CHAIN_TYPE.read();
-	param_ref.shortname()
+	CHAIN_PARAMS.read().chain_type.shortname()
 }
 
 /// Converts an iterator of block difficulty data to more a more manageable
 /// vector and pads if needed (which will) only be needed for the first few
-/// blocks after genesis
-
+/// blocks after genesis. Uses the process wide chain parameters, for tests
+/// and tools only.
 pub fn difficulty_data_to_vector<T>(cursor: T) -> Vec<HeaderInfo>
+where
+	T: IntoIterator<Item = HeaderInfo>,
+{
+	difficulty_data_to_vector_with_params(&chain_params(), cursor)
+}
+
+/// Converts an iterator of block difficulty data to a vector, padded as
+/// needed (see `difficulty_data_to_vector`), with explicit chain parameters.
+pub fn difficulty_data_to_vector_with_params<T>(params: &ChainParams, cursor: T) -> Vec<HeaderInfo>
 where
 	T: IntoIterator<Item = HeaderInfo>,
 {
@@ -339,7 +563,7 @@ where
 		let last_ts_delta = if n > 1 {
 			last_n[0].timestamp - last_n[1].timestamp
 		} else {
-			BLOCK_TIME_SEC
+			params.block_time_sec
 		};
 		let last_diff = last_n[0].difficulty;
 
@@ -347,7 +571,12 @@ where
 		let mut last_ts = last_n.last().unwrap().timestamp;
 		for _ in n..needed_block_count {
 			last_ts = last_ts.saturating_sub(last_ts_delta);
-			last_n.push(HeaderInfo::from_ts_diff(last_ts, last_diff.clone()));
+			last_n.push(HeaderInfo::new(
+				last_ts,
+				last_diff.clone(),
+				params.initial_graph_weight,
+				true,
+			));
 		}
 	}
 	last_n.reverse();
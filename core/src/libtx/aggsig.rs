@@ -461,3 +461,191 @@ pub fn sign_with_blinding(
 	let sig = aggsig::sign_single(secp, &msg, skey, None, None, None, pubkey_sum, None)?;
 	Ok(sig)
 }
+
+/// Calculates a partial adaptor (pre-)signature, adapted by the point
+/// `adaptor` (`t*G`, where the secret `t` may be unknown to the signer).
+/// Works as [`calculate_partial_sig`](fn.calculate_partial_sig.html) except
+/// that the adaptor point is added to the nonce sum encoded in e. The sum of
+/// all partial adaptor signatures only becomes a valid signature once
+/// completed with `t`, and publishing it reveals `t` to whoever holds the
+/// adaptor signature.
+///
+/// # Arguments
+///
+/// * `secp` - A Secp256k1 Context initialized for Signing
+/// * `sec_key` - The signer's secret key
+/// * `sec_nonce` - The signer's secret nonce (the public version of which
+/// was added to the `nonce_sum` total)
+/// * `nonce_sum` - The sum of the public nonces of all signers participating
+/// in the full signature, without the adaptor point.
+/// * `adaptor` - The adaptor point `t*G`.
+/// * `pubkey_sum` - (Optional) The sum of the public keys of all signers participating
+/// in the full signature. If included, this value is encoded in e.
+/// * `msg` - The message to sign.
+
+pub fn calculate_adaptor_partial_sig(
+	secp: &Secp256k1,
+	sec_key: &SecretKey,
+	sec_nonce: &SecretKey,
+	nonce_sum: &PublicKey,
+	adaptor: &PublicKey,
+	pubkey_sum: Option<&PublicKey>,
+	msg: &secp::Message,
+) -> Result<Signature, Error> {
+	let adapted_nonce_sum = adapted_nonce_sum(secp, nonce_sum, adaptor)?;
+	calculate_partial_sig(
+		secp,
+		sec_key,
+		sec_nonce,
+		&adapted_nonce_sum,
+		pubkey_sum,
+		msg,
+	)
+}
+
+/// Verifies a partial adaptor signature, created via a call to
+/// [`calculate_adaptor_partial_sig`](fn.calculate_adaptor_partial_sig.html),
+/// from the public key of its signer. Also verifies the sum of all partial
+/// adaptor signatures (see [`add_adaptor_signatures`](fn.add_adaptor_signatures.html))
+/// when given the sum of the public keys as `pubkey`.
+/// Returns `Ok(())` if the adaptor signature is valid, or a Signature
+/// [ErrorKind](../enum.ErrorKind.html) otherwise
+///
+/// # Arguments
+///
+/// * `secp` - A Secp256k1 Context initialized for Validation
+/// * `sig` - The adaptor signature to validate
+/// * `pub_nonce_sum` - The sum of the public nonces of all signers participating
+/// in the full signature, without the adaptor point.
+/// * `adaptor` - The adaptor point `t*G`.
+/// * `pubkey` - Corresponding Public Key of the private key used to sign the message.
+/// * `pubkey_sum` - (Optional) The sum of the public keys of all signers participating
+/// in the full signature. If included, this value is encoded in e.
+/// * `msg` - The message to verify.
+
+pub fn verify_adaptor_partial_sig(
+	secp: &Secp256k1,
+	sig: &Signature,
+	pub_nonce_sum: &PublicKey,
+	adaptor: &PublicKey,
+	pubkey: &PublicKey,
+	pubkey_sum: Option<&PublicKey>,
+	msg: &secp::Message,
+) -> Result<(), Error> {
+	let adapted_nonce_sum = adapted_nonce_sum(secp, pub_nonce_sum, adaptor)?;
+	verify_partial_sig(secp, sig, &adapted_nonce_sum, pubkey, pubkey_sum, msg)
+}
+
+/// Adds partial adaptor signatures into the adaptor signature of the whole
+/// set of signers, verifiable with
+/// [`verify_adaptor_partial_sig`](fn.verify_adaptor_partial_sig.html) against
+/// the sum of their public keys. Note the nonce sum here doesn't include
+/// the adaptor point.
+pub fn add_adaptor_signatures(
+	secp: &Secp256k1,
+	part_sigs: Vec<&Signature>,
+	nonce_sum: &PublicKey,
+) -> Result<Signature, Error> {
+	add_signatures(secp, part_sigs, nonce_sum)
+}
+
+/// Completes an adaptor signature with the secret `t` behind its adaptor
+/// point, giving a regular signature verifying against the sum of the public
+/// keys (see [`verify_completed_sig`](fn.verify_completed_sig.html)).
+///
+/// # Arguments
+///
+/// * `secp` - A Secp256k1 Context initialized for Signing
+/// * `adaptor_sig` - The (summed) adaptor signature
+/// * `secret` - The secret `t`
+/// * `nonce_sum` - The sum of the public nonces of all signers participating
+/// in the full signature, without the adaptor point.
+/// * `pubkey_sum` - The sum of the public keys of all signers participating
+/// in the full signature, also encoded in e.
+/// * `msg` - The signed message.
+
+pub fn complete_adaptor_sig(
+	secp: &Secp256k1,
+	adaptor_sig: &Signature,
+	secret: &SecretKey,
+	nonce_sum: &PublicKey,
+	pubkey_sum: &PublicKey,
+	msg: &secp::Message,
+) -> Result<Signature, Error> {
+	let adaptor = PublicKey::from_secret_key(secp, secret)?;
+	let adapted_nonce_sum = adapted_nonce_sum(secp, nonce_sum, &adaptor)?;
+	let nonce = adapted_nonce_sum.serialize_vec(secp, true);
+	let s = sig_scalar(secp, adaptor_sig)?;
+
+	// Signers negate their nonces when the adapted nonce sum doesn't have a
+	// quadratic residue y, in which case the secret needs to be subtracted
+	// instead of added. Only one of them verifies (as a kernel signature would).
+	let candidates = vec![
+		secp.blind_sum(vec![s.clone(), secret.clone()], vec![])?,
+		secp.blind_sum(vec![s], vec![secret.clone()])?,
+	];
+	for s in candidates {
+		let sig = sig_from_parts(&nonce[1..], &s)?;
+		if verify_single(secp, &sig, msg, None, pubkey_sum, Some(pubkey_sum), false) {
+			return Ok(sig);
+		}
+	}
+	Err(ErrorKind::Signature("Adaptor signature completion error".to_string()).into())
+}
+
+/// Extracts the secret `t` behind the adaptor point of an adaptor signature,
+/// given the completed signature (as found in a kernel for example).
+///
+/// # Arguments
+///
+/// * `secp` - A Secp256k1 Context initialized for Signing
+/// * `sig` - The completed signature
+/// * `adaptor_sig` - The (summed) adaptor signature it was completed from
+/// * `adaptor` - The adaptor point `t*G`.
+
+pub fn extract_adaptor_secret(
+	secp: &Secp256k1,
+	sig: &Signature,
+	adaptor_sig: &Signature,
+	adaptor: &PublicKey,
+) -> Result<SecretKey, Error> {
+	let s = sig_scalar(secp, sig)?;
+	let adaptor_s = sig_scalar(secp, adaptor_sig)?;
+
+	// The secret is the difference of both, with a sign depending on the
+	// adapted nonce sum (see `complete_adaptor_sig`).
+	let candidates = vec![
+		secp.blind_sum(vec![s.clone()], vec![adaptor_s.clone()])?,
+		secp.blind_sum(vec![adaptor_s], vec![s])?,
+	];
+	for secret in candidates {
+		if PublicKey::from_secret_key(secp, &secret)? == *adaptor {
+			return Ok(secret);
+		}
+	}
+	Err(ErrorKind::Signature("Adaptor secret extraction error".to_string()).into())
+}
+
+// Sum of the public nonces and of the adaptor point, the nonce of the
+// completed signature.
+fn adapted_nonce_sum(
+	secp: &Secp256k1,
+	nonce_sum: &PublicKey,
+	adaptor: &PublicKey,
+) -> Result<PublicKey, Error> {
+	Ok(PublicKey::from_combination(secp, vec![nonce_sum, adaptor])?)
+}
+
+// Signatures are serialized as the x coordinate of their nonce followed by
+// their scalar, both big endian.
+fn sig_scalar(secp: &Secp256k1, sig: &Signature) -> Result<SecretKey, Error> {
+	let raw = sig.to_raw_data();
+	Ok(SecretKey::from_slice(secp, &raw[32..])?)
+}
+
+fn sig_from_parts(nonce_x: &[u8], s: &SecretKey) -> Result<Signature, Error> {
+	let mut raw = [0; 64];
+	raw[..32].copy_from_slice(nonce_x);
+	raw[32..].copy_from_slice(&s.0);
+	Ok(Signature::from_raw_data(&raw)?)
+}
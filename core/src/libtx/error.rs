@@ -44,6 +44,12 @@ pub enum ErrorKind {
 	/// Rangeproof error
 	#[fail(display = "Rangeproof Error")]
 	RangeProof(String),
+	/// Multi-party transaction building error
+	#[fail(display = "Tx Builder Error")]
+	TxBuilder(String),
+	/// Payment proof error
+	#[fail(display = "Payment Proof Error")]
+	PaymentProof(String),
 }
 
 impl Fail for Error {
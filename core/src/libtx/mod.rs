@@ -24,9 +24,11 @@
 pub mod aggsig;
 pub mod build;
 mod error;
+pub mod payment_proof;
 pub mod proof;
 pub mod reward;
 pub mod secp_ser;
+pub mod tx_builder;
 
 use crate::consensus;
 use crate::core::Transaction;
@@ -0,0 +1,106 @@
+// Copyright 2018 The Grin Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Payment proofs, evidence that a transaction kernel paid a given amount to
+//! a receiver. The receiver signs the amount, the kernel excess and the
+//! address of the sender, who keeps the proof until a dispute arises. Anyone
+//! can then check the signature and, with a node, that the kernel made it to
+//! the chain. Addresses are plain secp256k1 public keys, how wallets derive
+//! them is up to them.
+
+use crate::blake2::blake2b::blake2b;
+use crate::core::TxKernel;
+use crate::libtx::error::{Error, ErrorKind};
+use crate::libtx::secp_ser;
+use crate::util::secp::key::{PublicKey, SecretKey};
+use crate::util::secp::pedersen::Commitment;
+use crate::util::secp::{Message, Secp256k1, Signature};
+
+/// Proof that the kernel with the provided excess paid the amount to the
+/// receiver, signed by the receiver.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PaymentProof {
+	/// Amount paid
+	#[serde(with = "secp_ser::string_or_u64")]
+	pub amount: u64,
+	/// Excess commitment of the kernel of the payment transaction
+	#[serde(
+		serialize_with = "secp_ser::as_hex",
+		deserialize_with = "secp_ser::commitment_from_hex"
+	)]
+	pub excess: Commitment,
+	/// Address (public key) of the sender
+	#[serde(with = "secp_ser::pubkey_serde")]
+	pub sender_address: PublicKey,
+	/// Address (public key) of the receiver
+	#[serde(with = "secp_ser::pubkey_serde")]
+	pub receiver_address: PublicKey,
+	/// Signature of the receiver over the amount, excess and sender address
+	#[serde(with = "secp_ser::sig_serde")]
+	pub receiver_signature: Signature,
+}
+
+impl PaymentProof {
+	/// The message signed by the receiver, hash of the amount, the kernel
+	/// excess and the sender address.
+	pub fn message(
+		secp: &Secp256k1,
+		amount: u64,
+		excess: &Commitment,
+		sender_address: &PublicKey,
+	) -> Result<Message, Error> {
+		let mut data = amount.to_be_bytes().to_vec();
+		data.extend_from_slice(&excess.0);
+		data.extend_from_slice(&sender_address.serialize_vec(secp, true));
+		let hash = blake2b(32, &[], &data);
+		Ok(Message::from_slice(hash.as_bytes())?)
+	}
+
+	/// Creates a payment proof, signed by the receiver with the secret key
+	/// of its address.
+	pub fn create(
+		secp: &Secp256k1,
+		amount: u64,
+		excess: Commitment,
+		sender_address: PublicKey,
+		receiver_key: &SecretKey,
+	) -> Result<PaymentProof, Error> {
+		let msg = PaymentProof::message(secp, amount, &excess, &sender_address)?;
+		let receiver_signature = secp.sign(&msg, receiver_key)?;
+		Ok(PaymentProof {
+			amount,
+			excess,
+			sender_address,
+			receiver_address: PublicKey::from_secret_key(secp, receiver_key)?,
+			receiver_signature,
+		})
+	}
+
+	/// Verifies the receiver signature of the payment proof. Says nothing
+	/// about the kernel being on chain, which needs a node to check.
+	pub fn verify(&self, secp: &Secp256k1) -> Result<(), Error> {
+		let msg = PaymentProof::message(secp, self.amount, &self.excess, &self.sender_address)?;
+		secp.verify(&msg, &self.receiver_signature, &self.receiver_address)
+			.map_err(|_| ErrorKind::PaymentProof("Invalid receiver signature".to_string()))?;
+		Ok(())
+	}
+
+	/// Checks the payment proof is for the provided kernel.
+	pub fn verify_kernel(&self, kernel: &TxKernel) -> Result<(), Error> {
+		if kernel.excess != self.excess {
+			return Err(ErrorKind::PaymentProof("Kernel excess mismatch".to_string()).into());
+		}
+		Ok(())
+	}
+}
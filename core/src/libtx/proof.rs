@@ -16,9 +16,10 @@
 
 use crate::keychain::{Identifier, Keychain};
 use crate::libtx::error::{Error, ErrorKind};
-use crate::util::secp::key::SecretKey;
+use crate::util::secp::key::{PublicKey, SecretKey, ZERO_KEY};
 use crate::util::secp::pedersen::{Commitment, ProofInfo, ProofMessage, RangeProof};
 use crate::util::secp::{self, Secp256k1};
+use rand::thread_rng;
 
 /// Create a bulletproof
 pub fn create<K>(
@@ -87,3 +88,149 @@ where
 	};
 	return Ok(proof_info);
 }
+
+/// A participant in the creation of a multi-party bulletproof, for a
+/// commitment whose blinding factor is the sum of the participants' ones.
+/// Proof creation takes 3 rounds:
+/// * each participant computes its public nonces (`nonces`), which are
+/// summed (`sum_multi_party_nonces`) and shared with all others
+/// * each participant computes its share of tau_x given the nonce sums
+/// (`tau_x`), the shares are summed (`sum_multi_party_tau_x`) and shared
+/// * any of the participants builds the proof (`finalize`)
+///
+/// All participants use the same amount, commitment, common nonce and extra
+/// data. The resulting proof verifies with `verify`. Holds secret keys, so
+/// neither cloned nor printed.
+pub struct MultiPartyProof {
+	/// Total amount of the commitment
+	pub amount: u64,
+	/// The commitment to prove
+	pub commit: Commitment,
+	/// Nonce shared by all participants, the proof can be rewound with it
+	pub common_nonce: SecretKey,
+	/// Extra data committed to by the proof
+	pub extra_data: Option<Vec<u8>>,
+	/// The participant's share of the blinding factor
+	blind: SecretKey,
+	/// The participant's private nonce, never shared
+	private_nonce: SecretKey,
+}
+
+impl MultiPartyProof {
+	/// New participant, with its share of the blinding factor and a random
+	/// private nonce.
+	pub fn new(
+		secp: &Secp256k1,
+		amount: u64,
+		commit: Commitment,
+		common_nonce: SecretKey,
+		extra_data: Option<Vec<u8>>,
+		blind: SecretKey,
+	) -> MultiPartyProof {
+		MultiPartyProof {
+			amount,
+			commit,
+			common_nonce,
+			extra_data,
+			blind,
+			private_nonce: SecretKey::new(secp, &mut thread_rng()),
+		}
+	}
+
+	/// First round, computes the public nonces (T1, T2) of this participant.
+	/// Their sums over all participants are required for the next rounds.
+	pub fn nonces(&self, secp: &Secp256k1) -> Result<(PublicKey, PublicKey), Error> {
+		let mut t_one = PublicKey::new();
+		let mut t_two = PublicKey::new();
+		self.step(secp, None, &mut t_one, &mut t_two, 0);
+		// Left untouched (zeroed) on failure.
+		if t_one == PublicKey::new() || t_two == PublicKey::new() {
+			return Err(ErrorKind::RangeProof(
+				"Multi-party proof nonces creation failed".to_string(),
+			)
+			.into());
+		}
+		Ok((t_one, t_two))
+	}
+
+	/// Second round, computes the share of tau_x of this participant given
+	/// the sums of the public nonces.
+	pub fn tau_x(
+		&self,
+		secp: &Secp256k1,
+		nonce_sums: &(PublicKey, PublicKey),
+	) -> Result<SecretKey, Error> {
+		let mut tau_x = ZERO_KEY;
+		let (mut t_one, mut t_two) = *nonce_sums;
+		self.step(secp, Some(&mut tau_x), &mut t_one, &mut t_two, 1);
+		// Same as for the nonces.
+		if tau_x == ZERO_KEY {
+			return Err(ErrorKind::RangeProof(
+				"Multi-party proof tau_x creation failed".to_string(),
+			)
+			.into());
+		}
+		Ok(tau_x)
+	}
+
+	/// Final round, builds the proof from the sums of the public nonces and
+	/// of the tau_x shares. Fails if any of the participants contributed
+	/// invalid values.
+	pub fn finalize(
+		&self,
+		secp: &Secp256k1,
+		nonce_sums: &(PublicKey, PublicKey),
+		tau_x_sum: &SecretKey,
+	) -> Result<RangeProof, Error> {
+		let mut tau_x = *tau_x_sum;
+		let (mut t_one, mut t_two) = *nonce_sums;
+		let proof = self
+			.step(secp, Some(&mut tau_x), &mut t_one, &mut t_two, 2)
+			.ok_or_else(|| {
+				ErrorKind::RangeProof("Multi-party proof creation failed".to_string())
+			})?;
+
+		// Catch invalid contributions before anyone relies on the proof.
+		verify(secp, self.commit, proof, self.extra_data.clone())
+			.map_err(|e| ErrorKind::RangeProof(e.to_string()))?;
+		Ok(proof)
+	}
+
+	fn step(
+		&self,
+		secp: &Secp256k1,
+		tau_x: Option<&mut SecretKey>,
+		t_one: &mut PublicKey,
+		t_two: &mut PublicKey,
+		step: u8,
+	) -> Option<RangeProof> {
+		secp.bullet_proof_multisig(
+			self.amount,
+			self.blind,
+			self.common_nonce,
+			self.extra_data.clone(),
+			None,
+			tau_x,
+			Some(t_one),
+			Some(t_two),
+			vec![self.commit],
+			Some(&self.private_nonce),
+			step,
+		)
+	}
+}
+
+/// Sums the public nonces of all participants of a multi-party bulletproof.
+pub fn sum_multi_party_nonces(
+	secp: &Secp256k1,
+	nonces: &[(PublicKey, PublicKey)],
+) -> Result<(PublicKey, PublicKey), Error> {
+	let t_one = PublicKey::from_combination(secp, nonces.iter().map(|n| &n.0).collect())?;
+	let t_two = PublicKey::from_combination(secp, nonces.iter().map(|n| &n.1).collect())?;
+	Ok((t_one, t_two))
+}
+
+/// Sums the tau_x shares of all participants of a multi-party bulletproof.
+pub fn sum_multi_party_tau_x(secp: &Secp256k1, shares: Vec<SecretKey>) -> Result<SecretKey, Error> {
+	Ok(secp.blind_sum(shares, vec![])?)
+}
@@ -52,6 +52,48 @@ pub mod pubkey_serde {
 	}
 }
 
+/// Serializes an Option<secp PublicKey> to and from hex
+pub mod option_pubkey_serde {
+	use crate::serde::{Deserialize, Deserializer, Serializer};
+	use crate::util::secp::key::PublicKey;
+	use crate::util::{from_hex, static_secp_instance, to_hex};
+	use serde::de::Error;
+
+	///
+	pub fn serialize<S>(key: &Option<PublicKey>, serializer: S) -> Result<S::Ok, S::Error>
+	where
+		S: Serializer,
+	{
+		match key {
+			Some(key) => {
+				let static_secp = static_secp_instance();
+				let static_secp = static_secp.lock();
+				serializer.serialize_str(&to_hex(key.serialize_vec(&static_secp, true).to_vec()))
+			}
+			None => serializer.serialize_none(),
+		}
+	}
+
+	///
+	pub fn deserialize<'de, D>(deserializer: D) -> Result<Option<PublicKey>, D::Error>
+	where
+		D: Deserializer<'de>,
+	{
+		let static_secp = static_secp_instance();
+		let static_secp = static_secp.lock();
+		Option::<String>::deserialize(deserializer).and_then(|res| match res {
+			Some(string) => from_hex(string.to_string())
+				.map_err(|err| Error::custom(err.to_string()))
+				.and_then(|bytes: Vec<u8>| {
+					PublicKey::from_slice(&static_secp, &bytes)
+						.map(|val| Some(val))
+						.map_err(|err| Error::custom(err.to_string()))
+				}),
+			None => Ok(None),
+		})
+	}
+}
+
 /// Serializes an Option<secp::Signature> to and from hex
 pub mod option_sig_serde {
 	use crate::serde::{Deserialize, Deserializer, Serializer};
@@ -279,6 +321,8 @@ mod test {
 	struct SerTest {
 		#[serde(with = "pubkey_serde")]
 		pub pub_key: PublicKey,
+		#[serde(with = "option_pubkey_serde")]
+		pub opt_pub_key: Option<PublicKey>,
 		#[serde(with = "option_sig_serde")]
 		pub opt_sig: Option<Signature>,
 		#[serde(with = "sig_serde")]
@@ -300,6 +344,7 @@ mod test {
 			let sig = aggsig::sign_single(&secp, &msg, &sk, None, None).unwrap();
 			SerTest {
 				pub_key: PublicKey::from_secret_key(&secp, &sk).unwrap(),
+				opt_pub_key: Some(PublicKey::from_secret_key(&secp, &sk).unwrap()),
 				opt_sig: Some(sig.clone()),
 				sig: sig.clone(),
 				num: 30,
@@ -0,0 +1,379 @@
+// Copyright 2018 The Grin Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Interactive building of a transaction between any number of participants.
+//!
+//! The participants pass an exchange document (the `TxBuilder`) around, each
+//! one adding its contribution to it in turn, round after round:
+//!
+//! 1. `add_elements`: every participant adds its inputs and outputs, along
+//!    with the public key of its share of the kernel excess.
+//! 2. `commit_nonce`: every participant adds its public nonce.
+//! 3. `sign`: every participant adds its partial signature, after checking
+//!    the ones already in the document.
+//! 4. `finalize`: anyone can assemble the final transaction.
+//!
+//! The secrets of each participant are kept in a local `ParticipantContext`
+//! and never go in the document.
+
+use crate::core::committed::Committed;
+use crate::core::transaction::{kernel_features, kernel_sig_msg};
+use crate::core::{Transaction, TxKernel};
+use crate::keychain::{BlindSum, BlindingFactor, Keychain};
+use crate::libtx::build::{self, Append};
+use crate::libtx::error::{Error, ErrorKind};
+use crate::libtx::{aggsig, secp_ser};
+use crate::util::secp::key::{PublicKey, SecretKey};
+use crate::util::secp::{Message, Secp256k1, Signature};
+
+/// Version of the exchange documents we produce and understand.
+pub const TX_BUILDER_VERSION: u16 = 1;
+
+/// Rounds of the protocol, in order.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum Round {
+	/// Participants add their inputs, outputs and public excess.
+	AddElements,
+	/// Participants add their public nonce.
+	CommitNonces,
+	/// Participants add their partial signature.
+	Sign,
+	/// All partial signatures are in, the transaction can be assembled.
+	Finalize,
+}
+
+/// Public data of a participant, as exchanged in the document.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ParticipantData {
+	/// Id of the participant, its index in the document
+	pub id: usize,
+	/// Public key of the participant share of the kernel excess
+	#[serde(with = "secp_ser::pubkey_serde")]
+	pub public_excess: PublicKey,
+	/// Public nonce of the participant, once committed to
+	#[serde(with = "secp_ser::option_pubkey_serde")]
+	pub public_nonce: Option<PublicKey>,
+	/// Partial signature of the participant, once signed
+	#[serde(with = "secp_ser::option_sig_serde")]
+	pub part_sig: Option<Signature>,
+}
+
+/// Secrets of a participant, kept locally for the duration of the protocol.
+pub struct ParticipantContext {
+	/// Id of the participant in the document
+	pub id: usize,
+	sec_key: SecretKey,
+	sec_nonce: SecretKey,
+}
+
+/// Exchange document of the protocol, passed around between the
+/// participants as it gets built.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TxBuilder {
+	/// Version of the document
+	pub version: u16,
+	/// Number of participants expected to take part
+	pub num_participants: usize,
+	/// Current round of the protocol
+	pub round: Round,
+	/// Fee of the transaction
+	#[serde(with = "secp_ser::string_or_u64")]
+	pub fee: u64,
+	/// Lock height of the transaction
+	#[serde(with = "secp_ser::string_or_u64")]
+	pub lock_height: u64,
+	/// Inputs, outputs and offset contributed so far, the kernel only being
+	/// added on finalization
+	pub tx: Transaction,
+	/// Public data of the participants, in order of contribution
+	pub participants: Vec<ParticipantData>,
+}
+
+fn builder_error(msg: String) -> Error {
+	ErrorKind::TxBuilder(msg).into()
+}
+
+impl TxBuilder {
+	/// Starts a new exchange document for the provided number of participants
+	/// and transaction terms.
+	pub fn new(num_participants: usize, fee: u64, lock_height: u64) -> Result<TxBuilder, Error> {
+		if num_participants == 0 {
+			return Err(builder_error("no participants".to_string()));
+		}
+		Ok(TxBuilder {
+			version: TX_BUILDER_VERSION,
+			num_participants,
+			round: Round::AddElements,
+			fee,
+			lock_height,
+			tx: Transaction::empty(),
+			participants: vec![],
+		})
+	}
+
+	/// Adds the inputs and outputs built from the provided elements (see
+	/// `build`) as a new participant. Returns the context holding the
+	/// secrets of the participant, needed in the following rounds.
+	pub fn add_elements<K>(
+		&mut self,
+		keychain: &K,
+		elems: Vec<Box<Append<K>>>,
+	) -> Result<ParticipantContext, Error>
+	where
+		K: Keychain,
+	{
+		self.check_round(Round::AddElements)?;
+		let secp = keychain.secp();
+		let (part_tx, blind_sum) = build::partial_transaction(elems, keychain)?;
+
+		// Part of our blinding factor goes in the kernel excess, the rest in
+		// the transaction offset.
+		let split = blind_sum.split(secp)?;
+		let sec_key = split.blind_1.secret_key(secp)?;
+		let sec_nonce = aggsig::create_secnonce(secp)?;
+		let offset = if self.tx.offset == BlindingFactor::zero() {
+			split.blind_2
+		} else {
+			keychain.blind_sum(
+				&BlindSum::new()
+					.add_blinding_factor(self.tx.offset.clone())
+					.add_blinding_factor(split.blind_2),
+			)?
+		};
+
+		let mut tx = self.tx.clone().with_offset(offset);
+		for input in part_tx.inputs() {
+			tx = tx.with_input(input.clone());
+		}
+		for output in part_tx.outputs() {
+			tx = tx.with_output(output.clone());
+		}
+		self.tx = tx;
+
+		let id = self.participants.len();
+		self.participants.push(ParticipantData {
+			id,
+			public_excess: PublicKey::from_secret_key(secp, &sec_key)?,
+			public_nonce: None,
+			part_sig: None,
+		});
+		self.round = self.content_round();
+
+		Ok(ParticipantContext {
+			id,
+			sec_key,
+			sec_nonce,
+		})
+	}
+
+	/// Adds the public nonce of the participant.
+	pub fn commit_nonce(
+		&mut self,
+		secp: &Secp256k1,
+		ctx: &ParticipantContext,
+	) -> Result<(), Error> {
+		self.check_round(Round::CommitNonces)?;
+		let public_nonce = PublicKey::from_secret_key(secp, &ctx.sec_nonce)?;
+		let participant = self.participant_mut(secp, ctx)?;
+		if participant.public_nonce.is_some() {
+			return Err(builder_error(format!(
+				"participant {} already committed to a nonce",
+				ctx.id
+			)));
+		}
+		participant.public_nonce = Some(public_nonce);
+		self.round = self.content_round();
+		Ok(())
+	}
+
+	/// Adds the partial signature of the participant, once the partial
+	/// signatures already in the document have been checked.
+	pub fn sign(&mut self, secp: &Secp256k1, ctx: &ParticipantContext) -> Result<(), Error> {
+		self.check_round(Round::Sign)?;
+		self.verify_part_sigs(secp)?;
+
+		let msg = self.msg_to_sign()?;
+		let nonce_sum = self.nonce_sum(secp)?;
+		let excess_sum = self.excess_sum(secp)?;
+		let sig = aggsig::calculate_partial_sig(
+			secp,
+			&ctx.sec_key,
+			&ctx.sec_nonce,
+			&nonce_sum,
+			Some(&excess_sum),
+			&msg,
+		)?;
+
+		let participant = self.participant_mut(secp, ctx)?;
+		if participant.part_sig.is_some() {
+			return Err(builder_error(format!(
+				"participant {} already signed",
+				ctx.id
+			)));
+		}
+		aggsig::verify_partial_sig(
+			secp,
+			&sig,
+			&nonce_sum,
+			&participant.public_excess,
+			Some(&excess_sum),
+			&msg,
+		)?;
+		participant.part_sig = Some(sig);
+		self.round = self.content_round();
+		Ok(())
+	}
+
+	/// Assembles the final transaction from the contributions of all the
+	/// participants, checking every partial signature as well as the final
+	/// one against the kernel excess.
+	pub fn finalize(&self, secp: &Secp256k1) -> Result<Transaction, Error> {
+		self.check_round(Round::Finalize)?;
+		self.verify_part_sigs(secp)?;
+
+		let msg = self.msg_to_sign()?;
+		let nonce_sum = self.nonce_sum(secp)?;
+		let excess_sum = self.excess_sum(secp)?;
+		let part_sigs = self
+			.participants
+			.iter()
+			.filter_map(|p| p.part_sig.as_ref())
+			.collect();
+		let sig = aggsig::add_signatures(secp, part_sigs, &nonce_sum)?;
+		aggsig::verify_completed_sig(secp, &sig, &excess_sum, Some(&excess_sum), &msg)?;
+
+		// The kernel excess is what the inputs, outputs and fee sum to, less
+		// the offset. It has to match the public excesses we signed for.
+		let mut positive = self.tx.outputs_committed();
+		let mut negative = self.tx.inputs_committed();
+		if self.fee > 0 {
+			positive.push(secp.commit_value(self.fee)?);
+		}
+		if self.tx.offset != BlindingFactor::zero() {
+			negative.push(secp.commit(0, self.tx.offset.secret_key(secp)?)?);
+		}
+		let excess = secp.commit_sum(positive, negative)?;
+		if excess.to_pubkey(secp)? != excess_sum {
+			return Err(builder_error(
+				"kernel excess not matching the participants excess".to_string(),
+			));
+		}
+
+		let kernel = TxKernel {
+			features: kernel_features(self.lock_height),
+			fee: self.fee,
+			lock_height: self.lock_height,
+			excess,
+			excess_sig: sig,
+		};
+		Ok(self.tx.clone().with_kernel(kernel))
+	}
+
+	// The round the content of the document is at, any round transition
+	// follows from it.
+	fn content_round(&self) -> Round {
+		if self.participants.len() < self.num_participants {
+			Round::AddElements
+		} else if self.participants.iter().any(|p| p.public_nonce.is_none()) {
+			Round::CommitNonces
+		} else if self.participants.iter().any(|p| p.part_sig.is_none()) {
+			Round::Sign
+		} else {
+			Round::Finalize
+		}
+	}
+
+	fn check_round(&self, round: Round) -> Result<(), Error> {
+		if self.version != TX_BUILDER_VERSION {
+			return Err(builder_error(format!(
+				"unsupported document version {}",
+				self.version
+			)));
+		}
+		if self.participants.len() > self.num_participants
+			|| self.participants.iter().enumerate().any(|(i, p)| p.id != i)
+		{
+			return Err(builder_error("inconsistent participants".to_string()));
+		}
+		if self.round != self.content_round() {
+			return Err(builder_error(format!(
+				"document at round {:?} with the content of round {:?}",
+				self.round,
+				self.content_round()
+			)));
+		}
+		if self.round != round {
+			return Err(builder_error(format!(
+				"expected round {:?}, document at round {:?}",
+				round, self.round
+			)));
+		}
+		Ok(())
+	}
+
+	// The data of the participant of the provided context, making sure the
+	// context is the one of that participant.
+	fn participant_mut(
+		&mut self,
+		secp: &Secp256k1,
+		ctx: &ParticipantContext,
+	) -> Result<&mut ParticipantData, Error> {
+		let public_excess = PublicKey::from_secret_key(secp, &ctx.sec_key)?;
+		match self.participants.get_mut(ctx.id) {
+			Some(p) if p.public_excess == public_excess => Ok(p),
+			_ => Err(builder_error(format!("unknown participant {}", ctx.id))),
+		}
+	}
+
+	fn verify_part_sigs(&self, secp: &Secp256k1) -> Result<(), Error> {
+		let msg = self.msg_to_sign()?;
+		let nonce_sum = self.nonce_sum(secp)?;
+		let excess_sum = self.excess_sum(secp)?;
+		for p in &self.participants {
+			if let Some(sig) = &p.part_sig {
+				aggsig::verify_partial_sig(
+					secp,
+					sig,
+					&nonce_sum,
+					&p.public_excess,
+					Some(&excess_sum),
+					&msg,
+				)
+				.map_err(|_| {
+					builder_error(format!("invalid partial signature of participant {}", p.id))
+				})?;
+			}
+		}
+		Ok(())
+	}
+
+	fn msg_to_sign(&self) -> Result<Message, Error> {
+		let features = kernel_features(self.lock_height);
+		Ok(kernel_sig_msg(self.fee, self.lock_height, features)?)
+	}
+
+	fn nonce_sum(&self, secp: &Secp256k1) -> Result<PublicKey, Error> {
+		let nonces = self
+			.participants
+			.iter()
+			.filter_map(|p| p.public_nonce.as_ref())
+			.collect();
+		Ok(PublicKey::from_combination(secp, nonces)?)
+	}
+
+	fn excess_sum(&self, secp: &Secp256k1) -> Result<PublicKey, Error> {
+		let excesses = self.participants.iter().map(|p| &p.public_excess).collect();
+		Ok(PublicKey::from_combination(secp, excesses)?)
+	}
+}
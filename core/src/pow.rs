@@ -38,12 +38,13 @@ pub mod cuckatoo;
 mod error;
 #[allow(dead_code)]
 pub mod lean;
+pub mod mean;
 mod siphash;
 mod types;
 
 use crate::core::{Block, BlockHeader};
 use crate::genesis;
-use crate::global;
+use crate::global::{self, ChainParams};
 use chrono::prelude::{DateTime, NaiveDateTime, Utc};
 
 pub use self::common::EdgeType;
@@ -51,13 +52,15 @@ pub use self::types::*;
 pub use crate::pow::cuckaroo::{new_cuckaroo_ctx, CuckarooContext};
 pub use crate::pow::cuckatoo::{new_cuckatoo_ctx, CuckatooContext};
 pub use crate::pow::error::Error;
+pub use crate::pow::mean::{new_mean_ctx, MeanContext};
 
 const MAX_SOLS: u32 = 10;
 
 /// Validates the proof of work of a given header, and that the proof of work
-/// satisfies the requirements of the header.
-pub fn verify_size(bh: &BlockHeader) -> Result<(), Error> {
-	let mut ctx = global::create_pow_context::<u64>(
+/// satisfies the requirements of the header on the chain with the provided
+/// parameters.
+pub fn verify_size(params: &ChainParams, bh: &BlockHeader) -> Result<(), Error> {
+	let mut ctx = params.create_pow_context::<u64>(
 		bh.height,
 		bh.pow.edge_bits(),
 		bh.pow.proof.nonces.len(),
@@ -67,49 +70,68 @@ pub fn verify_size(bh: &BlockHeader) -> Result<(), Error> {
 	ctx.verify(&bh.pow.proof)
 }
 
-/// Mines a genesis block using the internal miner
+/// Mines a genesis block using the internal miner, for the process wide
+/// chain parameters
 pub fn mine_genesis_block() -> Result<Block, Error> {
+	mine_genesis_block_with_params(&global::chain_params())
+}
+
+/// Mines a genesis block using the internal miner, for the chain with the
+/// provided parameters
+pub fn mine_genesis_block_with_params(params: &ChainParams) -> Result<Block, Error> {
 	let mut gen = genesis::genesis_dev();
-	if global::is_user_testing_mode() || global::is_automated_testing_mode() {
-		gen = genesis::genesis_dev();
+	gen.header.pow.nonce = params.genesis_nonce;
+	if params.is_user_testing_mode() || params.is_automated_testing_mode() {
 		gen.header.timestamp = Utc::now();
 	}
 
 	// total_difficulty on the genesis header *is* the difficulty of that block
 	let genesis_difficulty = gen.header.pow.total_difficulty;
 
-	let sz = global::min_edge_bits();
-	let proof_size = global::proofsize();
+	let sz = params.min_edge_bits;
+	let proof_size = params.proofsize;
 
-	pow_size(&mut gen.header, genesis_difficulty, proof_size, sz)?;
+	pow_size_with_params(params, &mut gen.header, genesis_difficulty, proof_size, sz)?;
 	Ok(gen)
 }
 
 /// Runs a proof of work computation over the provided block using the provided
 /// Mining Worker, until the required difficulty target is reached. May take a
-/// while for a low target...
+/// while for a low target... Uses the process wide chain parameters.
 pub fn pow_size(
 	bh: &mut BlockHeader,
 	diff: Difficulty,
 	proof_size: usize,
 	sz: u8,
+) -> Result<(), Error> {
+	pow_size_with_params(&global::chain_params(), bh, diff, proof_size, sz)
+}
+
+/// Runs a proof of work computation over the provided block (see
+/// `pow_size`) for the chain with the provided parameters.
+pub fn pow_size_with_params(
+	params: &ChainParams,
+	bh: &mut BlockHeader,
+	diff: Difficulty,
+	proof_size: usize,
+	sz: u8,
 ) -> Result<(), Error> {
 	let start_nonce = bh.pow.nonce;
 
 	// set the nonce for faster solution finding in user testing
-	if bh.height == 0 && global::is_user_testing_mode() {
-		bh.pow.nonce = global::get_genesis_nonce();
+	if bh.height == 0 && params.is_user_testing_mode() {
+		bh.pow.nonce = params.genesis_nonce;
 	}
 
 	// try to find a cuckoo cycle on that header hash
 	loop {
 		// if we found a cycle (not guaranteed) and the proof hash is higher that the
 		// diff, we're all good
-		let mut ctx = global::create_pow_context::<u32>(bh.height, sz, proof_size, MAX_SOLS)?;
+		let mut ctx = params.create_pow_context::<u32>(bh.height, sz, proof_size, MAX_SOLS)?;
 		ctx.set_header_nonce(bh.pre_pow(), None, true)?;
 		if let Ok(proofs) = ctx.find_cycles() {
 			bh.pow.proof = proofs[0].clone();
-			if bh.pow.to_difficulty(bh.height) >= diff {
+			if bh.pow.to_difficulty(params, bh.height) >= diff {
 				return Ok(());
 			}
 		}
@@ -151,7 +173,7 @@ mod test {
 		.unwrap();
 		println!("nonce {}", b.header.pow.nonce);
 		assert_ne!(b.header.pow.nonce, 310);
-		assert!(b.header.pow.to_difficulty(0) >= Difficulty::min());
-		assert!(verify_size(&b.header).is_ok());
+		assert!(b.header.pow.to_difficulty(&global::chain_params(), 0) >= Difficulty::min());
+		assert!(verify_size(&global::chain_params(), &b.header).is_ok());
 	}
 }
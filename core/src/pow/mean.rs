@@ -0,0 +1,569 @@
+// Copyright 2018 The Grin Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Multi-threaded mean solver for Cuckatoo and Cuckaroo, after the mean
+//! miner of John Tromp (https://github.com/tromp/cuckoo).
+//!
+//! Edges are trimmed in rounds, alternating between the two sides of the
+//! bipartite graph, until mostly cycles are left. Remaining edges are kept
+//! with both their endpoints, in buckets keyed by the high bits of the node
+//! on the side being trimmed. Node degrees can then be counted on a small,
+//! cache friendly, range and all buckets trimmed in parallel. This costs a
+//! lot more memory than the lean miner (up to 8 bytes per edge) but is much
+//! faster. Cycles are finally looked for among the few edges left, and their
+//! nonces recovered by generating all edges one last time.
+
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use rayon::prelude::*;
+
+use crate::global::PoWContextTypes;
+use crate::pow::common::{self, EdgeType};
+use crate::pow::cuckaroo::new_cuckaroo_ctx;
+use crate::pow::cuckatoo::new_cuckatoo_ctx;
+use crate::pow::error::{Error, ErrorKind};
+use crate::pow::siphash::{siphash24, siphash_block_all, SIPHASH_BLOCK_SIZE};
+use crate::pow::{PoWContext, Proof};
+
+/// Maximum number of trimming rounds, each trimming a single side
+const MAX_TRIM_ROUNDS: usize = 256;
+
+/// Maximum number of node bits used to select a bucket
+const MAX_BUCKET_BITS: u8 = 10;
+
+/// Buckets of edges, each made of pieces filled by different threads.
+/// Edges are packed as the node of the side being trimmed (high 32 bits)
+/// and the node of the other side (low 32 bits).
+type Buckets = Vec<Vec<Vec<u64>>>;
+
+/// Instantiate a new mean solver as a PowContext, for either Cuckatoo or
+/// Cuckaroo. Supports edge_bits up to 32.
+pub fn new_mean_ctx<T>(
+	variant: PoWContextTypes,
+	edge_bits: u8,
+	proof_size: usize,
+	max_sols: u32,
+) -> Result<Box<dyn PoWContext<T>>, Error>
+where
+	T: EdgeType + 'static,
+{
+	Ok(Box::new(MeanContext::new_impl(
+		variant, edge_bits, proof_size, max_sols,
+	)?))
+}
+
+/// Mean solver context
+pub struct MeanContext {
+	solver: Solver,
+	/// Reference verifier all solutions are checked against
+	verifier: Box<dyn PoWContext<u64>>,
+}
+
+/// Graph parameters and keys, shared by all threads while solving
+struct Solver {
+	variant: PoWContextTypes,
+	edge_bits: u8,
+	proof_size: usize,
+	max_sols: u32,
+	siphash_keys: [u64; 4],
+}
+
+impl<T> PoWContext<T> for MeanContext
+where
+	T: EdgeType,
+{
+	fn set_header_nonce(
+		&mut self,
+		header: Vec<u8>,
+		nonce: Option<u32>,
+		_solve: bool,
+	) -> Result<(), Error> {
+		self.solver.siphash_keys = common::set_header_nonce(&header, nonce)?;
+		self.verifier.set_header_nonce(header, nonce, false)
+	}
+
+	fn find_cycles(&mut self) -> Result<Vec<Proof>, Error> {
+		self.find_cycles_impl()
+	}
+
+	fn verify(&self, proof: &Proof) -> Result<(), Error> {
+		self.verifier.verify(proof)
+	}
+}
+
+impl MeanContext {
+	/// New solver context
+	pub fn new_impl(
+		variant: PoWContextTypes,
+		edge_bits: u8,
+		proof_size: usize,
+		max_sols: u32,
+	) -> Result<MeanContext, Error> {
+		// both nodes of an edge are packed in a u64
+		if edge_bits > 32 {
+			return Err(ErrorKind::IntegerCast)?;
+		}
+		let verifier = match variant {
+			PoWContextTypes::Cuckatoo => new_cuckatoo_ctx(edge_bits, proof_size, max_sols)?,
+			PoWContextTypes::Cuckaroo => new_cuckaroo_ctx(edge_bits, proof_size)?,
+			PoWContextTypes::Cuckoo => {
+				return Err(ErrorKind::Verification(
+					"no mean solver for cuckoo".to_owned(),
+				))?;
+			}
+		};
+		Ok(MeanContext {
+			solver: Solver {
+				variant,
+				edge_bits,
+				proof_size,
+				max_sols,
+				siphash_keys: [0; 4],
+			},
+			verifier,
+		})
+	}
+
+	/// Trims the graph, finds cycles on the remaining edges and returns the
+	/// proofs the reference verifier accepts.
+	pub fn find_cycles_impl(&self) -> Result<Vec<Proof>, Error> {
+		let edges = self.solver.trim();
+		let cycles = self.solver.cycles(&edges);
+
+		let mut sols: Vec<Proof> = vec![];
+		for mut proof in self.solver.recover_nonces(cycles) {
+			proof.edge_bits = self.solver.edge_bits;
+			if self.verifier.verify(&proof).is_ok() && !sols.contains(&proof) {
+				sols.push(proof);
+			}
+			if sols.len() >= self.solver.max_sols as usize {
+				break;
+			}
+		}
+		if sols.is_empty() {
+			Err(ErrorKind::NoSolution)?
+		} else {
+			Ok(sols)
+		}
+	}
+}
+
+impl Solver {
+	fn num_edges(&self) -> u64 {
+		1 << self.edge_bits
+	}
+
+	fn node_mask(&self) -> u64 {
+		self.num_edges() - 1
+	}
+
+	fn bucket_bits(&self) -> u8 {
+		(self.edge_bits / 2).min(MAX_BUCKET_BITS)
+	}
+
+	fn num_buckets(&self) -> usize {
+		1 << self.bucket_bits()
+	}
+
+	// Shift to get the bucket of a node
+	fn bucket_shift(&self) -> u8 {
+		self.edge_bits - self.bucket_bits()
+	}
+
+	// Ranges of nonces generated by a single task, aligned on siphash blocks
+	fn nonce_ranges(&self) -> Vec<(u64, u64)> {
+		let num_edges = self.num_edges();
+		let tasks = 4 * rayon::current_num_threads() as u64;
+		let block = SIPHASH_BLOCK_SIZE;
+		let size = ((num_edges / tasks + block - 1) / block * block).max(block);
+		(0..num_edges)
+			.step_by(size as usize)
+			.map(|start| (start, (start + size).min(num_edges)))
+			.collect()
+	}
+
+	// Calls the provided function with the nonce and both nodes of all edges
+	// in the range.
+	fn for_each_edge<F>(&self, start: u64, end: u64, mut f: F)
+	where
+		F: FnMut(u64, u64, u64),
+	{
+		let mask = self.node_mask();
+		match self.variant {
+			PoWContextTypes::Cuckaroo => {
+				for block in (start..end).step_by(SIPHASH_BLOCK_SIZE as usize) {
+					let hashes = siphash_block_all(&self.siphash_keys, block);
+					for (i, h) in hashes.iter().enumerate() {
+						let nonce = block + i as u64;
+						if nonce < end {
+							f(nonce, h & mask, (h >> 32) & mask);
+						}
+					}
+				}
+			}
+			_ => {
+				for nonce in start..end {
+					let u = siphash24(&self.siphash_keys, 2 * nonce) & mask;
+					let v = siphash24(&self.siphash_keys, 2 * nonce + 1) & mask;
+					f(nonce, u, v);
+				}
+			}
+		}
+	}
+
+	// Whether an edge on a node survives, given the degree of that node (or
+	// whether it has an edge at all) and the one of its Cuckatoo partner.
+	fn survives(&self, degree: u8, partner_degree: u8) -> bool {
+		match self.variant {
+			PoWContextTypes::Cuckaroo => degree > 1,
+			_ => partner_degree > 0,
+		}
+	}
+
+	// The node an endpoint connects to in a cycle, Cuckatoo pairs nodes n
+	// and n ^ 1.
+	fn partner(&self, node: u64) -> u64 {
+		match self.variant {
+			PoWContextTypes::Cuckaroo => node,
+			_ => node ^ 1,
+		}
+	}
+
+	/// Trims edges that can't be part of a cycle and returns the remaining
+	/// ones.
+	fn trim(&self) -> Vec<(u64, u64)> {
+		let mut buckets = self.first_round();
+		let mut side = 0;
+		let mut remaining = buckets_len(&buckets);
+		let mut unchanged = 0;
+
+		for _ in 0..MAX_TRIM_ROUNDS {
+			buckets = self.trim_round(buckets);
+			side ^= 1;
+
+			let count = buckets_len(&buckets);
+			if count == remaining {
+				unchanged += 1;
+				// nothing left to trim on either side
+				if unchanged == 2 {
+					break;
+				}
+			} else {
+				unchanged = 0;
+			}
+			remaining = count;
+		}
+
+		// unpack the edges, the trimmed side being either u or v
+		buckets
+			.into_iter()
+			.flatten()
+			.flatten()
+			.map(|e| {
+				let (key, other) = (e >> 32, e & 0xffff_ffff);
+				if side == 0 {
+					(key, other)
+				} else {
+					(other, key)
+				}
+			})
+			.collect()
+	}
+
+	// First round, trims both sides without storing any edge: nodes are
+	// first counted in bitmaps, then all edges generated again and the
+	// surviving ones bucketed by their u node.
+	fn first_round(&self) -> Buckets {
+		let num_buckets = self.num_buckets();
+		let shift = self.bucket_shift();
+		let words = ((self.num_edges() + 63) / 64) as usize;
+		let twice = match self.variant {
+			PoWContextTypes::Cuckaroo => words,
+			_ => 0,
+		};
+		let counts = [NodeCounts::new(words, twice), NodeCounts::new(words, twice)];
+
+		let ranges = self.nonce_ranges();
+		ranges.par_iter().for_each(|(start, end)| {
+			self.for_each_edge(*start, *end, |_, u, v| {
+				counts[0].add(u);
+				counts[1].add(v);
+			});
+		});
+
+		let pieces = ranges
+			.par_iter()
+			.map(|(start, end)| {
+				let mut out = vec![vec![]; num_buckets];
+				self.for_each_edge(*start, *end, |_, u, v| {
+					if self.survives(counts[0].degree(u), counts[0].degree(u ^ 1))
+						&& self.survives(counts[1].degree(v), counts[1].degree(v ^ 1))
+					{
+						out[(u >> shift) as usize].push(u << 32 | v);
+					}
+				});
+				out
+			})
+			.collect::<Vec<_>>();
+		transpose(pieces, num_buckets)
+	}
+
+	// Trims the side edges are keyed on in the buckets, returning the
+	// survivors keyed on the other side.
+	fn trim_round(&self, buckets: Buckets) -> Buckets {
+		let num_buckets = self.num_buckets();
+		let shift = self.bucket_shift();
+		let range_mask = (1u64 << shift) - 1;
+
+		let pieces = buckets
+			.into_par_iter()
+			.map_init(
+				|| vec![0u8; 1 << shift],
+				|degrees, pieces| {
+					let local = |e: u64| ((e >> 32) & range_mask) as usize;
+					for e in pieces.iter().flatten() {
+						let n = local(*e);
+						degrees[n] = (degrees[n] + 1).min(2);
+					}
+
+					let mut out = vec![vec![]; num_buckets];
+					for e in pieces.iter().flatten() {
+						let n = local(*e);
+						if self.survives(degrees[n], degrees[n ^ 1]) {
+							let (key, other) = (e >> 32, e & 0xffff_ffff);
+							out[(other >> shift) as usize].push(other << 32 | key);
+						}
+					}
+
+					// reset the degrees for the next bucket
+					for e in pieces.iter().flatten() {
+						degrees[local(*e)] = 0;
+					}
+					out
+				},
+			)
+			.collect::<Vec<_>>();
+		transpose(pieces, num_buckets)
+	}
+
+	/// Finds all cycles of the proof size among the provided edges. Edges
+	/// are added one by one, looking for a path of the right length between
+	/// both their endpoints in the edges added before (mostly a forest).
+	fn cycles(&self, edges: &[(u64, u64)]) -> Vec<Vec<(u64, u64)>> {
+		// edges by node, for both sides
+		let mut adj: [HashMap<u64, Vec<usize>>; 2] = [HashMap::new(), HashMap::new()];
+		let mut cycles = vec![];
+
+		for (i, &(u, v)) in edges.iter().enumerate() {
+			let linked = |side: usize, node: u64| adj[side].contains_key(&self.partner(node));
+			if linked(0, u) && linked(1, v) {
+				let mut path = vec![i];
+				self.find_paths(edges, &adj, &mut path, 0, &mut cycles);
+			}
+			adj[0].entry(u).or_insert_with(Vec::new).push(i);
+			adj[1].entry(v).or_insert_with(Vec::new).push(i);
+		}
+
+		cycles
+			.into_iter()
+			.map(|path| path.into_iter().map(|i| edges[i]).collect())
+			.collect()
+	}
+
+	// Extends the path from its last edge, leaving through the provided side,
+	// and records the cycles closing back on its first edge.
+	fn find_paths(
+		&self,
+		edges: &[(u64, u64)],
+		adj: &[HashMap<u64, Vec<usize>>; 2],
+		path: &mut Vec<usize>,
+		side: usize,
+		cycles: &mut Vec<Vec<usize>>,
+	) {
+		let endpoint = |i: usize, side: usize| {
+			if side == 0 {
+				edges[i].0
+			} else {
+				edges[i].1
+			}
+		};
+		let last = path[path.len() - 1];
+		let node = self.partner(endpoint(last, side));
+		let next = match adj[side].get(&node) {
+			Some(next) => next,
+			None => return,
+		};
+
+		for &j in next {
+			if path.contains(&j) {
+				continue;
+			}
+			path.push(j);
+			if path.len() == self.proof_size {
+				// closes back on the first edge on the other side
+				if self.partner(endpoint(j, side ^ 1)) == endpoint(path[0], side ^ 1) {
+					cycles.push(path.clone());
+				}
+			} else {
+				self.find_paths(edges, adj, path, side ^ 1, cycles);
+			}
+			path.pop();
+		}
+	}
+
+	/// Generates all edges again to find the nonces of the edges of the
+	/// cycles.
+	fn recover_nonces(&self, cycles: Vec<Vec<(u64, u64)>>) -> Vec<Proof> {
+		if cycles.is_empty() {
+			return vec![];
+		}
+		let wanted: HashSet<(u64, u64)> = cycles.iter().flatten().cloned().collect();
+		let nonces: HashMap<(u64, u64), u64> = self
+			.nonce_ranges()
+			.par_iter()
+			.map(|(start, end)| {
+				let mut found = vec![];
+				self.for_each_edge(*start, *end, |nonce, u, v| {
+					if wanted.contains(&(u, v)) {
+						found.push(((u, v), nonce));
+					}
+				});
+				found
+			})
+			.flatten()
+			.collect();
+
+		cycles
+			.iter()
+			.map(|cycle| Proof::new(cycle.iter().map(|e| nonces[e]).collect()))
+			.collect()
+	}
+}
+
+// Transposes pieces of buckets produced by tasks into buckets of pieces,
+// leaving empty pieces out.
+fn transpose(pieces: Vec<Vec<Vec<u64>>>, num_buckets: usize) -> Buckets {
+	let mut buckets: Buckets = (0..num_buckets)
+		.map(|_| Vec::with_capacity(pieces.len()))
+		.collect();
+	for task in pieces {
+		for (b, piece) in task.into_iter().enumerate() {
+			if !piece.is_empty() {
+				buckets[b].push(piece);
+			}
+		}
+	}
+	buckets
+}
+
+fn buckets_len(buckets: &Buckets) -> usize {
+	buckets.iter().flatten().map(|p| p.len()).sum()
+}
+
+/// Degrees of all nodes of a side, saturating at 2 (only whether a node has
+/// an edge when the second bitmap is left empty).
+struct NodeCounts {
+	once: Vec<AtomicU64>,
+	twice: Vec<AtomicU64>,
+}
+
+impl NodeCounts {
+	fn new(once: usize, twice: usize) -> NodeCounts {
+		NodeCounts {
+			once: (0..once).map(|_| AtomicU64::new(0)).collect(),
+			twice: (0..twice).map(|_| AtomicU64::new(0)).collect(),
+		}
+	}
+
+	fn add(&self, node: u64) {
+		let (word, bit) = ((node >> 6) as usize, 1 << (node & 63));
+		let prev = self.once[word].fetch_or(bit, Ordering::Relaxed);
+		if prev & bit != 0 && !self.twice.is_empty() {
+			self.twice[word].fetch_or(bit, Ordering::Relaxed);
+		}
+	}
+
+	fn degree(&self, node: u64) -> u8 {
+		let (word, bit) = ((node >> 6) as usize, 1 << (node & 63));
+		if self.once[word].load(Ordering::Relaxed) & bit == 0 {
+			0
+		} else if !self.twice.is_empty() && self.twice[word].load(Ordering::Relaxed) & bit != 0 {
+			2
+		} else {
+			1
+		}
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use crate::pow::cuckatoo::CuckatooContext;
+
+	// empty 80 bytes header, nonce 71 (see cuckaroo tests)
+	static V1_19_HASH: [u64; 4] = [
+		0x23796193872092ea,
+		0xf1017d8a68c4b745,
+		0xd312bd53d2cd307b,
+		0x840acce5833ddc52,
+	];
+	static V1_19_SOL: [u64; 42] = [
+		0x45e9, 0x6a59, 0xf1ad, 0x10ef7, 0x129e8, 0x13e58, 0x17936, 0x19f7f, 0x208df, 0x23704,
+		0x24564, 0x27e64, 0x2b828, 0x2bb41, 0x2ffc0, 0x304c5, 0x31f2a, 0x347de, 0x39686, 0x3ab6c,
+		0x429ad, 0x45254, 0x49200, 0x4f8f8, 0x5697f, 0x57ad1, 0x5dd47, 0x607f8, 0x66199, 0x686c7,
+		0x6d5f3, 0x6da7a, 0x6dbdf, 0x6f6bf, 0x6ffbb, 0x7580e, 0x78594, 0x785ac, 0x78b1d, 0x7b80d,
+		0x7c11c, 0x7da35,
+	];
+
+	fn nonces(mut sols: Vec<Proof>) -> Vec<Vec<u64>> {
+		let mut nonces = sols.drain(..).map(|s| s.nonces).collect::<Vec<_>>();
+		nonces.sort();
+		nonces
+	}
+
+	// Same solutions as the reference Cuckatoo solver
+	#[test]
+	fn mean_cuckatoo() {
+		let header = [0u8; 80].to_vec();
+		let nonce = 1546569;
+
+		let mut reference = CuckatooContext::<u32>::new_impl(15, 42, 10).unwrap();
+		reference
+			.set_header_nonce(header.clone(), Some(nonce), true)
+			.unwrap();
+		let expected = nonces(reference.find_cycles().unwrap());
+		assert_eq!(expected.len(), 2);
+
+		let mut ctx = MeanContext::new_impl(PoWContextTypes::Cuckatoo, 15, 42, 10).unwrap();
+		PoWContext::<u32>::set_header_nonce(&mut ctx, header, Some(nonce), true).unwrap();
+		let sols = ctx.find_cycles_impl().unwrap();
+		for s in &sols {
+			assert_eq!(s.edge_bits, 15);
+			assert!(PoWContext::<u32>::verify(&ctx, s).is_ok());
+		}
+		assert_eq!(nonces(sols), expected);
+	}
+
+	// Finds a known Cuckaroo solution
+	#[test]
+	fn mean_cuckaroo19() {
+		let mut ctx = MeanContext::new_impl(PoWContextTypes::Cuckaroo, 19, 42, 10).unwrap();
+		PoWContext::<u64>::set_header_nonce(&mut ctx, vec![0; 80], Some(71), true).unwrap();
+		assert_eq!(ctx.solver.siphash_keys, V1_19_HASH);
+
+		let sols = nonces(ctx.find_cycles_impl().unwrap());
+		assert!(sols.contains(&V1_19_SOL.to_vec()));
+	}
+}
@@ -21,9 +21,9 @@ use std::{fmt, iter};
 use rand::{thread_rng, Rng};
 use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
 
-use crate::consensus::{graph_weight, MIN_DIFFICULTY, SECOND_POW_EDGE_BITS};
+use crate::consensus::{graph_weight_with_params, MIN_DIFFICULTY, SECOND_POW_EDGE_BITS};
 use crate::core::hash::{DefaultHashable, Hashed};
-use crate::global;
+use crate::global::{self, ChainParams};
 use crate::ser::{self, FixedLength, Readable, Reader, Writeable, Writer};
 
 use crate::pow::common::EdgeType;
@@ -85,9 +85,13 @@ impl Difficulty {
 	/// Computes the difficulty from a hash. Divides the maximum target by the
 	/// provided hash and applies the Cuck(at)oo size adjustment factor (see
 	/// https://lists.launchpad.net/mimblewimble/msg00494.html).
-	fn from_proof_adjusted(height: u64, proof: &Proof) -> Difficulty {
+	fn from_proof_adjusted(params: &ChainParams, height: u64, proof: &Proof) -> Difficulty {
 		// scale with natural scaling factor
-		Difficulty::from_num(proof.scaled_difficulty(graph_weight(height, proof.edge_bits)))
+		Difficulty::from_num(proof.scaled_difficulty(graph_weight_with_params(
+			params,
+			height,
+			proof.edge_bits,
+		)))
 	}
 
 	/// Same as `from_proof_adjusted` but instead of an adjustment based on
@@ -275,14 +279,15 @@ impl ProofOfWork {
 		Ok(())
 	}
 
-	/// Maximum difficulty this proof of work can achieve
-	pub fn to_difficulty(&self, height: u64) -> Difficulty {
+	/// Maximum difficulty this proof of work can achieve on the chain with
+	/// the provided parameters
+	pub fn to_difficulty(&self, params: &ChainParams, height: u64) -> Difficulty {
 		// 2 proof of works, Cuckoo29 (for now) and Cuckoo30+, which are scaled
 		// differently (scaling not controlled for now)
 		if self.proof.edge_bits == SECOND_POW_EDGE_BITS {
 			Difficulty::from_proof_scaled(&self.proof, self.secondary_scaling)
 		} else {
-			Difficulty::from_proof_adjusted(height, &self.proof)
+			Difficulty::from_proof_adjusted(params, height, &self.proof)
 		}
 	}
 
@@ -292,12 +297,12 @@ impl ProofOfWork {
 	}
 
 	/// Whether this proof of work is for the primary algorithm (as opposed
-	/// to secondary). Only depends on the edge_bits at this time.
-	pub fn is_primary(&self) -> bool {
+	/// to secondary) on the chain with the provided parameters. Only depends
+	/// on the edge_bits at this time.
+	pub fn is_primary(&self, params: &ChainParams) -> bool {
 		// 2 conditions are redundant right now but not necessarily in
 		// the future
-		self.proof.edge_bits != SECOND_POW_EDGE_BITS
-			&& self.proof.edge_bits >= global::min_edge_bits()
+		self.proof.edge_bits != SECOND_POW_EDGE_BITS && self.proof.edge_bits >= params.min_edge_bits
 	}
 
 	/// Whether this proof of work is for the secondary algorithm (as opposed
@@ -397,16 +402,21 @@ impl Readable for Proof {
 			return Err(ser::Error::CorruptedData);
 		}
 
-		// prepare nonces and read the right number of bytes
-		let mut nonces = Vec::with_capacity(global::proofsize());
+		// prepare nonces and read the right number of bytes, the proof size
+		// being the one of the chain we read from
+		let proof_size = reader
+			.chain_params()
+			.ok_or(ser::Error::NoChainParams)?
+			.proofsize;
+		let mut nonces = Vec::with_capacity(proof_size);
 		let nonce_bits = edge_bits as usize;
-		let bits_len = nonce_bits * global::proofsize();
+		let bits_len = nonce_bits * proof_size;
 		let bytes_len = BitVec::bytes_len(bits_len);
 		let bits = reader.read_fixed_bytes(bytes_len)?;
 
 		// set our nonces from what we read in the bitvec
 		let bitvec = BitVec { bits };
-		for n in 0..global::proofsize() {
+		for n in 0..proof_size {
 			let mut nonce = 0;
 			for bit in 0..nonce_bits {
 				if bitvec.bit_at(n * nonce_bits + (bit as usize)) {
@@ -434,7 +444,7 @@ impl Writeable for Proof {
 			writer.write_u8(self.edge_bits)?;
 		}
 		let nonce_bits = self.edge_bits as usize;
-		let mut bitvec = BitVec::new(nonce_bits * global::proofsize());
+		let mut bitvec = BitVec::new(nonce_bits * self.nonces.len());
 		for (n, nonce) in self.nonces.iter().enumerate() {
 			for bit in 0..nonce_bits {
 				if nonce & (1 << bit) != 0 {
@@ -20,6 +20,7 @@
 //! `serialize` or `deserialize` functions on them as appropriate.
 
 use crate::core::hash::{DefaultHashable, Hash, Hashed};
+use crate::global::{self, ChainParams};
 use crate::keychain::{BlindingFactor, Identifier, IDENTIFIER_SIZE};
 use crate::util::read_write::read_exact;
 use crate::util::secp::constants::{
@@ -65,6 +66,8 @@ pub enum Error {
 	SortError,
 	/// Inputs/outputs/kernels must be unique.
 	DuplicateError,
+	/// Reading data depending on the chain parameters without any
+	NoChainParams,
 }
 
 impl From<io::Error> for Error {
@@ -87,6 +90,7 @@ impl fmt::Display for Error {
 			Error::DuplicateError => f.write_str("duplicate"),
 			Error::TooLargeReadErr => f.write_str("too large read"),
 			Error::HexError(ref e) => write!(f, "hex error {:?}", e),
+			Error::NoChainParams => f.write_str("no chain parameters"),
 		}
 	}
 }
@@ -109,10 +113,64 @@ impl error::Error for Error {
 			Error::DuplicateError => "duplicate error",
 			Error::TooLargeReadErr => "too large read",
 			Error::HexError(_) => "hex error",
+			Error::NoChainParams => "no chain parameters",
 		}
 	}
 }
 
+/// Our local protocol version.
+/// We will increment the protocol version with every change to the binary
+/// serialization of p2p messages or of the data we store locally, so we will
+/// likely connect with peers with both higher and lower protocol versions,
+/// and open dbs written with lower protocol versions.
+/// We need to be aware that some formats will be potentially incompatible and
+/// handle this for each individual peer connection and db.
+/// Note: A peer may disconnect and reconnect with an updated protocol version.
+/// Normally the protocol version will increase but we need to handle
+/// decreasing values also as a peer may rollback to previous version of the
+/// code.
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// Protocol version for serialization/deserialization.
+/// Used both by the p2p layer (negotiated with each peer during the
+/// handshake) and our local db storage layer (recorded in the db). We may
+/// speak multiple versions to various peers and a potentially *different*
+/// version for our local db.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, Ord, PartialEq, PartialOrd, Serialize)]
+pub struct ProtocolVersion(pub u32);
+
+impl ProtocolVersion {
+	/// Our local protocol version, the highest one we know about.
+	pub fn local() -> ProtocolVersion {
+		ProtocolVersion(PROTOCOL_VERSION)
+	}
+}
+
+impl fmt::Display for ProtocolVersion {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "{}", self.0)
+	}
+}
+
+impl From<ProtocolVersion> for u32 {
+	fn from(v: ProtocolVersion) -> u32 {
+		v.0
+	}
+}
+
+impl Writeable for ProtocolVersion {
+	fn write<W: Writer>(&self, writer: &mut W) -> Result<(), Error> {
+		writer.write_u32(self.0)
+	}
+}
+
+impl Readable for ProtocolVersion {
+	fn read(reader: &mut dyn Reader) -> Result<ProtocolVersion, Error> {
+		let version = reader.read_u32()?;
+		Ok(ProtocolVersion(version))
+	}
+}
+
 /// Signal to a serializable object how much of its data should be serialized
 #[derive(Copy, Clone, PartialEq, Eq)]
 pub enum SerializationMode {
@@ -128,6 +186,9 @@ pub trait Writer {
 	/// The mode this serializer is writing in
 	fn serialization_mode(&self) -> SerializationMode;
 
+	/// The protocol version this serializer is writing for
+	fn protocol_version(&self) -> ProtocolVersion;
+
 	/// Writes a u8 as bytes
 	fn write_u8(&mut self, n: u8) -> Result<(), Error> {
 		self.write_fixed_bytes(&[n])
@@ -202,6 +263,11 @@ pub trait Reader {
 	/// Consumes a byte from the reader, producing an error if it doesn't have
 	/// the expected value
 	fn expect_u8(&mut self, val: u8) -> Result<u8, Error>;
+	/// The protocol version the data being read was written for
+	fn protocol_version(&self) -> ProtocolVersion;
+	/// The parameters of the chain the data being read comes from, needed
+	/// for block headers (proof size) and transaction bodies (max weight)
+	fn chain_params(&self) -> Option<&ChainParams>;
 }
 
 /// Trait that every type that can be serialized as binary must implement.
@@ -279,29 +345,83 @@ where
 	fn read(reader: &mut dyn Reader) -> Result<Self, Error>;
 }
 
-/// Deserializes a Readable from any std::io::Read implementation.
-pub fn deserialize<T: Readable>(source: &mut dyn Read) -> Result<T, Error> {
-	let mut reader = BinReader { source };
+/// Deserializes a Readable from any std::io::Read implementation, written
+/// for the provided protocol version.
+pub fn deserialize<T: Readable>(
+	source: &mut dyn Read,
+	version: ProtocolVersion,
+) -> Result<T, Error> {
+	let mut reader = BinReader::new(source, version);
+	T::read(&mut reader)
+}
+
+/// Deserializes a Readable from any std::io::Read implementation, written
+/// for the provided protocol version on the chain with the provided
+/// parameters.
+pub fn deserialize_with_params<T: Readable>(
+	source: &mut dyn Read,
+	version: ProtocolVersion,
+	params: &ChainParams,
+) -> Result<T, Error> {
+	let mut reader = BinReader::new(source, version).with_chain_params(params);
 	T::read(&mut reader)
 }
 
-/// Serializes a Writeable into any std::io::Write implementation.
-pub fn serialize<W: Writeable>(sink: &mut dyn Write, thing: &W) -> Result<(), Error> {
-	let mut writer = BinWriter { sink };
+/// Deserialize a Readable based on our local protocol version and the
+/// process wide chain parameters.
+pub fn deserialize_default<T: Readable>(source: &mut dyn Read) -> Result<T, Error> {
+	deserialize_with_params(source, ProtocolVersion::local(), &global::chain_params())
+}
+
+/// Serializes a Writeable into any std::io::Write implementation, for the
+/// provided protocol version.
+pub fn serialize<W: Writeable>(
+	sink: &mut dyn Write,
+	version: ProtocolVersion,
+	thing: &W,
+) -> Result<(), Error> {
+	let mut writer = BinWriter::new(sink, version);
 	thing.write(&mut writer)
 }
 
+/// Serialize a Writeable according to our local protocol version.
+pub fn serialize_default<W: Writeable>(sink: &mut dyn Write, thing: &W) -> Result<(), Error> {
+	serialize(sink, ProtocolVersion::local(), thing)
+}
+
 /// Utility function to serialize a writeable directly in memory using a
-/// Vec<u8>.
-pub fn ser_vec<W: Writeable>(thing: &W) -> Result<Vec<u8>, Error> {
+/// Vec<u8>, for the provided protocol version.
+pub fn ser_vec<W: Writeable>(thing: &W, version: ProtocolVersion) -> Result<Vec<u8>, Error> {
 	let mut vec = vec![];
-	serialize(&mut vec, thing)?;
+	serialize(&mut vec, version, thing)?;
 	Ok(vec)
 }
 
 /// Utility to read from a binary source
-struct BinReader<'a> {
+pub struct BinReader<'a> {
 	source: &'a mut dyn Read,
+	version: ProtocolVersion,
+	params: Option<&'a ChainParams>,
+}
+
+impl<'a> BinReader<'a> {
+	/// Constructor for a new BinReader for the provided source and protocol
+	/// version.
+	pub fn new(source: &'a mut dyn Read, version: ProtocolVersion) -> BinReader<'a> {
+		BinReader {
+			source,
+			version,
+			params: None,
+		}
+	}
+
+	/// Reads the data of the chain with the provided parameters.
+	pub fn with_chain_params(self, params: &'a ChainParams) -> BinReader<'a> {
+		BinReader {
+			params: Some(params),
+			..self
+		}
+	}
 }
 
 fn map_io_err(err: io::Error) -> Error {
@@ -359,24 +479,49 @@ impl<'a> Reader for BinReader<'a> {
 			})
 		}
 	}
+
+	fn protocol_version(&self) -> ProtocolVersion {
+		self.version
+	}
+
+	fn chain_params(&self) -> Option<&ChainParams> {
+		self.params
+	}
 }
 
 /// A reader that reads straight off a stream.
 /// Tracks total bytes read so we can verify we read the right number afterwards.
 pub struct StreamingReader<'a> {
 	total_bytes_read: u64,
+	version: ProtocolVersion,
 	stream: &'a mut dyn Read,
 	timeout: Duration,
+	params: Option<&'a ChainParams>,
 }
 
 impl<'a> StreamingReader<'a> {
-	/// Create a new streaming reader with the provided underlying stream.
-	/// Also takes a duration to be used for each individual read_exact call.
-	pub fn new(stream: &'a mut dyn Read, timeout: Duration) -> StreamingReader<'a> {
+	/// Create a new streaming reader with the provided underlying stream and
+	/// protocol version. Also takes a duration to be used for each individual
+	/// read_exact call.
+	pub fn new(
+		stream: &'a mut dyn Read,
+		version: ProtocolVersion,
+		timeout: Duration,
+	) -> StreamingReader<'a> {
 		StreamingReader {
 			total_bytes_read: 0,
+			version,
 			stream,
 			timeout,
+			params: None,
+		}
+	}
+
+	/// Reads the data of the chain with the provided parameters.
+	pub fn with_chain_params(self, params: &'a ChainParams) -> StreamingReader<'a> {
+		StreamingReader {
+			params: Some(params),
+			..self
 		}
 	}
 
@@ -389,32 +534,32 @@ impl<'a> StreamingReader<'a> {
 impl<'a> Reader for StreamingReader<'a> {
 	fn read_u8(&mut self) -> Result<u8, Error> {
 		let buf = self.read_fixed_bytes(1)?;
-		deserialize(&mut &buf[..])
+		deserialize(&mut &buf[..], self.version)
 	}
 
 	fn read_u16(&mut self) -> Result<u16, Error> {
 		let buf = self.read_fixed_bytes(2)?;
-		deserialize(&mut &buf[..])
+		deserialize(&mut &buf[..], self.version)
 	}
 
 	fn read_u32(&mut self) -> Result<u32, Error> {
 		let buf = self.read_fixed_bytes(4)?;
-		deserialize(&mut &buf[..])
+		deserialize(&mut &buf[..], self.version)
 	}
 
 	fn read_i32(&mut self) -> Result<i32, Error> {
 		let buf = self.read_fixed_bytes(4)?;
-		deserialize(&mut &buf[..])
+		deserialize(&mut &buf[..], self.version)
 	}
 
 	fn read_u64(&mut self) -> Result<u64, Error> {
 		let buf = self.read_fixed_bytes(8)?;
-		deserialize(&mut &buf[..])
+		deserialize(&mut &buf[..], self.version)
 	}
 
 	fn read_i64(&mut self) -> Result<i64, Error> {
 		let buf = self.read_fixed_bytes(8)?;
-		deserialize(&mut &buf[..])
+		deserialize(&mut &buf[..], self.version)
 	}
 
 	/// Read a variable size vector from the underlying stream. Expects a usize
@@ -443,6 +588,14 @@ impl<'a> Reader for StreamingReader<'a> {
 			})
 		}
 	}
+
+	fn protocol_version(&self) -> ProtocolVersion {
+		self.version
+	}
+
+	fn chain_params(&self) -> Option<&ChainParams> {
+		self.params
+	}
 }
 
 impl Readable for Commitment {
@@ -567,12 +720,19 @@ impl<T: Hashed> VerifySortedAndUnique<T> for Vec<T> {
 /// to write numbers, byte vectors, hashes, etc.
 pub struct BinWriter<'a> {
 	sink: &'a mut dyn Write,
+	version: ProtocolVersion,
 }
 
 impl<'a> BinWriter<'a> {
-	/// Wraps a standard Write in a new BinWriter
-	pub fn new(write: &'a mut dyn Write) -> BinWriter<'a> {
-		BinWriter { sink: write }
+	/// Wraps a standard Write in a new BinWriter, for the provided protocol
+	/// version.
+	pub fn new(sink: &'a mut dyn Write, version: ProtocolVersion) -> BinWriter<'a> {
+		BinWriter { sink, version }
+	}
+
+	/// Constructor for BinWriter with our local protocol version.
+	pub fn default(sink: &'a mut dyn Write) -> BinWriter<'a> {
+		BinWriter::new(sink, ProtocolVersion::local())
 	}
 }
 
@@ -581,6 +741,10 @@ impl<'a> Writer for BinWriter<'a> {
 		SerializationMode::Full
 	}
 
+	fn protocol_version(&self) -> ProtocolVersion {
+		self.version
+	}
+
 	fn write_fixed_bytes<T: AsFixedBytes>(&mut self, fixed: &T) -> Result<(), Error> {
 		let bs = fixed.as_ref();
 		self.sink.write_all(bs)?;
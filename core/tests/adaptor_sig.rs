@@ -0,0 +1,325 @@
+// Copyright 2018 The Grin Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Adaptor signature tests
+
+use self::core::core::transaction::{self, Weighting};
+use self::core::core::verifier_cache::LruVerifierCache;
+use self::core::core::{Input, Output, OutputFeatures, Transaction, TxKernel};
+use self::core::libtx::{aggsig, proof};
+use self::keychain::{ExtKeychain, Keychain};
+use self::util::secp::key::{PublicKey, SecretKey};
+use self::util::secp::{ContextFlag, Message, Secp256k1};
+use self::util::RwLock;
+use grin_core as core;
+use grin_keychain as keychain;
+use grin_util as util;
+use std::sync::Arc;
+
+fn key(secp: &Secp256k1, byte: u8) -> SecretKey {
+	SecretKey::from_slice(secp, &[byte; 32]).unwrap()
+}
+
+fn public(secp: &Secp256k1, key: &SecretKey) -> PublicKey {
+	PublicKey::from_secret_key(secp, key).unwrap()
+}
+
+fn sum(secp: &Secp256k1, keys: Vec<&PublicKey>) -> PublicKey {
+	PublicKey::from_combination(secp, keys).unwrap()
+}
+
+#[test]
+fn adaptor_sig_vectors() {
+	let secp = Secp256k1::with_caps(ContextFlag::Full);
+	let (sk_a, sk_b) = (key(&secp, 1), key(&secp, 2));
+	let (nonce_a, nonce_b) = (key(&secp, 3), key(&secp, 4));
+	let secret = key(&secp, 5);
+	let msg = Message::from_slice(&[6; 32]).unwrap();
+
+	let (pk_a, pk_b) = (public(&secp, &sk_a), public(&secp, &sk_b));
+	let pk_sum = sum(&secp, vec![&pk_a, &pk_b]);
+	let nonce_sum = sum(
+		&secp,
+		vec![&public(&secp, &nonce_a), &public(&secp, &nonce_b)],
+	);
+	let adaptor = public(&secp, &secret);
+
+	let part_a = aggsig::calculate_adaptor_partial_sig(
+		&secp,
+		&sk_a,
+		&nonce_a,
+		&nonce_sum,
+		&adaptor,
+		Some(&pk_sum),
+		&msg,
+	)
+	.unwrap();
+	let part_b = aggsig::calculate_adaptor_partial_sig(
+		&secp,
+		&sk_b,
+		&nonce_b,
+		&nonce_sum,
+		&adaptor,
+		Some(&pk_sum),
+		&msg,
+	)
+	.unwrap();
+
+	// Partial adaptor signatures only verify against their own key and
+	// adaptor point.
+	aggsig::verify_adaptor_partial_sig(
+		&secp,
+		&part_a,
+		&nonce_sum,
+		&adaptor,
+		&pk_a,
+		Some(&pk_sum),
+		&msg,
+	)
+	.unwrap();
+	aggsig::verify_adaptor_partial_sig(
+		&secp,
+		&part_b,
+		&nonce_sum,
+		&adaptor,
+		&pk_b,
+		Some(&pk_sum),
+		&msg,
+	)
+	.unwrap();
+	assert!(aggsig::verify_adaptor_partial_sig(
+		&secp,
+		&part_a,
+		&nonce_sum,
+		&adaptor,
+		&pk_b,
+		Some(&pk_sum),
+		&msg,
+	)
+	.is_err());
+	assert!(aggsig::verify_adaptor_partial_sig(
+		&secp,
+		&part_a,
+		&nonce_sum,
+		&public(&secp, &key(&secp, 7)),
+		&pk_a,
+		Some(&pk_sum),
+		&msg,
+	)
+	.is_err());
+
+	// The adaptor signature verifies against the sum of the keys, but isn't a
+	// valid signature by itself.
+	let adaptor_sig =
+		aggsig::add_adaptor_signatures(&secp, vec![&part_a, &part_b], &nonce_sum).unwrap();
+	aggsig::verify_adaptor_partial_sig(
+		&secp,
+		&adaptor_sig,
+		&nonce_sum,
+		&adaptor,
+		&pk_sum,
+		Some(&pk_sum),
+		&msg,
+	)
+	.unwrap();
+	assert!(!aggsig::verify_single(
+		&secp,
+		&adaptor_sig,
+		&msg,
+		None,
+		&pk_sum,
+		Some(&pk_sum),
+		false
+	));
+
+	let sig = aggsig::complete_adaptor_sig(&secp, &adaptor_sig, &secret, &nonce_sum, &pk_sum, &msg)
+		.unwrap();
+	assert!(aggsig::verify_single(
+		&secp,
+		&sig,
+		&msg,
+		None,
+		&pk_sum,
+		Some(&pk_sum),
+		false
+	));
+	aggsig::verify_completed_sig(&secp, &sig, &pk_sum, Some(&pk_sum), &msg).unwrap();
+
+	// The completed signature is the one we'd get if signer a had used the
+	// nonce `nonce_a + secret`.
+	let adapted_nonce_a = secp
+		.blind_sum(vec![nonce_a.clone(), secret.clone()], vec![])
+		.unwrap();
+	let adapted_nonce_sum = sum(&secp, vec![&nonce_sum, &adaptor]);
+	let expected = aggsig::add_signatures(
+		&secp,
+		vec![
+			&aggsig::calculate_partial_sig(
+				&secp,
+				&sk_a,
+				&adapted_nonce_a,
+				&adapted_nonce_sum,
+				Some(&pk_sum),
+				&msg,
+			)
+			.unwrap(),
+			&aggsig::calculate_partial_sig(
+				&secp,
+				&sk_b,
+				&nonce_b,
+				&adapted_nonce_sum,
+				Some(&pk_sum),
+				&msg,
+			)
+			.unwrap(),
+		],
+		&adapted_nonce_sum,
+	)
+	.unwrap();
+	assert_eq!(sig, expected);
+
+	// The secret can be extracted back, but only with the right pair of
+	// signatures.
+	let extracted = aggsig::extract_adaptor_secret(&secp, &sig, &adaptor_sig, &adaptor).unwrap();
+	assert_eq!(extracted, secret);
+	assert!(aggsig::extract_adaptor_secret(&secp, &sig, &part_a, &adaptor).is_err());
+
+	// Completing with another secret doesn't give a valid signature.
+	assert!(aggsig::complete_adaptor_sig(
+		&secp,
+		&adaptor_sig,
+		&key(&secp, 7),
+		&nonce_sum,
+		&pk_sum,
+		&msg,
+	)
+	.is_err());
+}
+
+// Alice sends grins to Bob in exchange for coins on another chain, which Bob
+// locked with a point only Alice knows the secret of. The grin transaction
+// needs that secret to go through, so Bob learns it from the kernel as soon
+// as Alice's transaction is out, and can claim the other coins.
+#[test]
+fn adaptor_sig_swap() {
+	let alice = ExtKeychain::from_random_seed(false).unwrap();
+	let bob = ExtKeychain::from_random_seed(false).unwrap();
+	let secp = alice.secp();
+	let key_id = ExtKeychain::derive_key_id(1, 1, 0, 0, 0);
+
+	// Alice spends 100 to Bob, minus the fee.
+	let input_commit = alice.commit(100, &key_id).unwrap();
+	let input = Input::new(OutputFeatures::Plain, input_commit);
+	let output_commit = bob.commit(98, &key_id).unwrap();
+	let output = Output {
+		features: OutputFeatures::Plain,
+		commit: output_commit,
+		proof: proof::create(&bob, 98, &key_id, output_commit, None).unwrap(),
+	};
+	let mut kernel = TxKernel::empty().with_fee(2);
+	let msg = kernel.msg_to_sign().unwrap();
+
+	// Each side's share of the kernel excess, no offset.
+	let input_key = alice.derive_key(100, &key_id).unwrap();
+	let sk_alice = secp.blind_sum(vec![], vec![input_key]).unwrap();
+	let sk_bob = bob.derive_key(98, &key_id).unwrap();
+	let nonce_alice = aggsig::create_secnonce(secp).unwrap();
+	let nonce_bob = aggsig::create_secnonce(secp).unwrap();
+
+	let pk_alice = public(secp, &sk_alice);
+	let pk_bob = public(secp, &sk_bob);
+	let pk_sum = sum(secp, vec![&pk_alice, &pk_bob]);
+	let nonce_sum = sum(
+		secp,
+		vec![&public(secp, &nonce_alice), &public(secp, &nonce_bob)],
+	);
+
+	// Alice's secret, locking Bob's coins on the other chain.
+	let secret = SecretKey::new(secp, &mut rand::thread_rng());
+	let adaptor = public(secp, &secret);
+
+	// Both sides pre-sign, checking each other's partial adaptor signature.
+	let part_alice = aggsig::calculate_adaptor_partial_sig(
+		secp,
+		&sk_alice,
+		&nonce_alice,
+		&nonce_sum,
+		&adaptor,
+		Some(&pk_sum),
+		&msg,
+	)
+	.unwrap();
+	let part_bob = aggsig::calculate_adaptor_partial_sig(
+		secp,
+		&sk_bob,
+		&nonce_bob,
+		&nonce_sum,
+		&adaptor,
+		Some(&pk_sum),
+		&msg,
+	)
+	.unwrap();
+	aggsig::verify_adaptor_partial_sig(
+		secp,
+		&part_alice,
+		&nonce_sum,
+		&adaptor,
+		&pk_alice,
+		Some(&pk_sum),
+		&msg,
+	)
+	.unwrap();
+	aggsig::verify_adaptor_partial_sig(
+		secp,
+		&part_bob,
+		&nonce_sum,
+		&adaptor,
+		&pk_bob,
+		Some(&pk_sum),
+		&msg,
+	)
+	.unwrap();
+	let adaptor_sig =
+		aggsig::add_adaptor_signatures(secp, vec![&part_alice, &part_bob], &nonce_sum).unwrap();
+
+	let excess_key = secp
+		.blind_sum(vec![sk_alice.clone(), sk_bob.clone()], vec![])
+		.unwrap();
+	kernel.excess = secp.commit(0, excess_key).unwrap();
+	assert_eq!(kernel.excess.to_pubkey(secp).unwrap(), pk_sum);
+
+	// Without the secret the transaction doesn't validate.
+	kernel.excess_sig = adaptor_sig;
+	let tx = Transaction::new(vec![input], vec![output], vec![kernel.clone()]);
+	let verifier_cache = Arc::new(RwLock::new(LruVerifierCache::new()));
+	assert_eq!(
+		tx.validate(Weighting::AsTransaction, verifier_cache.clone()),
+		Err(transaction::Error::IncorrectSignature)
+	);
+
+	// Alice completes the signature with her secret to get the transaction
+	// out, revealing the secret to Bob in the process.
+	let sig = aggsig::complete_adaptor_sig(secp, &adaptor_sig, &secret, &nonce_sum, &pk_sum, &msg)
+		.unwrap();
+	kernel.excess_sig = sig;
+	let tx = Transaction::new(vec![input], vec![output], vec![kernel.clone()]);
+	tx.validate(Weighting::AsTransaction, verifier_cache.clone())
+		.unwrap();
+
+	// Bob sees the kernel on chain and gets the secret to claim his coins on
+	// the other chain.
+	let extracted =
+		aggsig::extract_adaptor_secret(secp, &kernel.excess_sig, &adaptor_sig, &adaptor).unwrap();
+	assert_eq!(extracted, secret);
+}
@@ -64,7 +64,11 @@ fn too_large_block() {
 	let key_id = ExtKeychain::derive_key_id(1, 1, 0, 0, 0);
 	let b = new_block(vec![&tx], &keychain, &prev, &key_id);
 	assert!(b
-		.validate(&BlindingFactor::zero(), verifier_cache())
+		.validate(
+			&global::chain_params(),
+			&BlindingFactor::zero(),
+			verifier_cache()
+		)
 		.is_err());
 }
 
@@ -109,8 +113,12 @@ fn block_with_cut_through() {
 
 	// block should have been automatically compacted (including reward
 	// output) and should still be valid
-	b.validate(&BlindingFactor::zero(), verifier_cache())
-		.unwrap();
+	b.validate(
+		&global::chain_params(),
+		&BlindingFactor::zero(),
+		verifier_cache(),
+	)
+	.unwrap();
 	assert_eq!(b.inputs().len(), 3);
 	assert_eq!(b.outputs().len(), 3);
 }
@@ -145,7 +153,11 @@ fn empty_block_with_coinbase_is_valid() {
 	// the block should be valid here (single coinbase output with corresponding
 	// txn kernel)
 	assert!(b
-		.validate(&BlindingFactor::zero(), verifier_cache())
+		.validate(
+			&global::chain_params(),
+			&BlindingFactor::zero(),
+			verifier_cache()
+		)
 		.is_ok());
 }
 
@@ -167,7 +179,11 @@ fn remove_coinbase_output_flag() {
 		.verify_kernel_sums(b.header.overage(), b.header.total_kernel_offset())
 		.is_ok());
 	assert_eq!(
-		b.validate(&BlindingFactor::zero(), verifier_cache()),
+		b.validate(
+			&global::chain_params(),
+			&BlindingFactor::zero(),
+			verifier_cache()
+		),
 		Err(Error::CoinbaseSumMismatch)
 	);
 }
@@ -193,7 +209,11 @@ fn remove_coinbase_kernel_flag() {
 	// Also results in the block no longer validating correctly
 	// because the message being signed on each tx kernel includes the kernel features.
 	assert_eq!(
-		b.validate(&BlindingFactor::zero(), verifier_cache()),
+		b.validate(
+			&global::chain_params(),
+			&BlindingFactor::zero(),
+			verifier_cache()
+		),
 		Err(Error::Transaction(transaction::Error::IncorrectSignature))
 	);
 }
@@ -207,8 +227,8 @@ fn serialize_deserialize_block_header() {
 	let header1 = b.header;
 
 	let mut vec = Vec::new();
-	ser::serialize(&mut vec, &header1).expect("serialization failed");
-	let header2: BlockHeader = ser::deserialize(&mut &vec[..]).unwrap();
+	ser::serialize_default(&mut vec, &header1).expect("serialization failed");
+	let header2: BlockHeader = ser::deserialize_default(&mut &vec[..]).unwrap();
 
 	assert_eq!(header1.hash(), header2.hash());
 	assert_eq!(header1, header2);
@@ -223,8 +243,8 @@ fn serialize_deserialize_block() {
 	let b = new_block(vec![&tx1], &keychain, &prev, &key_id);
 
 	let mut vec = Vec::new();
-	ser::serialize(&mut vec, &b).expect("serialization failed");
-	let b2: Block = ser::deserialize(&mut &vec[..]).unwrap();
+	ser::serialize_default(&mut vec, &b).expect("serialization failed");
+	let b2: Block = ser::deserialize_default(&mut &vec[..]).unwrap();
 
 	assert_eq!(b.hash(), b2.hash());
 	assert_eq!(b.header, b2.header);
@@ -240,7 +260,7 @@ fn empty_block_serialized_size() {
 	let key_id = ExtKeychain::derive_key_id(1, 1, 0, 0, 0);
 	let b = new_block(vec![], &keychain, &prev, &key_id);
 	let mut vec = Vec::new();
-	ser::serialize(&mut vec, &b).expect("serialization failed");
+	ser::serialize_default(&mut vec, &b).expect("serialization failed");
 	let target_len = 1_265;
 	assert_eq!(vec.len(), target_len);
 }
@@ -253,7 +273,7 @@ fn block_single_tx_serialized_size() {
 	let key_id = ExtKeychain::derive_key_id(1, 1, 0, 0, 0);
 	let b = new_block(vec![&tx1], &keychain, &prev, &key_id);
 	let mut vec = Vec::new();
-	ser::serialize(&mut vec, &b).expect("serialization failed");
+	ser::serialize_default(&mut vec, &b).expect("serialization failed");
 	let target_len = 2_847;
 	assert_eq!(vec.len(), target_len);
 }
@@ -266,7 +286,7 @@ fn empty_compact_block_serialized_size() {
 	let b = new_block(vec![], &keychain, &prev, &key_id);
 	let cb: CompactBlock = b.into();
 	let mut vec = Vec::new();
-	ser::serialize(&mut vec, &cb).expect("serialization failed");
+	ser::serialize_default(&mut vec, &cb).expect("serialization failed");
 	let target_len = 1_273;
 	assert_eq!(vec.len(), target_len);
 }
@@ -280,7 +300,7 @@ fn compact_block_single_tx_serialized_size() {
 	let b = new_block(vec![&tx1], &keychain, &prev, &key_id);
 	let cb: CompactBlock = b.into();
 	let mut vec = Vec::new();
-	ser::serialize(&mut vec, &cb).expect("serialization failed");
+	ser::serialize_default(&mut vec, &cb).expect("serialization failed");
 	let target_len = 1_279;
 	assert_eq!(vec.len(), target_len);
 }
@@ -299,7 +319,7 @@ fn block_10_tx_serialized_size() {
 	let key_id = ExtKeychain::derive_key_id(1, 1, 0, 0, 0);
 	let b = new_block(txs.iter().collect(), &keychain, &prev, &key_id);
 	let mut vec = Vec::new();
-	ser::serialize(&mut vec, &b).expect("serialization failed");
+	ser::serialize_default(&mut vec, &b).expect("serialization failed");
 	let target_len = 17_085;
 	assert_eq!(vec.len(), target_len,);
 }
@@ -318,7 +338,7 @@ fn compact_block_10_tx_serialized_size() {
 	let b = new_block(txs.iter().collect(), &keychain, &prev, &key_id);
 	let cb: CompactBlock = b.into();
 	let mut vec = Vec::new();
-	ser::serialize(&mut vec, &cb).expect("serialization failed");
+	ser::serialize_default(&mut vec, &cb).expect("serialization failed");
 	let target_len = 1_333;
 	assert_eq!(vec.len(), target_len,);
 }
@@ -400,7 +420,7 @@ fn serialize_deserialize_compact_block() {
 	let mut cb1: CompactBlock = b.into();
 
 	let mut vec = Vec::new();
-	ser::serialize(&mut vec, &cb1).expect("serialization failed");
+	ser::serialize_default(&mut vec, &cb1).expect("serialization failed");
 
 	// After header serialization, timestamp will lose 'nanos' info, that's the designed behavior.
 	// To suppress 'nanos' difference caused assertion fail, we force b.header also lose 'nanos'.
@@ -408,7 +428,7 @@ fn serialize_deserialize_compact_block() {
 	cb1.header.timestamp =
 		origin_ts - Duration::nanoseconds(origin_ts.timestamp_subsec_nanos() as i64);
 
-	let cb2: CompactBlock = ser::deserialize(&mut &vec[..]).unwrap();
+	let cb2: CompactBlock = ser::deserialize_default(&mut &vec[..]).unwrap();
 
 	assert_eq!(cb1.header, cb2.header);
 	assert_eq!(cb1.kern_ids(), cb2.kern_ids());
@@ -455,7 +475,11 @@ fn same_amount_outputs_copy_range_proof() {
 
 	// block should have been automatically compacted (including reward
 	// output) and should still be valid
-	match b.validate(&BlindingFactor::zero(), verifier_cache()) {
+	match b.validate(
+		&global::chain_params(),
+		&BlindingFactor::zero(),
+		verifier_cache(),
+	) {
 		Err(Error::Transaction(transaction::Error::Secp(secp::Error::InvalidRangeProof))) => {}
 		_ => panic!("Bad range proof should be invalid"),
 	}
@@ -512,7 +536,61 @@ fn wrong_amount_range_proof() {
 
 	// block should have been automatically compacted (including reward
 	// output) and should still be valid
-	match b.validate(&BlindingFactor::zero(), verifier_cache()) {
+	match b.validate(
+		&global::chain_params(),
+		&BlindingFactor::zero(),
+		verifier_cache(),
+	) {
+		Err(Error::Transaction(transaction::Error::Secp(secp::Error::InvalidRangeProof))) => {}
+		_ => panic!("Bad range proof should be invalid"),
+	}
+}
+
+// Enough outputs for the rangeproofs to be verified across multiple batches.
+// A bad range proof in any one of the batches must invalidate the block.
+#[test]
+fn many_outputs_bad_range_proof() {
+	let keychain = keychain::ExtKeychain::from_random_seed(false).unwrap();
+
+	let mut parts = vec![input(1_000, ExtKeychain::derive_key_id(1, 0, 0, 0, 0))];
+	for n in 1..151 {
+		parts.push(output(5, ExtKeychain::derive_key_id(1, n, 0, 0, 0)));
+	}
+	parts.push(with_fee(250));
+	let tx = build::transaction(parts, &keychain).unwrap();
+
+	let key_id = ExtKeychain::derive_key_id(1, 151, 0, 0, 0);
+	let prev = BlockHeader::default();
+	let b = new_block(vec![&tx], &keychain, &prev, &key_id);
+	b.validate(
+		&global::chain_params(),
+		&BlindingFactor::zero(),
+		verifier_cache(),
+	)
+	.unwrap();
+
+	// now swap the range proofs of the first and last outputs
+	// (same amount but the wrong blinding factors)
+	let mut outs = tx.outputs().clone();
+	let last = outs.len() - 1;
+	let proof = outs[0].proof;
+	outs[0].proof = outs[last].proof;
+	outs[last].proof = proof;
+
+	let b = new_block(
+		vec![
+			&Transaction::new(tx.inputs().clone(), outs, tx.kernels().clone())
+				.with_offset(tx.offset.clone()),
+		],
+		&keychain,
+		&prev,
+		&key_id,
+	);
+	match b.validate(
+		&global::chain_params(),
+		&BlindingFactor::zero(),
+		verifier_cache(),
+	) {
 		Err(Error::Transaction(transaction::Error::Secp(secp::Error::InvalidRangeProof))) => {}
 		_ => panic!("Bad range proof should be invalid"),
 	}
@@ -0,0 +1,106 @@
+// Copyright 2018 The Grin Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+pub mod common;
+use crate::common::{new_block, tx2i1o};
+use crate::core::core::block_filter::BlockFilter;
+use crate::core::core::hash::{Hash, Hashed};
+use crate::core::core::BlockHeader;
+use crate::core::ser;
+use crate::keychain::{ExtKeychain, Keychain};
+use crate::util::secp::pedersen::Commitment;
+use grin_core as core;
+use grin_keychain as keychain;
+use grin_util as util;
+
+// Arbitrary (not necessarily valid) commitments, only their bytes matter to
+// the filters.
+fn commits(from: u64, count: u64) -> Vec<Commitment> {
+	(from..from + count)
+		.map(|i| {
+			let mut bytes = vec![8];
+			bytes.extend(i.hash().to_vec());
+			Commitment::from_vec(bytes)
+		})
+		.collect()
+}
+
+#[test]
+fn block_filter_matches() {
+	let block_hash = Hash::from_vec(&[1, 2, 3]);
+	let members = commits(0, 500);
+	let filter = BlockFilter::new(block_hash, &members);
+	assert_eq!(filter.n, 500);
+
+	for commit in &members {
+		assert!(filter.matches(commit));
+	}
+	assert!(filter.matches_any(&[commits(1000, 1)[0], members[42]]));
+
+	// False positives happen about once every 784931 commitments.
+	let false_positives = commits(1000, 5000)
+		.iter()
+		.filter(|c| filter.matches(c))
+		.count();
+	assert!(false_positives < 5);
+
+	// Filters are keyed by the block hash.
+	let other = BlockFilter::new(Hash::from_vec(&[3, 2, 1]), &members);
+	assert_ne!(filter.data, other.data);
+
+	// Duplicates are only counted once.
+	let mut dups = members.clone();
+	dups.extend(members.iter().cloned());
+	assert_eq!(BlockFilter::new(block_hash, &dups), filter);
+}
+
+#[test]
+fn empty_block_filter() {
+	let filter = BlockFilter::new(Hash::from_vec(&[1]), &[]);
+	assert_eq!(filter.n, 0);
+	assert!(filter.data.is_empty());
+	assert!(!filter.matches_any(&commits(0, 100)));
+}
+
+#[test]
+fn block_filter_ser_deser() {
+	let filter = BlockFilter::new(Hash::from_vec(&[1]), &commits(0, 100));
+	let vec = ser::ser_vec(&filter, ser::ProtocolVersion::local()).unwrap();
+	let filter2: BlockFilter = ser::deserialize_default(&mut &vec[..]).unwrap();
+	assert_eq!(filter, filter2);
+
+	// A truncated filter matches everything.
+	let mut truncated = filter.clone();
+	truncated.data.truncate(10);
+	assert!(truncated.matches_any(&commits(1000, 1)));
+}
+
+#[test]
+fn block_filter_from_block() {
+	let keychain = ExtKeychain::from_random_seed(false).unwrap();
+	let key_id = ExtKeychain::derive_key_id(1, 1, 0, 0, 0);
+	let tx = tx2i1o();
+	let b = new_block(vec![&tx], &keychain, &BlockHeader::default(), &key_id);
+
+	let filter = BlockFilter::from_block(&b);
+	assert_eq!(filter.block_hash, b.hash());
+	assert_eq!(filter.n, 4);
+	for output in b.outputs() {
+		assert!(filter.matches(&output.commitment()));
+	}
+	for input in b.inputs() {
+		assert!(filter.matches(&input.commitment()));
+	}
+	assert!(!filter.matches_any(&commits(0, 10)));
+}
@@ -0,0 +1,126 @@
+// Copyright 2018 The Grin Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Compact transaction integration tests
+
+pub mod common;
+
+use crate::common::{tx1i1o, tx1i2o, tx2i1o, txspend1i1o};
+use crate::core::core::hash::Hashed;
+use crate::core::core::id::ShortIdentifiable;
+use crate::core::core::{transaction, CompactTransaction, Transaction};
+use crate::core::libtx::build::{self, input, output, with_fee};
+use crate::core::ser;
+use crate::keychain::{ExtKeychain, Keychain};
+use grin_core as core;
+use grin_keychain as keychain;
+
+#[test]
+fn compact_transaction_no_known() {
+	let tx = tx1i2o();
+	let ct = CompactTransaction::new(tx.clone(), &[tx1i1o()]);
+
+	// Nothing in common with the known tx, everything is sent in full.
+	assert_eq!(ct.inp_full(), tx.inputs());
+	assert_eq!(ct.out_full(), tx.outputs());
+	assert_eq!(ct.kern_full(), tx.kernels());
+	assert!(ct.inp_ids().is_empty());
+	assert!(ct.out_ids().is_empty());
+
+	let htx = Transaction::hydrate_from(ct, vec![]).unwrap();
+	assert_eq!(htx, tx);
+}
+
+#[test]
+fn hydrate_aggregated_transaction() {
+	let tx1 = tx1i1o();
+	let tx2 = tx1i2o();
+	let tx3 = tx2i1o();
+	let agg = transaction::aggregate(vec![tx1.clone(), tx2.clone(), tx3.clone()]).unwrap();
+
+	// Peers already know about the first 2 txs.
+	let ct = CompactTransaction::new(agg.clone(), &[tx1.clone(), tx2.clone()]);
+	assert_eq!(ct.inp_full(), tx3.inputs());
+	assert_eq!(ct.out_full(), tx3.outputs());
+	assert_eq!(ct.kern_full(), agg.kernels());
+	assert_eq!(ct.inp_ids().len(), 2);
+	assert_eq!(ct.out_ids().len(), 3);
+	assert!(ct
+		.out_ids()
+		.contains(&tx1.outputs()[0].short_id(&ct.hash(), ct.nonce)));
+
+	let htx = Transaction::hydrate_from(ct.clone(), vec![tx1, tx2]).unwrap();
+	assert_eq!(htx, agg);
+
+	// Missing one of the txs, not hydrated correctly.
+	let htx = Transaction::hydrate_from(ct, vec![tx3]).unwrap();
+	assert_ne!(htx, agg);
+	assert_eq!(htx.kernels(), agg.kernels());
+}
+
+#[test]
+fn hydrate_transaction_spending_known_output() {
+	let keychain = ExtKeychain::from_random_seed(false).unwrap();
+	let key_id1 = ExtKeychain::derive_key_id(1, 1, 0, 0, 0);
+	let key_id2 = ExtKeychain::derive_key_id(1, 2, 0, 0, 0);
+	let key_id3 = ExtKeychain::derive_key_id(1, 3, 0, 0, 0);
+
+	let tx1 = build::transaction(
+		vec![input(5, key_id1), output(3, key_id2.clone()), with_fee(2)],
+		&keychain,
+	)
+	.unwrap();
+	let tx2 = txspend1i1o(3, &keychain, key_id2, key_id3);
+
+	// The input of tx2 spends the output of tx1, peers know about it.
+	let ct = CompactTransaction::new(tx2.clone(), &[tx1.clone()]);
+	assert!(ct.inp_full().is_empty());
+	assert_eq!(ct.inp_ids().len(), 1);
+	assert_eq!(ct.out_full(), tx2.outputs());
+	assert!(ct.out_ids().is_empty());
+
+	let htx = Transaction::hydrate_from(ct, vec![tx1]).unwrap();
+	assert_eq!(htx, tx2);
+}
+
+#[test]
+fn serialize_deserialize_compact_transaction() {
+	let tx1 = tx1i1o();
+	let tx2 = tx1i2o();
+	let agg = transaction::aggregate(vec![tx1.clone(), tx2]).unwrap();
+	let ct1 = CompactTransaction::new(agg.clone(), &[tx1.clone()]);
+
+	let mut vec = Vec::new();
+	ser::serialize_default(&mut vec, &ct1).expect("serialization failed");
+	let ct2: CompactTransaction = ser::deserialize_default(&mut &vec[..]).unwrap();
+
+	assert_eq!(ct1.hash(), ct2.hash());
+	assert_eq!(ct1.nonce, ct2.nonce);
+	assert_eq!(ct1.inp_full(), ct2.inp_full());
+	assert_eq!(ct1.out_full(), ct2.out_full());
+	assert_eq!(ct1.kern_full(), ct2.kern_full());
+	assert_eq!(ct1.inp_ids(), ct2.inp_ids());
+	assert_eq!(ct1.out_ids(), ct2.out_ids());
+
+	// Short ids are still valid after the round trip.
+	let htx = Transaction::hydrate_from(ct2, vec![tx1]).unwrap();
+	assert_eq!(htx, agg);
+
+	// A compact transaction has at least one kernel.
+	let empty = CompactTransaction::new(Transaction::empty(), &[]);
+	let mut vec = Vec::new();
+	ser::serialize_default(&mut vec, &empty).expect("serialization failed");
+	let res: Result<CompactTransaction, _> = ser::deserialize_default(&mut &vec[..]);
+	assert_eq!(res.unwrap_err(), ser::Error::CorruptedData);
+}
@@ -21,6 +21,7 @@ use self::core::core::block::Error::KernelLockHeight;
 use self::core::core::hash::{Hashed, ZERO_HASH};
 use self::core::core::verifier_cache::{LruVerifierCache, VerifierCache};
 use self::core::core::{aggregate, deaggregate, KernelFeatures, Output, Transaction, Weighting};
+use self::core::global;
 use self::core::libtx::build::{
 	self, initial_tx, input, output, with_excess, with_fee, with_lock_height,
 };
@@ -38,7 +39,7 @@ use std::sync::Arc;
 fn simple_tx_ser() {
 	let tx = tx2i1o();
 	let mut vec = Vec::new();
-	ser::serialize(&mut vec, &tx).expect("serialization failed");
+	ser::serialize_default(&mut vec, &tx).expect("serialization failed");
 	let target_len = 955;
 	assert_eq!(vec.len(), target_len,);
 }
@@ -47,8 +48,8 @@ fn simple_tx_ser() {
 fn simple_tx_ser_deser() {
 	let tx = tx2i1o();
 	let mut vec = Vec::new();
-	ser::serialize(&mut vec, &tx).expect("serialization failed");
-	let dtx: Transaction = ser::deserialize(&mut &vec[..]).unwrap();
+	ser::serialize_default(&mut vec, &tx).expect("serialization failed");
+	let dtx: Transaction = ser::deserialize_default(&mut &vec[..]).unwrap();
 	assert_eq!(dtx.fee(), 2);
 	assert_eq!(dtx.inputs().len(), 2);
 	assert_eq!(dtx.outputs().len(), 1);
@@ -61,12 +62,12 @@ fn tx_double_ser_deser() {
 	let btx = tx2i1o();
 
 	let mut vec = Vec::new();
-	assert!(ser::serialize(&mut vec, &btx).is_ok());
-	let dtx: Transaction = ser::deserialize(&mut &vec[..]).unwrap();
+	assert!(ser::serialize_default(&mut vec, &btx).is_ok());
+	let dtx: Transaction = ser::deserialize_default(&mut &vec[..]).unwrap();
 
 	let mut vec2 = Vec::new();
-	assert!(ser::serialize(&mut vec2, &btx).is_ok());
-	let dtx2: Transaction = ser::deserialize(&mut &vec2[..]).unwrap();
+	assert!(ser::serialize_default(&mut vec2, &btx).is_ok());
+	let dtx2: Transaction = ser::deserialize_default(&mut &vec2[..]).unwrap();
 
 	assert_eq!(btx.hash(), dtx.hash());
 	assert_eq!(dtx.hash(), dtx2.hash());
@@ -455,7 +456,11 @@ fn reward_empty_block() {
 
 	b.cut_through()
 		.unwrap()
-		.validate(&BlindingFactor::zero(), verifier_cache())
+		.validate(
+			&global::chain_params(),
+			&BlindingFactor::zero(),
+			verifier_cache(),
+		)
 		.unwrap();
 }
 
@@ -475,7 +480,7 @@ fn reward_with_tx_block() {
 	block
 		.cut_through()
 		.unwrap()
-		.validate(&BlindingFactor::zero(), vc.clone())
+		.validate(&global::chain_params(), &BlindingFactor::zero(), vc.clone())
 		.unwrap();
 }
 
@@ -497,7 +502,8 @@ fn simple_block() {
 		&key_id,
 	);
 
-	b.validate(&BlindingFactor::zero(), vc.clone()).unwrap();
+	b.validate(&global::chain_params(), &BlindingFactor::zero(), vc.clone())
+		.unwrap();
 }
 
 #[test]
@@ -526,7 +532,8 @@ fn test_block_with_timelocked_tx() {
 	let previous_header = BlockHeader::default();
 
 	let b = new_block(vec![&tx1], &keychain, &previous_header, &key_id3.clone());
-	b.validate(&BlindingFactor::zero(), vc.clone()).unwrap();
+	b.validate(&global::chain_params(), &BlindingFactor::zero(), vc.clone())
+		.unwrap();
 
 	// now try adding a timelocked tx where lock height is greater than current
 	// block height
@@ -544,7 +551,7 @@ fn test_block_with_timelocked_tx() {
 	let previous_header = BlockHeader::default();
 	let b = new_block(vec![&tx1], &keychain, &previous_header, &key_id3.clone());
 
-	match b.validate(&BlindingFactor::zero(), vc.clone()) {
+	match b.validate(&global::chain_params(), &BlindingFactor::zero(), vc.clone()) {
 		Err(KernelLockHeight(height)) => {
 			assert_eq!(height, 2);
 		}
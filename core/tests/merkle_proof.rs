@@ -14,8 +14,9 @@
 
 mod vec_backend;
 
-use self::core::core::merkle_proof::MerkleProof;
+use self::core::core::merkle_proof::{MerkleProof, MerkleProofError};
 use self::core::core::pmmr::PMMR;
+use self::core::core::{BlockHeader, TxKernel};
 use self::core::ser;
 use self::core::ser::PMMRIndexHashable;
 use crate::vec_backend::{TestElem, VecBackend};
@@ -38,8 +39,8 @@ fn merkle_proof_ser_deser() {
 	let proof = pmmr.merkle_proof(9).unwrap();
 
 	let mut vec = Vec::new();
-	ser::serialize(&mut vec, &proof).expect("serialization failed");
-	let proof_2: MerkleProof = ser::deserialize(&mut &vec[..]).unwrap();
+	ser::serialize_default(&mut vec, &proof).expect("serialization failed");
+	let proof_2: MerkleProof = ser::deserialize_default(&mut &vec[..]).unwrap();
 
 	assert_eq!(proof, proof_2);
 }
@@ -188,3 +189,87 @@ fn pmmr_merkle_proof() {
 	assert_eq!(proof.path, vec![pos_9, pos_6]);
 	assert!(proof.verify(pmmr.root(), &elems[6], 11).is_ok());
 }
+
+#[test]
+fn merkle_proof_verify_leaf_rewound() {
+	let mut ba = VecBackend::new();
+	let mut pmmr = PMMR::new(&mut ba);
+	let mut roots = vec![];
+	for x in 0..9 {
+		pmmr.push(&TestElem([0, 0, 0, x])).unwrap();
+		roots.push((pmmr.unpruned_size(), pmmr.root()));
+	}
+	let last_pos = pmmr.unpruned_size();
+
+	// A proof built against the MMR rewound to an earlier size verifies
+	// against the root at that size, and only that one.
+	let elem = TestElem([0, 0, 0, 2]);
+	let (size, root) = roots[4];
+	let proof = PMMR::at(&mut ba, size).merkle_proof(4).unwrap();
+	assert_eq!(proof.mmr_size, size);
+	assert!(proof.verify_leaf(root, size, &elem, 4).is_ok());
+
+	let (later_size, later_root) = roots[8];
+	assert_eq!(
+		proof.verify_leaf(later_root, later_size, &elem, 4),
+		Err(MerkleProofError::SizeMismatch)
+	);
+	assert_eq!(
+		proof.verify_leaf(root, size, &TestElem([0, 0, 0, 3]), 4),
+		Err(MerkleProofError::RootMismatch)
+	);
+	assert_eq!(
+		proof.verify_leaf(root, size, &elem, 3),
+		Err(MerkleProofError::InvalidPosition)
+	);
+	assert_eq!(
+		proof.verify_leaf(root, size, &elem, size + 1),
+		Err(MerkleProofError::InvalidPosition)
+	);
+
+	let proof = PMMR::at(&mut ba, last_pos).merkle_proof(4).unwrap();
+	assert!(proof.verify_leaf(later_root, later_size, &elem, 4).is_ok());
+}
+
+#[test]
+fn merkle_proof_verify_kernel() {
+	let kernels: Vec<TxKernel> = (1..8).map(|fee| TxKernel::empty().with_fee(fee)).collect();
+
+	let mut ba = VecBackend::new();
+	let mut pmmr = PMMR::new(&mut ba);
+	let mut headers = vec![];
+	let mut positions = vec![];
+	for kernel in &kernels {
+		positions.push(pmmr.push(kernel).unwrap());
+		headers.push(BlockHeader {
+			kernel_root: pmmr.root(),
+			kernel_mmr_size: pmmr.unpruned_size(),
+			..BlockHeader::default()
+		});
+	}
+
+	// Proofs against the header of the block each kernel was included in,
+	// built on the kernel MMR rewound to that header.
+	for (i, kernel) in kernels.iter().enumerate() {
+		let header = &headers[i];
+		let proof = PMMR::at(&mut ba, header.kernel_mmr_size)
+			.merkle_proof(positions[i])
+			.unwrap();
+		assert!(proof.verify_kernel(header, kernel, positions[i]).is_ok());
+
+		// Still only valid for that kernel, at that position.
+		let other = &kernels[(i + 1) % kernels.len()];
+		assert!(proof.verify_kernel(header, other, positions[i]).is_err());
+		if i > 0 {
+			assert!(proof.verify_kernel(header, kernel, positions[0]).is_err());
+		}
+		// And against that header only.
+		let last = headers.last().unwrap();
+		if header.kernel_mmr_size != last.kernel_mmr_size {
+			assert_eq!(
+				proof.verify_kernel(last, kernel, positions[i]),
+				Err(MerkleProofError::SizeMismatch)
+			);
+		}
+	}
+}
@@ -0,0 +1,134 @@
+// Copyright 2018 The Grin Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Multi-party rangeproof tests
+
+use self::core::core::{Output, OutputFeatures};
+use self::core::libtx::proof::{self, MultiPartyProof};
+use self::keychain::{ExtKeychain, Keychain};
+use self::util::secp::key::SecretKey;
+use self::util::secp::pedersen::{Commitment, RangeProof};
+use self::util::secp::{ContextFlag, Secp256k1};
+use grin_core as core;
+use grin_keychain as keychain;
+use grin_util as util;
+use rand::thread_rng;
+
+// Blinding factor shares of n participants, each with their own keychain.
+fn blinds(n: usize) -> Vec<SecretKey> {
+	let key_id = ExtKeychain::derive_key_id(1, 1, 0, 0, 0);
+	(0..n)
+		.map(|_| {
+			let keychain = ExtKeychain::from_random_seed(false).unwrap();
+			keychain.derive_key(0, &key_id).unwrap()
+		})
+		.collect()
+}
+
+// Runs all rounds of the protocol, the provided participant finalizing.
+fn multi_party_proof(
+	secp: &Secp256k1,
+	participants: &[MultiPartyProof],
+	finalizer: usize,
+	tamper: bool,
+) -> Result<RangeProof, core::libtx::Error> {
+	let nonces = participants
+		.iter()
+		.map(|p| p.nonces(secp).unwrap())
+		.collect::<Vec<_>>();
+	let nonce_sums = proof::sum_multi_party_nonces(secp, &nonces).unwrap();
+
+	let mut shares = participants
+		.iter()
+		.map(|p| p.tau_x(secp, &nonce_sums).unwrap())
+		.collect::<Vec<_>>();
+	if tamper {
+		shares[1] = SecretKey::new(secp, &mut thread_rng());
+	}
+	let tau_x_sum = proof::sum_multi_party_tau_x(secp, shares).unwrap();
+
+	participants[finalizer].finalize(secp, &nonce_sums, &tau_x_sum)
+}
+
+fn participants(
+	secp: &Secp256k1,
+	amount: u64,
+	blinds: Vec<SecretKey>,
+	extra_data: Option<Vec<u8>>,
+) -> (Commitment, SecretKey, Vec<MultiPartyProof>) {
+	let blind_sum = secp.blind_sum(blinds.clone(), vec![]).unwrap();
+	let commit = secp.commit(amount, blind_sum).unwrap();
+	let common_nonce = SecretKey::new(secp, &mut thread_rng());
+	let participants = blinds
+		.into_iter()
+		.map(|blind| {
+			MultiPartyProof::new(
+				secp,
+				amount,
+				commit,
+				common_nonce,
+				extra_data.clone(),
+				blind,
+			)
+		})
+		.collect();
+	(commit, common_nonce, participants)
+}
+
+#[test]
+fn multi_party_proof_2_of_2() {
+	let secp = Secp256k1::with_caps(ContextFlag::Commit);
+	let (commit, common_nonce, participants) = participants(&secp, 5_000, blinds(2), None);
+
+	let rproof = multi_party_proof(&secp, &participants, 0, false).unwrap();
+	proof::verify(&secp, commit, rproof, None).unwrap();
+
+	// A shared output with the proof validates like any other.
+	let output = Output {
+		features: OutputFeatures::Plain,
+		commit,
+		proof: rproof,
+	};
+	output.verify_proof().unwrap();
+
+	// The common nonce rewinds the proof.
+	let info = secp
+		.rewind_bullet_proof(commit, common_nonce, None, rproof)
+		.unwrap();
+	assert_eq!(info.value, 5_000);
+
+	// The proof doesn't verify for another commitment.
+	let other = secp
+		.commit(5_000, SecretKey::new(&secp, &mut thread_rng()))
+		.unwrap();
+	assert!(proof::verify(&secp, other, rproof, None).is_err());
+}
+
+#[test]
+fn multi_party_proof_3_parties() {
+	let secp = Secp256k1::with_caps(ContextFlag::Commit);
+	let extra_data = Some(vec![1, 2, 3]);
+	let (commit, _, participants) = participants(&secp, 1_234_567, blinds(3), extra_data.clone());
+
+	let rproof = multi_party_proof(&secp, &participants, 0, false).unwrap();
+	proof::verify(&secp, commit, rproof, extra_data).unwrap();
+	assert!(proof::verify(&secp, commit, rproof, None).is_err());
+
+	// Any participant can finalize.
+	let rproof = multi_party_proof(&secp, &participants, 1, false).unwrap();
+	proof::verify(&secp, commit, rproof, Some(vec![1, 2, 3])).unwrap();
+
+	// An invalid tau_x share gives no valid proof.
+	assert!(multi_party_proof(&secp, &participants, 0, true).is_err());
+}
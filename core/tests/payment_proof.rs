@@ -0,0 +1,82 @@
+// Copyright 2018 The Grin Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Payment proof tests
+
+pub mod common;
+
+use self::core::libtx::payment_proof::PaymentProof;
+use self::util::secp::key::{PublicKey, SecretKey};
+use self::util::secp::{ContextFlag, Secp256k1};
+use crate::common::{tx1i1o, tx2i1o};
+use grin_core as core;
+use grin_util as util;
+use rand::thread_rng;
+
+fn address(secp: &Secp256k1) -> (SecretKey, PublicKey) {
+	let key = SecretKey::new(secp, &mut thread_rng());
+	let address = PublicKey::from_secret_key(secp, &key).unwrap();
+	(key, address)
+}
+
+#[test]
+fn payment_proof_create_verify() {
+	let secp = Secp256k1::with_caps(ContextFlag::Full);
+	let (_, sender) = address(&secp);
+	let (receiver_key, receiver) = address(&secp);
+	let tx = tx1i1o();
+	let kernel = &tx.kernels()[0];
+
+	let proof = PaymentProof::create(&secp, 3, kernel.excess, sender, &receiver_key).unwrap();
+	assert_eq!(proof.receiver_address, receiver);
+	proof.verify(&secp).unwrap();
+	proof.verify_kernel(kernel).unwrap();
+	assert!(proof.verify_kernel(&tx2i1o().kernels()[0]).is_err());
+
+	// Any change to the signed data invalidates the proof.
+	let mut bad = proof.clone();
+	bad.amount += 1;
+	assert!(bad.verify(&secp).is_err());
+
+	let mut bad = proof.clone();
+	bad.excess = tx2i1o().kernels()[0].excess;
+	assert!(bad.verify(&secp).is_err());
+
+	let mut bad = proof.clone();
+	bad.sender_address = address(&secp).1;
+	assert!(bad.verify(&secp).is_err());
+
+	// So does claiming another receiver.
+	let mut bad = proof.clone();
+	bad.receiver_address = address(&secp).1;
+	assert!(bad.verify(&secp).is_err());
+}
+
+#[test]
+fn payment_proof_serde() {
+	let secp = Secp256k1::with_caps(ContextFlag::Full);
+	let (_, sender) = address(&secp);
+	let (receiver_key, _) = address(&secp);
+	let excess = tx1i1o().kernels()[0].excess;
+	let proof = PaymentProof::create(&secp, 1_000, excess, sender, &receiver_key).unwrap();
+
+	let json = serde_json::to_string(&proof).unwrap();
+	let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+	assert_eq!(value["amount"], "1000");
+	assert_eq!(value["excess"], util::to_hex(excess.0.to_vec()));
+
+	let proof2: PaymentProof = serde_json::from_str(&json).unwrap();
+	assert_eq!(proof2, proof);
+	proof2.verify(&secp).unwrap();
+}
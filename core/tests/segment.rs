@@ -0,0 +1,235 @@
+// Copyright 2018 The Grin Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+mod vec_backend;
+
+use self::core::core::hash::Hash;
+use self::core::core::pmmr::{
+	self, Backend, BitmapSegment, ReadonlyPMMR, Segment, SegmentError, SegmentIdentifier, PMMR,
+};
+use self::core::core::BlockHeader;
+use self::core::ser;
+use crate::vec_backend::{TestElem, VecBackend};
+use croaring::Bitmap;
+use grin_core as core;
+
+fn build_mmr(n: u32, salt: u32) -> (VecBackend<TestElem>, u64) {
+	let mut ba = VecBackend::new();
+	let mut pmmr = PMMR::new(&mut ba);
+	for x in 0..n {
+		pmmr.push(&TestElem([0, 0, salt, x])).unwrap();
+	}
+	let size = pmmr.unpruned_size();
+	(ba, size)
+}
+
+/// Hides everything beneath the provided pruned roots, as a compacted
+/// backend would.
+struct CompactedBackend {
+	inner: VecBackend<TestElem>,
+	roots: Vec<u64>,
+}
+
+impl CompactedBackend {
+	fn is_compacted(&self, pos: u64) -> bool {
+		self.roots
+			.iter()
+			.any(|root| pos < *root && pos >= pmmr::bintree_leftmost(*root))
+	}
+}
+
+impl Backend<TestElem> for CompactedBackend {
+	fn append(&mut self, _data: &TestElem, _hashes: Vec<Hash>) -> Result<(), String> {
+		unimplemented!()
+	}
+
+	fn rewind(&mut self, _position: u64, _rewind_rm_pos: &Bitmap) -> Result<(), String> {
+		unimplemented!()
+	}
+
+	fn get_hash(&self, position: u64) -> Option<Hash> {
+		self.get_from_file(position)
+	}
+
+	fn get_data(&self, position: u64) -> Option<TestElem> {
+		self.get_data_from_file(position)
+	}
+
+	fn get_from_file(&self, position: u64) -> Option<Hash> {
+		if self.is_compacted(position) {
+			None
+		} else {
+			self.inner.get_from_file(position)
+		}
+	}
+
+	fn get_data_from_file(&self, position: u64) -> Option<TestElem> {
+		if self.is_compacted(position) {
+			None
+		} else {
+			self.inner.get_data_from_file(position)
+		}
+	}
+
+	fn leaf_pos_iter(&self) -> Box<dyn Iterator<Item = u64> + '_> {
+		unimplemented!()
+	}
+
+	fn remove(&mut self, _position: u64) -> Result<(), String> {
+		unimplemented!()
+	}
+
+	fn release_files(&mut self) {}
+
+	fn snapshot(&self, _header: &BlockHeader) -> Result<(), String> {
+		Ok(())
+	}
+
+	fn dump_stats(&self) {}
+}
+
+#[test]
+fn segments_cover_mmr() {
+	for n in 1..34 {
+		let (ba, size) = build_mmr(n, 0);
+		let pmmr: ReadonlyPMMR<'_, TestElem, _> = ReadonlyPMMR::at(&ba, size);
+		let root = pmmr.root();
+		for height in 0..4 {
+			let count = SegmentIdentifier::count(height, size);
+			let mut leaves = 0;
+			for idx in 0..count {
+				let id = SegmentIdentifier { height, idx };
+				let segment = Segment::from_pmmr(id, &pmmr).unwrap();
+				assert_eq!(segment.validate(size, root), Ok(()));
+				assert!(segment.hashes().is_empty());
+				leaves += segment.leaf_data().len();
+			}
+			assert_eq!(leaves, n as usize);
+
+			let id = SegmentIdentifier { height, idx: count };
+			assert_eq!(
+				Segment::<TestElem>::from_pmmr(id, &pmmr),
+				Err(SegmentError::NonExistent)
+			);
+		}
+	}
+}
+
+#[test]
+fn segment_wrong_root() {
+	let (ba, size) = build_mmr(13, 0);
+	let (other, other_size) = build_mmr(13, 1);
+	assert_eq!(size, other_size);
+	let root = ReadonlyPMMR::<TestElem, _>::at(&ba, size).root();
+	let other_pmmr: ReadonlyPMMR<'_, TestElem, _> = ReadonlyPMMR::at(&other, size);
+
+	for height in 0..3 {
+		for idx in 0..SegmentIdentifier::count(height, size) {
+			let id = SegmentIdentifier { height, idx };
+			let segment = Segment::from_pmmr(id, &other_pmmr).unwrap();
+			assert_eq!(
+				segment.validate(size, root),
+				Err(SegmentError::RootMismatch)
+			);
+		}
+	}
+
+	// A segment is only valid for the MMR size it was built for.
+	let pmmr: ReadonlyPMMR<'_, TestElem, _> = ReadonlyPMMR::at(&ba, size);
+	let segment = Segment::from_pmmr(SegmentIdentifier { height: 1, idx: 0 }, &pmmr).unwrap();
+	let (_, smaller) = build_mmr(12, 0);
+	assert!(segment.validate(smaller, root).is_err());
+}
+
+#[test]
+fn segments_compacted_mmr() {
+	// 16 leaves, everything beneath 7 (the first 4 leaves) and 10 (the next 2)
+	// compacted.
+	let (ba, size) = build_mmr(16, 0);
+	let root = ReadonlyPMMR::<TestElem, _>::at(&ba, size).root();
+	let hash_7 = ba.get_from_file(7).unwrap();
+	let hash_10 = ba.get_from_file(10).unwrap();
+	let backend = CompactedBackend {
+		inner: ba,
+		roots: vec![7, 10],
+	};
+	let pmmr: ReadonlyPMMR<'_, TestElem, _> = ReadonlyPMMR::at(&backend, size);
+	assert_eq!(pmmr.root(), root);
+
+	for height in 0..5 {
+		for idx in 0..SegmentIdentifier::count(height, size) {
+			let id = SegmentIdentifier { height, idx };
+			let segment = Segment::from_pmmr(id, &pmmr).unwrap();
+			assert_eq!(segment.validate(size, root), Ok(()));
+		}
+	}
+
+	// Segments beneath a compacted subtree are proven by its root.
+	let segment = Segment::from_pmmr(SegmentIdentifier { height: 1, idx: 1 }, &pmmr).unwrap();
+	assert_eq!(segment.hashes(), &[(7, hash_7)]);
+	assert!(segment.leaf_data().is_empty());
+
+	let segment = Segment::from_pmmr(SegmentIdentifier { height: 2, idx: 1 }, &pmmr).unwrap();
+	assert_eq!(segment.hashes(), &[(10, hash_10)]);
+	assert_eq!(
+		segment.leaf_data().iter().map(|x| x.0).collect::<Vec<_>>(),
+		vec![11, 12]
+	);
+}
+
+#[test]
+fn segment_ser_deser() {
+	let (ba, size) = build_mmr(11, 0);
+	let pmmr: ReadonlyPMMR<'_, TestElem, _> = ReadonlyPMMR::at(&ba, size);
+	let segment = Segment::from_pmmr(SegmentIdentifier { height: 2, idx: 2 }, &pmmr).unwrap();
+
+	let vec = ser::ser_vec(&segment, ser::ProtocolVersion::local()).unwrap();
+	let segment_2: Segment<TestElem> = ser::deserialize_default(&mut &vec[..]).unwrap();
+	assert_eq!(segment, segment_2);
+	assert_eq!(segment_2.validate(size, pmmr.root()), Ok(()));
+}
+
+#[test]
+fn bitmap_segments() {
+	let (_, size) = build_mmr(21, 0);
+	let leaves = [0u64, 3, 4, 9, 16, 20];
+	let bitmap: Bitmap = leaves
+		.iter()
+		.map(|x| pmmr::insertion_to_pmmr_index(x + 1) as u32)
+		.collect();
+
+	let height = 3;
+	let mut positions = vec![];
+	for idx in 0..SegmentIdentifier::count(height, size) {
+		let id = SegmentIdentifier { height, idx };
+		let segment = BitmapSegment::from_bitmap(id, size, &bitmap).unwrap();
+		assert_eq!(segment.validate(size), Ok(()));
+
+		let vec = ser::ser_vec(&segment, ser::ProtocolVersion::local()).unwrap();
+		let segment_2: BitmapSegment = ser::deserialize_default(&mut &vec[..]).unwrap();
+		assert_eq!(segment, segment_2);
+
+		positions.extend(segment.leaf_positions());
+	}
+	assert_eq!(
+		positions,
+		bitmap.iter().map(|x| x as u64).collect::<Vec<_>>()
+	);
+
+	let id = SegmentIdentifier { height, idx: 3 };
+	assert_eq!(
+		BitmapSegment::from_bitmap(id, size, &bitmap),
+		Err(SegmentError::NonExistent)
+	);
+}
@@ -16,12 +16,15 @@
 
 pub mod common;
 
-use self::core::core::{Output, OutputFeatures};
-use self::core::libtx::proof;
+use self::core::core::transaction::Error;
+use self::core::core::{Output, OutputFeatures, TransactionBody, TxKernel};
+use self::core::libtx::{aggsig, proof};
 use self::core::ser;
-use self::keychain::{ExtKeychain, Keychain};
+use self::keychain::{BlindingFactor, ExtKeychain, Keychain};
+use self::util::secp;
 use grin_core as core;
 use grin_keychain as keychain;
+use grin_util as util;
 
 #[test]
 fn test_output_ser_deser() {
@@ -37,10 +40,101 @@ fn test_output_ser_deser() {
 	};
 
 	let mut vec = vec![];
-	ser::serialize(&mut vec, &out).expect("serialized failed");
-	let dout: Output = ser::deserialize(&mut &vec[..]).unwrap();
+	ser::serialize_default(&mut vec, &out).expect("serialized failed");
+	let dout: Output = ser::deserialize_default(&mut &vec[..]).unwrap();
 
 	assert_eq!(dout.features, OutputFeatures::Plain);
 	assert_eq!(dout.commit, out.commit);
 	assert_eq!(dout.proof, out.proof);
 }
+
+// Verifies the kernels one by one, the result batch verification must match.
+fn verify_one_by_one(kernels: &[TxKernel]) -> Result<(), Error> {
+	kernels.iter().map(|k| k.verify()).collect()
+}
+
+// Plain and height locked kernels, all properly signed.
+fn valid_kernels(n: usize) -> Vec<TxKernel> {
+	let keychain = ExtKeychain::from_random_seed(false).unwrap();
+	let secp = keychain.secp();
+	(0..n)
+		.map(|i| {
+			let key_id = ExtKeychain::derive_key_id(1, i as u32, 0, 0, 0);
+			let skey = keychain.derive_key(0, &key_id).unwrap();
+			let mut kernel = TxKernel::empty()
+				.with_fee(i as u64 + 1)
+				.with_lock_height(i as u64 % 3);
+			let msg = kernel.msg_to_sign().unwrap();
+			kernel.excess = secp.commit(0, skey.clone()).unwrap();
+			let pubkey = kernel.excess.to_pubkey(secp).unwrap();
+			let blind = BlindingFactor::from_secret_key(skey);
+			kernel.excess_sig =
+				aggsig::sign_with_blinding(secp, &msg, &blind, Some(&pubkey)).unwrap();
+			kernel
+		})
+		.collect()
+}
+
+#[test]
+fn batch_verify_kernel_sigs() {
+	// Enough kernels to span several batches.
+	let kernels = valid_kernels(core::core::transaction::KERNEL_SIG_BATCH_SIZE + 10);
+
+	assert_eq!(verify_one_by_one(&kernels), Ok(()));
+	assert_eq!(TxKernel::batch_sig_verify(&kernels), Ok(()));
+	assert_eq!(TransactionBody::verify_kernel_sigs(&kernels), Ok(()));
+	assert_eq!(TxKernel::batch_sig_verify(&[]), Ok(()));
+}
+
+#[test]
+fn batch_verify_kernel_sigs_invalid() {
+	let kernels = valid_kernels(10);
+
+	// A single kernel with a tampered signature fails the whole batch.
+	let mut tampered = kernels.clone();
+	let mut raw = tampered[5].excess_sig.to_raw_data();
+	raw[40] ^= 1;
+	tampered[5].excess_sig = secp::Signature::from_raw_data(&raw).unwrap();
+	assert_eq!(verify_one_by_one(&tampered), Err(Error::IncorrectSignature));
+	assert_eq!(
+		TxKernel::batch_sig_verify(&tampered),
+		Err(Error::IncorrectSignature)
+	);
+	assert_eq!(
+		TransactionBody::verify_kernel_sigs(&tampered),
+		Err(Error::IncorrectSignature)
+	);
+
+	// So does a signature valid for another kernel.
+	let mut swapped = kernels.clone();
+	swapped[2].excess_sig = kernels[3].excess_sig;
+	assert_eq!(verify_one_by_one(&swapped), Err(Error::IncorrectSignature));
+	assert_eq!(
+		TxKernel::batch_sig_verify(&swapped),
+		Err(Error::IncorrectSignature)
+	);
+
+	// And a signed message not matching the kernel (fee changed).
+	let mut fee_changed = kernels.clone();
+	fee_changed[7].fee += 1;
+	assert_eq!(
+		verify_one_by_one(&fee_changed),
+		Err(Error::IncorrectSignature)
+	);
+	assert_eq!(
+		TxKernel::batch_sig_verify(&fee_changed),
+		Err(Error::IncorrectSignature)
+	);
+
+	// Invalid features are caught before any signature is checked.
+	let mut bad_features = kernels.clone();
+	bad_features[3].lock_height = 10;
+	assert_eq!(
+		verify_one_by_one(&bad_features),
+		Err(Error::InvalidKernelFeatures)
+	);
+	assert_eq!(
+		TxKernel::batch_sig_verify(&bad_features),
+		Err(Error::InvalidKernelFeatures)
+	);
+}
@@ -0,0 +1,187 @@
+// Copyright 2018 The Grin Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Multi-party transaction building tests
+
+use self::core::core::transaction::Weighting;
+use self::core::core::verifier_cache::{LruVerifierCache, VerifierCache};
+use self::core::core::{KernelFeatures, Transaction};
+use self::core::libtx::build::{input, output};
+use self::core::libtx::tx_builder::{Round, TxBuilder};
+use self::core::libtx::ErrorKind;
+use self::keychain::{ExtKeychain, Keychain};
+use self::util::RwLock;
+use grin_core as core;
+use grin_keychain as keychain;
+use grin_util as util;
+use std::sync::Arc;
+
+fn verifier_cache() -> Arc<RwLock<dyn VerifierCache>> {
+	Arc::new(RwLock::new(LruVerifierCache::new()))
+}
+
+// The document goes over the wire between every step.
+fn exchange(doc: &TxBuilder) -> TxBuilder {
+	let json = serde_json::to_string(doc).unwrap();
+	serde_json::from_str(&json).unwrap()
+}
+
+fn is_builder_error(res: Result<(), core::libtx::Error>) -> bool {
+	match res.map_err(|e| e.kind()) {
+		Err(ErrorKind::TxBuilder(_)) => true,
+		_ => false,
+	}
+}
+
+#[test]
+fn build_tx_three_parties() {
+	let k1 = ExtKeychain::from_random_seed(false).unwrap();
+	let k2 = ExtKeychain::from_random_seed(false).unwrap();
+	let k3 = ExtKeychain::from_random_seed(false).unwrap();
+	let key_id1 = ExtKeychain::derive_key_id(1, 1, 0, 0, 0);
+	let key_id2 = ExtKeychain::derive_key_id(1, 2, 0, 0, 0);
+
+	// Two senders with some change, one receiver.
+	let mut doc = TxBuilder::new(3, 5, 0).unwrap();
+	let ctx1 = doc
+		.add_elements(
+			&k1,
+			vec![input(60, key_id1.clone()), output(10, key_id2.clone())],
+		)
+		.unwrap();
+	let mut doc = exchange(&doc);
+	let ctx2 = doc
+		.add_elements(&k2, vec![input(40, key_id1.clone())])
+		.unwrap();
+	let mut doc = exchange(&doc);
+	assert_eq!(doc.round, Round::AddElements);
+	let ctx3 = doc
+		.add_elements(&k3, vec![output(85, key_id1.clone())])
+		.unwrap();
+	assert_eq!(doc.round, Round::CommitNonces);
+
+	let mut doc = exchange(&doc);
+	doc.commit_nonce(k1.secp(), &ctx1).unwrap();
+	doc.commit_nonce(k3.secp(), &ctx3).unwrap();
+	let mut doc = exchange(&doc);
+	doc.commit_nonce(k2.secp(), &ctx2).unwrap();
+	assert_eq!(doc.round, Round::Sign);
+
+	let mut doc = exchange(&doc);
+	doc.sign(k2.secp(), &ctx2).unwrap();
+	let mut doc = exchange(&doc);
+	doc.sign(k1.secp(), &ctx1).unwrap();
+	let mut doc = exchange(&doc);
+	doc.sign(k3.secp(), &ctx3).unwrap();
+	assert_eq!(doc.round, Round::Finalize);
+
+	let doc = exchange(&doc);
+	let tx: Transaction = doc.finalize(k1.secp()).unwrap();
+	assert_eq!(tx.inputs().len(), 2);
+	assert_eq!(tx.outputs().len(), 2);
+	assert_eq!(tx.kernels().len(), 1);
+	assert_eq!(tx.fee(), 5);
+	tx.validate(Weighting::AsTransaction, verifier_cache())
+		.unwrap();
+}
+
+#[test]
+fn build_height_locked_tx_two_parties() {
+	let k1 = ExtKeychain::from_random_seed(false).unwrap();
+	let k2 = ExtKeychain::from_random_seed(false).unwrap();
+	let key_id = ExtKeychain::derive_key_id(1, 1, 0, 0, 0);
+
+	let mut doc = TxBuilder::new(2, 2, 42).unwrap();
+	let ctx1 = doc
+		.add_elements(&k1, vec![input(10, key_id.clone())])
+		.unwrap();
+	let ctx2 = doc
+		.add_elements(&k2, vec![output(8, key_id.clone())])
+		.unwrap();
+	doc.commit_nonce(k1.secp(), &ctx1).unwrap();
+	doc.commit_nonce(k2.secp(), &ctx2).unwrap();
+	doc.sign(k1.secp(), &ctx1).unwrap();
+	doc.sign(k2.secp(), &ctx2).unwrap();
+
+	let tx = doc.finalize(k2.secp()).unwrap();
+	assert_eq!(tx.kernels()[0].features, KernelFeatures::HeightLocked);
+	assert_eq!(tx.lock_height(), 42);
+	tx.validate(Weighting::AsTransaction, verifier_cache())
+		.unwrap();
+}
+
+#[test]
+fn tx_builder_round_checks() {
+	let k1 = ExtKeychain::from_random_seed(false).unwrap();
+	let k2 = ExtKeychain::from_random_seed(false).unwrap();
+	let key_id = ExtKeychain::derive_key_id(1, 1, 0, 0, 0);
+
+	assert!(TxBuilder::new(0, 2, 0).is_err());
+
+	let mut doc = TxBuilder::new(2, 2, 0).unwrap();
+	let ctx1 = doc
+		.add_elements(&k1, vec![input(10, key_id.clone())])
+		.unwrap();
+
+	// Nonces only once everyone added their elements.
+	assert!(is_builder_error(doc.commit_nonce(k1.secp(), &ctx1)));
+
+	let ctx2 = doc
+		.add_elements(&k2, vec![output(8, key_id.clone())])
+		.unwrap();
+	assert!(doc
+		.add_elements(&k2, vec![output(1, key_id.clone())])
+		.is_err());
+
+	// Signing only once everyone committed to a nonce, and only once.
+	doc.commit_nonce(k1.secp(), &ctx1).unwrap();
+	assert!(is_builder_error(doc.commit_nonce(k1.secp(), &ctx1)));
+	assert!(is_builder_error(doc.sign(k1.secp(), &ctx1)));
+	doc.commit_nonce(k2.secp(), &ctx2).unwrap();
+	doc.sign(k1.secp(), &ctx1).unwrap();
+	assert!(is_builder_error(doc.sign(k1.secp(), &ctx1)));
+	assert!(doc.finalize(k1.secp()).is_err());
+
+	// A document claiming a round its content isn't at.
+	let mut bad = doc.clone();
+	bad.round = Round::Finalize;
+	assert!(bad.finalize(k1.secp()).is_err());
+
+	// A document from a newer version of the protocol.
+	let mut bad = doc.clone();
+	bad.version += 1;
+	assert!(is_builder_error(bad.sign(k2.secp(), &ctx2)));
+
+	// The context of a participant doesn't sign for another one.
+	let mut bad = doc.clone();
+	let mut ctx = TxBuilder::new(1, 2, 0)
+		.unwrap()
+		.add_elements(&k2, vec![output(8, key_id.clone())])
+		.unwrap();
+	ctx.id = ctx2.id;
+	assert!(is_builder_error(bad.sign(k2.secp(), &ctx)));
+
+	// A tampered partial signature is caught by the next signer.
+	let mut bad = doc.clone();
+	let sig = bad.participants[ctx1.id].part_sig.clone().unwrap();
+	let mut raw = sig.to_raw_data();
+	raw[40] ^= 1;
+	bad.participants[ctx1.id].part_sig = Some(util::secp::Signature::from_raw_data(&raw).unwrap());
+	assert!(is_builder_error(bad.sign(k2.secp(), &ctx2)));
+
+	doc.sign(k2.secp(), &ctx2).unwrap();
+	let tx = doc.finalize(k1.secp()).unwrap();
+	tx.validate(Weighting::AsTransaction, verifier_cache())
+		.unwrap();
+}
@@ -15,10 +15,11 @@
 pub mod common;
 
 use self::core::core::verifier_cache::{LruVerifierCache, VerifierCache};
-use self::core::core::{Output, OutputFeatures};
+use self::core::core::{Output, OutputFeatures, TransactionBody};
 use self::core::libtx::proof;
 use self::keychain::{ExtKeychain, Keychain};
 use self::util::RwLock;
+use crate::common::{tx1i1o, tx2i1o};
 use grin_core as core;
 use grin_keychain as keychain;
 use grin_util as util;
@@ -63,3 +64,39 @@ fn test_verifier_cache_rangeproofs() {
 		assert_eq!(unverified, vec![]);
 	}
 }
+
+#[test]
+fn test_verifier_cache_kernel_sigs() {
+	let cache = verifier_cache();
+
+	let kernel1 = tx1i1o().kernels()[0].clone();
+	let kernel2 = tx2i1o().kernels()[0].clone();
+
+	{
+		let mut cache = cache.write();
+		let unverified = cache.filter_kernel_sig_unverified(&vec![kernel1.clone()]);
+		assert_eq!(unverified, vec![kernel1.clone()]);
+	}
+
+	// Batch verifying the kernels caches them.
+	TransactionBody::verify_kernel_sigs_cached(&vec![kernel1.clone()], cache.clone()).unwrap();
+	{
+		let mut cache = cache.write();
+		let unverified =
+			cache.filter_kernel_sig_unverified(&vec![kernel1.clone(), kernel2.clone()]);
+		assert_eq!(unverified, vec![kernel2.clone()]);
+	}
+
+	// A kernel with a bad signature doesn't make it in the cache.
+	let mut bad_kernel = kernel2.clone();
+	bad_kernel.excess_sig = kernel1.excess_sig;
+	assert!(
+		TransactionBody::verify_kernel_sigs_cached(&vec![bad_kernel.clone()], cache.clone())
+			.is_err()
+	);
+	{
+		let mut cache = cache.write();
+		let unverified = cache.filter_kernel_sig_unverified(&vec![bad_kernel.clone()]);
+		assert_eq!(unverified, vec![bad_kernel]);
+	}
+}
@@ -140,15 +140,17 @@ fn main() {
 	gen.header.pow.proof.nonces = solver_sols.sols[0].to_u64s();
 	assert!(gen.header.pow.is_secondary(), "Not a secondary header");
 	println!("Built genesis:\n{:?}", gen);
-	core::pow::verify_size(&gen.header).unwrap();
+	let params = core::global::chain_params();
+	core::pow::verify_size(&params, &gen.header).unwrap();
 	gen.validate(
+		&params,
 		&BlindingFactor::zero(),
 		Arc::new(util::RwLock::new(LruVerifierCache::new())),
 	)
 	.unwrap();
 
 	println!("\nFinal genesis cyclehash: {}", gen.hash().to_hex());
-	let gen_bin = core::ser::ser_vec(&gen).unwrap();
+	let gen_bin = core::ser::ser_vec(&gen, core::ser::ProtocolVersion::local()).unwrap();
 	println!("Final genesis full hash: {}\n", gen_bin.hash().to_hex());
 
 	update_genesis_rs(&gen);
@@ -9,5 +9,5 @@ use grin_p2p::msg::BanReason;
 
 fuzz_target!(|data: &[u8]| {
 	let mut d = data.clone();
-	let _t: Result<BanReason, ser::Error> = ser::deserialize(&mut d);
+	let _t: Result<BanReason, ser::Error> = ser::deserialize_default(&mut d);
 });
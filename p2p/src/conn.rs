@@ -26,10 +26,11 @@ use std::net::{Shutdown, TcpStream};
 use std::sync::{mpsc, Arc};
 use std::{cmp, thread, time};
 
+use crate::core::global::ChainParams;
 use crate::core::ser;
-use crate::core::ser::FixedLength;
+use crate::core::ser::{FixedLength, ProtocolVersion};
 use crate::msg::{read_body, read_header, read_item, write_to_buf, MsgHeader, Type};
-use crate::types::Error;
+use crate::types::{Error, TxHashSetUpload};
 use crate::util::read_write::{read_exact, write_all};
 use crate::util::{RateCounter, RwLock};
 
@@ -58,8 +59,8 @@ macro_rules! try_break {
 			Err(e) => {
 				let _ = $chan.send(e);
 				break;
-				}
 			}
+		}
 	};
 }
 
@@ -68,22 +69,34 @@ macro_rules! try_break {
 pub struct Message<'a> {
 	pub header: MsgHeader,
 	stream: &'a mut dyn Read,
+	version: ProtocolVersion,
+	params: &'a ChainParams,
 }
 
 impl<'a> Message<'a> {
-	fn from_header(header: MsgHeader, stream: &'a mut dyn Read) -> Message<'a> {
-		Message { header, stream }
+	fn from_header(
+		header: MsgHeader,
+		stream: &'a mut dyn Read,
+		version: ProtocolVersion,
+		params: &'a ChainParams,
+	) -> Message<'a> {
+		Message {
+			header,
+			stream,
+			version,
+			params,
+		}
 	}
 
 	/// Read the message body from the underlying connection
 	pub fn body<T: ser::Readable>(&mut self) -> Result<T, Error> {
-		read_body(&self.header, self.stream)
+		read_body(&self.header, self.stream, self.version, self.params)
 	}
 
 	/// Read a single "thing" from the underlying connection.
 	/// Return the thing and the total bytes read.
 	pub fn streaming_read<T: ser::Readable>(&mut self) -> Result<(T, u64), Error> {
-		read_item(self.stream)
+		read_item(self.stream, self.version, self.params)
 	}
 
 	pub fn copy_attachment(&mut self, len: usize, writer: &mut dyn Write) -> Result<usize, Error> {
@@ -107,28 +120,40 @@ impl<'a> Message<'a> {
 /// Response to a `Message`.
 pub struct Response<'a> {
 	resp_type: Type,
+	version: ProtocolVersion,
 	body: Vec<u8>,
 	stream: &'a mut dyn Write,
 	attachment: Option<File>,
+	upload: Option<TxHashSetUpload>,
 }
 
 impl<'a> Response<'a> {
 	pub fn new<T: ser::Writeable>(
 		resp_type: Type,
+		version: ProtocolVersion,
 		body: T,
 		stream: &'a mut dyn Write,
 	) -> Result<Response<'a>, Error> {
-		let body = ser::ser_vec(&body)?;
+		let body = ser::ser_vec(&body, version)?;
 		Ok(Response {
 			resp_type,
+			version,
 			body,
 			stream,
 			attachment: None,
+			upload: None,
 		})
 	}
 
-	fn write(mut self, sent_bytes: Arc<RwLock<RateCounter>>) -> Result<(), Error> {
-		let mut msg = ser::ser_vec(&MsgHeader::new(self.resp_type, self.body.len() as u64))?;
+	fn write(
+		mut self,
+		params: &ChainParams,
+		sent_bytes: Arc<RwLock<RateCounter>>,
+	) -> Result<(), Error> {
+		let mut msg = ser::ser_vec(
+			&MsgHeader::new(params, self.resp_type, self.body.len() as u64),
+			self.version,
+		)?;
 		msg.append(&mut self.body);
 		write_all(&mut self.stream, &msg[..], time::Duration::from_secs(10))?;
 		// Increase sent bytes counter
@@ -152,12 +177,20 @@ impl<'a> Response<'a> {
 				}
 			}
 		}
+		// Everything sent, we can release the upload slot.
+		drop(self.upload);
 		Ok(())
 	}
 
 	pub fn add_attachment(&mut self, file: File) {
 		self.attachment = Some(file);
 	}
+
+	/// Holds on to the provided upload slot until the response (attachment
+	/// included) has been sent.
+	pub fn add_upload(&mut self, upload: Option<TxHashSetUpload>) {
+		self.upload = upload;
+	}
 }
 
 pub const SEND_CHANNEL_CAP: usize = 10;
@@ -173,6 +206,10 @@ pub struct Tracker {
 	pub close_channel: mpsc::Sender<()>,
 	/// Channel to check for errors on the connection
 	pub error_channel: mpsc::Receiver<Error>,
+	/// Protocol version negotiated with the peer
+	pub version: ProtocolVersion,
+	/// Parameters of the chain we talk about with the peer
+	pub params: ChainParams,
 }
 
 impl Tracker {
@@ -180,7 +217,7 @@ impl Tracker {
 	where
 		T: ser::Writeable,
 	{
-		let buf = write_to_buf(body, msg_type)?;
+		let buf = write_to_buf(body, msg_type, self.version, &self.params)?;
 		let buf_len = buf.len();
 		self.send_channel.try_send(buf)?;
 
@@ -195,7 +232,12 @@ impl Tracker {
 /// Start listening on the provided connection and wraps it. Does not hang
 /// the current thread, instead just returns a future and the Connection
 /// itself.
-pub fn listen<H>(stream: TcpStream, handler: H) -> Tracker
+pub fn listen<H>(
+	stream: TcpStream,
+	version: ProtocolVersion,
+	params: ChainParams,
+	handler: H,
+) -> Tracker
 where
 	H: MessageHandler,
 {
@@ -213,6 +255,8 @@ where
 		.expect("Non-blocking IO not available.");
 	poll(
 		stream,
+		version,
+		params.clone(),
 		handler,
 		send_rx,
 		error_tx,
@@ -227,11 +271,15 @@ where
 		send_channel: send_tx,
 		close_channel: close_tx,
 		error_channel: error_rx,
+		version,
+		params,
 	}
 }
 
 fn poll<H>(
 	conn: TcpStream,
+	version: ProtocolVersion,
+	params: ChainParams,
 	handler: H,
 	send_rx: mpsc::Receiver<Vec<u8>>,
 	error_tx: mpsc::Sender<Error>,
@@ -252,8 +300,10 @@ fn poll<H>(
 			let mut retry_send = Err(());
 			loop {
 				// check the read end
-				if let Some(h) = try_break!(error_tx, read_header(&mut reader, None)) {
-					let msg = Message::from_header(h, &mut reader);
+				if let Some(h) =
+					try_break!(error_tx, read_header(&mut reader, version, &params, None))
+				{
+					let msg = Message::from_header(h, &mut reader, version, &params);
 
 					trace!(
 						"Received message header, type {:?}, len {}.",
@@ -271,7 +321,7 @@ fn poll<H>(
 					if let Some(Some(resp)) =
 						try_break!(error_tx, handler.consume(msg, &mut writer, received))
 					{
-						try_break!(error_tx, resp.write(sent_bytes.clone()));
+						try_break!(error_tx, resp.write(&params, sent_bytes.clone()));
 					}
 				}
 
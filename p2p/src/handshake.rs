@@ -13,8 +13,10 @@
 // limitations under the License.
 
 use crate::core::core::hash::Hash;
+use crate::core::global::ChainParams;
 use crate::core::pow::Difficulty;
-use crate::msg::{read_message, write_message, Hand, Shake, Type, PROTOCOL_VERSION, USER_AGENT};
+use crate::core::ser::ProtocolVersion;
+use crate::msg::{read_message, write_message, Hand, Shake, Type, USER_AGENT};
 use crate::peer::Peer;
 use crate::types::{Capabilities, Direction, Error, P2PConfig, PeerAddr, PeerInfo, PeerLiveInfo};
 use crate::util::RwLock;
@@ -45,19 +47,30 @@ pub struct Handshake {
 	/// ok).
 	genesis: Hash,
 	config: P2PConfig,
+	/// The protocol version we advertise, the highest one we speak.
+	protocol_version: ProtocolVersion,
+	/// Parameters of our chain, messages of other chains are refused.
+	params: ChainParams,
 }
 
 impl Handshake {
 	/// Creates a new handshake handler
-	pub fn new(genesis: Hash, config: P2PConfig) -> Handshake {
+	pub fn new(genesis: Hash, config: P2PConfig, params: ChainParams) -> Handshake {
 		Handshake {
 			nonces: Arc::new(RwLock::new(VecDeque::with_capacity(NONCES_CAP))),
 			addrs: Arc::new(RwLock::new(VecDeque::with_capacity(ADDRS_CAP))),
 			genesis,
 			config,
+			protocol_version: ProtocolVersion::local(),
+			params,
 		}
 	}
 
+	/// Parameters of the chain we handshake for.
+	pub fn params(&self) -> &ChainParams {
+		&self.params
+	}
+
 	pub fn initiate(
 		&self,
 		capab: Capabilities,
@@ -73,7 +86,7 @@ impl Handshake {
 		};
 
 		let hand = Hand {
-			version: PROTOCOL_VERSION,
+			version: self.protocol_version,
 			capabilities: capab,
 			nonce: nonce,
 			genesis: self.genesis,
@@ -84,8 +97,8 @@ impl Handshake {
 		};
 
 		// write and read the handshake response
-		write_message(conn, hand, Type::Hand)?;
-		let shake: Shake = read_message(conn, Type::Shake)?;
+		write_message(conn, hand, Type::Hand, self.protocol_version, &self.params)?;
+		let shake: Shake = read_message(conn, self.protocol_version, &self.params, Type::Shake)?;
 		if shake.genesis != self.genesis {
 			return Err(Error::GenesisMismatch {
 				us: self.genesis,
@@ -96,7 +109,7 @@ impl Handshake {
 			capabilities: shake.capabilities,
 			user_agent: shake.user_agent,
 			addr: peer_addr,
-			version: shake.version,
+			version: self.negotiate_protocol_version(shake.version),
 			live_info: Arc::new(RwLock::new(PeerLiveInfo::new(shake.total_difficulty))),
 			direction: Direction::Outbound,
 		};
@@ -114,7 +127,6 @@ impl Handshake {
 			peer_info.user_agent,
 			peer_info.capabilities
 		);
-		// when more than one protocol version is supported, choosing should go here
 		Ok(peer_info)
 	}
 
@@ -124,7 +136,7 @@ impl Handshake {
 		total_difficulty: Difficulty,
 		conn: &mut TcpStream,
 	) -> Result<PeerInfo, Error> {
-		let hand: Hand = read_message(conn, Type::Hand)?;
+		let hand: Hand = read_message(conn, self.protocol_version, &self.params, Type::Hand)?;
 
 		// all the reasons we could refuse this connection for
 		if hand.genesis != self.genesis {
@@ -152,7 +164,7 @@ impl Handshake {
 			capabilities: hand.capabilities,
 			user_agent: hand.user_agent,
 			addr: resolve_peer_addr(hand.sender_addr, &conn),
-			version: hand.version,
+			version: self.negotiate_protocol_version(hand.version),
 			live_info: Arc::new(RwLock::new(PeerLiveInfo::new(hand.total_difficulty))),
 			direction: Direction::Inbound,
 		};
@@ -167,20 +179,38 @@ impl Handshake {
 
 		// send our reply with our info
 		let shake = Shake {
-			version: PROTOCOL_VERSION,
+			version: self.protocol_version,
 			capabilities: capab,
 			genesis: self.genesis,
 			total_difficulty: total_difficulty,
 			user_agent: USER_AGENT.to_string(),
 		};
 
-		write_message(conn, shake, Type::Shake)?;
+		write_message(
+			conn,
+			shake,
+			Type::Shake,
+			self.protocol_version,
+			&self.params,
+		)?;
 		trace!("Success handshake with {}.", peer_info.addr);
 
-		// when more than one protocol version is supported, choosing should go here
 		Ok(peer_info)
 	}
 
+	/// Both sides talk the lowest of the protocol versions they advertised,
+	/// the peer may be running an older or a newer version than ours.
+	fn negotiate_protocol_version(&self, other: ProtocolVersion) -> ProtocolVersion {
+		let version = std::cmp::min(self.protocol_version, other);
+		trace!(
+			"negotiate_protocol_version: local {}, other {}, using {}",
+			self.protocol_version,
+			other,
+			version
+		);
+		version
+	}
+
 	/// Generate a new random nonce and store it in our ring buffer
 	fn next_nonce(&self) -> u64 {
 		let nonce = thread_rng().gen();
@@ -53,5 +53,6 @@ pub use crate::serv::{DummyAdapter, Server};
 pub use crate::store::{PeerData, State};
 pub use crate::types::{
 	Capabilities, ChainAdapter, Direction, Error, P2PConfig, PeerAddr, PeerInfo, ReasonForBan,
-	Seeding, TxHashSetRead, MAX_BLOCK_HEADERS, MAX_LOCATORS, MAX_PEER_ADDRS,
+	Seeding, TxHashSetPartial, TxHashSetRead, TxHashSetUpload, TxHashSetUploads, MAX_BLOCK_HEADERS,
+	MAX_LOCATORS, MAX_PEER_ADDRS,
 };
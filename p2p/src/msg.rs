@@ -18,34 +18,24 @@ use num::FromPrimitive;
 use std::io::{Read, Write};
 use std::time;
 
+use crate::core::consensus;
 use crate::core::core::hash::Hash;
+use crate::core::core::pmmr::{SegmentIdentifier, MAX_SEGMENT_HEIGHT};
 use crate::core::core::BlockHeader;
+use crate::core::global::ChainParams;
 use crate::core::pow::Difficulty;
-use crate::core::ser::{self, FixedLength, Readable, Reader, StreamingReader, Writeable, Writer};
-use crate::core::{consensus, global};
+use crate::core::ser::{
+	self, FixedLength, ProtocolVersion, Readable, Reader, StreamingReader, Writeable, Writer,
+};
 use crate::types::{
 	Capabilities, Error, PeerAddr, ReasonForBan, MAX_BLOCK_HEADERS, MAX_LOCATORS, MAX_PEER_ADDRS,
 };
 use crate::util::read_write::read_exact;
-
-/// Our local node protocol version.
-/// We will increment the protocol version with every change to p2p msg serialization
-/// so we will likely connect with peers with both higher and lower protocol versions.
-/// We need to be aware that some msg formats will be potentially incompatible and handle
-/// this for each individual peer connection.
-/// Note: A peer may disconnect and reconnect with an updated protocol version. Normally
-/// the protocol version will increase but we need to handle decreasing values also
-/// as a peer may rollback to previous version of the code.
-pub const PROTOCOL_VERSION: u32 = 1;
+use crate::util::secp::constants::MAX_PROOF_SIZE;
 
 /// Grin's user agent with current version
 pub const USER_AGENT: &'static str = concat!("MW/Grin ", env!("CARGO_PKG_VERSION"));
 
-/// Magic numbers expected in the header of every message
-const OTHER_MAGIC: [u8; 2] = [73, 43];
-const FLOONET_MAGIC: [u8; 2] = [83, 59];
-const MAINNET_MAGIC: [u8; 2] = [97, 61];
-
 /// Types of messages.
 /// Note: Values here are *important* so we should only add new values at the
 /// end.
@@ -73,16 +63,40 @@ enum_from_primitive! {
 		BanReason = 18,
 		GetTransaction = 19,
 		TransactionKernel = 20,
-	}
-}
-
-/// Max theoretical size of a block filled with outputs.
-fn max_block_size() -> u64 {
-	(global::max_block_weight() / consensus::BLOCK_OUTPUT_WEIGHT * 708) as u64
+		GetOutputBitmapSegment = 21,
+		OutputBitmapSegment = 22,
+		GetOutputSegment = 23,
+		OutputSegment = 24,
+		GetRangeProofSegment = 25,
+		RangeProofSegment = 26,
+		GetKernelSegment = 27,
+		KernelSegment = 28,
+		TxHashSetRangeRequest = 29,
+		TxHashSetArchiveRange = 30,
+		GetOutputProof = 31,
+		OutputProof = 32,
+		GetBlockFilter = 33,
+		BlockFilter = 34,
+		GetCompactTransaction = 35,
+		CompactTransaction = 36,
+	}
+}
+
+/// Max theoretical size of a block filled with outputs, on the chain with
+/// the provided parameters.
+fn max_block_size(params: &ChainParams) -> u64 {
+	(params.max_block_weight / consensus::BLOCK_OUTPUT_WEIGHT * 708) as u64
+}
+
+/// Max size of a txhashset segment with leaves of the provided size, leaf
+/// data and hashes (both with their positions) plus the merkle proof.
+fn max_segment_size(leaf_size: u64) -> u64 {
+	let capacity = 1u64 << MAX_SEGMENT_HEIGHT;
+	32 + 9 + 3 * 8 + capacity * (8 + leaf_size) + 2 * capacity * (8 + 32) + 64 * 32
 }
 
 // Max msg size for each msg type.
-fn max_msg_size(msg_type: Type) -> u64 {
+fn max_msg_size(params: &ChainParams, msg_type: Type) -> u64 {
 	match msg_type {
 		Type::Error => 0,
 		Type::Hand => 128,
@@ -95,39 +109,52 @@ fn max_msg_size(msg_type: Type) -> u64 {
 		Type::Header => 365,
 		Type::Headers => 2 + 365 * MAX_BLOCK_HEADERS as u64,
 		Type::GetBlock => 32,
-		Type::Block => max_block_size(),
+		Type::Block => max_block_size(params),
 		Type::GetCompactBlock => 32,
-		Type::CompactBlock => max_block_size() / 10,
-		Type::StemTransaction => max_block_size(),
-		Type::Transaction => max_block_size(),
+		Type::CompactBlock => max_block_size(params) / 10,
+		Type::StemTransaction => max_block_size(params),
+		Type::Transaction => max_block_size(params),
 		Type::TxHashSetRequest => 40,
 		Type::TxHashSetArchive => 64,
 		Type::BanReason => 64,
 		Type::GetTransaction => 32,
 		Type::TransactionKernel => 32,
-	}
-}
-
-fn magic() -> [u8; 2] {
-	match *global::CHAIN_TYPE.read() {
-		global::ChainTypes::Floonet => FLOONET_MAGIC,
-		global::ChainTypes::Mainnet => MAINNET_MAGIC,
-		_ => OTHER_MAGIC,
+		Type::GetOutputBitmapSegment => 41,
+		Type::OutputBitmapSegment => 32 + 9 + 8 + (1 << MAX_SEGMENT_HEIGHT) / 8,
+		Type::GetOutputSegment => 41,
+		Type::OutputSegment => max_segment_size(1 + 33),
+		Type::GetRangeProofSegment => 41,
+		Type::RangeProofSegment => max_segment_size(8 + MAX_PROOF_SIZE as u64),
+		Type::GetKernelSegment => 41,
+		Type::KernelSegment => max_segment_size(1 + 8 + 8 + 33 + 64),
+		Type::TxHashSetRangeRequest => 48,
+		Type::TxHashSetArchiveRange => 104,
+		Type::GetOutputProof => 33,
+		Type::OutputProof => 32 + (1 + 33) + 8 + 16 + 64 * 32,
+		Type::GetBlockFilter => 32,
+		Type::BlockFilter => 32 + 8 + 8 + max_block_size(params) / 10,
+		Type::GetCompactTransaction => 32,
+		Type::CompactTransaction => max_block_size(params),
 	}
 }
 
 /// Read a header from the provided stream without blocking if the
 /// underlying stream is async. Typically headers will be polled for, so
 /// we do not want to block.
-pub fn read_header(stream: &mut dyn Read, msg_type: Option<Type>) -> Result<MsgHeader, Error> {
+pub fn read_header(
+	stream: &mut dyn Read,
+	version: ProtocolVersion,
+	params: &ChainParams,
+	msg_type: Option<Type>,
+) -> Result<MsgHeader, Error> {
 	let mut head = vec![0u8; MsgHeader::LEN];
 	if Some(Type::Hand) == msg_type {
 		read_exact(stream, &mut head, time::Duration::from_millis(10), true)?;
 	} else {
 		read_exact(stream, &mut head, time::Duration::from_secs(10), false)?;
 	}
-	let header = ser::deserialize::<MsgHeader>(&mut &head[..])?;
-	let max_len = max_msg_size(header.msg_type);
+	let header = ser::deserialize_with_params::<MsgHeader>(&mut &head[..], version, params)?;
+	let max_len = max_msg_size(params, header.msg_type);
 
 	// TODO 4x the limits for now to leave ourselves space to change things
 	if header.msg_len > max_len * 4 {
@@ -143,39 +170,62 @@ pub fn read_header(stream: &mut dyn Read, msg_type: Option<Type>) -> Result<MsgH
 /// Read a single item from the provided stream, always blocking until we
 /// have a result (or timeout).
 /// Returns the item and the total bytes read.
-pub fn read_item<T: Readable>(stream: &mut dyn Read) -> Result<(T, u64), Error> {
+pub fn read_item<T: Readable>(
+	stream: &mut dyn Read,
+	version: ProtocolVersion,
+	params: &ChainParams,
+) -> Result<(T, u64), Error> {
 	let timeout = time::Duration::from_secs(20);
-	let mut reader = StreamingReader::new(stream, timeout);
+	let mut reader = StreamingReader::new(stream, version, timeout).with_chain_params(params);
 	let res = T::read(&mut reader)?;
 	Ok((res, reader.total_bytes_read()))
 }
 
 /// Read a message body from the provided stream, always blocking
 /// until we have a result (or timeout).
-pub fn read_body<T: Readable>(h: &MsgHeader, stream: &mut dyn Read) -> Result<T, Error> {
+pub fn read_body<T: Readable>(
+	h: &MsgHeader,
+	stream: &mut dyn Read,
+	version: ProtocolVersion,
+	params: &ChainParams,
+) -> Result<T, Error> {
 	let mut body = vec![0u8; h.msg_len as usize];
 	read_exact(stream, &mut body, time::Duration::from_secs(20), true)?;
-	ser::deserialize(&mut &body[..]).map_err(From::from)
+	ser::deserialize_with_params(&mut &body[..], version, params).map_err(From::from)
 }
 
 /// Reads a full message from the underlying stream.
-pub fn read_message<T: Readable>(stream: &mut dyn Read, msg_type: Type) -> Result<T, Error> {
-	let header = read_header(stream, Some(msg_type))?;
+pub fn read_message<T: Readable>(
+	stream: &mut dyn Read,
+	version: ProtocolVersion,
+	params: &ChainParams,
+	msg_type: Type,
+) -> Result<T, Error> {
+	let header = read_header(stream, version, params, Some(msg_type))?;
 	if header.msg_type != msg_type {
 		return Err(Error::BadMessage);
 	}
-	read_body(&header, stream)
+	read_body(&header, stream, version, params)
 }
 
-pub fn write_to_buf<T: Writeable>(msg: T, msg_type: Type) -> Result<Vec<u8>, Error> {
+pub fn write_to_buf<T: Writeable>(
+	msg: T,
+	msg_type: Type,
+	version: ProtocolVersion,
+	params: &ChainParams,
+) -> Result<Vec<u8>, Error> {
 	// prepare the body first so we know its serialized length
 	let mut body_buf = vec![];
-	ser::serialize(&mut body_buf, &msg)?;
+	ser::serialize(&mut body_buf, version, &msg)?;
 
 	// build and serialize the header using the body size
 	let mut msg_buf = vec![];
 	let blen = body_buf.len() as u64;
-	ser::serialize(&mut msg_buf, &MsgHeader::new(msg_type, blen))?;
+	ser::serialize(
+		&mut msg_buf,
+		version,
+		&MsgHeader::new(params, msg_type, blen),
+	)?;
 	msg_buf.append(&mut body_buf);
 
 	Ok(msg_buf)
@@ -185,8 +235,10 @@ pub fn write_message<T: Writeable>(
 	stream: &mut dyn Write,
 	msg: T,
 	msg_type: Type,
+	version: ProtocolVersion,
+	params: &ChainParams,
 ) -> Result<(), Error> {
-	let buf = write_to_buf(msg, msg_type)?;
+	let buf = write_to_buf(msg, msg_type, version, params)?;
 	stream.write_all(&buf[..])?;
 	Ok(())
 }
@@ -201,10 +253,11 @@ pub struct MsgHeader {
 }
 
 impl MsgHeader {
-	/// Creates a new message header.
-	pub fn new(msg_type: Type, len: u64) -> MsgHeader {
+	/// Creates a new message header, starting with the magic numbers of the
+	/// chain with the provided parameters.
+	pub fn new(params: &ChainParams, msg_type: Type, len: u64) -> MsgHeader {
 		MsgHeader {
-			magic: magic(),
+			magic: params.magic,
 			msg_type: msg_type,
 			msg_len: len,
 		}
@@ -230,7 +283,11 @@ impl Writeable for MsgHeader {
 
 impl Readable for MsgHeader {
 	fn read(reader: &mut dyn Reader) -> Result<MsgHeader, ser::Error> {
-		let m = magic();
+		// only talk to peers of our own chain
+		let m = reader
+			.chain_params()
+			.ok_or(ser::Error::NoChainParams)?
+			.magic;
 		reader.expect_u8(m[0])?;
 		reader.expect_u8(m[1])?;
 		let (t, len) = ser_multiread!(reader, read_u8, read_u64);
@@ -249,7 +306,7 @@ impl Readable for MsgHeader {
 /// characteristics.
 pub struct Hand {
 	/// protocol version of the sender
-	pub version: u32,
+	pub version: ProtocolVersion,
 	/// capabilities of the sender
 	pub capabilities: Capabilities,
 	/// randomly generated for each handshake, helps detect self
@@ -269,9 +326,9 @@ pub struct Hand {
 
 impl Writeable for Hand {
 	fn write<W: Writer>(&self, writer: &mut W) -> Result<(), ser::Error> {
+		self.version.write(writer)?;
 		ser_multiwrite!(
 			writer,
-			[write_u32, self.version],
 			[write_u32, self.capabilities.bits()],
 			[write_u64, self.nonce]
 		);
@@ -286,7 +343,8 @@ impl Writeable for Hand {
 
 impl Readable for Hand {
 	fn read(reader: &mut dyn Reader) -> Result<Hand, ser::Error> {
-		let (version, capab, nonce) = ser_multiread!(reader, read_u32, read_u32, read_u64);
+		let version = ProtocolVersion::read(reader)?;
+		let (capab, nonce) = ser_multiread!(reader, read_u32, read_u64);
 		let capabilities = Capabilities::from_bits_truncate(capab);
 		let total_diff = Difficulty::read(reader)?;
 		let sender_addr = PeerAddr::read(reader)?;
@@ -311,7 +369,7 @@ impl Readable for Hand {
 /// version and characteristics.
 pub struct Shake {
 	/// sender version
-	pub version: u32,
+	pub version: ProtocolVersion,
 	/// sender capabilities
 	pub capabilities: Capabilities,
 	/// genesis block of our chain, only connect to peers on the same chain
@@ -325,11 +383,8 @@ pub struct Shake {
 
 impl Writeable for Shake {
 	fn write<W: Writer>(&self, writer: &mut W) -> Result<(), ser::Error> {
-		ser_multiwrite!(
-			writer,
-			[write_u32, self.version],
-			[write_u32, self.capabilities.bits()]
-		);
+		self.version.write(writer)?;
+		writer.write_u32(self.capabilities.bits())?;
 		self.total_difficulty.write(writer)?;
 		writer.write_bytes(&self.user_agent)?;
 		self.genesis.write(writer)?;
@@ -339,7 +394,8 @@ impl Writeable for Shake {
 
 impl Readable for Shake {
 	fn read(reader: &mut dyn Reader) -> Result<Shake, ser::Error> {
-		let (version, capab) = ser_multiread!(reader, read_u32, read_u32);
+		let version = ProtocolVersion::read(reader)?;
+		let capab = reader.read_u32()?;
 		let capabilities = Capabilities::from_bits_truncate(capab);
 		let total_diff = Difficulty::read(reader)?;
 		let ua = reader.read_bytes_len_prefix()?;
@@ -619,3 +675,130 @@ impl Readable for TxHashSetArchive {
 		})
 	}
 }
+
+/// Request to resend a txhashset archive from a given byte offset, to resume
+/// an interrupted download.
+pub struct TxHashSetRangeRequest {
+	/// Hash of the block for which the txhashset should be provided
+	pub hash: Hash,
+	/// Height of the corresponding block
+	pub height: u64,
+	/// Offset in bytes in the archive to start from
+	pub offset: u64,
+}
+
+impl Writeable for TxHashSetRangeRequest {
+	fn write<W: Writer>(&self, writer: &mut W) -> Result<(), ser::Error> {
+		self.hash.write(writer)?;
+		ser_multiwrite!(writer, [write_u64, self.height], [write_u64, self.offset]);
+		Ok(())
+	}
+}
+
+impl Readable for TxHashSetRangeRequest {
+	fn read(reader: &mut dyn Reader) -> Result<TxHashSetRangeRequest, ser::Error> {
+		let hash = Hash::read(reader)?;
+		let (height, offset) = ser_multiread!(reader, read_u64, read_u64);
+
+		Ok(TxHashSetRangeRequest {
+			hash,
+			height,
+			offset,
+		})
+	}
+}
+
+/// Response to a txhashset range request, must include the archive from the
+/// requested offset to its end as a stream after the message body.
+pub struct TxHashSetArchiveRange {
+	/// Hash of the block for which the txhashset are provided
+	pub hash: Hash,
+	/// Height of the corresponding block
+	pub height: u64,
+	/// Size in bytes of the whole archive
+	pub bytes: u64,
+	/// Offset in bytes in the archive the stream starts from
+	pub offset: u64,
+	/// Hash of the whole archive, the range can only be spliced onto bytes
+	/// received from the very same archive
+	pub archive_hash: Hash,
+}
+
+impl Writeable for TxHashSetArchiveRange {
+	fn write<W: Writer>(&self, writer: &mut W) -> Result<(), ser::Error> {
+		self.hash.write(writer)?;
+		ser_multiwrite!(
+			writer,
+			[write_u64, self.height],
+			[write_u64, self.bytes],
+			[write_u64, self.offset]
+		);
+		self.archive_hash.write(writer)
+	}
+}
+
+impl Readable for TxHashSetArchiveRange {
+	fn read(reader: &mut dyn Reader) -> Result<TxHashSetArchiveRange, ser::Error> {
+		let hash = Hash::read(reader)?;
+		let (height, bytes, offset) = ser_multiread!(reader, read_u64, read_u64, read_u64);
+		let archive_hash = Hash::read(reader)?;
+
+		Ok(TxHashSetArchiveRange {
+			hash,
+			height,
+			bytes,
+			offset,
+			archive_hash,
+		})
+	}
+}
+
+/// Request for a segment of one of the txhashset MMRs (or of the output
+/// bitmap) at a given block, for PIBD.
+pub struct SegmentRequest {
+	/// Hash of the block the txhashset segment is requested at
+	pub block_hash: Hash,
+	/// Identifier of the requested segment
+	pub identifier: SegmentIdentifier,
+}
+
+impl Writeable for SegmentRequest {
+	fn write<W: Writer>(&self, writer: &mut W) -> Result<(), ser::Error> {
+		self.block_hash.write(writer)?;
+		self.identifier.write(writer)
+	}
+}
+
+impl Readable for SegmentRequest {
+	fn read(reader: &mut dyn Reader) -> Result<SegmentRequest, ser::Error> {
+		Ok(SegmentRequest {
+			block_hash: Hash::read(reader)?,
+			identifier: SegmentIdentifier::read(reader)?,
+		})
+	}
+}
+
+/// Response to a segment request, the segment along with the hash of the
+/// block it belongs to.
+pub struct SegmentResponse<T> {
+	/// Hash of the block the txhashset segment is at
+	pub block_hash: Hash,
+	/// The segment itself
+	pub segment: T,
+}
+
+impl<T: Writeable> Writeable for SegmentResponse<T> {
+	fn write<W: Writer>(&self, writer: &mut W) -> Result<(), ser::Error> {
+		self.block_hash.write(writer)?;
+		self.segment.write(writer)
+	}
+}
+
+impl<T: Readable> Readable for SegmentResponse<T> {
+	fn read(reader: &mut dyn Reader) -> Result<SegmentResponse<T>, ser::Error> {
+		Ok(SegmentResponse {
+			block_hash: Hash::read(reader)?,
+			segment: T::read(reader)?,
+		})
+	}
+}
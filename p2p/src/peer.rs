@@ -21,16 +21,23 @@ use std::sync::Arc;
 
 use crate::chain;
 use crate::conn;
+use crate::core::core;
 use crate::core::core::hash::{Hash, Hashed};
+use crate::core::core::pmmr::{BitmapSegment, Segment, SegmentIdentifier};
+use crate::core::core::{OutputIdentifier, TxKernelEntry};
+use crate::core::global::{self, ChainParams};
 use crate::core::pow::Difficulty;
-use crate::core::{core, global};
 use crate::handshake::Handshake;
-use crate::msg::{self, BanReason, GetPeerAddrs, Locator, Ping, TxHashSetRequest};
+use crate::msg::{
+	self, BanReason, GetPeerAddrs, Locator, Ping, SegmentRequest, TxHashSetRangeRequest,
+	TxHashSetRequest,
+};
 use crate::protocol::Protocol;
 use crate::types::{
 	Capabilities, ChainAdapter, Error, NetAdapter, P2PConfig, PeerAddr, PeerInfo, ReasonForBan,
 	TxHashSetRead,
 };
+use crate::util::secp::pedersen::{Commitment, RangeProof};
 use chrono::prelude::{DateTime, Utc};
 
 const MAX_TRACK_SIZE: usize = 30;
@@ -51,6 +58,8 @@ enum State {
 
 pub struct Peer {
 	pub info: PeerInfo,
+	// parameters of the chain we talk about with the peer
+	params: ChainParams,
 	state: Arc<RwLock<State>>,
 	// set of all hashes known to this peer (so no need to send)
 	tracking_adapter: TrackingAdapter,
@@ -62,7 +71,7 @@ macro_rules! connection {
 		match $holder.connection.as_ref() {
 			Some(conn) => conn.lock(),
 			None => return Err(Error::ConnectionClose),
-			}
+		}
 	};
 }
 
@@ -74,9 +83,10 @@ impl fmt::Debug for Peer {
 
 impl Peer {
 	// Only accept and connect can be externally used to build a peer
-	fn new(info: PeerInfo, adapter: Arc<dyn NetAdapter>) -> Peer {
+	fn new(info: PeerInfo, params: ChainParams, adapter: Arc<dyn NetAdapter>) -> Peer {
 		Peer {
 			info,
+			params,
 			state: Arc::new(RwLock::new(State::Connected)),
 			tracking_adapter: TrackingAdapter::new(adapter),
 			connection: None,
@@ -93,7 +103,7 @@ impl Peer {
 		debug!("accept: handshaking from {:?}", conn.peer_addr());
 		let info = hs.accept(capab, total_difficulty, conn);
 		match info {
-			Ok(peer_info) => Ok(Peer::new(peer_info, adapter)),
+			Ok(peer_info) => Ok(Peer::new(peer_info, hs.params().clone(), adapter)),
 			Err(e) => {
 				debug!(
 					"accept: handshaking from {:?} failed with error: {:?}",
@@ -119,7 +129,7 @@ impl Peer {
 		debug!("connect: handshaking with {:?}", conn.peer_addr());
 		let info = hs.initiate(capab, total_difficulty, self_addr, conn);
 		match info {
-			Ok(peer_info) => Ok(Peer::new(peer_info, na)),
+			Ok(peer_info) => Ok(Peer::new(peer_info, hs.params().clone(), na)),
 			Err(e) => {
 				debug!(
 					"connect: handshaking with {:?} failed with error: {:?}",
@@ -139,7 +149,12 @@ impl Peer {
 	pub fn start(&mut self, conn: TcpStream) {
 		let adapter = Arc::new(self.tracking_adapter.clone());
 		let handler = Protocol::new(adapter, self.info.clone());
-		self.connection = Some(Mutex::new(conn::listen(conn, handler)));
+		self.connection = Some(Mutex::new(conn::listen(
+			conn,
+			self.info.version,
+			self.params.clone(),
+			handler,
+		)));
 	}
 
 	pub fn is_denied(config: &P2PConfig, peer_addr: PeerAddr) -> bool {
@@ -372,6 +387,15 @@ impl Peer {
 		connection!(self).send(&h, msg::Type::GetTransaction)
 	}
 
+	/// Sends a request for the compact representation of a tx by kernel hash
+	pub fn send_compact_tx_request(&self, h: Hash) -> Result<(), Error> {
+		debug!(
+			"Requesting compact tx (kernel hash) {} from peer {}.",
+			h, self.info.addr
+		);
+		connection!(self).send(&h, msg::Type::GetCompactTransaction)
+	}
+
 	/// Sends a request for a specific block by hash
 	pub fn send_block_request(&self, h: Hash) -> Result<(), Error> {
 		debug!("Requesting block {} from peer {}.", h, self.info.addr);
@@ -406,6 +430,75 @@ impl Peer {
 		)
 	}
 
+	/// Asks the peer for the rest of the txhashset archive at the provided
+	/// block, from the provided byte offset.
+	pub fn send_txhashset_range_request(
+		&self,
+		height: u64,
+		hash: Hash,
+		offset: u64,
+	) -> Result<(), Error> {
+		debug!(
+			"Asking {} for txhashset archive at {} {} from {}.",
+			self.info.addr, height, hash, offset
+		);
+		connection!(self).send(
+			&TxHashSetRangeRequest {
+				hash,
+				height,
+				offset,
+			},
+			msg::Type::TxHashSetRangeRequest,
+		)
+	}
+
+	/// Asks the peer for a segment of the txhashset (PIBD) at the provided
+	/// block.
+	pub fn send_segment_request(
+		&self,
+		segment_type: chain::SegmentType,
+		block_hash: Hash,
+		identifier: SegmentIdentifier,
+	) -> Result<(), Error> {
+		let msg_type = match segment_type {
+			chain::SegmentType::Bitmap => msg::Type::GetOutputBitmapSegment,
+			chain::SegmentType::Output => msg::Type::GetOutputSegment,
+			chain::SegmentType::RangeProof => msg::Type::GetRangeProofSegment,
+			chain::SegmentType::Kernel => msg::Type::GetKernelSegment,
+		};
+		trace!(
+			"Asking {} for {:?} segment {:?} at {}.",
+			self.info.addr,
+			segment_type,
+			identifier,
+			block_hash
+		);
+		connection!(self).send(
+			&SegmentRequest {
+				block_hash,
+				identifier,
+			},
+			msg_type,
+		)
+	}
+
+	/// Asks the peer for a merkle proof of the unspent output with the
+	/// provided commitment, against its head.
+	pub fn send_output_proof_request(&self, commit: Commitment) -> Result<(), Error> {
+		debug!(
+			"Asking {} for a proof of output {:?}.",
+			self.info.addr, commit
+		);
+		connection!(self).send(&commit, msg::Type::GetOutputProof)
+	}
+
+	/// Asks the peer for the compact filter of the block with the provided
+	/// hash.
+	pub fn send_block_filter_request(&self, h: Hash) -> Result<(), Error> {
+		debug!("Asking {} for the filter of block {}.", self.info.addr, h);
+		connection!(self).send(&h, msg::Type::GetBlockFilter)
+	}
+
 	/// Stops the peer, closing its connection
 	pub fn stop(&self) {
 		if let Some(conn) = self.connection.as_ref() {
@@ -542,6 +635,20 @@ impl ChainAdapter for TrackingAdapter {
 		self.adapter.tx_kernel_received(kernel_hash, peer_info)
 	}
 
+	fn get_compact_transaction(&self, kernel_hash: Hash) -> Option<core::CompactTransaction> {
+		self.adapter.get_compact_transaction(kernel_hash)
+	}
+
+	fn compact_transaction_received(
+		&self,
+		ct: core::CompactTransaction,
+		peer_info: &PeerInfo,
+	) -> Result<bool, chain::Error> {
+		let kernel = &ct.kern_full()[0];
+		self.push_recv(kernel.hash());
+		self.adapter.compact_transaction_received(ct, peer_info)
+	}
+
 	fn transaction_received(
 		&self,
 		tx: core::Transaction,
@@ -636,6 +743,92 @@ impl ChainAdapter for TrackingAdapter {
 	fn get_tmpfile_pathname(&self, tmpfile_name: String) -> PathBuf {
 		self.adapter.get_tmpfile_pathname(tmpfile_name)
 	}
+
+	fn get_output_bitmap_segment(&self, h: Hash, id: SegmentIdentifier) -> Option<BitmapSegment> {
+		self.adapter.get_output_bitmap_segment(h, id)
+	}
+
+	fn get_output_segment(
+		&self,
+		h: Hash,
+		id: SegmentIdentifier,
+	) -> Option<Segment<OutputIdentifier>> {
+		self.adapter.get_output_segment(h, id)
+	}
+
+	fn get_rangeproof_segment(
+		&self,
+		h: Hash,
+		id: SegmentIdentifier,
+	) -> Option<Segment<RangeProof>> {
+		self.adapter.get_rangeproof_segment(h, id)
+	}
+
+	fn get_kernel_segment(&self, h: Hash, id: SegmentIdentifier) -> Option<Segment<TxKernelEntry>> {
+		self.adapter.get_kernel_segment(h, id)
+	}
+
+	fn receive_output_bitmap_segment(
+		&self,
+		h: Hash,
+		segment: BitmapSegment,
+		peer_info: &PeerInfo,
+	) -> Result<bool, chain::Error> {
+		self.adapter
+			.receive_output_bitmap_segment(h, segment, peer_info)
+	}
+
+	fn receive_output_segment(
+		&self,
+		h: Hash,
+		segment: Segment<OutputIdentifier>,
+		peer_info: &PeerInfo,
+	) -> Result<bool, chain::Error> {
+		self.adapter.receive_output_segment(h, segment, peer_info)
+	}
+
+	fn receive_rangeproof_segment(
+		&self,
+		h: Hash,
+		segment: Segment<RangeProof>,
+		peer_info: &PeerInfo,
+	) -> Result<bool, chain::Error> {
+		self.adapter
+			.receive_rangeproof_segment(h, segment, peer_info)
+	}
+
+	fn receive_kernel_segment(
+		&self,
+		h: Hash,
+		segment: Segment<TxKernelEntry>,
+		peer_info: &PeerInfo,
+	) -> Result<bool, chain::Error> {
+		self.adapter.receive_kernel_segment(h, segment, peer_info)
+	}
+
+	fn get_output_proof(&self, commit: Commitment) -> Option<chain::OutputProof> {
+		self.adapter.get_output_proof(commit)
+	}
+
+	fn receive_output_proof(
+		&self,
+		proof: &chain::OutputProof,
+		peer_info: &PeerInfo,
+	) -> Result<bool, chain::Error> {
+		self.adapter.receive_output_proof(proof, peer_info)
+	}
+
+	fn get_block_filter(&self, h: Hash) -> Option<core::block_filter::BlockFilter> {
+		self.adapter.get_block_filter(h)
+	}
+
+	fn receive_block_filter(
+		&self,
+		filter: &core::block_filter::BlockFilter,
+		peer_info: &PeerInfo,
+	) -> Result<bool, chain::Error> {
+		self.adapter.receive_block_filter(filter, peer_info)
+	}
 }
 
 impl NetAdapter for TrackingAdapter {
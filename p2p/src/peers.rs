@@ -14,9 +14,11 @@
 
 use crate::util::RwLock;
 use std::collections::HashMap;
+use std::fmt;
 use std::fs::File;
 use std::path::PathBuf;
 use std::sync::Arc;
+use std::{thread, time};
 
 use rand::seq::SliceRandom;
 use rand::thread_rng;
@@ -24,8 +26,11 @@ use rand::thread_rng;
 use crate::chain;
 use crate::core::core;
 use crate::core::core::hash::{Hash, Hashed};
+use crate::core::core::pmmr::{BitmapSegment, Segment, SegmentIdentifier};
+use crate::core::core::{OutputIdentifier, TxKernelEntry};
 use crate::core::global;
 use crate::core::pow::Difficulty;
+use crate::util::secp::pedersen::{Commitment, RangeProof};
 use chrono::prelude::*;
 use chrono::Duration;
 
@@ -33,7 +38,7 @@ use crate::peer::Peer;
 use crate::store::{PeerData, PeerStore, State};
 use crate::types::{
 	Capabilities, ChainAdapter, Error, NetAdapter, P2PConfig, PeerAddr, PeerInfo, ReasonForBan,
-	TxHashSetRead, MAX_PEER_ADDRS,
+	TxHashSetRead, TxHashSetUploads, MAX_PEER_ADDRS,
 };
 
 pub struct Peers {
@@ -41,15 +46,24 @@ pub struct Peers {
 	store: PeerStore,
 	peers: RwLock<HashMap<PeerAddr, Arc<Peer>>>,
 	config: P2PConfig,
+	txhashset_uploads: TxHashSetUploads,
+	// output proofs we asked for, along with the valid proof once received
+	output_proofs: RwLock<HashMap<Commitment, Option<chain::OutputProof>>>,
+	// same for block filters
+	block_filters: RwLock<HashMap<Hash, Option<core::block_filter::BlockFilter>>>,
 }
 
 impl Peers {
 	pub fn new(store: PeerStore, adapter: Arc<dyn ChainAdapter>, config: P2PConfig) -> Peers {
+		let txhashset_uploads = TxHashSetUploads::new(config.txhashset_max_uploads());
 		Peers {
 			adapter,
 			store,
 			config,
 			peers: RwLock::new(HashMap::new()),
+			txhashset_uploads,
+			output_proofs: RwLock::new(HashMap::new()),
+			block_filters: RwLock::new(HashMap::new()),
 		}
 	}
 
@@ -201,6 +215,44 @@ impl Peers {
 		self.most_work_peers().pop()
 	}
 
+	/// Asks one of our most work peers serving output proofs for a merkle
+	/// proof of the unspent output with the provided commitment, and waits
+	/// for it up to the provided timeout. The proof is verified against our
+	/// header chain as it comes in, so this is usable by header-only nodes.
+	pub fn request_output_proof(
+		&self,
+		commit: Commitment,
+		timeout: time::Duration,
+	) -> Option<chain::OutputProof> {
+		let peer = self
+			.most_work_peers()
+			.into_iter()
+			.find(|p| p.info.capabilities.contains(Capabilities::OUTPUT_PROOFS))?;
+		request_and_wait(&self.output_proofs, commit, timeout, &peer, |p| {
+			p.send_output_proof_request(commit)
+		})
+	}
+
+	/// Asks one of our most work peers serving block filters for the compact
+	/// filter of the block with the provided hash, and waits for it up to the
+	/// provided timeout. Filters aren't committed to in the block header: we
+	/// can only check one against the block itself, when we have it. For a
+	/// block we don't have the peer could send any filter, hiding the block
+	/// from a wallet testing its commitments against it.
+	pub fn request_block_filter(
+		&self,
+		h: Hash,
+		timeout: time::Duration,
+	) -> Option<core::block_filter::BlockFilter> {
+		let peer = self
+			.most_work_peers()
+			.into_iter()
+			.find(|p| p.info.capabilities.contains(Capabilities::BLOCK_FILTERS))?;
+		request_and_wait(&self.block_filters, h, timeout, &peer, |p| {
+			p.send_block_filter_request(h)
+		})
+	}
+
 	pub fn is_banned(&self, peer_addr: PeerAddr) -> bool {
 		if let Ok(peer) = self.store.get_peer(peer_addr) {
 			if peer.flags == State::Banned {
@@ -498,6 +550,18 @@ impl ChainAdapter for Peers {
 		self.adapter.tx_kernel_received(kernel_hash, peer_info)
 	}
 
+	fn get_compact_transaction(&self, kernel_hash: Hash) -> Option<core::CompactTransaction> {
+		self.adapter.get_compact_transaction(kernel_hash)
+	}
+
+	fn compact_transaction_received(
+		&self,
+		ct: core::CompactTransaction,
+		peer_info: &PeerInfo,
+	) -> Result<bool, chain::Error> {
+		self.adapter.compact_transaction_received(ct, peer_info)
+	}
+
 	fn transaction_received(
 		&self,
 		tx: core::Transaction,
@@ -586,7 +650,21 @@ impl ChainAdapter for Peers {
 	}
 
 	fn txhashset_read(&self, h: Hash) -> Option<TxHashSetRead> {
-		self.adapter.txhashset_read(h)
+		let upload = match self.txhashset_uploads.acquire() {
+			Some(upload) => upload,
+			None => {
+				debug!(
+					"txhashset_read: already uploading {} archives, not serving {}",
+					self.txhashset_uploads.count(),
+					h
+				);
+				return None;
+			}
+		};
+		self.adapter.txhashset_read(h).map(|read| TxHashSetRead {
+			upload: Some(upload),
+			..read
+		})
 	}
 
 	fn txhashset_receive_ready(&self) -> bool {
@@ -628,6 +706,149 @@ impl ChainAdapter for Peers {
 	fn get_tmpfile_pathname(&self, tmpfile_name: String) -> PathBuf {
 		self.adapter.get_tmpfile_pathname(tmpfile_name)
 	}
+
+	fn get_output_bitmap_segment(&self, h: Hash, id: SegmentIdentifier) -> Option<BitmapSegment> {
+		self.adapter.get_output_bitmap_segment(h, id)
+	}
+
+	fn get_output_segment(
+		&self,
+		h: Hash,
+		id: SegmentIdentifier,
+	) -> Option<Segment<OutputIdentifier>> {
+		self.adapter.get_output_segment(h, id)
+	}
+
+	fn get_rangeproof_segment(
+		&self,
+		h: Hash,
+		id: SegmentIdentifier,
+	) -> Option<Segment<RangeProof>> {
+		self.adapter.get_rangeproof_segment(h, id)
+	}
+
+	fn get_kernel_segment(&self, h: Hash, id: SegmentIdentifier) -> Option<Segment<TxKernelEntry>> {
+		self.adapter.get_kernel_segment(h, id)
+	}
+
+	fn receive_output_bitmap_segment(
+		&self,
+		h: Hash,
+		segment: BitmapSegment,
+		peer_info: &PeerInfo,
+	) -> Result<bool, chain::Error> {
+		let res = self
+			.adapter
+			.receive_output_bitmap_segment(h, segment, peer_info)?;
+		Ok(self.check_segment(res, peer_info))
+	}
+
+	fn receive_output_segment(
+		&self,
+		h: Hash,
+		segment: Segment<OutputIdentifier>,
+		peer_info: &PeerInfo,
+	) -> Result<bool, chain::Error> {
+		let res = self.adapter.receive_output_segment(h, segment, peer_info)?;
+		Ok(self.check_segment(res, peer_info))
+	}
+
+	fn receive_rangeproof_segment(
+		&self,
+		h: Hash,
+		segment: Segment<RangeProof>,
+		peer_info: &PeerInfo,
+	) -> Result<bool, chain::Error> {
+		let res = self
+			.adapter
+			.receive_rangeproof_segment(h, segment, peer_info)?;
+		Ok(self.check_segment(res, peer_info))
+	}
+
+	fn receive_kernel_segment(
+		&self,
+		h: Hash,
+		segment: Segment<TxKernelEntry>,
+		peer_info: &PeerInfo,
+	) -> Result<bool, chain::Error> {
+		let res = self.adapter.receive_kernel_segment(h, segment, peer_info)?;
+		Ok(self.check_segment(res, peer_info))
+	}
+
+	fn get_output_proof(&self, commit: Commitment) -> Option<chain::OutputProof> {
+		self.adapter.get_output_proof(commit)
+	}
+
+	fn receive_output_proof(
+		&self,
+		proof: &chain::OutputProof,
+		peer_info: &PeerInfo,
+	) -> Result<bool, chain::Error> {
+		let commit = proof.output.commit;
+		if !self.output_proofs.read().contains_key(&commit) {
+			debug!(
+				"Received an output proof we didn't ask for from {}, ignoring it",
+				peer_info.addr
+			);
+			return Ok(true);
+		}
+		if !self.adapter.receive_output_proof(proof, peer_info)? {
+			debug!(
+				"Received a bad output proof from {}, the peer will be banned",
+				peer_info.addr
+			);
+			self.ban_peer(peer_info.addr, ReasonForBan::BadOutputProof);
+			return Ok(false);
+		}
+		if let Some(pending) = self.output_proofs.write().get_mut(&commit) {
+			*pending = Some(proof.clone());
+		}
+		Ok(true)
+	}
+
+	fn get_block_filter(&self, h: Hash) -> Option<core::block_filter::BlockFilter> {
+		self.adapter.get_block_filter(h)
+	}
+
+	fn receive_block_filter(
+		&self,
+		filter: &core::block_filter::BlockFilter,
+		peer_info: &PeerInfo,
+	) -> Result<bool, chain::Error> {
+		if !self.block_filters.read().contains_key(&filter.block_hash) {
+			debug!(
+				"Received a block filter we didn't ask for from {}, ignoring it",
+				peer_info.addr
+			);
+			return Ok(true);
+		}
+		if !self.adapter.receive_block_filter(filter, peer_info)? {
+			debug!(
+				"Received a bad block filter from {}, the peer will be banned",
+				peer_info.addr
+			);
+			self.ban_peer(peer_info.addr, ReasonForBan::BadBlockFilter);
+			return Ok(false);
+		}
+		if let Some(pending) = self.block_filters.write().get_mut(&filter.block_hash) {
+			*pending = Some(filter.clone());
+		}
+		Ok(true)
+	}
+}
+
+impl Peers {
+	// Bans the peer if the txhashset segment it sent us was invalid.
+	fn check_segment(&self, valid: bool, peer_info: &PeerInfo) -> bool {
+		if !valid {
+			debug!(
+				"Received a bad txhashset segment from {}, the peer will be banned",
+				peer_info.addr
+			);
+			self.ban_peer(peer_info.addr, ReasonForBan::BadSegment);
+		}
+		valid
+	}
 }
 
 impl NetAdapter for Peers {
@@ -677,3 +898,44 @@ impl NetAdapter for Peers {
 		}
 	}
 }
+
+// Sends a request to the peer, marking the response as pending under the
+// provided key, and waits for it up to the provided timeout. Responses are
+// filled in as they come in (see receive_output_proof, receive_block_filter).
+fn request_and_wait<K, V, F>(
+	pending: &RwLock<HashMap<K, Option<V>>>,
+	key: K,
+	timeout: time::Duration,
+	peer: &Peer,
+	send_request: F,
+) -> Option<V>
+where
+	K: Copy + Eq + std::hash::Hash + fmt::Debug,
+	F: FnOnce(&Peer) -> Result<(), Error>,
+{
+	pending.write().entry(key).or_insert(None);
+	if let Err(e) = send_request(peer) {
+		debug!(
+			"Failed to send request for {:?} to {}: {:?}",
+			key, peer.info.addr, e
+		);
+		pending.write().remove(&key);
+		return None;
+	}
+
+	let deadline = time::Instant::now() + timeout;
+	loop {
+		if let Some(Some(_)) = pending.read().get(&key) {
+			break;
+		}
+		if time::Instant::now() > deadline {
+			debug!(
+				"Timed out waiting for the response for {:?} from {}",
+				key, peer.info.addr
+			);
+			break;
+		}
+		thread::sleep(time::Duration::from_millis(10));
+	}
+	pending.write().remove(&key).and_then(|v| v)
+}
@@ -12,22 +12,25 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use rand::{thread_rng, Rng};
 use std::cmp;
-use std::fs::{self, File, OpenOptions};
-use std::io::{BufWriter, Write};
+use std::fs::File;
+use std::io::{self, BufWriter, Seek, SeekFrom, Write};
 use std::sync::Arc;
 
+use crate::chain;
 use crate::conn::{Message, MessageHandler, Response};
-use crate::core::core::{self, hash::Hash, CompactBlock};
+use crate::core::core::hash::{Hash, ZERO_HASH};
+use crate::core::core::{self, CompactBlock};
+use crate::util::secp::pedersen::Commitment;
 use crate::util::{RateCounter, RwLock};
 use chrono::prelude::Utc;
 
 use crate::msg::{
-	BanReason, GetPeerAddrs, Headers, Locator, PeerAddrs, Ping, Pong, TxHashSetArchive,
+	BanReason, GetPeerAddrs, Headers, Locator, PeerAddrs, Ping, Pong, SegmentRequest,
+	SegmentResponse, TxHashSetArchive, TxHashSetArchiveRange, TxHashSetRangeRequest,
 	TxHashSetRequest, Type,
 };
-use crate::types::{Error, NetAdapter, PeerInfo};
+use crate::types::{txhashset_archive_hash, Error, NetAdapter, PeerInfo, TxHashSetPartial};
 
 pub struct Protocol {
 	adapter: Arc<dyn NetAdapter>,
@@ -38,6 +41,152 @@ impl Protocol {
 	pub fn new(adapter: Arc<dyn NetAdapter>, peer_info: PeerInfo) -> Protocol {
 		Protocol { adapter, peer_info }
 	}
+
+	// Saves the txhashset archive attached to the message (or the rest of it,
+	// from the provided offset) in the tmp dir and hands it over to the
+	// adapter once complete. The download is persisted as it goes so that it
+	// can be resumed if the connection drops halfway.
+	fn receive_txhashset(
+		&self,
+		msg: &mut Message<'_>,
+		hash: Hash,
+		height: u64,
+		total_size: u64,
+		offset: u64,
+		archive_hash: Hash,
+		received_bytes: Arc<RwLock<RateCounter>>,
+	) -> Result<bool, Error> {
+		if !self.adapter.txhashset_receive_ready() {
+			error!(
+				"handle_payload: txhashset archive received but SyncStatus not on TxHashsetDownload",
+			);
+			return Err(Error::BadMessage);
+		}
+
+		let tmp_dir = self.adapter.get_tmp_dir();
+		let partial = TxHashSetPartial {
+			hash,
+			height,
+			total_size,
+			peer_addr: self.peer_info.addr,
+			archive_hash,
+		};
+
+		// A range can only be appended to the download in progress it is the
+		// continuation of, from the same peer and archive. Anything else and
+		// we drop what we have to start over.
+		let file = if offset == 0 {
+			partial.start(&tmp_dir)?
+		} else {
+			match TxHashSetPartial::load(&tmp_dir) {
+				Some((current, received))
+					if received == offset && current.continued_by(&partial) =>
+				{
+					partial.save(&tmp_dir)?;
+					TxHashSetPartial::resume(&tmp_dir)?
+				}
+				_ => {
+					warn!(
+						"handle_payload: txhashset archive range from {} not matching our download, restarting",
+						offset
+					);
+					TxHashSetPartial::clear(&tmp_dir);
+					msg.copy_attachment(
+						total_size.saturating_sub(offset) as usize,
+						&mut io::sink(),
+					)?;
+					return Ok(false);
+				}
+			}
+		};
+
+		let download_start_time = Utc::now();
+		self.adapter
+			.txhashset_download_update(download_start_time, offset, total_size);
+
+		let mut save_txhashset_to_file = |file: File| -> Result<(), Error> {
+			// Whatever we received is flushed to the file even if we fail
+			// halfway, to resume from there.
+			let mut tmp_zip = BufWriter::new(file);
+			let total_size = total_size as usize;
+			let mut downloaded_size = offset as usize;
+			let mut request_size = cmp::min(48_000, total_size - downloaded_size);
+			while request_size > 0 {
+				let size = msg.copy_attachment(request_size, &mut tmp_zip)?;
+				downloaded_size += size;
+				request_size = cmp::min(48_000, total_size - downloaded_size);
+				self.adapter.txhashset_download_update(
+					download_start_time,
+					downloaded_size as u64,
+					total_size as u64,
+				);
+
+				// Increase received bytes quietly (without affecting the counters).
+				// Otherwise we risk banning a peer as "abusive".
+				{
+					let mut received_bytes = received_bytes.write();
+					received_bytes.inc_quiet(size as u64);
+				}
+			}
+			tmp_zip
+				.into_inner()
+				.map_err(|_| Error::Internal)?
+				.sync_all()?;
+			Ok(())
+		};
+
+		if let Err(e) = save_txhashset_to_file(file) {
+			error!(
+				"handle_payload: txhashset archive save to file fail. err={:?}",
+				e
+			);
+			return Err(e);
+		}
+
+		let archive_path = TxHashSetPartial::archive_path(&tmp_dir);
+		trace!(
+			"handle_payload: txhashset archive save to file {:?} success",
+			archive_path,
+		);
+
+		let mut tmp_zip = File::open(archive_path)?;
+		if archive_hash != ZERO_HASH && txhashset_archive_hash(&mut tmp_zip)? != archive_hash {
+			warn!(
+				"handle_payload: txhashset archive for {} at {} doesn't match its hash, restarting",
+				hash, height
+			);
+			TxHashSetPartial::clear(&tmp_dir);
+			return Ok(false);
+		}
+		tmp_zip.seek(SeekFrom::Start(0))?;
+		let res = self.adapter.txhashset_write(hash, tmp_zip, &self.peer_info);
+
+		// Valid or not, we're done with this archive.
+		TxHashSetPartial::clear(&tmp_dir);
+
+		let res = res?;
+		debug!(
+			"handle_payload: txhashset archive for {} at {}, DONE. Data Ok: {}",
+			hash, height, res
+		);
+		Ok(true)
+	}
+
+	/// Asks the peer for the whole txhashset archive at the provided block,
+	/// when the download in progress couldn't be completed from a range.
+	fn restart_txhashset<'a>(
+		&self,
+		hash: Hash,
+		height: u64,
+		writer: &'a mut dyn Write,
+	) -> Result<Option<Response<'a>>, Error> {
+		Ok(Some(Response::new(
+			Type::TxHashSetRequest,
+			self.peer_info.version,
+			TxHashSetRequest { hash, height },
+			writer,
+		)?))
+	}
 }
 
 impl MessageHandler for Protocol {
@@ -67,6 +216,7 @@ impl MessageHandler for Protocol {
 
 				Ok(Some(Response::new(
 					Type::Pong,
+					self.peer_info.version,
 					Pong {
 						total_difficulty: adapter.total_difficulty()?,
 						height: adapter.total_height()?,
@@ -105,7 +255,12 @@ impl MessageHandler for Protocol {
 				);
 				let tx = adapter.get_transaction(h);
 				if let Some(tx) = tx {
-					Ok(Some(Response::new(Type::Transaction, tx, writer)?))
+					Ok(Some(Response::new(
+						Type::Transaction,
+						self.peer_info.version,
+						tx,
+						writer,
+					)?))
 				} else {
 					Ok(None)
 				}
@@ -131,6 +286,35 @@ impl MessageHandler for Protocol {
 				Ok(None)
 			}
 
+			Type::GetCompactTransaction => {
+				let h: Hash = msg.body()?;
+				debug!(
+					"handle_payload: GetCompactTransaction: {}, msg_len: {}",
+					h, msg.header.msg_len,
+				);
+				let ct = adapter.get_compact_transaction(h);
+				if let Some(ct) = ct {
+					Ok(Some(Response::new(
+						Type::CompactTransaction,
+						self.peer_info.version,
+						ct,
+						writer,
+					)?))
+				} else {
+					Ok(None)
+				}
+			}
+
+			Type::CompactTransaction => {
+				debug!(
+					"handle_payload: received compact tx: msg_len: {}",
+					msg.header.msg_len
+				);
+				let ct: core::CompactTransaction = msg.body()?;
+				adapter.compact_transaction_received(ct, &self.peer_info)?;
+				Ok(None)
+			}
+
 			Type::GetBlock => {
 				let h: Hash = msg.body()?;
 				trace!(
@@ -141,7 +325,12 @@ impl MessageHandler for Protocol {
 
 				let bo = adapter.get_block(h);
 				if let Some(b) = bo {
-					return Ok(Some(Response::new(Type::Block, b, writer)?));
+					return Ok(Some(Response::new(
+						Type::Block,
+						self.peer_info.version,
+						b,
+						writer,
+					)?));
 				}
 				Ok(None)
 			}
@@ -163,7 +352,12 @@ impl MessageHandler for Protocol {
 				let h: Hash = msg.body()?;
 				if let Some(b) = adapter.get_block(h) {
 					let cb: CompactBlock = b.into();
-					Ok(Some(Response::new(Type::CompactBlock, cb, writer)?))
+					Ok(Some(Response::new(
+						Type::CompactBlock,
+						self.peer_info.version,
+						cb,
+						writer,
+					)?))
 				} else {
 					Ok(None)
 				}
@@ -188,6 +382,7 @@ impl MessageHandler for Protocol {
 				// serialize and send all the headers over
 				Ok(Some(Response::new(
 					Type::Headers,
+					self.peer_info.version,
 					Headers { headers },
 					writer,
 				)?))
@@ -233,6 +428,7 @@ impl MessageHandler for Protocol {
 				let peers = adapter.find_peer_addrs(get_peers.capabilities);
 				Ok(Some(Response::new(
 					Type::PeerAddrs,
+					self.peer_info.version,
 					PeerAddrs { peers },
 					writer,
 				)?))
@@ -257,6 +453,7 @@ impl MessageHandler for Protocol {
 					let file_sz = txhashset.reader.metadata()?.len();
 					let mut resp = Response::new(
 						Type::TxHashSetArchive,
+						self.peer_info.version,
 						&TxHashSetArchive {
 							height: sm_req.height as u64,
 							hash: sm_req.hash,
@@ -265,6 +462,44 @@ impl MessageHandler for Protocol {
 						writer,
 					)?;
 					resp.add_attachment(txhashset.reader);
+					resp.add_upload(txhashset.upload);
+					Ok(Some(resp))
+				} else {
+					Ok(None)
+				}
+			}
+
+			Type::TxHashSetRangeRequest => {
+				let sm_req: TxHashSetRangeRequest = msg.body()?;
+				debug!(
+					"handle_payload: txhashset range req for {} at {} from {}",
+					sm_req.hash, sm_req.height, sm_req.offset
+				);
+
+				let txhashset = self.adapter.txhashset_read(sm_req.hash);
+
+				if let Some(txhashset) = txhashset {
+					let mut reader = txhashset.reader;
+					let file_sz = reader.metadata()?.len();
+					if sm_req.offset >= file_sz {
+						return Ok(None);
+					}
+					let archive_hash = txhashset_archive_hash(&mut reader)?;
+					reader.seek(SeekFrom::Start(sm_req.offset))?;
+					let mut resp = Response::new(
+						Type::TxHashSetArchiveRange,
+						self.peer_info.version,
+						&TxHashSetArchiveRange {
+							height: sm_req.height,
+							hash: sm_req.hash,
+							bytes: file_sz,
+							offset: sm_req.offset,
+							archive_hash,
+						},
+						writer,
+					)?;
+					resp.add_attachment(reader);
+					resp.add_upload(txhashset.upload);
 					Ok(Some(resp))
 				} else {
 					Ok(None)
@@ -277,80 +512,173 @@ impl MessageHandler for Protocol {
 					"handle_payload: txhashset archive for {} at {}. size={}",
 					sm_arch.hash, sm_arch.height, sm_arch.bytes,
 				);
-				if !self.adapter.txhashset_receive_ready() {
-					error!(
-						"handle_payload: txhashset archive received but SyncStatus not on TxHashsetDownload",
-					);
-					return Err(Error::BadMessage);
+				self.receive_txhashset(
+					&mut msg,
+					sm_arch.hash,
+					sm_arch.height,
+					sm_arch.bytes,
+					0,
+					ZERO_HASH,
+					received_bytes,
+				)?;
+				Ok(None)
+			}
+
+			Type::TxHashSetArchiveRange => {
+				let sm_arch: TxHashSetArchiveRange = msg.body()?;
+				debug!(
+					"handle_payload: txhashset archive for {} at {}. size={}, from {}",
+					sm_arch.hash, sm_arch.height, sm_arch.bytes, sm_arch.offset,
+				);
+				let done = self.receive_txhashset(
+					&mut msg,
+					sm_arch.hash,
+					sm_arch.height,
+					sm_arch.bytes,
+					sm_arch.offset,
+					sm_arch.archive_hash,
+					received_bytes,
+				)?;
+				if done {
+					Ok(None)
+				} else {
+					self.restart_txhashset(sm_arch.hash, sm_arch.height, writer)
 				}
+			}
 
-				let download_start_time = Utc::now();
-				self.adapter
-					.txhashset_download_update(download_start_time, 0, sm_arch.bytes);
-
-				let nonce: u32 = thread_rng().gen_range(0, 1_000_000);
-				let tmp = self.adapter.get_tmpfile_pathname(format!(
-					"txhashset-{}-{}.zip",
-					download_start_time.timestamp(),
-					nonce
-				));
-				let mut save_txhashset_to_file = |file| -> Result<(), Error> {
-					let mut tmp_zip =
-						BufWriter::new(OpenOptions::new().write(true).create_new(true).open(file)?);
-					let total_size = sm_arch.bytes as usize;
-					let mut downloaded_size: usize = 0;
-					let mut request_size = cmp::min(48_000, total_size);
-					while request_size > 0 {
-						let size = msg.copy_attachment(request_size, &mut tmp_zip)?;
-						downloaded_size += size;
-						request_size = cmp::min(48_000, total_size - downloaded_size);
-						self.adapter.txhashset_download_update(
-							download_start_time,
-							downloaded_size as u64,
-							total_size as u64,
-						);
-
-						// Increase received bytes quietly (without affecting the counters).
-						// Otherwise we risk banning a peer as "abusive".
-						{
-							let mut received_bytes = received_bytes.write();
-							received_bytes.inc_quiet(size as u64);
-						}
-					}
-					tmp_zip
-						.into_inner()
-						.map_err(|_| Error::Internal)?
-						.sync_all()?;
-					Ok(())
-				};
-
-				if let Err(e) = save_txhashset_to_file(tmp.clone()) {
-					error!(
-						"handle_payload: txhashset archive save to file fail. err={:?}",
-						e
-					);
-					return Err(e);
+			Type::GetOutputBitmapSegment => {
+				let req: SegmentRequest = msg.body()?;
+				match adapter.get_output_bitmap_segment(req.block_hash, req.identifier) {
+					Some(segment) => Ok(Some(Response::new(
+						Type::OutputBitmapSegment,
+						self.peer_info.version,
+						SegmentResponse {
+							block_hash: req.block_hash,
+							segment,
+						},
+						writer,
+					)?)),
+					None => Ok(None),
 				}
+			}
 
-				trace!(
-					"handle_payload: txhashset archive save to file {:?} success",
-					tmp,
-				);
+			Type::GetOutputSegment => {
+				let req: SegmentRequest = msg.body()?;
+				match adapter.get_output_segment(req.block_hash, req.identifier) {
+					Some(segment) => Ok(Some(Response::new(
+						Type::OutputSegment,
+						self.peer_info.version,
+						SegmentResponse {
+							block_hash: req.block_hash,
+							segment,
+						},
+						writer,
+					)?)),
+					None => Ok(None),
+				}
+			}
+
+			Type::GetRangeProofSegment => {
+				let req: SegmentRequest = msg.body()?;
+				match adapter.get_rangeproof_segment(req.block_hash, req.identifier) {
+					Some(segment) => Ok(Some(Response::new(
+						Type::RangeProofSegment,
+						self.peer_info.version,
+						SegmentResponse {
+							block_hash: req.block_hash,
+							segment,
+						},
+						writer,
+					)?)),
+					None => Ok(None),
+				}
+			}
 
-				let tmp_zip = File::open(tmp.clone())?;
-				let res = self
-					.adapter
-					.txhashset_write(sm_arch.hash, tmp_zip, &self.peer_info)?;
+			Type::GetKernelSegment => {
+				let req: SegmentRequest = msg.body()?;
+				match adapter.get_kernel_segment(req.block_hash, req.identifier) {
+					Some(segment) => Ok(Some(Response::new(
+						Type::KernelSegment,
+						self.peer_info.version,
+						SegmentResponse {
+							block_hash: req.block_hash,
+							segment,
+						},
+						writer,
+					)?)),
+					None => Ok(None),
+				}
+			}
 
-				debug!(
-					"handle_payload: txhashset archive for {} at {}, DONE. Data Ok: {}",
-					sm_arch.hash, sm_arch.height, res
-				);
+			// An invalid segment gets the peer banned by the adapter (see
+			// Peers::check_segment), which also drops it from our PIBD peers.
+			Type::OutputBitmapSegment => {
+				let resp: SegmentResponse<_> = msg.body()?;
+				adapter.receive_output_bitmap_segment(
+					resp.block_hash,
+					resp.segment,
+					&self.peer_info,
+				)?;
+				Ok(None)
+			}
 
-				if let Err(e) = fs::remove_file(tmp.clone()) {
-					warn!("fail to remove tmp file: {:?}. err: {}", tmp, e);
+			Type::OutputSegment => {
+				let resp: SegmentResponse<_> = msg.body()?;
+				adapter.receive_output_segment(resp.block_hash, resp.segment, &self.peer_info)?;
+				Ok(None)
+			}
+
+			Type::RangeProofSegment => {
+				let resp: SegmentResponse<_> = msg.body()?;
+				adapter.receive_rangeproof_segment(
+					resp.block_hash,
+					resp.segment,
+					&self.peer_info,
+				)?;
+				Ok(None)
+			}
+
+			Type::KernelSegment => {
+				let resp: SegmentResponse<_> = msg.body()?;
+				adapter.receive_kernel_segment(resp.block_hash, resp.segment, &self.peer_info)?;
+				Ok(None)
+			}
+
+			Type::GetOutputProof => {
+				let commit: Commitment = msg.body()?;
+				match adapter.get_output_proof(commit) {
+					Some(proof) => Ok(Some(Response::new(
+						Type::OutputProof,
+						self.peer_info.version,
+						proof,
+						writer,
+					)?)),
+					None => Ok(None),
+				}
+			}
+
+			Type::OutputProof => {
+				let proof: chain::OutputProof = msg.body()?;
+				adapter.receive_output_proof(&proof, &self.peer_info)?;
+				Ok(None)
+			}
+
+			Type::GetBlockFilter => {
+				let h: Hash = msg.body()?;
+				match adapter.get_block_filter(h) {
+					Some(filter) => Ok(Some(Response::new(
+						Type::BlockFilter,
+						self.peer_info.version,
+						filter,
+						writer,
+					)?)),
+					None => Ok(None),
 				}
+			}
 
+			Type::BlockFilter => {
+				let filter: core::block_filter::BlockFilter = msg.body()?;
+				adapter.receive_block_filter(&filter, &self.peer_info)?;
 				Ok(None)
 			}
 
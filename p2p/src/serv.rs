@@ -22,7 +22,9 @@ use std::{io, thread};
 use crate::chain;
 use crate::core::core;
 use crate::core::core::hash::Hash;
-use crate::core::global;
+use crate::core::core::pmmr::{BitmapSegment, Segment, SegmentIdentifier};
+use crate::core::core::{OutputIdentifier, TxKernelEntry};
+use crate::core::global::ChainParams;
 use crate::core::pow::Difficulty;
 use crate::handshake::Handshake;
 use crate::peer::Peer;
@@ -32,6 +34,7 @@ use crate::types::{
 	Capabilities, ChainAdapter, Error, NetAdapter, P2PConfig, PeerAddr, PeerInfo, ReasonForBan,
 	TxHashSetRead,
 };
+use crate::util::secp::pedersen::{Commitment, RangeProof};
 use crate::util::{Mutex, StopState};
 use chrono::prelude::{DateTime, Utc};
 
@@ -43,6 +46,7 @@ pub struct Server {
 	handshake: Arc<Handshake>,
 	pub peers: Arc<Peers>,
 	stop_state: Arc<Mutex<StopState>>,
+	params: ChainParams,
 }
 
 // TODO TLS
@@ -54,17 +58,24 @@ impl Server {
 		config: P2PConfig,
 		adapter: Arc<dyn ChainAdapter>,
 		genesis: Hash,
+		params: ChainParams,
 		stop_state: Arc<Mutex<StopState>>,
 	) -> Result<Server, Error> {
 		Ok(Server {
 			config: config.clone(),
 			capabilities: capab,
-			handshake: Arc::new(Handshake::new(genesis, config.clone())),
+			handshake: Arc::new(Handshake::new(genesis, config.clone(), params.clone())),
 			peers: Arc::new(Peers::new(PeerStore::new(db_root)?, adapter, config)),
 			stop_state,
+			params,
 		})
 	}
 
+	/// The parameters of the chain our peers are on.
+	pub fn params(&self) -> &ChainParams {
+		&self.params
+	}
+
 	/// Starts a new TCP server and listen to incoming connections. This is a
 	/// blocking call until the TCP server stops.
 	pub fn listen(&self) -> Result<(), Error> {
@@ -116,7 +127,7 @@ impl Server {
 			return Err(Error::ConnectionClose);
 		}
 
-		if global::is_production_mode() {
+		if self.params.is_production_mode() {
 			let hs = self.handshake.clone();
 			let addrs = hs.addrs.read();
 			if addrs.contains(&addr) {
@@ -245,6 +256,16 @@ impl ChainAdapter for DummyAdapter {
 	fn tx_kernel_received(&self, _h: Hash, _peer_info: &PeerInfo) -> Result<bool, chain::Error> {
 		Ok(true)
 	}
+	fn get_compact_transaction(&self, _h: Hash) -> Option<core::CompactTransaction> {
+		None
+	}
+	fn compact_transaction_received(
+		&self,
+		_ct: core::CompactTransaction,
+		_peer_info: &PeerInfo,
+	) -> Result<bool, chain::Error> {
+		Ok(true)
+	}
 	fn transaction_received(
 		&self,
 		_: core::Transaction,
@@ -315,6 +336,94 @@ impl ChainAdapter for DummyAdapter {
 	fn get_tmpfile_pathname(&self, _tmpfile_name: String) -> PathBuf {
 		unimplemented!()
 	}
+
+	fn get_output_bitmap_segment(&self, _h: Hash, _id: SegmentIdentifier) -> Option<BitmapSegment> {
+		None
+	}
+
+	fn get_output_segment(
+		&self,
+		_h: Hash,
+		_id: SegmentIdentifier,
+	) -> Option<Segment<OutputIdentifier>> {
+		None
+	}
+
+	fn get_rangeproof_segment(
+		&self,
+		_h: Hash,
+		_id: SegmentIdentifier,
+	) -> Option<Segment<RangeProof>> {
+		None
+	}
+
+	fn get_kernel_segment(
+		&self,
+		_h: Hash,
+		_id: SegmentIdentifier,
+	) -> Option<Segment<TxKernelEntry>> {
+		None
+	}
+
+	fn receive_output_bitmap_segment(
+		&self,
+		_h: Hash,
+		_segment: BitmapSegment,
+		_peer_info: &PeerInfo,
+	) -> Result<bool, chain::Error> {
+		Ok(false)
+	}
+
+	fn receive_output_segment(
+		&self,
+		_h: Hash,
+		_segment: Segment<OutputIdentifier>,
+		_peer_info: &PeerInfo,
+	) -> Result<bool, chain::Error> {
+		Ok(false)
+	}
+
+	fn receive_rangeproof_segment(
+		&self,
+		_h: Hash,
+		_segment: Segment<RangeProof>,
+		_peer_info: &PeerInfo,
+	) -> Result<bool, chain::Error> {
+		Ok(false)
+	}
+
+	fn receive_kernel_segment(
+		&self,
+		_h: Hash,
+		_segment: Segment<TxKernelEntry>,
+		_peer_info: &PeerInfo,
+	) -> Result<bool, chain::Error> {
+		Ok(false)
+	}
+
+	fn get_output_proof(&self, _commit: Commitment) -> Option<chain::OutputProof> {
+		None
+	}
+
+	fn receive_output_proof(
+		&self,
+		_proof: &chain::OutputProof,
+		_peer_info: &PeerInfo,
+	) -> Result<bool, chain::Error> {
+		Ok(false)
+	}
+
+	fn get_block_filter(&self, _h: Hash) -> Option<core::block_filter::BlockFilter> {
+		None
+	}
+
+	fn receive_block_filter(
+		&self,
+		_filter: &core::block_filter::BlockFilter,
+		_peer_info: &PeerInfo,
+	) -> Result<bool, chain::Error> {
+		Ok(false)
+	}
 }
 
 impl NetAdapter for DummyAdapter {
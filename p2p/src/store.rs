@@ -21,11 +21,15 @@ use rand::thread_rng;
 
 use crate::core::ser::{self, Readable, Reader, Writeable, Writer};
 use crate::types::{Capabilities, PeerAddr, ReasonForBan};
-use grin_store::{self, option_to_not_found, to_key, Error};
+use grin_store::{self, migration, option_to_not_found, to_key, Error};
 
 const DB_NAME: &'static str = "peer";
 const STORE_SUBPATH: &'static str = "peers";
 
+/// Current schema version of the peer db.
+/// Bump this (and add a migration) whenever the format of the data changes.
+const SCHEMA_VERSION: u32 = 1;
+
 const PEER_PREFIX: u8 = 'P' as u8;
 
 /// Types of messages
@@ -117,6 +121,7 @@ impl PeerStore {
 	/// Instantiates a new peer store under the provided root path.
 	pub fn new(db_root: &str) -> Result<PeerStore, Error> {
 		let db = grin_store::Store::new(db_root, Some(DB_NAME), Some(STORE_SUBPATH), None)?;
+		migration::migrate(&db, SCHEMA_VERSION, &[])?;
 		Ok(PeerStore { db: db })
 	}
 
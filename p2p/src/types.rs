@@ -12,12 +12,12 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use crate::util::RwLock;
+use crate::util::{Mutex, RwLock};
 use std::convert::From;
-use std::fs::File;
-use std::io;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom};
 use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV4, SocketAddrV6};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use std::sync::mpsc;
 use std::sync::Arc;
@@ -26,10 +26,13 @@ use chrono::prelude::*;
 
 use crate::chain;
 use crate::core::core;
-use crate::core::core::hash::Hash;
+use crate::core::core::hash::{Hash, HashWriter, ZERO_HASH};
+use crate::core::core::pmmr::{BitmapSegment, Segment, SegmentIdentifier};
+use crate::core::core::{OutputIdentifier, TxKernelEntry};
 use crate::core::global;
 use crate::core::pow::Difficulty;
-use crate::core::ser::{self, Readable, Reader, Writeable, Writer};
+use crate::core::ser::{self, ProtocolVersion, Readable, Reader, Writeable, Writer};
+use crate::util::secp::pedersen::{Commitment, RangeProof};
 use grin_store;
 
 /// Maximum number of block headers a peer should ever send
@@ -54,6 +57,9 @@ const PEER_MAX_COUNT: u32 = 125;
 /// min preferred peer count
 const PEER_MIN_PREFERRED_COUNT: u32 = 8;
 
+/// Max number of txhashset archives uploaded to our peers at the same time
+const TXHASHSET_MAX_UPLOADS: usize = 4;
+
 #[derive(Debug)]
 pub enum Error {
 	Serialization(ser::Error),
@@ -234,6 +240,10 @@ pub struct P2PConfig {
 	pub peer_min_preferred_count: Option<u32>,
 
 	pub dandelion_peer: Option<PeerAddr>,
+
+	/// Max number of txhashset archives we upload to syncing peers at the
+	/// same time
+	pub txhashset_max_uploads: Option<usize>,
 }
 
 /// Default address for peer-to-peer connections.
@@ -253,6 +263,7 @@ impl Default for P2PConfig {
 			peer_max_count: None,
 			peer_min_preferred_count: None,
 			dandelion_peer: None,
+			txhashset_max_uploads: None,
 		}
 	}
 }
@@ -283,6 +294,14 @@ impl P2PConfig {
 			None => PEER_MIN_PREFERRED_COUNT,
 		}
 	}
+
+	/// return txhashset_max_uploads
+	pub fn txhashset_max_uploads(&self) -> usize {
+		match self.txhashset_max_uploads {
+			Some(n) => n,
+			None => TXHASHSET_MAX_UPLOADS,
+		}
+	}
 }
 
 /// Type of seeding the server will use to find other peers on the network.
@@ -320,6 +339,20 @@ bitflags! {
 		const PEER_LIST = 0b00000100;
 		/// Can broadcast and request txs by kernel hash.
 		const TX_KERNEL_HASH = 0b00001000;
+		/// Can provide segments of the TxHashSet for some recent-enough
+		/// height (PIBD).
+		const PIBD_HIST = 0b00010000;
+		/// Can resend a txhashset archive from a given byte offset, to
+		/// resume an interrupted download.
+		const TXHASHSET_RANGE = 0b00100000;
+		/// Can provide merkle proofs of unspent outputs against the output
+		/// root of its head, for header-only nodes.
+		const OUTPUT_PROOFS = 0b01000000;
+		/// Can provide the compact filters of blocks, for wallets.
+		const BLOCK_FILTERS = 0b10000000;
+		/// Can relay txs in compact form, with short_ids for the inputs and
+		/// outputs we likely already know about.
+		const COMPACT_TX = 0b100000000;
 
 		/// All nodes right now are "full nodes".
 		/// Some nodes internally may maintain longer block histories (archival_mode)
@@ -328,7 +361,12 @@ bitflags! {
 		const FULL_NODE = Capabilities::HEADER_HIST.bits
 			| Capabilities::TXHASHSET_HIST.bits
 			| Capabilities::PEER_LIST.bits
-			| Capabilities::TX_KERNEL_HASH.bits;
+			| Capabilities::TX_KERNEL_HASH.bits
+			| Capabilities::PIBD_HIST.bits
+			| Capabilities::TXHASHSET_RANGE.bits
+			| Capabilities::OUTPUT_PROOFS.bits
+			| Capabilities::BLOCK_FILTERS.bits
+			| Capabilities::COMPACT_TX.bits;
 	}
 }
 
@@ -353,6 +391,9 @@ enum_from_primitive! {
 		ManualBan = 5,
 		FraudHeight = 6,
 		BadHandshake = 7,
+		BadSegment = 8,
+		BadOutputProof = 9,
+		BadBlockFilter = 10,
 	}
 }
 
@@ -370,7 +411,7 @@ pub struct PeerLiveInfo {
 pub struct PeerInfo {
 	pub capabilities: Capabilities,
 	pub user_agent: String,
-	pub version: u32,
+	pub version: ProtocolVersion,
 	pub addr: PeerAddr,
 	pub direction: Direction,
 	pub live_info: Arc<RwLock<PeerLiveInfo>>,
@@ -432,7 +473,7 @@ impl PeerInfo {
 pub struct PeerInfoDisplay {
 	pub capabilities: Capabilities,
 	pub user_agent: String,
-	pub version: u32,
+	pub version: ProtocolVersion,
 	pub addr: PeerAddr,
 	pub direction: Direction,
 	pub total_difficulty: Difficulty,
@@ -444,7 +485,7 @@ impl From<PeerInfo> for PeerInfoDisplay {
 		PeerInfoDisplay {
 			capabilities: info.capabilities.clone(),
 			user_agent: info.user_agent.clone(),
-			version: info.version.clone(),
+			version: info.version,
 			addr: info.addr.clone(),
 			direction: info.direction.clone(),
 			total_difficulty: info.total_difficulty(),
@@ -462,6 +503,178 @@ pub struct TxHashSetRead {
 	pub kernel_index: u64,
 	/// Binary stream for the txhashset zipped data
 	pub reader: File,
+	/// Upload slot taken until the data is sent, if uploads are capped
+	pub upload: Option<TxHashSetUpload>,
+}
+
+/// Keeps count of the txhashset archives we're uploading to our peers, to
+/// cap how many we upload at the same time.
+#[derive(Clone)]
+pub struct TxHashSetUploads {
+	count: Arc<Mutex<usize>>,
+	max: usize,
+}
+
+impl TxHashSetUploads {
+	/// New count, allowing up to max uploads at the same time.
+	pub fn new(max: usize) -> TxHashSetUploads {
+		TxHashSetUploads {
+			count: Arc::new(Mutex::new(0)),
+			max,
+		}
+	}
+
+	/// Takes an upload slot, if any is left.
+	pub fn acquire(&self) -> Option<TxHashSetUpload> {
+		let mut count = self.count.lock();
+		if *count >= self.max {
+			return None;
+		}
+		*count += 1;
+		Some(TxHashSetUpload {
+			count: self.count.clone(),
+		})
+	}
+
+	/// Number of uploads in progress.
+	pub fn count(&self) -> usize {
+		*self.count.lock()
+	}
+}
+
+/// A txhashset upload slot, released when dropped.
+pub struct TxHashSetUpload {
+	count: Arc<Mutex<usize>>,
+}
+
+impl Drop for TxHashSetUpload {
+	fn drop(&mut self) {
+		*self.count.lock() -= 1;
+	}
+}
+
+const TXHASHSET_PARTIAL_FILE: &'static str = "txhashset_partial.zip";
+const TXHASHSET_PARTIAL_META_FILE: &'static str = "txhashset_partial.meta";
+
+/// A txhashset archive download, persisted in the tmp dir along with the
+/// archive bytes received so far, so an interrupted download can be resumed
+/// from where it stopped instead of starting over.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TxHashSetPartial {
+	/// Hash of the block the archive is for
+	pub hash: Hash,
+	/// Height of the block the archive is for
+	pub height: u64,
+	/// Size in bytes of the complete archive
+	pub total_size: u64,
+	/// Peer we're downloading the archive from, archives produced by
+	/// different peers can't be spliced together
+	pub peer_addr: PeerAddr,
+	/// Hash of the complete archive, `ZERO_HASH` as long as the peer hasn't
+	/// told us (only ranges carry it)
+	pub archive_hash: Hash,
+}
+
+impl Writeable for TxHashSetPartial {
+	fn write<W: Writer>(&self, writer: &mut W) -> Result<(), ser::Error> {
+		self.hash.write(writer)?;
+		writer.write_u64(self.height)?;
+		writer.write_u64(self.total_size)?;
+		self.peer_addr.write(writer)?;
+		self.archive_hash.write(writer)
+	}
+}
+
+impl Readable for TxHashSetPartial {
+	fn read(reader: &mut dyn Reader) -> Result<TxHashSetPartial, ser::Error> {
+		Ok(TxHashSetPartial {
+			hash: Hash::read(reader)?,
+			height: reader.read_u64()?,
+			total_size: reader.read_u64()?,
+			peer_addr: PeerAddr::read(reader)?,
+			archive_hash: Hash::read(reader)?,
+		})
+	}
+}
+
+impl TxHashSetPartial {
+	/// Path of the (partial) archive in the provided tmp dir.
+	pub fn archive_path(tmp_dir: &Path) -> PathBuf {
+		tmp_dir.join(TXHASHSET_PARTIAL_FILE)
+	}
+
+	/// Download in progress persisted in the provided tmp dir, if any, along
+	/// with the number of archive bytes received so far.
+	pub fn load(tmp_dir: &Path) -> Option<(TxHashSetPartial, u64)> {
+		let meta = fs::read(tmp_dir.join(TXHASHSET_PARTIAL_META_FILE)).ok()?;
+		let partial: TxHashSetPartial = ser::deserialize_default(&mut &meta[..]).ok()?;
+		let received = fs::metadata(TxHashSetPartial::archive_path(tmp_dir))
+			.ok()?
+			.len();
+		if received > partial.total_size {
+			return None;
+		}
+		Some((partial, received))
+	}
+
+	/// Whether a range described by the provided partial can be appended to
+	/// this download: same block and archive size from the same peer, and
+	/// the same archive as far as we know its hash.
+	pub fn continued_by(&self, other: &TxHashSetPartial) -> bool {
+		self.hash == other.hash
+			&& self.height == other.height
+			&& self.total_size == other.total_size
+			&& self.peer_addr == other.peer_addr
+			&& (self.archive_hash == ZERO_HASH || self.archive_hash == other.archive_hash)
+	}
+
+	/// Persists this download in the provided tmp dir, starting over with an
+	/// empty archive. Returns the archive file, to append to.
+	pub fn start(&self, tmp_dir: &Path) -> io::Result<File> {
+		fs::create_dir_all(tmp_dir)?;
+		self.save(tmp_dir)?;
+		File::create(TxHashSetPartial::archive_path(tmp_dir))
+	}
+
+	/// Persists the metadata of this download in the provided tmp dir,
+	/// leaving the archive received so far untouched.
+	pub fn save(&self, tmp_dir: &Path) -> io::Result<()> {
+		let meta = ser::ser_vec(self, ProtocolVersion::local())
+			.map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("{:?}", e)))?;
+		fs::write(tmp_dir.join(TXHASHSET_PARTIAL_META_FILE), meta)
+	}
+
+	/// Opens the archive of the download in progress in the provided tmp
+	/// dir, to append the bytes following the ones received so far.
+	pub fn resume(tmp_dir: &Path) -> io::Result<File> {
+		OpenOptions::new()
+			.append(true)
+			.open(TxHashSetPartial::archive_path(tmp_dir))
+	}
+
+	/// Removes any download persisted in the provided tmp dir.
+	pub fn clear(tmp_dir: &Path) {
+		let _ = fs::remove_file(tmp_dir.join(TXHASHSET_PARTIAL_META_FILE));
+		let _ = fs::remove_file(TxHashSetPartial::archive_path(tmp_dir));
+	}
+}
+
+/// Hash of the whole txhashset archive in the provided file, read from its
+/// start. The file position is left at its end.
+pub fn txhashset_archive_hash(file: &mut File) -> io::Result<Hash> {
+	file.seek(SeekFrom::Start(0))?;
+	let mut hasher = HashWriter::default();
+	let mut buf = vec![0; 48_000];
+	loop {
+		let n = file.read(&mut buf)?;
+		if n == 0 {
+			break;
+		}
+		hasher
+			.write_fixed_bytes(&&buf[..n])
+			.map_err(|e| io::Error::new(io::ErrorKind::Other, format!("{:?}", e)))?;
+	}
+	Ok(hasher.into_hash())
 }
 
 /// Bridge between the networking layer and the rest of the system. Handles the
@@ -486,6 +699,20 @@ pub trait ChainAdapter: Sync + Send {
 		peer_info: &PeerInfo,
 	) -> Result<bool, chain::Error>;
 
+	/// Compact representation of the tx with the provided kernel hash, if
+	/// we have it, with short_ids for the inputs and outputs our peers
+	/// likely know about.
+	fn get_compact_transaction(&self, kernel_hash: Hash) -> Option<core::CompactTransaction>;
+
+	/// A compact transaction has been received from one of our peers. It
+	/// gets hydrated from our pool, or the full transaction requested if we
+	/// don't know about all its inputs and outputs.
+	fn compact_transaction_received(
+		&self,
+		ct: core::CompactTransaction,
+		peer_info: &PeerInfo,
+	) -> Result<bool, chain::Error>;
+
 	/// A block has been received from one of our peers. Returns true if the
 	/// block could be handled properly and is not deemed defective by the
 	/// chain. Returning false means the block will never be valid and
@@ -562,6 +789,85 @@ pub trait ChainAdapter: Sync + Send {
 	/// Get a tmp file path in above specific tmp dir (create tmp dir if not exist)
 	/// Delete file if tmp file already exists
 	fn get_tmpfile_pathname(&self, tmpfile_name: String) -> PathBuf;
+
+	/// Segment of the output bitmap (unspent outputs) at the provided block,
+	/// if we can provide it (PIBD).
+	fn get_output_bitmap_segment(&self, h: Hash, id: SegmentIdentifier) -> Option<BitmapSegment>;
+
+	/// Segment of the output MMR at the provided block, if we can provide it.
+	fn get_output_segment(
+		&self,
+		h: Hash,
+		id: SegmentIdentifier,
+	) -> Option<Segment<OutputIdentifier>>;
+
+	/// Segment of the rangeproof MMR at the provided block, if we can provide
+	/// it.
+	fn get_rangeproof_segment(&self, h: Hash, id: SegmentIdentifier)
+		-> Option<Segment<RangeProof>>;
+
+	/// Segment of the kernel MMR at the provided block, if we can provide it.
+	fn get_kernel_segment(&self, h: Hash, id: SegmentIdentifier) -> Option<Segment<TxKernelEntry>>;
+
+	/// A segment of the output bitmap has been received. Returns false if the
+	/// segment is invalid.
+	fn receive_output_bitmap_segment(
+		&self,
+		h: Hash,
+		segment: BitmapSegment,
+		peer_info: &PeerInfo,
+	) -> Result<bool, chain::Error>;
+
+	/// A segment of the output MMR has been received. Returns false if the
+	/// segment is invalid.
+	fn receive_output_segment(
+		&self,
+		h: Hash,
+		segment: Segment<OutputIdentifier>,
+		peer_info: &PeerInfo,
+	) -> Result<bool, chain::Error>;
+
+	/// A segment of the rangeproof MMR has been received. Returns false if the
+	/// segment is invalid.
+	fn receive_rangeproof_segment(
+		&self,
+		h: Hash,
+		segment: Segment<RangeProof>,
+		peer_info: &PeerInfo,
+	) -> Result<bool, chain::Error>;
+
+	/// A segment of the kernel MMR has been received. Returns false if the
+	/// segment is invalid.
+	fn receive_kernel_segment(
+		&self,
+		h: Hash,
+		segment: Segment<TxKernelEntry>,
+		peer_info: &PeerInfo,
+	) -> Result<bool, chain::Error>;
+
+	/// Merkle proof of the unspent output with the provided commitment
+	/// against our head, if we can provide it.
+	fn get_output_proof(&self, commit: Commitment) -> Option<chain::OutputProof>;
+
+	/// An output proof has been received. Returns false if the proof is
+	/// invalid.
+	fn receive_output_proof(
+		&self,
+		proof: &chain::OutputProof,
+		peer_info: &PeerInfo,
+	) -> Result<bool, chain::Error>;
+
+	/// Compact filter of the block with the provided hash, if we can
+	/// provide it.
+	fn get_block_filter(&self, h: Hash) -> Option<core::block_filter::BlockFilter>;
+
+	/// A block filter has been received. Returns false if the filter is
+	/// known to be wrong.
+	fn receive_block_filter(
+		&self,
+		filter: &core::block_filter::BlockFilter,
+		peer_info: &PeerInfo,
+	) -> Result<bool, chain::Error>;
 }
 
 /// Additional methods required by the protocol that don't need to be
@@ -23,7 +23,10 @@ use std::sync::Arc;
 use std::{thread, time};
 
 use crate::core::core::hash::Hash;
+use crate::core::global::{self, ChainParams, ChainTypes};
 use crate::core::pow::Difficulty;
+use crate::core::ser::ProtocolVersion;
+use crate::p2p::msg::{self, Hand, Ping, Pong, Shake, Type};
 use crate::p2p::types::PeerAddr;
 use crate::p2p::Peer;
 
@@ -35,12 +38,8 @@ fn open_port() -> u16 {
 	listener.local_addr().unwrap().port()
 }
 
-// Starts a server and connects a client peer to it to check handshake,
-// followed by a ping/pong exchange to make sure the connection is live.
-#[test]
-fn peer_handshake() {
-	util::init_test_logger();
-
+// Starts a listening server running with the provided chain parameters.
+fn start_server(db_root: &str, params: ChainParams) -> (p2p::P2PConfig, Arc<p2p::Server>) {
 	let p2p_config = p2p::P2PConfig {
 		host: "127.0.0.1".parse().unwrap(),
 		port: open_port(),
@@ -48,14 +47,14 @@ fn peer_handshake() {
 		peers_deny: None,
 		..p2p::P2PConfig::default()
 	};
-	let net_adapter = Arc::new(p2p::DummyAdapter {});
 	let server = Arc::new(
 		p2p::Server::new(
-			".grin",
+			db_root,
 			p2p::Capabilities::UNKNOWN,
 			p2p_config.clone(),
-			net_adapter.clone(),
+			Arc::new(p2p::DummyAdapter {}),
 			Hash::from_vec(&vec![]),
+			params,
 			Arc::new(Mutex::new(StopState::new())),
 		)
 		.unwrap(),
@@ -65,22 +64,42 @@ fn peer_handshake() {
 	let _ = thread::spawn(move || p2p_inner.listen());
 
 	thread::sleep(time::Duration::from_secs(1));
+	(p2p_config, server)
+}
 
+// Connects a client peer to the server, handshaking with the provided chain
+// parameters.
+fn connect(
+	p2p_config: &p2p::P2PConfig,
+	my_addr: PeerAddr,
+	params: ChainParams,
+) -> Result<(Peer, TcpStream), p2p::Error> {
 	let addr = SocketAddr::new(p2p_config.host, p2p_config.port);
 	let mut socket = TcpStream::connect_timeout(&addr, time::Duration::from_secs(10)).unwrap();
-
-	let my_addr = PeerAddr("127.0.0.1:5000".parse().unwrap());
-	let mut peer = Peer::connect(
+	let peer = Peer::connect(
 		&mut socket,
 		p2p::Capabilities::UNKNOWN,
 		Difficulty::min(),
 		my_addr,
-		&p2p::handshake::Handshake::new(Hash::from_vec(&vec![]), p2p_config.clone()),
-		net_adapter,
-	)
-	.unwrap();
+		&p2p::handshake::Handshake::new(Hash::from_vec(&vec![]), p2p_config.clone(), params),
+		Arc::new(p2p::DummyAdapter {}),
+	)?;
+	Ok((peer, socket))
+}
+
+// Starts a server and connects a client peer to it to check handshake,
+// followed by a ping/pong exchange to make sure the connection is live.
+#[test]
+fn peer_handshake() {
+	util::init_test_logger();
+
+	let (p2p_config, server) = start_server(".grin", global::chain_params());
+
+	let my_addr = PeerAddr("127.0.0.1:5000".parse().unwrap());
+	let (mut peer, socket) = connect(&p2p_config, my_addr, global::chain_params()).unwrap();
 
 	assert!(peer.info.user_agent.ends_with(env!("CARGO_PKG_VERSION")));
+	assert_eq!(peer.info.version, ProtocolVersion::local());
 
 	peer.start(socket);
 	thread::sleep(time::Duration::from_secs(1));
@@ -92,3 +111,64 @@ fn peer_handshake() {
 	assert_eq!(server_peer.info.total_difficulty(), Difficulty::min());
 	assert!(server.peers.peer_count() > 0);
 }
+
+// Servers on different chains run side by side in the same process, each
+// only accepting peers of its own chain.
+#[test]
+fn peer_handshake_chain_params() {
+	util::init_test_logger();
+
+	let (floo_config, _floo_server) = start_server(".grin_floo", ChainTypes::Floonet.params());
+	let (main_config, _main_server) = start_server(".grin_main", ChainTypes::Mainnet.params());
+
+	let floo_peer = thread::spawn(move || {
+		let params = ChainTypes::Floonet.params();
+		let my_addr = PeerAddr("127.0.0.1:5001".parse().unwrap());
+		let floo = connect(&floo_config, my_addr, params.clone()).is_ok();
+		let main = connect(&main_config, my_addr, params).is_ok();
+		(floo, main)
+	});
+	assert_eq!(floo_peer.join().unwrap(), (true, false));
+}
+
+// A peer advertising an older protocol version than ours: both sides settle
+// on the older one, which the server then serializes its messages with.
+#[test]
+fn peer_handshake_older_version() {
+	util::init_test_logger();
+
+	let params = global::chain_params();
+	let (p2p_config, server) = start_server(".grin_old_peer", params.clone());
+	let old_version = ProtocolVersion(ProtocolVersion::local().0 - 1);
+
+	// Handshake by hand, as an older node would.
+	let addr = SocketAddr::new(p2p_config.host, p2p_config.port);
+	let mut socket = TcpStream::connect_timeout(&addr, time::Duration::from_secs(10)).unwrap();
+	let my_addr = PeerAddr("127.0.0.1:5002".parse().unwrap());
+	let hand = Hand {
+		version: old_version,
+		capabilities: p2p::Capabilities::UNKNOWN,
+		nonce: 42,
+		genesis: Hash::from_vec(&vec![]),
+		total_difficulty: Difficulty::min(),
+		sender_addr: my_addr,
+		receiver_addr: PeerAddr(addr),
+		user_agent: "MW/Grin old".to_owned(),
+	};
+	msg::write_message(&mut socket, hand, Type::Hand, old_version, &params).unwrap();
+
+	// The server advertises its own version but talks ours.
+	let shake: Shake = msg::read_message(&mut socket, old_version, &params, Type::Shake).unwrap();
+	assert_eq!(shake.version, ProtocolVersion::local());
+	thread::sleep(time::Duration::from_secs(1));
+	let server_peer = server.peers.get_connected_peer(my_addr).unwrap();
+	assert_eq!(server_peer.info.version, old_version);
+
+	let ping = Ping {
+		total_difficulty: Difficulty::min(),
+		height: 0,
+	};
+	msg::write_message(&mut socket, ping, Type::Ping, old_version, &params).unwrap();
+	let pong: Pong = msg::read_message(&mut socket, old_version, &params, Type::Pong).unwrap();
+	assert_eq!(pong.height, 0);
+}
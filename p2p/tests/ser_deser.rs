@@ -12,9 +12,17 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use grin_core as core;
 use grin_p2p as p2p;
 
 use num::FromPrimitive;
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+
+use crate::core::core::hash::{Hash, ZERO_HASH};
+use crate::p2p::types::PeerAddr;
+use crate::p2p::TxHashSetPartial;
 
 // Test that Healthy == 0.
 #[test]
@@ -47,29 +55,59 @@ fn test_capabilities() {
 		p2p::types::Capabilities::UNKNOWN
 	);
 	assert_eq!(
-		p2p::types::Capabilities::from_bits_truncate(0b10000000 as u32),
+		p2p::types::Capabilities::from_bits_truncate(0b1000000000 as u32),
 		p2p::types::Capabilities::UNKNOWN
 	);
 
 	assert_eq!(
-		p2p::types::Capabilities::from_bits_truncate(0b1111 as u32),
+		p2p::types::Capabilities::from_bits_truncate(0b111111111 as u32),
 		p2p::types::Capabilities::FULL_NODE
 	);
 	assert_eq!(
-		p2p::types::Capabilities::from_bits_truncate(0b00001111 as u32),
+		p2p::types::Capabilities::from_bits_truncate(0b0111111111 as u32),
 		p2p::types::Capabilities::FULL_NODE
 	);
 	assert_eq!(
-		p2p::types::Capabilities::from_bits_truncate(0b11111111 as u32),
+		p2p::types::Capabilities::from_bits_truncate(0b1111111111 as u32),
 		p2p::types::Capabilities::FULL_NODE
 	);
-	assert_eq!(
-		p2p::types::Capabilities::from_bits_truncate(0b00101111 as u32),
+	assert_ne!(
+		p2p::types::Capabilities::from_bits_truncate(0b011111111 as u32),
 		p2p::types::Capabilities::FULL_NODE
 	);
 
+	// Nodes not providing txhashset segments.
 	assert!(
-		p2p::types::Capabilities::from_bits_truncate(0b00101111 as u32)
+		!p2p::types::Capabilities::from_bits_truncate(0b00001111 as u32)
+			.contains(p2p::types::Capabilities::PIBD_HIST)
+	);
+
+	// Nodes not serving txhashset archive ranges.
+	assert!(
+		!p2p::types::Capabilities::from_bits_truncate(0b00011111 as u32)
+			.contains(p2p::types::Capabilities::TXHASHSET_RANGE)
+	);
+
+	// Nodes not serving output proofs.
+	assert!(
+		!p2p::types::Capabilities::from_bits_truncate(0b00111111 as u32)
+			.contains(p2p::types::Capabilities::OUTPUT_PROOFS)
+	);
+
+	// Nodes not serving block filters.
+	assert!(
+		!p2p::types::Capabilities::from_bits_truncate(0b01111111 as u32)
+			.contains(p2p::types::Capabilities::BLOCK_FILTERS)
+	);
+
+	// Nodes not relaying compact txs.
+	assert!(
+		!p2p::types::Capabilities::from_bits_truncate(0b011111111 as u32)
+			.contains(p2p::types::Capabilities::COMPACT_TX)
+	);
+
+	assert!(
+		p2p::types::Capabilities::from_bits_truncate(0b111111111 as u32)
 			.contains(p2p::types::Capabilities::FULL_NODE)
 	);
 
@@ -78,3 +116,55 @@ fn test_capabilities() {
 			.contains(p2p::types::Capabilities::TX_KERNEL_HASH)
 	);
 }
+
+#[test]
+fn test_txhashset_partial() {
+	let tmp_dir = Path::new(".grin_txhashset_partial");
+	let _ = fs::remove_dir_all(tmp_dir);
+	assert_eq!(TxHashSetPartial::load(tmp_dir), None);
+
+	let partial = TxHashSetPartial {
+		hash: Hash::from_vec(&[1, 2, 3]),
+		height: 1440,
+		total_size: 10,
+		peer_addr: PeerAddr("127.0.0.1:3414".parse().unwrap()),
+		archive_hash: ZERO_HASH,
+	};
+	partial.start(tmp_dir).unwrap().write_all(&[0; 4]).unwrap();
+	assert_eq!(TxHashSetPartial::load(tmp_dir), Some((partial.clone(), 4)));
+
+	// Until we know its hash, any range of the same archive size for the
+	// same block from the same peer continues the download. Once known,
+	// only ranges of that very archive do.
+	let mut range = partial.clone();
+	range.archive_hash = Hash::from_vec(&[4, 5, 6]);
+	assert!(partial.continued_by(&range));
+	range.save(tmp_dir).unwrap();
+	let (partial, received) = TxHashSetPartial::load(tmp_dir).unwrap();
+	assert_eq!((partial.archive_hash, received), (range.archive_hash, 4));
+	assert!(partial.continued_by(&range));
+	let mut other = range.clone();
+	other.archive_hash = Hash::from_vec(&[7, 8, 9]);
+	assert!(!partial.continued_by(&other));
+	let mut other = range.clone();
+	other.total_size = 11;
+	assert!(!partial.continued_by(&other));
+
+	// Resuming appends to what was received so far.
+	TxHashSetPartial::resume(tmp_dir)
+		.unwrap()
+		.write_all(&[0; 6])
+		.unwrap();
+	assert_eq!(TxHashSetPartial::load(tmp_dir), Some((partial.clone(), 10)));
+
+	// More than announced isn't a download we can resume.
+	TxHashSetPartial::resume(tmp_dir)
+		.unwrap()
+		.write_all(&[0])
+		.unwrap();
+	assert_eq!(TxHashSetPartial::load(tmp_dir), None);
+
+	TxHashSetPartial::clear(tmp_dir);
+	assert_eq!(TxHashSetPartial::load(tmp_dir), None);
+	let _ = fs::remove_dir_all(tmp_dir);
+}
@@ -20,8 +20,9 @@ use self::core::core::id::{ShortId, ShortIdentifiable};
 use self::core::core::transaction;
 use self::core::core::verifier_cache::VerifierCache;
 use self::core::core::{
-	Block, BlockHeader, BlockSums, Committed, Transaction, TxKernel, Weighting,
+	Block, BlockHeader, BlockSums, Committed, CompactTransaction, Transaction, TxKernel, Weighting,
 };
+use self::core::global::ChainParams;
 use self::util::RwLock;
 use crate::types::{BlockChain, PoolEntry, PoolError};
 use grin_core as core;
@@ -37,6 +38,8 @@ pub struct Pool {
 	pub blockchain: Arc<dyn BlockChain>,
 	pub verifier_cache: Arc<RwLock<dyn VerifierCache>>,
 	pub name: String,
+	/// The parameters of the chain the pool is for
+	pub params: ChainParams,
 }
 
 impl Pool {
@@ -44,12 +47,14 @@ impl Pool {
 		chain: Arc<dyn BlockChain>,
 		verifier_cache: Arc<RwLock<dyn VerifierCache>>,
 		name: String,
+		params: ChainParams,
 	) -> Pool {
 		Pool {
 			entries: vec![],
 			blockchain: chain,
 			verifier_cache,
 			name,
+			params,
 		}
 	}
 
@@ -115,6 +120,52 @@ impl Pool {
 		)
 	}
 
+	/// Query the tx pool for all txs sharing inputs or outputs with the
+	/// provided compact_transaction, based on its inp_ids and out_ids. An
+	/// input also matches the output it spends.
+	/// Note: does not validate that we return the full set of required txs.
+	/// The caller will need to validate that themselves.
+	pub fn retrieve_compact_tx_elements(
+		&self,
+		ct: &CompactTransaction,
+	) -> (Vec<Transaction>, Vec<ShortId>) {
+		let hash = ct.hash();
+		let mut txs = vec![];
+		let mut found_ids = HashSet::new();
+
+		// Rehash all inputs and outputs in the pool using short_ids based on the
+		// compact transaction hash and nonce.
+		for x in &self.entries {
+			let mut found = false;
+			for inp in x.tx.inputs() {
+				let short_id = inp.short_id(&hash, ct.nonce);
+				if ct.inp_ids().contains(&short_id) {
+					found_ids.insert(short_id);
+					found = true;
+				}
+			}
+			for out in x.tx.outputs() {
+				let short_id = out.short_id(&hash, ct.nonce);
+				if ct.inp_ids().contains(&short_id) || ct.out_ids().contains(&short_id) {
+					found_ids.insert(short_id);
+					found = true;
+				}
+			}
+			if found {
+				txs.push(x.tx.clone());
+			}
+		}
+		(
+			txs,
+			ct.inp_ids()
+				.iter()
+				.chain(ct.out_ids())
+				.filter(|id| !found_ids.contains(id))
+				.cloned()
+				.collect(),
+		)
+	}
+
 	/// Take pool transactions, filtering and ordering them in a way that's
 	/// appropriate to put in a mined block. Aggregates chains of dependent
 	/// transactions, orders by fee over weight and ensures the total weight
@@ -155,7 +206,11 @@ impl Pool {
 		let tx = transaction::aggregate(txs)?;
 
 		// Validate the single aggregate transaction "as pool", not subject to tx weight limits.
-		tx.validate(Weighting::NoLimit, self.verifier_cache.clone())?;
+		tx.validate_with_params(
+			&self.params,
+			Weighting::NoLimit,
+			self.verifier_cache.clone(),
+		)?;
 
 		Ok(Some(tx))
 	}
@@ -222,7 +277,7 @@ impl Pool {
 	) -> Result<BlockSums, PoolError> {
 		// Validate the tx, conditionally checking against weight limits,
 		// based on weight verification type.
-		tx.validate(weighting, self.verifier_cache.clone())?;
+		tx.validate_with_params(&self.params, weighting, self.verifier_cache.clone())?;
 
 		// Validate the tx against current chain state.
 		// Check all inputs are in the current UTXO set.
@@ -365,6 +420,7 @@ impl Pool {
 
 					if let Ok(new_bucket) = bucket.aggregate_with_tx(
 						entry.tx.clone(),
+						&self.params,
 						weighting,
 						self.verifier_cache.clone(),
 					) {
@@ -467,13 +523,14 @@ impl Bucket {
 	fn aggregate_with_tx(
 		&self,
 		new_tx: Transaction,
+		params: &ChainParams,
 		weighting: Weighting,
 		verifier_cache: Arc<RwLock<dyn VerifierCache>>,
 	) -> Result<Bucket, PoolError> {
 		let mut raw_txs = self.raw_txs.clone();
 		raw_txs.push(new_tx);
 		let agg_tx = transaction::aggregate(raw_txs.clone())?;
-		agg_tx.validate(weighting, verifier_cache)?;
+		agg_tx.validate_with_params(params, weighting, verifier_cache)?;
 		Ok(Bucket {
 			fee_to_weight: agg_tx.fee_to_weight(),
 			raw_txs: raw_txs,
@@ -20,13 +20,17 @@
 use self::core::core::hash::{Hash, Hashed};
 use self::core::core::id::ShortId;
 use self::core::core::verifier_cache::VerifierCache;
-use self::core::core::{transaction, Block, BlockHeader, Transaction, Weighting};
+use self::core::core::{
+	transaction, Block, BlockHeader, CompactTransaction, Transaction, Weighting,
+};
+use self::core::global::ChainParams;
 use self::util::RwLock;
 use crate::pool::Pool;
 use crate::types::{BlockChain, PoolAdapter, PoolConfig, PoolEntry, PoolError, TxSource};
 use chrono::prelude::*;
 use grin_core as core;
 use grin_util as util;
+use std::cmp::min;
 use std::collections::VecDeque;
 use std::sync::Arc;
 
@@ -45,6 +49,8 @@ pub struct TransactionPool {
 	pub verifier_cache: Arc<RwLock<dyn VerifierCache>>,
 	/// The pool adapter
 	pub adapter: Arc<dyn PoolAdapter>,
+	/// The parameters of the chain the pool is for
+	pub params: ChainParams,
 }
 
 impl TransactionPool {
@@ -54,19 +60,27 @@ impl TransactionPool {
 		chain: Arc<dyn BlockChain>,
 		verifier_cache: Arc<RwLock<dyn VerifierCache>>,
 		adapter: Arc<dyn PoolAdapter>,
+		params: ChainParams,
 	) -> TransactionPool {
 		TransactionPool {
 			config,
-			txpool: Pool::new(chain.clone(), verifier_cache.clone(), "txpool".to_string()),
+			txpool: Pool::new(
+				chain.clone(),
+				verifier_cache.clone(),
+				"txpool".to_string(),
+				params.clone(),
+			),
 			stempool: Pool::new(
 				chain.clone(),
 				verifier_cache.clone(),
 				"stempool".to_string(),
+				params.clone(),
 			),
 			reorg_cache: Arc::new(RwLock::new(VecDeque::new())),
 			blockchain: chain,
 			verifier_cache,
 			adapter,
+			params,
 		}
 	}
 
@@ -105,7 +119,11 @@ impl TransactionPool {
 				let tx = transaction::deaggregate(entry.tx, txs)?;
 
 				// Validate this deaggregated tx "as tx", subject to regular tx weight limits.
-				tx.validate(Weighting::AsTransaction, self.verifier_cache.clone())?;
+				tx.validate_with_params(
+					&self.params,
+					Weighting::AsTransaction,
+					self.verifier_cache.clone(),
+				)?;
 
 				entry.tx = tx;
 				entry.src.debug_name = "deagg".to_string();
@@ -142,8 +160,12 @@ impl TransactionPool {
 
 		// Make sure the transaction is valid before anything else.
 		// Validate tx accounting for max tx weight.
-		tx.validate(Weighting::AsTransaction, self.verifier_cache.clone())
-			.map_err(PoolError::InvalidTx)?;
+		tx.validate_with_params(
+			&self.params,
+			Weighting::AsTransaction,
+			self.verifier_cache.clone(),
+		)
+		.map_err(PoolError::InvalidTx)?;
 
 		// Check the tx lock_time is valid based on current chain state.
 		self.blockchain.verify_tx_lock_height(&tx)?;
@@ -236,6 +258,32 @@ impl TransactionPool {
 		self.txpool.retrieve_transactions(hash, nonce, kern_ids)
 	}
 
+	/// Build the compact representation of a transaction for relay. Its inputs
+	/// and outputs also found in the other txs of the txpool are likely known
+	/// to our peers as well (these were broadcast already), so are only sent
+	/// as short_ids.
+	pub fn compact_transaction(&self, tx: Transaction) -> CompactTransaction {
+		let tx_hash = tx.hash();
+		let known = self
+			.txpool
+			.entries
+			.iter()
+			.filter(|x| x.tx.hash() != tx_hash)
+			.map(|x| x.tx.clone())
+			.collect::<Vec<_>>();
+		CompactTransaction::new(tx, &known)
+	}
+
+	/// Retrieve all transactions needed to hydrate the provided "compact
+	/// transaction" based on its input and output short_ids.
+	/// Note: we only look in the txpool for this (stempool is under embargo).
+	pub fn retrieve_compact_tx_elements(
+		&self,
+		ct: &CompactTransaction,
+	) -> (Vec<Transaction>, Vec<ShortId>) {
+		self.txpool.retrieve_compact_tx_elements(ct)
+	}
+
 	/// Whether the transaction is acceptable to the pool, given both how
 	/// full the pool is and the transaction weight.
 	fn is_acceptable(&self, tx: &Transaction, stem: bool) -> Result<(), PoolError> {
@@ -271,9 +319,12 @@ impl TransactionPool {
 	}
 
 	/// Returns a vector of transactions from the txpool so we can build a
-	/// block from them.
+	/// block from them, never heavier than a block of our chain allows.
 	pub fn prepare_mineable_transactions(&self) -> Result<Vec<Transaction>, PoolError> {
-		self.txpool
-			.prepare_mineable_transactions(self.config.mineable_max_weight)
+		let max_weight = match self.config.mineable_max_weight {
+			Some(max_weight) => min(max_weight, self.params.max_block_weight),
+			None => self.params.max_block_weight,
+		};
+		self.txpool.prepare_mineable_transactions(max_weight)
 	}
 }
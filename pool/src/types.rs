@@ -17,12 +17,12 @@
 
 use chrono::prelude::{DateTime, Utc};
 
+use self::core::consensus;
 use self::core::core::block;
 use self::core::core::committed;
 use self::core::core::hash::Hash;
 use self::core::core::transaction::{self, Transaction};
 use self::core::core::{BlockHeader, BlockSums};
-use self::core::{consensus, global};
 use failure::Fail;
 use grin_core as core;
 use grin_keychain as keychain;
@@ -104,9 +104,9 @@ pub struct PoolConfig {
 
 	/// Maximum total weight of transactions that can get selected to build a
 	/// block from. Allows miners to restrict the maximum weight of their
-	/// blocks.
+	/// blocks, defaults to the max block weight of the chain.
 	#[serde = "default_mineable_max_weight"]
-	pub mineable_max_weight: usize,
+	pub mineable_max_weight: Option<usize>,
 }
 
 impl Default for PoolConfig {
@@ -129,8 +129,8 @@ fn default_max_pool_size() -> usize {
 fn default_max_stempool_size() -> usize {
 	50_000
 }
-fn default_mineable_max_weight() -> usize {
-	global::max_block_weight()
+fn default_mineable_max_weight() -> Option<usize> {
+	None
 }
 
 /// Represents a single entry in the pool.
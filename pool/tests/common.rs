@@ -19,6 +19,7 @@ use self::chain::types::Tip;
 use self::core::core::hash::{Hash, Hashed};
 use self::core::core::verifier_cache::VerifierCache;
 use self::core::core::{Block, BlockHeader, BlockSums, Committed, Transaction};
+use self::core::global;
 use self::core::libtx;
 use self::keychain::{ExtKeychain, Keychain};
 use self::pool::types::*;
@@ -43,7 +44,7 @@ pub struct ChainAdapter {
 impl ChainAdapter {
 	pub fn init(db_root: String) -> Result<ChainAdapter, String> {
 		let target_dir = format!("target/{}", db_root);
-		let chain_store = ChainStore::new(&target_dir)
+		let chain_store = ChainStore::new(&target_dir, global::chain_params())
 			.map_err(|e| format!("failed to init chain_store, {:?}", e))?;
 		let store = Arc::new(RwLock::new(chain_store));
 		let utxo = Arc::new(RwLock::new(HashSet::new()));
@@ -157,11 +158,12 @@ pub fn test_setup(
 			accept_fee_base: 0,
 			max_pool_size: 50,
 			max_stempool_size: 50,
-			mineable_max_weight: 10_000,
+			mineable_max_weight: Some(10_000),
 		},
 		chain.clone(),
 		verifier_cache.clone(),
 		Arc::new(NoopAdapter {}),
+		global::chain_params(),
 	)
 }
 
@@ -0,0 +1,117 @@
+// Copyright 2018 The Grin Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+pub mod common;
+
+use self::core::core::hash::Hashed;
+use self::core::core::verifier_cache::LruVerifierCache;
+use self::core::core::{Block, BlockHeader, Transaction};
+use self::core::libtx;
+use self::core::pow::Difficulty;
+use self::keychain::{ExtKeychain, Keychain};
+use self::util::RwLock;
+use crate::common::*;
+use grin_core as core;
+use grin_keychain as keychain;
+use grin_util as util;
+use std::sync::Arc;
+
+#[test]
+fn test_compact_transaction_hydration() {
+	util::init_test_logger();
+	let keychain: ExtKeychain = Keychain::from_random_seed(false).unwrap();
+
+	let db_root = ".grin_compact_transaction".to_string();
+	clean_output_dir(db_root.clone());
+
+	{
+		let mut chain = ChainAdapter::init(db_root.clone()).unwrap();
+
+		let verifier_cache = Arc::new(RwLock::new(LruVerifierCache::new()));
+
+		let add_block =
+			|prev_header: BlockHeader, txs: Vec<Transaction>, chain: &mut ChainAdapter| {
+				let height = prev_header.height + 1;
+				let key_id = ExtKeychain::derive_key_id(1, height as u32, 0, 0, 0);
+				let fee = txs.iter().map(|x| x.fee()).sum();
+				let reward = libtx::reward::output(&keychain, &key_id, fee, false).unwrap();
+				let mut block = Block::new(&prev_header, txs, Difficulty::min(), reward).unwrap();
+
+				// Set the prev_root to the prev hash for testing purposes (no MMR to obtain a root from).
+				block.header.prev_root = prev_header.hash();
+
+				chain.update_db_for_block(&block);
+				block
+			};
+
+		let block = add_block(BlockHeader::default(), vec![], &mut chain);
+		let header = block.header;
+
+		// Now create tx to spend that first coinbase (now matured).
+		// Provides us with some useful outputs to test with.
+		let initial_tx = test_transaction_spending_coinbase(&keychain, &header, vec![10, 20, 30]);
+
+		// Mine that initial tx so we can spend it with multiple txs
+		let block = add_block(header, vec![initial_tx], &mut chain);
+		let header = block.header;
+
+		let pool = RwLock::new(test_setup(Arc::new(chain.clone()), verifier_cache.clone()));
+		let empty_pool = RwLock::new(test_setup(Arc::new(chain.clone()), verifier_cache));
+
+		let root_tx = test_transaction(&keychain, vec![10, 20], vec![24]);
+		let other_tx = test_transaction(&keychain, vec![30], vec![28]);
+		let child_tx = test_transaction(&keychain, vec![24], vec![22]);
+
+		{
+			let mut write_pool = pool.write();
+			write_pool
+				.add_to_pool(test_source(), root_tx.clone(), false, &header)
+				.unwrap();
+			write_pool
+				.add_to_pool(test_source(), other_tx.clone(), false, &header)
+				.unwrap();
+		}
+
+		// The input of the child tx spends an output of a tx in the pool,
+		// only its short_id gets relayed.
+		let ct = pool.read().compact_transaction(child_tx.clone());
+		assert!(ct.inp_full().is_empty());
+		assert_eq!(ct.inp_ids().len(), 1);
+		assert_eq!(ct.out_full(), child_tx.outputs());
+		assert!(ct.out_ids().is_empty());
+
+		// A tx already in the pool isn't "known" from itself.
+		let root_ct = pool.read().compact_transaction(root_tx.clone());
+		assert_eq!(root_ct.inp_full(), root_tx.inputs());
+		assert_eq!(root_ct.out_full(), root_tx.outputs());
+
+		// We can hydrate it from the pool, only the parent tx is needed.
+		let (txs, missing) = pool.read().retrieve_compact_tx_elements(&ct);
+		assert!(missing.is_empty());
+		assert_eq!(txs, vec![root_tx.clone()]);
+		let tx = Transaction::hydrate_from(ct.clone(), txs).unwrap();
+		assert_eq!(tx, child_tx);
+		pool.write()
+			.add_to_pool(test_source(), tx, false, &header)
+			.unwrap();
+		assert_eq!(pool.read().total_size(), 3);
+
+		// But not from an empty pool, the input short_id is unknown.
+		let (txs, missing) = empty_pool.read().retrieve_compact_tx_elements(&ct);
+		assert!(txs.is_empty());
+		assert_eq!(missing, ct.inp_ids().clone());
+	}
+	// Cleanup db directory
+	clean_output_dir(db_root.clone());
+}
@@ -28,15 +28,17 @@ use crate::common::types::{
 	self, ChainValidationMode, DandelionEpoch, ServerConfig, SyncState, SyncStatus,
 };
 use crate::core::core::hash::{Hash, Hashed};
+use crate::core::core::pmmr::{BitmapSegment, Segment, SegmentIdentifier};
 use crate::core::core::transaction::Transaction;
 use crate::core::core::verifier_cache::VerifierCache;
-use crate::core::core::{BlockHeader, BlockSums, CompactBlock};
+use crate::core::core::{BlockHeader, BlockSums, CompactBlock, OutputIdentifier, TxKernelEntry};
 use crate::core::pow::Difficulty;
 use crate::core::{core, global};
 use crate::p2p;
 use crate::p2p::types::PeerInfo;
 use crate::pool;
 use crate::pool::types::DandelionConfig;
+use crate::util::secp::pedersen::{Commitment, RangeProof};
 use crate::util::OneTime;
 use chrono::prelude::*;
 use chrono::Duration;
@@ -73,26 +75,93 @@ impl p2p::ChainAdapter for NetToChainAdapter {
 		kernel_hash: Hash,
 		peer_info: &PeerInfo,
 	) -> Result<bool, chain::Error> {
-		// nothing much we can do with a new transaction while syncing
-		if self.sync_state.is_syncing() {
+		// nothing much we can do with a new transaction while syncing, or
+		// without a txhashset to validate it against
+		if self.sync_state.is_syncing() || self.header_only() {
 			return Ok(true);
 		}
 
 		let tx = self.tx_pool.read().retrieve_tx_by_kernel_hash(kernel_hash);
 
 		if tx.is_none() {
-			self.request_transaction(kernel_hash, peer_info);
+			if peer_info
+				.capabilities
+				.contains(p2p::Capabilities::COMPACT_TX)
+			{
+				self.request_compact_transaction(kernel_hash, peer_info);
+			} else {
+				self.request_transaction(kernel_hash, peer_info);
+			}
 		}
 		Ok(true)
 	}
 
+	fn get_compact_transaction(&self, kernel_hash: Hash) -> Option<core::CompactTransaction> {
+		let tx_pool = self.tx_pool.read();
+		tx_pool
+			.retrieve_tx_by_kernel_hash(kernel_hash)
+			.map(|tx| tx_pool.compact_transaction(tx))
+	}
+
+	fn compact_transaction_received(
+		&self,
+		ct: core::CompactTransaction,
+		peer_info: &PeerInfo,
+	) -> Result<bool, chain::Error> {
+		// nothing much we can do with a new transaction while syncing, or
+		// without a txhashset to validate it against
+		if self.sync_state.is_syncing() || self.header_only() {
+			return Ok(true);
+		}
+
+		let kernel_hash = ct.kern_full()[0].hash();
+		debug!(
+			"Received compact tx {} from {} [in/out/kern/in_ids/out_ids: {}/{}/{}/{}/{}]",
+			kernel_hash,
+			peer_info.addr,
+			ct.inp_full().len(),
+			ct.out_full().len(),
+			ct.kern_full().len(),
+			ct.inp_ids().len(),
+			ct.out_ids().len(),
+		);
+
+		let (txs, missing_short_ids) = self.tx_pool.read().retrieve_compact_tx_elements(&ct);
+		if !missing_short_ids.is_empty() {
+			debug!(
+				"adapter: compact tx {} has {} unknown short_ids, requesting full tx",
+				kernel_hash,
+				missing_short_ids.len(),
+			);
+			self.request_transaction(kernel_hash, peer_info);
+			return Ok(true);
+		}
+
+		match Transaction::hydrate_from(ct, txs) {
+			Ok(tx) => {
+				// An invalid hydrated tx may just be a short_id collision,
+				// fallback to requesting the full tx from the same peer.
+				if !self.transaction_received(tx, false)? {
+					debug!("adapter: tx invalid after hydration, requesting full tx");
+					self.request_transaction(kernel_hash, peer_info);
+				}
+				Ok(true)
+			}
+			Err(e) => {
+				debug!("Invalid hydrated tx {}: {:?}", kernel_hash, e);
+				Ok(false)
+			}
+		}
+	}
+
 	fn transaction_received(
 		&self,
 		tx: core::Transaction,
 		stem: bool,
 	) -> Result<bool, chain::Error> {
-		// nothing much we can do with a new transaction while syncing
-		if self.sync_state.is_syncing() {
+		// nothing much we can do with a new transaction while syncing, or
+		// without a txhashset to validate it against
+		if self.sync_state.is_syncing() || self.header_only() {
 			return Ok(true);
 		}
 
@@ -125,6 +194,11 @@ impl p2p::ChainAdapter for NetToChainAdapter {
 		peer_info: &PeerInfo,
 		was_requested: bool,
 	) -> Result<bool, chain::Error> {
+		// nothing we can do with a new block while rebuilding our chain state
+		if let SyncStatus::Reindex { .. } = self.sync_state.status() {
+			return Ok(true);
+		}
+
 		debug!(
 			"Received block {} at {} from {} [in/out/kern: {}/{}/{}] going to process.",
 			b.hash(),
@@ -134,6 +208,9 @@ impl p2p::ChainAdapter for NetToChainAdapter {
 			b.outputs().len(),
 			b.kernels().len(),
 		);
+		if self.header_only() {
+			return self.process_headers_only(&[b.header], was_requested);
+		}
 		self.process_block(b, peer_info, was_requested)
 	}
 
@@ -153,6 +230,10 @@ impl p2p::ChainAdapter for NetToChainAdapter {
 			cb.kern_ids().len(),
 		);
 
+		if self.header_only() {
+			return self.process_headers_only(&[cb.header], false);
+		}
+
 		let cb_hash = cb.hash();
 		if cb.kern_ids().is_empty() {
 			// push the freshly hydrated block through the chain pipeline
@@ -214,7 +295,11 @@ impl p2p::ChainAdapter for NetToChainAdapter {
 
 			if let Ok(prev) = self.chain().get_previous_header(&cb.header) {
 				if block
-					.validate(&prev.total_kernel_offset, self.verifier_cache.clone())
+					.validate(
+						self.chain().params(),
+						&prev.total_kernel_offset,
+						self.verifier_cache.clone(),
+					)
 					.is_ok()
 				{
 					debug!("successfully hydrated block from tx pool!");
@@ -247,6 +332,10 @@ impl p2p::ChainAdapter for NetToChainAdapter {
 			bhash, bh.height, peer_info.addr,
 		);
 
+		if self.header_only() {
+			return self.process_headers_only(&[bh], false);
+		}
+
 		// pushing the new block header through the header chain pipeline
 		// we will go ask for the block if this is a new header
 		let res = self
@@ -287,6 +376,10 @@ impl p2p::ChainAdapter for NetToChainAdapter {
 			return Ok(false);
 		}
 
+		if self.header_only() {
+			return self.process_headers_only(bhs, true);
+		}
+
 		// try to add headers to our header chain
 		match self.chain().sync_block_headers(bhs, self.chain_opts(true)) {
 			Ok(_) => Ok(true),
@@ -353,6 +446,7 @@ impl p2p::ChainAdapter for NetToChainAdapter {
 				output_index: out_index,
 				kernel_index: kernel_index,
 				reader: read,
+				upload: None,
 			}),
 			Err(e) => {
 				warn!("Couldn't produce txhashset data for block {}: {:?}", h, e);
@@ -432,9 +526,219 @@ impl p2p::ChainAdapter for NetToChainAdapter {
 	fn get_tmpfile_pathname(&self, tmpfile_name: String) -> PathBuf {
 		self.chain().get_tmpfile_pathname(tmpfile_name)
 	}
+
+	fn get_output_bitmap_segment(&self, h: Hash, id: SegmentIdentifier) -> Option<BitmapSegment> {
+		match self.chain().get_output_bitmap_segment(h, id) {
+			Ok(segment) => Some(segment),
+			Err(e) => {
+				debug!(
+					"Couldn't produce output bitmap segment {:?} at {}: {:?}",
+					id, h, e
+				);
+				None
+			}
+		}
+	}
+
+	fn get_output_segment(
+		&self,
+		h: Hash,
+		id: SegmentIdentifier,
+	) -> Option<Segment<OutputIdentifier>> {
+		match self.chain().get_output_segment(h, id) {
+			Ok(segment) => Some(segment),
+			Err(e) => {
+				debug!("Couldn't produce output segment {:?} at {}: {:?}", id, h, e);
+				None
+			}
+		}
+	}
+
+	fn get_rangeproof_segment(
+		&self,
+		h: Hash,
+		id: SegmentIdentifier,
+	) -> Option<Segment<RangeProof>> {
+		match self.chain().get_rangeproof_segment(h, id) {
+			Ok(segment) => Some(segment),
+			Err(e) => {
+				debug!(
+					"Couldn't produce rangeproof segment {:?} at {}: {:?}",
+					id, h, e
+				);
+				None
+			}
+		}
+	}
+
+	fn get_kernel_segment(&self, h: Hash, id: SegmentIdentifier) -> Option<Segment<TxKernelEntry>> {
+		match self.chain().get_kernel_segment(h, id) {
+			Ok(segment) => Some(segment),
+			Err(e) => {
+				debug!("Couldn't produce kernel segment {:?} at {}: {:?}", id, h, e);
+				None
+			}
+		}
+	}
+
+	fn receive_output_bitmap_segment(
+		&self,
+		h: Hash,
+		segment: BitmapSegment,
+		peer_info: &PeerInfo,
+	) -> Result<bool, chain::Error> {
+		self.receive_segment(peer_info, |chain| {
+			chain.pibd_receive_bitmap_segment(h, segment)
+		})
+	}
+
+	fn receive_output_segment(
+		&self,
+		h: Hash,
+		segment: Segment<OutputIdentifier>,
+		peer_info: &PeerInfo,
+	) -> Result<bool, chain::Error> {
+		self.receive_segment(peer_info, |chain| {
+			chain.pibd_receive_output_segment(h, segment)
+		})
+	}
+
+	fn receive_rangeproof_segment(
+		&self,
+		h: Hash,
+		segment: Segment<RangeProof>,
+		peer_info: &PeerInfo,
+	) -> Result<bool, chain::Error> {
+		self.receive_segment(peer_info, |chain| {
+			chain.pibd_receive_rangeproof_segment(h, segment)
+		})
+	}
+
+	fn receive_kernel_segment(
+		&self,
+		h: Hash,
+		segment: Segment<TxKernelEntry>,
+		peer_info: &PeerInfo,
+	) -> Result<bool, chain::Error> {
+		self.receive_segment(peer_info, |chain| {
+			chain.pibd_receive_kernel_segment(h, segment)
+		})
+	}
+
+	fn get_output_proof(&self, commit: Commitment) -> Option<chain::OutputProof> {
+		if self.header_only() {
+			return None;
+		}
+		match self.chain().get_output_proof(commit) {
+			Ok(proof) => Some(proof),
+			Err(e) => {
+				debug!("Couldn't produce a proof of output {:?}: {:?}", commit, e);
+				None
+			}
+		}
+	}
+
+	fn receive_output_proof(
+		&self,
+		proof: &chain::OutputProof,
+		peer_info: &PeerInfo,
+	) -> Result<bool, chain::Error> {
+		match self.chain().verify_output_proof(proof) {
+			Ok(_) => Ok(true),
+			Err(e) => {
+				debug!(
+					"Output proof from {} refused by chain: {:?}",
+					peer_info.addr, e
+				);
+				if e.is_bad_data() {
+					Ok(false)
+				} else {
+					Err(e)
+				}
+			}
+		}
+	}
+
+	fn get_block_filter(&self, h: Hash) -> Option<core::block_filter::BlockFilter> {
+		self.chain().get_block_filter(&h).ok()
+	}
+
+	fn receive_block_filter(
+		&self,
+		filter: &core::block_filter::BlockFilter,
+		peer_info: &PeerInfo,
+	) -> Result<bool, chain::Error> {
+		// We can only check the filter against the one of our own block, when
+		// we have it.
+		match self.chain().get_block_filter(&filter.block_hash) {
+			Ok(ours) if ours != *filter => {
+				debug!(
+					"Block filter from {} doesn't match our block {}",
+					peer_info.addr, filter.block_hash
+				);
+				Ok(false)
+			}
+			_ => Ok(true),
+		}
+	}
 }
 
 impl NetToChainAdapter {
+	// Adds a txhashset segment received from a peer to the download in
+	// progress, false if the segment is invalid (the peer gets banned). The
+	// segment completing the download kicks off the full validation of the
+	// txhashset, on its own thread as it takes a while.
+	fn receive_segment<F>(&self, peer_info: &PeerInfo, add_segment: F) -> Result<bool, chain::Error>
+	where
+		F: FnOnce(&chain::Chain) -> Result<bool, chain::Error>,
+	{
+		if let SyncStatus::TxHashsetPibd { .. } = self.sync_state.status() {
+		} else {
+			return Ok(true);
+		}
+
+		let chain = self.chain();
+		let completed = match add_segment(&chain) {
+			Ok(completed) => completed,
+			Err(e) => {
+				if e.is_bad_data() {
+					warn!(
+						"Received an invalid txhashset segment from {}: {}",
+						peer_info.addr, e
+					);
+					return Ok(false);
+				}
+				debug!(
+					"Couldn't add txhashset segment from {}: {}",
+					peer_info.addr, e
+				);
+				return Ok(true);
+			}
+		};
+
+		if !completed {
+			if let Some((received, total)) = chain.pibd_progress() {
+				self.sync_state
+					.update(SyncStatus::TxHashsetPibd { received, total });
+			}
+			return Ok(true);
+		}
+
+		let sync_state = self.sync_state.clone();
+		let _ = thread::Builder::new()
+			.name("pibd_finalize".to_string())
+			.spawn(move || {
+				if let Err(e) = chain.pibd_finalize(sync_state.as_ref()) {
+					chain.clean_txhashset_sandbox();
+					error!("Failed to validate txhashset from segments: {}", e);
+					sync_state.set_sync_error(types::Error::Chain(e));
+				} else {
+					info!("Received valid txhashset segments.");
+				}
+			});
+		Ok(true)
+	}
+
 	/// Construct a new NetToChainAdapter instance
 	pub fn new(
 		sync_state: Arc<SyncState>,
@@ -455,6 +759,33 @@ impl NetToChainAdapter {
 		}
 	}
 
+	fn header_only(&self) -> bool {
+		self.config.header_only.unwrap_or(false)
+	}
+
+	// Header-only nodes never ask for nor process full blocks, the headers
+	// of the blocks we hear about go straight to the header chain.
+	fn process_headers_only(
+		&self,
+		bhs: &[BlockHeader],
+		was_requested: bool,
+	) -> Result<bool, chain::Error> {
+		match self
+			.chain()
+			.sync_header_chain(bhs, self.chain_opts(was_requested))
+		{
+			Ok(_) => Ok(true),
+			Err(e) => {
+				debug!("Block headers refused by chain: {:?}", e);
+				if e.is_bad_data() {
+					Ok(false)
+				} else {
+					Err(e)
+				}
+			}
+		}
+	}
+
 	/// Initialize a NetToChainAdaptor with reference to a Peers object.
 	/// Should only be called once.
 	pub fn init(&self, peers: Arc<p2p::Peers>) {
@@ -504,7 +835,7 @@ impl NetToChainAdapter {
 			let head = self.chain().head()?;
 			let horizon = head
 				.height
-				.saturating_sub(global::cut_through_horizon() as u64);
+				.saturating_sub(self.chain().params().cut_through_horizon as u64);
 			if b.header.height < horizon {
 				return Ok(true);
 			}
@@ -513,13 +844,15 @@ impl NetToChainAdapter {
 		let bhash = b.hash();
 		let previous = self.chain().get_previous_header(&b.header);
 
-		match self
-			.chain()
-			.process_block(b, self.chain_opts(was_requested))
-		{
+		match self.chain().process_block_from(
+			b,
+			self.chain_opts(was_requested),
+			Some(peer_info.addr.to_string()),
+		) {
 			Ok(_) => {
 				self.validate_chain(bhash);
 				self.check_compact();
+				self.check_txhashset_archive();
 				Ok(true)
 			}
 			Err(ref e) if e.is_bad_data() => {
@@ -602,10 +935,38 @@ impl NetToChainAdapter {
 		}
 	}
 
+	fn check_txhashset_archive(&self) {
+		// Skip building archives for our peers if we are syncing.
+		if self.sync_state.is_syncing() {
+			return;
+		}
+
+		// Build the txhashset archive once per archive height, ahead of our
+		// peers asking for it, in a different thread as it takes a while.
+		match self.chain().txhashset_archive_needed() {
+			Ok(true) => {
+				let chain = self.chain().clone();
+				let _ = thread::Builder::new()
+					.name("txhashset_archiver".to_string())
+					.spawn(move || {
+						if let Err(e) = chain.build_txhashset_archive() {
+							error!("Could not build txhashset archive: {:?}", e);
+						}
+					});
+			}
+			Ok(false) => {}
+			Err(e) => debug!("Could not check txhashset archive: {:?}", e),
+		}
+	}
+
 	fn request_transaction(&self, h: Hash, peer_info: &PeerInfo) {
 		self.send_tx_request_to_peer(h, peer_info, |peer, h| peer.send_tx_request(h))
 	}
 
+	fn request_compact_transaction(&self, h: Hash, peer_info: &PeerInfo) {
+		self.send_tx_request_to_peer(h, peer_info, |peer, h| peer.send_compact_tx_request(h))
+	}
+
 	// After receiving a compact block if we cannot successfully hydrate
 	// it into a full block then fallback to requesting the full block
 	// from the same peer that gave us the compact block
@@ -14,6 +14,7 @@
 
 //! Server types
 use std::convert::From;
+use std::fs;
 use std::sync::Arc;
 
 use chrono::prelude::{DateTime, Utc};
@@ -21,14 +22,17 @@ use rand::prelude::*;
 
 use crate::api;
 use crate::chain;
-use crate::core::global::ChainTypes;
-use crate::core::{core, libtx, pow};
+use crate::core::core::Block;
+use crate::core::global::{ChainParams, ChainTypes, PoWSolverTypes};
+use crate::core::ser::{self, ProtocolVersion};
+use crate::core::{consensus, core, libtx, pow};
 use crate::keychain;
 use crate::p2p;
+use crate::p2p::PeerAddr;
 use crate::pool;
 use crate::pool::types::DandelionConfig;
 use crate::store;
-use crate::util::RwLock;
+use crate::util::{self, RwLock};
 
 /// Error type wrapping underlying module errors.
 #[derive(Debug)]
@@ -158,6 +162,10 @@ pub struct ServerConfig {
 	#[serde(default)]
 	pub chain_type: ChainTypes,
 
+	/// Chain file of a custom network, required with the Custom chain type
+	#[serde(default)]
+	pub custom_chain_file: Option<String>,
+
 	/// Automatically run full chain validation during normal block processing?
 	#[serde(default)]
 	pub chain_validation_mode: ChainValidationMode,
@@ -165,6 +173,18 @@ pub struct ServerConfig {
 	/// Whether this node is a full archival node or a fast-sync, pruned node
 	pub archive_mode: Option<bool>,
 
+	/// Whether to run a header-only node, following the header chain without
+	/// ever downloading the txhashset or full blocks. Outputs are checked with
+	/// merkle proofs obtained from full nodes.
+	#[serde(default)]
+	pub header_only: Option<bool>,
+
+	/// Whether to rebuild the full chain state from the blocks in the db on
+	/// startup (archive nodes only). Only set via `grin server --reindex`,
+	/// never read from the config file so a reindex doesn't run on every start.
+	#[serde(skip)]
+	pub reindex: Option<bool>,
+
 	/// Whether to skip the sync timeout on startup
 	/// (To assist testing on solo chains)
 	pub skip_sync_wait: Option<bool>,
@@ -179,9 +199,17 @@ pub struct ServerConfig {
 	/// Test miner wallet URL
 	pub test_miner_wallet_url: Option<String>,
 
+	/// PoW solver used by the test miner (reference solver if not set)
+	#[serde(default)]
+	pub test_miner_solver: Option<PoWSolverTypes>,
+
 	/// Configuration for the peer-to-peer server
 	pub p2p_config: p2p::P2PConfig,
 
+	/// Orphan block pool configuration
+	#[serde(default)]
+	pub orphan_config: chain::OrphanConfig,
+
 	/// Transaction pool configuration
 	#[serde(default)]
 	pub pool_config: pool::PoolConfig,
@@ -211,18 +239,134 @@ impl Default for ServerConfig {
 			dandelion_config: pool::DandelionConfig::default(),
 			stratum_mining_config: Some(StratumServerConfig::default()),
 			chain_type: ChainTypes::default(),
+			custom_chain_file: None,
 			archive_mode: Some(false),
+			header_only: Some(false),
+			reindex: None,
 			chain_validation_mode: ChainValidationMode::default(),
+			orphan_config: chain::OrphanConfig::default(),
 			pool_config: pool::PoolConfig::default(),
 			skip_sync_wait: Some(false),
 			run_tui: Some(true),
 			run_test_miner: Some(false),
 			test_miner_wallet_url: None,
+			test_miner_solver: None,
 			webhook_config: WebHooksConfig::default(),
 		}
 	}
 }
 
+impl ServerConfig {
+	/// The chain file of our custom network, if we run one.
+	pub fn custom_chain(&self) -> Result<Option<CustomChainConfig>, Error> {
+		if self.chain_type != ChainTypes::Custom {
+			return Ok(None);
+		}
+		match &self.custom_chain_file {
+			Some(path) => Ok(Some(CustomChainConfig::read(path)?)),
+			None => Err(Error::Configuration(
+				"custom_chain_file must be configured for chain type Custom".to_owned(),
+			)),
+		}
+	}
+
+	/// The parameters of the chain we run, from the chain file on a custom
+	/// network.
+	pub fn chain_params(&self) -> Result<ChainParams, Error> {
+		match self.custom_chain()? {
+			Some(custom) => Ok(custom.params()),
+			None => Ok(self.chain_type.params()),
+		}
+	}
+}
+
+/// Chain file of a custom local network: everything that differs from the
+/// defaults of a custom chain (see `ChainParams::custom`), its seeds and its
+/// genesis block.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct CustomChainConfig {
+	/// Magic bytes starting every p2p message, should be unique to the network
+	pub magic: [u8; 2],
+	/// Number of blocks before a coinbase output can be spent
+	pub coinbase_maturity: u64,
+	/// Block interval the difficulty adjustment targets, in seconds
+	pub block_time_sec: u64,
+	/// Difficulty of the genesis block, which the first blocks are mined at
+	pub initial_block_difficulty: u64,
+	/// Size of the Cuckatoo graphs blocks are mined on
+	pub edge_bits: u8,
+	/// Length of the cycles blocks are mined with
+	#[serde(default = "default_proofsize")]
+	pub proofsize: usize,
+	/// Peers to connect to when seeding from DNS, the network has no DNS
+	/// seeds
+	#[serde(default)]
+	pub seeds: Vec<PeerAddr>,
+	/// Genesis block, hex of its serialization, built by `grin server genesis`
+	#[serde(default)]
+	pub genesis: Option<String>,
+}
+
+fn default_proofsize() -> usize {
+	consensus::PROOFSIZE
+}
+
+/// Protocol version the genesis block of a chain file is serialized with,
+/// fixed so the file reads the same for nodes of any version.
+const CHAIN_FILE_PROTOCOL_VERSION: ProtocolVersion = ProtocolVersion(1);
+
+impl CustomChainConfig {
+	/// Reads the chain file at the provided path.
+	pub fn read(path: &str) -> Result<CustomChainConfig, Error> {
+		let contents = fs::read_to_string(path)?;
+		toml::from_str(&contents)
+			.map_err(|e| Error::Configuration(format!("invalid chain file {}: {}", path, e)))
+	}
+
+	/// Writes the chain file to the provided path.
+	pub fn write(&self, path: &str) -> Result<(), Error> {
+		let contents = toml::to_string(self)
+			.map_err(|e| Error::Configuration(format!("serializing chain file: {}", e)))?;
+		fs::write(path, contents)?;
+		Ok(())
+	}
+
+	/// Parameters of the custom chain.
+	pub fn params(&self) -> ChainParams {
+		ChainParams {
+			magic: self.magic,
+			coinbase_maturity: self.coinbase_maturity,
+			block_time_sec: self.block_time_sec,
+			initial_block_difficulty: self.initial_block_difficulty,
+			min_edge_bits: self.edge_bits,
+			base_edge_bits: self.edge_bits,
+			proofsize: self.proofsize,
+			..ChainParams::custom()
+		}
+	}
+
+	/// The genesis block of the custom chain, once built.
+	pub fn genesis_block(&self) -> Result<Block, Error> {
+		let hex = self.genesis.as_ref().ok_or_else(|| {
+			Error::Configuration(
+				"custom chain has no genesis block, build it with 'grin server genesis'".to_owned(),
+			)
+		})?;
+		let bytes = util::from_hex(hex.clone())
+			.map_err(|e| Error::Configuration(format!("invalid genesis block: {:?}", e)))?;
+		ser::deserialize_with_params(&mut &bytes[..], CHAIN_FILE_PROTOCOL_VERSION, &self.params())
+			.map_err(|e| Error::Configuration(format!("invalid genesis block: {:?}", e)))
+	}
+
+	/// Sets the genesis block of the custom chain.
+	pub fn set_genesis_block(&mut self, genesis: &Block) -> Result<(), Error> {
+		let bytes = ser::ser_vec(genesis, CHAIN_FILE_PROTOCOL_VERSION)
+			.map_err(|e| Error::General(format!("serializing genesis block: {:?}", e)))?;
+		self.genesis = Some(util::to_hex(bytes));
+		Ok(())
+	}
+}
+
 /// Stratum (Mining server) configuration
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct StratumServerConfig {
@@ -326,6 +470,9 @@ pub enum SyncStatus {
 		downloaded_size: u64,
 		total_size: u64,
 	},
+	/// Downloading the txhashset in segments from several peers (PIBD),
+	/// in number of segments
+	TxHashsetPibd { received: u64, total: u64 },
 	/// Setting up before validation
 	TxHashsetSetup,
 	/// Validating the full state
@@ -344,6 +491,11 @@ pub enum SyncStatus {
 		current_height: u64,
 		highest_height: u64,
 	},
+	/// Rebuilding the chain state from the blocks in our db
+	Reindex {
+		current_height: u64,
+		highest_height: u64,
+	},
 }
 
 /// Current sync state. Encapsulates the current SyncStatus.
@@ -465,6 +617,15 @@ impl chain::TxHashsetWriteStatus for SyncState {
 	}
 }
 
+impl chain::ReindexStatus for SyncState {
+	fn on_reindex(&self, current_height: u64, highest_height: u64) {
+		self.update(SyncStatus::Reindex {
+			current_height,
+			highest_height,
+		});
+	}
+}
+
 /// A node is either "stem" of "fluff" for the duration of a single epoch.
 /// A node also maintains an outbound relay peer for the epoch.
 #[derive(Debug)]
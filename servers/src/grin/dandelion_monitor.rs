@@ -133,7 +133,8 @@ fn process_fluff_phase(
 	);
 
 	let agg_tx = transaction::aggregate(fluffable_txs)?;
-	agg_tx.validate(
+	agg_tx.validate_with_params(
+		&tx_pool.params,
 		transaction::Weighting::AsTransaction,
 		verifier_cache.clone(),
 	)?;
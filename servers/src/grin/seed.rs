@@ -26,7 +26,6 @@ use std::net::ToSocketAddrs;
 use std::sync::{mpsc, Arc};
 use std::{cmp, str, thread, time};
 
-use crate::core::global;
 use crate::p2p;
 use crate::p2p::types::PeerAddr;
 use crate::p2p::ChainAdapter;
@@ -354,10 +353,10 @@ fn listen_for_addrs(
 	}
 }
 
-pub fn dns_seeds() -> Box<dyn Fn() -> Vec<PeerAddr> + Send> {
-	Box::new(|| {
+pub fn dns_seeds(floonet: bool) -> Box<dyn Fn() -> Vec<PeerAddr> + Send> {
+	Box::new(move || {
 		let mut addresses: Vec<PeerAddr> = vec![];
-		let net_seeds = if global::is_floonet() {
+		let net_seeds = if floonet {
 			FLOONET_DNS_SEEDS
 		} else {
 			MAINNET_DNS_SEEDS
@@ -369,7 +368,7 @@ pub fn dns_seeds() -> Box<dyn Fn() -> Vec<PeerAddr> + Send> {
 				Ok(addrs) => addresses.append(
 					&mut (addrs
 						.map(|mut addr| {
-							addr.set_port(if global::is_floonet() { 13414 } else { 3414 });
+							addr.set_port(if floonet { 13414 } else { 3414 });
 							PeerAddr(addr)
 						})
 						.filter(|addr| !temp_addresses.contains(addr))
@@ -18,11 +18,12 @@
 
 use std::fs;
 use std::fs::File;
-use std::io::prelude::*;
+use std::io::{self, prelude::*};
 use std::path::Path;
 use std::sync::Arc;
 use std::{thread, time};
 
+use chrono::prelude::{TimeZone, Utc};
 use fs2::FileExt;
 
 use crate::api;
@@ -34,10 +35,14 @@ use crate::common::adapters::{
 use crate::common::hooks::{init_chain_hooks, init_net_hooks};
 use crate::common::stats::{DiffBlock, DiffStats, PeerStats, ServerStateInfo, ServerStats};
 use crate::common::types::{Error, ServerConfig, StratumServerConfig, SyncState, SyncStatus};
-use crate::core::core::hash::{Hashed, ZERO_HASH};
+use crate::core::core::hash::{Hash, Hashed, ZERO_HASH};
 use crate::core::core::verifier_cache::{LruVerifierCache, VerifierCache};
+use crate::core::core::{Block, BlockHeader};
+use crate::core::pow::Difficulty;
+use crate::core::ser::ProtocolVersion;
 use crate::core::{consensus, genesis, global, pow};
 use crate::grin::{dandelion_monitor, seed, sync};
+use crate::keychain::BlindingFactor;
 use crate::mining::stratumserver;
 use crate::mining::test_miner::Miner;
 use crate::p2p;
@@ -142,12 +147,26 @@ impl Server {
 			Some(b) => b,
 		};
 
+		// Header-only nodes only follow the header chain, they can't provide
+		// anything but headers and peers.
+		let header_only = config.header_only.unwrap_or(false);
+		let capabilities = if header_only {
+			config.p2p_config.capabilities
+				& (p2p::Capabilities::HEADER_HIST | p2p::Capabilities::PEER_LIST)
+		} else {
+			config.p2p_config.capabilities
+		};
+
 		let stop_state = Arc::new(Mutex::new(StopState::new()));
 
 		// Shared cache for verification results.
 		// We cache rangeproof verification and kernel signature verification.
 		let verifier_cache = Arc::new(RwLock::new(LruVerifierCache::new()));
 
+		// Parameters of the chain we run, given to everything running it.
+		let custom_chain = config.custom_chain()?;
+		let params = config.chain_params()?;
+
 		let pool_adapter = Arc::new(PoolToChainAdapter::new());
 		let pool_net_adapter = Arc::new(PoolToNetAdapter::new(config.dandelion_config.clone()));
 		let tx_pool = Arc::new(RwLock::new(pool::TransactionPool::new(
@@ -155,6 +174,7 @@ impl Server {
 			pool_adapter.clone(),
 			verifier_cache.clone(),
 			pool_net_adapter.clone(),
+			params.clone(),
 		)));
 
 		let sync_state = Arc::new(SyncState::new());
@@ -164,27 +184,30 @@ impl Server {
 			init_chain_hooks(&config),
 		));
 
-		let genesis = match config.chain_type {
-			global::ChainTypes::AutomatedTesting => genesis::genesis_dev(),
-			global::ChainTypes::UserTesting => genesis::genesis_dev(),
-			global::ChainTypes::Floonet => genesis::genesis_floo(),
-			global::ChainTypes::Mainnet => genesis::genesis_main(),
-		};
+		let genesis = genesis_block(&config)?;
 
 		info!("Starting server, genesis block: {}", genesis.hash());
 
 		let shared_chain = Arc::new(chain::Chain::init(
 			config.db_root.clone(),
 			chain_adapter.clone(),
+			params.clone(),
 			genesis.clone(),
 			pow::verify_size,
 			verifier_cache.clone(),
 			archive_mode,
+			config.orphan_config.clone(),
 			stop_state.clone(),
 		)?);
 
 		pool_adapter.set_chain(shared_chain.clone());
 
+		// The header MMR only follows the full chain on startup, bring it back
+		// to our header head.
+		if header_only {
+			shared_chain.update_header_mmr()?;
+		}
+
 		let net_adapter = Arc::new(NetToChainAdapter::new(
 			sync_state.clone(),
 			shared_chain.clone(),
@@ -196,10 +219,11 @@ impl Server {
 
 		let p2p_server = Arc::new(p2p::Server::new(
 			&config.db_root,
-			config.p2p_config.capabilities,
+			capabilities,
 			config.p2p_config.clone(),
 			net_adapter.clone(),
 			genesis.hash(),
+			params,
 			stop_state.clone(),
 		)?);
 
@@ -208,6 +232,11 @@ impl Server {
 		pool_net_adapter.init(p2p_server.peers.clone());
 		net_adapter.init(p2p_server.peers.clone());
 
+		// Orphans reloaded from the db may already connect to our chain.
+		if shared_chain.orphans_len() > 0 {
+			shared_chain.check_orphans(shared_chain.head()?.height + 1);
+		}
+
 		if config.p2p_config.seeding_type != p2p::Seeding::Programmatic {
 			let seeder = match config.p2p_config.seeding_type {
 				p2p::Seeding::None => {
@@ -222,13 +251,17 @@ impl Server {
 						));
 					}
 				},
-				p2p::Seeding::DNSSeed => seed::dns_seeds(),
+				// A custom network has no DNS seeds, only the ones in its chain file.
+				p2p::Seeding::DNSSeed => match custom_chain {
+					Some(custom) => seed::predefined_seeds(custom.seeds),
+					None => seed::dns_seeds(p2p_server.params().is_floonet()),
+				},
 				_ => unreachable!(),
 			};
 
 			seed::connect_and_monitor(
 				p2p_server.clone(),
-				config.p2p_config.capabilities,
+				capabilities,
 				seeder,
 				config.p2p_config.peers_preferred.clone(),
 				stop_state.clone(),
@@ -244,6 +277,8 @@ impl Server {
 			sync_state.clone(),
 			p2p_server.peers.clone(),
 			shared_chain.clone(),
+			config.reindex.unwrap_or(false),
+			header_only,
 			stop_state.clone(),
 		);
 
@@ -324,8 +359,9 @@ impl Server {
 
 	/// Start a minimal "stratum" mining service on a separate thread
 	pub fn start_stratum_server(&self, config: StratumServerConfig) {
-		let edge_bits = global::min_edge_bits();
-		let proof_size = global::proofsize();
+		let params = self.chain.params().clone();
+		let edge_bits = params.min_edge_bits;
+		let proof_size = params.proofsize;
 		let sync_state = self.sync_state.clone();
 
 		let mut stratum_server = stratumserver::StratumServer::new(
@@ -374,6 +410,8 @@ impl Server {
 			stop_state,
 		);
 		miner.set_debug_output_id(format!("Port {}", self.config.p2p_config.port));
+		miner.set_solver(self.config.test_miner_solver.unwrap_or_default());
+		let params = self.chain.params().clone();
 		let _ = thread::Builder::new()
 			.name("test_miner".to_string())
 			.spawn(move || {
@@ -398,8 +436,8 @@ impl Server {
 	}
 
 	/// Current p2p layer protocol version.
-	pub fn protocol_version() -> u32 {
-		p2p::msg::PROTOCOL_VERSION
+	pub fn protocol_version() -> ProtocolVersion {
+		ProtocolVersion::local()
 	}
 
 	/// Returns a set of stats about this server. This and the ServerStats
@@ -417,9 +455,12 @@ impl Server {
 		// for release
 		let diff_stats = {
 			let last_blocks: Vec<consensus::HeaderInfo> =
-				global::difficulty_data_to_vector(self.chain.difficulty_iter()?)
-					.into_iter()
-					.collect();
+				global::difficulty_data_to_vector_with_params(
+					self.chain.params(),
+					self.chain.difficulty_iter()?,
+				)
+				.into_iter()
+				.collect();
 
 			let tip_height = self.head()?.height as i64;
 			let mut height = tip_height as i64 - last_blocks.len() as i64 + 1;
@@ -513,4 +554,163 @@ impl Server {
 		stop.lock().stop();
 		info!("stop_test_miner - stop",);
 	}
+
+	/// Bootstraps our chain state from the txhashset archive at the provided
+	/// path, at the block with the provided hash, instead of downloading it
+	/// from our peers. The archive goes through the same validation as one
+	/// received from a peer. Header sync must have reached the block already.
+	pub fn import_txhashset(config: ServerConfig, zip_path: &Path, h: Hash) -> Result<(), Error> {
+		let _lock_file = Server::one_grin_at_a_time(&config)?;
+		let chain = Server::open_chain(&config)?;
+
+		let header = chain.get_block_header(&h).map_err(|_| {
+			Error::ArgumentError(format!(
+				"unknown block {}, header sync has to reach it first",
+				h
+			))
+		})?;
+		let header_head = chain.header_head()?;
+		if header.height > header_head.height {
+			return Err(Error::ArgumentError(format!(
+				"block {} is past our header head at {}",
+				h, header_head.height
+			)));
+		}
+
+		info!(
+			"Importing txhashset at {} {} from {:?}",
+			header.height, h, zip_path
+		);
+		chain.txhashset_write(h, File::open(zip_path)?, &LogStatus)?;
+		Ok(())
+	}
+
+	/// Writes the txhashset archive at the block with the provided hash to
+	/// the provided path, by default the one we would send to a syncing peer
+	/// (at our current archive height). Any block of our current chain will
+	/// do, as far back as we can rewind (our cut-through horizon unless in
+	/// archive mode). Returns the header of the block.
+	pub fn export_txhashset(
+		config: ServerConfig,
+		zip_path: &Path,
+		h: Option<Hash>,
+	) -> Result<BlockHeader, Error> {
+		let _lock_file = Server::one_grin_at_a_time(&config)?;
+		let chain = Server::open_chain(&config)?;
+
+		let h = match h {
+			Some(h) => h,
+			None => chain.txhashset_archive_header()?.hash(),
+		};
+		let (header, mut zip) = chain.txhashset_export(h)?;
+		io::copy(&mut zip, &mut File::create(zip_path)?)?;
+		Ok(header)
+	}
+
+	/// Builds and solves the genesis block of our custom network, writing it
+	/// to the chain file of the network. Its previous root can commit to some
+	/// outside data, like a recent bitcoin block hash, to show it wasn't
+	/// mined ahead of time. The genesis block has no coinbase output or
+	/// kernel, see etc/gen_gen for a genesis with a coinbase.
+	pub fn build_genesis(config: ServerConfig, prev_root: Option<Hash>) -> Result<Block, Error> {
+		let mut custom = match config.custom_chain()? {
+			Some(custom) => custom,
+			None => {
+				return Err(Error::Configuration(
+					"genesis blocks are only built for chain type Custom".to_owned(),
+				));
+			}
+		};
+		if custom.genesis.is_some() {
+			return Err(Error::Configuration(
+				"the chain file already has a genesis block, remove it first".to_owned(),
+			));
+		}
+		let params = custom.params();
+
+		let difficulty = Difficulty::from_num(params.initial_block_difficulty);
+		let mut gen = genesis::genesis_dev();
+		gen.header.timestamp = Utc.timestamp(Utc::now().timestamp(), 0);
+		gen.header.prev_root = prev_root.unwrap_or(ZERO_HASH);
+		gen.header.pow.nonce = 0;
+		gen.header.pow.total_difficulty = difficulty;
+		gen.header.pow.secondary_scaling = params.initial_graph_weight;
+
+		info!(
+			"Mining genesis block at difficulty {} on cuckatoo{}",
+			difficulty.to_num(),
+			params.min_edge_bits
+		);
+		pow::pow_size_with_params(
+			&params,
+			&mut gen.header,
+			difficulty,
+			params.proofsize,
+			params.min_edge_bits,
+		)?;
+		pow::verify_size(&params, &gen.header)?;
+		gen.validate(
+			&params,
+			&BlindingFactor::zero(),
+			Arc::new(RwLock::new(LruVerifierCache::new())),
+		)?;
+
+		custom.set_genesis_block(&gen)?;
+		custom.write(config.custom_chain_file.as_ref().unwrap())?;
+		Ok(gen)
+	}
+
+	// Opens our chain alone (no peers, no pool) for the offline commands.
+	fn open_chain(config: &ServerConfig) -> Result<chain::Chain, Error> {
+		let chain = chain::Chain::init(
+			config.db_root.clone(),
+			Arc::new(chain::types::NoopAdapter {}),
+			config.chain_params()?,
+			genesis_block(config)?,
+			pow::verify_size,
+			Arc::new(RwLock::new(LruVerifierCache::new())),
+			config.archive_mode.unwrap_or(false),
+			config.orphan_config.clone(),
+			Arc::new(Mutex::new(StopState::new())),
+		)?;
+		Ok(chain)
+	}
+}
+
+fn genesis_block(config: &ServerConfig) -> Result<Block, Error> {
+	let genesis = match config.chain_type {
+		global::ChainTypes::AutomatedTesting => genesis::genesis_dev(),
+		global::ChainTypes::UserTesting => genesis::genesis_dev(),
+		global::ChainTypes::Floonet => genesis::genesis_floo(),
+		global::ChainTypes::Mainnet => genesis::genesis_main(),
+		global::ChainTypes::Custom => match config.custom_chain()? {
+			Some(custom) => custom.genesis_block()?,
+			None => unreachable!(),
+		},
+	};
+	Ok(genesis)
+}
+
+// Reports the progress of a txhashset import in the logs.
+struct LogStatus;
+
+impl chain::TxHashsetWriteStatus for LogStatus {
+	fn on_setup(&self) {
+		info!("txhashset import: extracting archive");
+	}
+
+	fn on_validation(&self, kernels: u64, kernel_total: u64, rproofs: u64, rproof_total: u64) {
+		info!(
+			"txhashset import: validated {}/{} kernels, {}/{} rangeproofs",
+			kernels, kernel_total, rproofs, rproof_total
+		);
+	}
+
+	fn on_save(&self) {
+		info!("txhashset import: saving chain state");
+	}
+
+	fn on_done(&self) {
+		info!("txhashset import: done");
+	}
 }
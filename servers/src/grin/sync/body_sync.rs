@@ -107,7 +107,10 @@ impl BodySync {
 		// also if the chain is already saturated with orphans, throttle
 		let block_count = cmp::min(
 			cmp::min(100, peers.len() * p2p::SEND_CHANNEL_CAP),
-			chain::MAX_ORPHAN_SIZE.saturating_sub(self.chain.orphans_len()) + 1,
+			self.chain
+				.orphans_max_size()
+				.saturating_sub(self.chain.orphans_len())
+				+ 1,
 		);
 
 		let hashes_to_get = hashes
@@ -14,20 +14,36 @@
 
 use chrono::prelude::{DateTime, Utc};
 use chrono::Duration;
+use std::collections::HashMap;
 use std::sync::Arc;
 
 use crate::chain;
 use crate::common::types::{Error, SyncState, SyncStatus};
 use crate::core::core::hash::Hashed;
-use crate::core::global;
-use crate::p2p::{self, Peer};
+use crate::core::core::pmmr::SegmentIdentifier;
+use crate::core::core::BlockHeader;
+use crate::p2p::{self, Capabilities, Peer, PeerAddr};
+
+/// Max number of txhashset segments requested but not received yet, across
+/// all our peers.
+const PIBD_MAX_IN_FLIGHT: usize = 64;
+
+/// A segment not received after this long is requested again (likely from
+/// another peer).
+const PIBD_SEGMENT_TIMEOUT_SECS: i64 = 30;
+
+/// The segmented download is restarted if no segment gets added for this
+/// long.
+const PIBD_STALL_TIMEOUT_SECS: i64 = 300;
 
 /// Fast sync has 3 "states":
 /// * syncing headers
 /// * once all headers are sync'd, requesting the txhashset state
 /// * once we have the state, get blocks after that
 ///
-/// The StateSync struct implements and monitors the middle step.
+/// The StateSync struct implements and monitors the middle step. The state
+/// is downloaded in segments from all our peers providing them (PIBD), or as
+/// a single archive from our most work peer otherwise.
 pub struct StateSync {
 	sync_state: Arc<SyncState>,
 	peers: Arc<p2p::Peers>,
@@ -35,6 +51,10 @@ pub struct StateSync {
 
 	prev_state_sync: Option<DateTime<Utc>>,
 	state_sync_peer: Option<Arc<Peer>>,
+
+	pibd_requests: HashMap<(chain::SegmentType, SegmentIdentifier), (PeerAddr, DateTime<Utc>)>,
+	pibd_next_peer: usize,
+	pibd_progress: Option<(u64, DateTime<Utc>)>,
 }
 
 impl StateSync {
@@ -49,6 +69,9 @@ impl StateSync {
 			chain,
 			prev_state_sync: None,
 			state_sync_peer: None,
+			pibd_requests: HashMap::new(),
+			pibd_next_peer: 0,
+			pibd_progress: None,
 		}
 	}
 
@@ -126,9 +149,27 @@ impl StateSync {
 
 			if go {
 				self.state_sync_peer = None;
-				match self.request_state(&header_head) {
-					Ok(peer) => {
-						self.state_sync_peer = Some(peer);
+				let pibd = !self.pibd_peers(head).is_empty();
+				match self.txhashset_head(header_head) {
+					Ok(txhashset_head) => {
+						if pibd {
+							info!(
+								"state_sync: downloading txhashset segments at {} / {}",
+								txhashset_head.height,
+								txhashset_head.hash()
+							);
+							if let Err(e) = self.chain.pibd_start(txhashset_head.hash()) {
+								error!("state_sync: failed to start segmented download: {:?}", e);
+								self.sync_state.set_sync_error(Error::Chain(e));
+							}
+						} else {
+							match self.request_state(&txhashset_head) {
+								Ok(peer) => {
+									self.state_sync_peer = Some(peer);
+								}
+								Err(e) => self.sync_state.set_sync_error(Error::P2P(e)),
+							}
+						}
 					}
 					Err(e) => self.sync_state.set_sync_error(Error::P2P(e)),
 				}
@@ -145,54 +186,173 @@ impl StateSync {
 					}
 				}
 
-				self.sync_state.update(SyncStatus::TxHashsetDownload {
-					start_time: Utc::now(),
-					prev_update_time: Utc::now(),
-					update_time: Utc::now(),
-					prev_downloaded_size: 0,
-					downloaded_size: 0,
-					total_size: 0,
-				});
+				if pibd {
+					let (received, total) = self.chain.pibd_progress().unwrap_or((0, 0));
+					self.sync_state
+						.update(SyncStatus::TxHashsetPibd { received, total });
+				} else {
+					self.sync_state.update(SyncStatus::TxHashsetDownload {
+						start_time: Utc::now(),
+						prev_update_time: Utc::now(),
+						update_time: Utc::now(),
+						prev_downloaded_size: 0,
+						downloaded_size: 0,
+						total_size: 0,
+					});
+				}
 			}
 		}
+
+		if let SyncStatus::TxHashsetPibd { .. } = self.sync_state.status() {
+			self.request_segments(head);
+		}
 		true
 	}
 
-	fn request_state(&self, header_head: &chain::Tip) -> Result<Arc<Peer>, p2p::Error> {
-		let threshold = global::state_sync_threshold() as u64;
+	// Header of the block we ask the txhashset at, state_sync_threshold blocks
+	// behind our header head rounded down to the txhashset archive interval
+	// (the archives our peers have ready).
+	fn txhashset_head(&self, header_head: &chain::Tip) -> Result<BlockHeader, p2p::Error> {
+		let height = self
+			.chain
+			.params()
+			.txhashset_archive_height(header_head.height);
 
-		if let Some(peer) = self.peers.most_work_peer() {
-			// ask for txhashset at state_sync_threshold
-			let mut txhashset_head = self
+		let mut txhashset_head = self
+			.chain
+			.get_block_header(&header_head.prev_block_h)
+			.map_err(|e| {
+				error!(
+					"chain error dirung getting a block header {}: {:?}",
+					&header_head.prev_block_h, e
+				);
+				p2p::Error::Internal
+			})?;
+		while txhashset_head.height > height {
+			txhashset_head = self
 				.chain
-				.get_block_header(&header_head.prev_block_h)
+				.get_previous_header(&txhashset_head)
 				.map_err(|e| {
 					error!(
-						"chain error dirung getting a block header {}: {:?}",
-						&header_head.prev_block_h, e
+						"chain error dirung getting a previous block header {}: {:?}",
+						txhashset_head.hash(),
+						e
 					);
 					p2p::Error::Internal
 				})?;
-			for _ in 0..threshold {
-				txhashset_head = self
-					.chain
-					.get_previous_header(&txhashset_head)
-					.map_err(|e| {
-						error!(
-							"chain error dirung getting a previous block header {}: {:?}",
-							txhashset_head.hash(),
-							e
-						);
-						p2p::Error::Internal
-					})?;
+		}
+		Ok(txhashset_head)
+	}
+
+	// Connected peers with more work than us able to provide txhashset
+	// segments. Peers banned for sending us a bad segment are left out.
+	fn pibd_peers(&self, head: &chain::Tip) -> Vec<Arc<Peer>> {
+		self.peers
+			.connected_peers()
+			.into_iter()
+			.filter(|p| {
+				!p.is_banned()
+					&& p.info.capabilities.contains(Capabilities::PIBD_HIST)
+					&& p.info.total_difficulty() > head.total_difficulty
+			})
+			.collect()
+	}
+
+	// Requests the segments we're missing, round robin across our peers,
+	// keeping track of the requests in flight to retry the ones that time
+	// out or were sent to a peer we since dropped.
+	fn request_segments(&mut self, head: &chain::Tip) {
+		let block_hash = match self.chain.pibd_block_hash() {
+			Some(h) => h,
+			None => return,
+		};
+		let now = Utc::now();
+
+		let received = self.chain.pibd_progress().map(|p| p.0).unwrap_or(0);
+		match self.pibd_progress {
+			Some((prev_received, since)) if prev_received == received => {
+				if now - since > Duration::seconds(PIBD_STALL_TIMEOUT_SECS) {
+					error!(
+						"state_sync: no txhashset segment received in {} secs!",
+						PIBD_STALL_TIMEOUT_SECS
+					);
+					self.pibd_progress = None;
+					self.sync_state
+						.set_sync_error(Error::P2P(p2p::Error::Timeout));
+					return;
+				}
+			}
+			_ => self.pibd_progress = Some((received, now)),
+		}
+
+		let missing = self.chain.pibd_missing_segments(PIBD_MAX_IN_FLIGHT);
+		let timeout = Duration::seconds(PIBD_SEGMENT_TIMEOUT_SECS);
+		let peers = self.pibd_peers(head);
+		self.pibd_requests.retain(|k, (addr, sent)| {
+			missing.contains(k)
+				&& now - *sent < timeout
+				&& peers.iter().any(|p| p.info.addr == *addr)
+		});
+
+		if peers.is_empty() {
+			return;
+		}
+		for (segment_type, id) in missing {
+			if self.pibd_requests.contains_key(&(segment_type, id)) {
+				continue;
+			}
+			let peer = &peers[self.pibd_next_peer % peers.len()];
+			self.pibd_next_peer = self.pibd_next_peer.wrapping_add(1);
+			match peer.send_segment_request(segment_type, block_hash, id) {
+				Ok(_) => {
+					self.pibd_requests
+						.insert((segment_type, id), (peer.info.addr, now));
+				}
+				Err(e) => debug!(
+					"state_sync: segment request to {} failed: {:?}",
+					peer.info.addr, e
+				),
+			}
+		}
+	}
+
+	fn request_state(&self, txhashset_head: &BlockHeader) -> Result<Arc<Peer>, p2p::Error> {
+		let bhash = txhashset_head.hash();
+
+		// Resume the download we have in progress for this txhashset, if the
+		// peer it comes from is still around (archives built by different
+		// peers aren't byte for byte identical).
+		let tmp_dir = self.chain.get_tmp_dir();
+		if let Some((partial, offset)) = p2p::TxHashSetPartial::load(&tmp_dir) {
+			let peer = self
+				.peers
+				.get_connected_peer(partial.peer_addr)
+				.filter(|p| {
+					p.is_connected() && p.info.capabilities.contains(Capabilities::TXHASHSET_RANGE)
+				});
+			match peer {
+				Some(peer)
+					if partial.hash == bhash && offset > 0 && offset < partial.total_size =>
+				{
+					info!(
+						"state_sync: resuming txhashset download from {}, {} of {} bytes received",
+						peer.info.addr, offset, partial.total_size
+					);
+					if let Err(e) = peer.send_txhashset_range_request(partial.height, bhash, offset)
+					{
+						error!("state_sync: send_txhashset_range_request err! {:?}", e);
+						return Err(e);
+					}
+					return Ok(peer);
+				}
+				_ => p2p::TxHashSetPartial::clear(&tmp_dir),
 			}
-			let bhash = txhashset_head.hash();
+		}
+
+		if let Some(peer) = self.peers.most_work_peer() {
 			debug!(
-				"state_sync: before txhashset request, header head: {} / {}, txhashset_head: {} / {}",
-				header_head.height,
-				header_head.last_block_h,
-				txhashset_head.height,
-				bhash
+				"state_sync: before txhashset request, txhashset_head: {} / {}",
+				txhashset_head.height, bhash
 			);
 			if let Err(e) = peer.send_txhashset_request(txhashset_head.height, bhash) {
 				error!("state_sync: send_txhashset_request err! {:?}", e);
@@ -225,5 +385,7 @@ impl StateSync {
 	fn state_sync_reset(&mut self) {
 		self.prev_state_sync = None;
 		self.state_sync_peer = None;
+		self.pibd_requests.clear();
+		self.pibd_progress = None;
 	}
 }
@@ -18,7 +18,6 @@ use std::time;
 
 use crate::chain;
 use crate::common::types::{SyncState, SyncStatus};
-use crate::core::global;
 use crate::core::pow::Difficulty;
 use crate::grin::sync::body_sync::BodySync;
 use crate::grin::sync::header_sync::HeaderSync;
@@ -30,12 +29,15 @@ pub fn run_sync(
 	sync_state: Arc<SyncState>,
 	peers: Arc<p2p::Peers>,
 	chain: Arc<chain::Chain>,
+	reindex: bool,
+	header_only: bool,
 	stop_state: Arc<Mutex<StopState>>,
 ) {
 	let _ = thread::Builder::new()
 		.name("sync".to_string())
 		.spawn(move || {
-			let runner = SyncRunner::new(sync_state, peers, chain, stop_state);
+			let runner =
+				SyncRunner::new(sync_state, peers, chain, reindex, header_only, stop_state);
 			runner.sync_loop();
 		});
 }
@@ -44,6 +46,9 @@ pub struct SyncRunner {
 	sync_state: Arc<SyncState>,
 	peers: Arc<p2p::Peers>,
 	chain: Arc<chain::Chain>,
+	reindex: bool,
+	// only sync the header chain, never the txhashset nor the blocks
+	header_only: bool,
 	stop_state: Arc<Mutex<StopState>>,
 }
 
@@ -52,12 +57,16 @@ impl SyncRunner {
 		sync_state: Arc<SyncState>,
 		peers: Arc<p2p::Peers>,
 		chain: Arc<chain::Chain>,
+		reindex: bool,
+		header_only: bool,
 		stop_state: Arc<Mutex<StopState>>,
 	) -> SyncRunner {
 		SyncRunner {
 			sync_state,
 			peers,
 			chain,
+			reindex,
+			header_only,
 			stop_state,
 		}
 	}
@@ -88,7 +97,7 @@ impl SyncRunner {
 					&& head.total_difficulty > Difficulty::zero())
 				|| n > wait_secs
 			{
-				if wp > 0 || !global::is_production_mode() {
+				if wp > 0 || !self.chain.params().is_production_mode() {
 					break;
 				}
 			}
@@ -112,6 +121,29 @@ impl SyncRunner {
 		}
 	));
 
+		// Rebuild the chain state from our local db first if requested, or if a
+		// previous reindex was interrupted. We only sync with peers once done,
+		// the chain refusing blocks and headers until then.
+		if self.reindex || self.chain.reindex_in_progress() {
+			let prev_status = self.sync_state.status();
+			self.sync_state.update(SyncStatus::Reindex {
+				current_height: 0,
+				highest_height: 0,
+			});
+			if let Err(e) = self.chain.reindex(self.sync_state.as_ref()) {
+				// Failing before the reindex started leaves our chain untouched.
+				if self.chain.reindex_in_progress() {
+					error!(
+						"reindex failed, not syncing until it completes (restart to resume it): {:?}",
+						e
+					);
+					return;
+				}
+				error!("reindex failed: {:?}", e);
+			}
+			self.sync_state.update(prev_status);
+		}
+
 		// Wait for connections reach at least MIN_PEERS
 		if let Err(e) = self.wait_for_min_peers() {
 			error!("wait_for_min_peers failed: {:?}", e);
@@ -164,7 +196,9 @@ impl SyncRunner {
 					// This triggers a chain compaction to keep out local node tidy.
 					// Note: Chain compaction runs with an internal threshold
 					// so can be safely run even if the node is restarted frequently.
-					unwrap_or_restart_loop!(self.chain.compact());
+					if !self.header_only {
+						unwrap_or_restart_loop!(self.chain.compact());
+					}
 				}
 
 				thread::sleep(time::Duration::from_secs(10));
@@ -180,9 +214,15 @@ impl SyncRunner {
 			// except for state sync that only runs if body sync return true (means txhashset is needed)
 			unwrap_or_restart_loop!(header_sync.check_run(&header_head, highest_height));
 
+			// header-only nodes are done once the header chain is synced
+			if self.header_only {
+				continue;
+			}
+
 			let mut check_state_sync = false;
 			match self.sync_state.status() {
 				SyncStatus::TxHashsetDownload { .. }
+				| SyncStatus::TxHashsetPibd { .. }
 				| SyncStatus::TxHashsetSetup
 				| SyncStatus::TxHashsetValidation { .. }
 				| SyncStatus::TxHashsetSave
@@ -216,7 +256,7 @@ impl SyncRunner {
 	/// Whether we're currently syncing the chain or we're fully caught up and
 	/// just receiving blocks through gossip.
 	fn needs_syncing(&self) -> Result<(bool, u64), chain::Error> {
-		let local_diff = self.chain.head()?.total_difficulty;
+		let local_diff = self.local_head()?.total_difficulty;
 		let mut is_syncing = self.sync_state.is_syncing();
 		let peer = self.peers.most_work_peer();
 
@@ -231,7 +271,7 @@ impl SyncRunner {
 		// difficulty than us
 		if is_syncing {
 			if peer_info.total_difficulty() <= local_diff {
-				let ch = self.chain.head()?;
+				let ch = self.local_head()?;
 				info!(
 					"synchronized at {} @ {} [{}]",
 					local_diff.to_num(),
@@ -270,4 +310,14 @@ impl SyncRunner {
 		}
 		Ok((is_syncing, peer_info.height()))
 	}
+
+	// The head we compare to our peers to decide whether we're synced, the
+	// header head for header-only nodes.
+	fn local_head(&self) -> Result<chain::Tip, chain::Error> {
+		if self.header_only {
+			self.chain.header_head()
+		} else {
+			self.chain.head()
+		}
+	}
 }
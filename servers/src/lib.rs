@@ -40,5 +40,5 @@ mod grin;
 mod mining;
 
 pub use crate::common::stats::{DiffBlock, PeerStats, ServerStats, StratumStats, WorkerStats};
-pub use crate::common::types::{ServerConfig, StratumServerConfig};
+pub use crate::common::types::{CustomChainConfig, ServerConfig, StratumServerConfig};
 pub use crate::grin::server::Server;
@@ -331,7 +331,7 @@ impl Handler {
 		// Serialize the block header into pre and post nonce strings
 		let mut header_buf = vec![];
 		{
-			let mut writer = ser::BinWriter::new(&mut header_buf);
+			let mut writer = ser::BinWriter::default(&mut header_buf);
 			bh.write_pre_pow(&mut writer).unwrap();
 			bh.pow.write_pre_pow(bh.version, &mut writer).unwrap();
 		}
@@ -381,7 +381,7 @@ impl Handler {
 		b.header.pow.nonce = params.nonce;
 		b.header.pow.proof.nonces = params.pow;
 
-		if !b.header.pow.is_primary() && !b.header.pow.is_secondary() {
+		if !b.header.pow.is_primary(self.chain.params()) && !b.header.pow.is_secondary() {
 			// Return error status
 			error!(
 					"(Server ID: {}) Failed to validate solution at height {}, hash {}, edge_bits {}, nonce {}, job_id {}: cuckoo size too small",
@@ -393,7 +393,11 @@ impl Handler {
 		}
 
 		// Get share difficulty
-		share_difficulty = b.header.pow.to_difficulty(b.header.height).to_num();
+		share_difficulty = b
+			.header
+			.pow
+			.to_difficulty(self.chain.params(), b.header.height)
+			.to_num();
 		// If the difficulty is too low its an error
 		if share_difficulty < state.minimum_share_difficulty {
 			// Return error status
@@ -442,7 +446,7 @@ impl Handler {
 				);
 		} else {
 			// Do some validation but dont submit
-			let res = pow::verify_size(&b.header);
+			let res = pow::verify_size(self.chain.params(), &b.header);
 			if !res.is_ok() {
 				// Return error status
 				error!(
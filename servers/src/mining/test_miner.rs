@@ -26,7 +26,7 @@ use crate::common::types::StratumServerConfig;
 use crate::core::core::hash::{Hash, Hashed};
 use crate::core::core::verifier_cache::VerifierCache;
 use crate::core::core::{Block, BlockHeader};
-use crate::core::global;
+use crate::core::global::PoWSolverTypes;
 use crate::mining::mine_block;
 use crate::pool;
 use crate::util::{Mutex, StopState};
@@ -37,6 +37,7 @@ pub struct Miner {
 	tx_pool: Arc<RwLock<pool::TransactionPool>>,
 	verifier_cache: Arc<RwLock<dyn VerifierCache>>,
 	stop_state: Arc<Mutex<StopState>>,
+	solver: PoWSolverTypes,
 
 	// Just to hold the port we're on, so this miner can be identified
 	// while watching debug output
@@ -60,9 +61,15 @@ impl Miner {
 			verifier_cache,
 			debug_output_id: String::from("none"),
 			stop_state,
+			solver: PoWSolverTypes::default(),
 		}
 	}
 
+	/// Sets the PoW solver finding cycles, the reference one by default
+	pub fn set_solver(&mut self, solver: PoWSolverTypes) {
+		self.solver = solver;
+	}
+
 	/// Keeping this optional so setting in a separate function
 	/// instead of in the new function
 	pub fn set_debug_output_id(&mut self, debug_output_id: String) {
@@ -81,11 +88,12 @@ impl Miner {
 		// look for a pow for at most 2 sec on the same block (to give a chance to new
 		// transactions) and as long as the head hasn't changed
 		let deadline = Utc::now().timestamp() + attempt_time_per_block as i64;
+		let params = self.chain.params();
 
 		debug!(
 			"(Server ID: {}) Mining Cuckoo{} for max {}s on {} @ {} [{}].",
 			self.debug_output_id,
-			global::min_edge_bits(),
+			params.min_edge_bits,
 			attempt_time_per_block,
 			b.header.total_difficulty(),
 			b.header.height,
@@ -94,18 +102,20 @@ impl Miner {
 		let mut iter_count = 0;
 
 		while head.hash() == *latest_hash && Utc::now().timestamp() < deadline {
-			let mut ctx = global::create_pow_context::<u32>(
-				head.height,
-				global::min_edge_bits(),
-				global::proofsize(),
-				10,
-			)
-			.unwrap();
+			let mut ctx = params
+				.create_solver_pow_context::<u32>(
+					self.solver,
+					head.height,
+					params.min_edge_bits,
+					params.proofsize,
+					10,
+				)
+				.unwrap();
 			ctx.set_header_nonce(b.header.pre_pow(), None, true)
 				.unwrap();
 			if let Ok(proofs) = ctx.find_cycles() {
 				b.header.pow.proof = proofs[0].clone();
-				let proof_diff = b.header.pow.to_difficulty(b.header.height);
+				let proof_diff = b.header.pow.to_difficulty(params, b.header.height);
 				if proof_diff >= (b.header.total_difficulty() - head.total_difficulty()) {
 					return true;
 				}
@@ -0,0 +1,89 @@
+// Copyright 2018 The Grin Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use self::core::core::hash::{Hash, Hashed};
+use self::core::global::ChainTypes;
+use self::core::pow;
+use self::servers::{CustomChainConfig, Server, ServerConfig};
+use grin_core as core;
+use grin_servers as servers;
+use std::fs;
+
+fn clean_output_dir(dir_name: &str) {
+	let _ = fs::remove_dir_all(dir_name);
+}
+
+#[test]
+fn custom_chain_genesis() {
+	let dir = ".grin_custom";
+	clean_output_dir(dir);
+	fs::create_dir_all(dir).unwrap();
+	let chain_file = format!("{}/chain.toml", dir);
+	fs::write(
+		&chain_file,
+		"magic = [12, 34]
+coinbase_maturity = 5
+block_time_sec = 10
+initial_block_difficulty = 1
+edge_bits = 9
+proofsize = 4
+seeds = [\"10.0.0.1:13414\"]
+",
+	)
+	.unwrap();
+
+	let config = ServerConfig {
+		db_root: format!("{}/chain_data", dir),
+		chain_type: ChainTypes::Custom,
+		custom_chain_file: Some(chain_file.clone()),
+		..ServerConfig::default()
+	};
+
+	let params = config.chain_params().unwrap();
+	assert_eq!(params.chain_type, ChainTypes::Custom);
+	assert_eq!(params.magic, [12, 34]);
+	assert_eq!(params.coinbase_maturity, 5);
+	assert_eq!(params.block_time_sec, 10);
+	assert_eq!(params.min_edge_bits, 9);
+	assert_eq!(params.proofsize, 4);
+
+	// No genesis block yet.
+	let custom = config.custom_chain().unwrap().unwrap();
+	assert_eq!(custom.seeds.len(), 1);
+	assert!(custom.genesis_block().is_err());
+
+	let prev_root = Hash::from_vec(&[7; 32]);
+	let gen = Server::build_genesis(config.clone(), Some(prev_root)).unwrap();
+	assert_eq!(gen.header.height, 0);
+	assert_eq!(gen.header.prev_root, prev_root);
+	pow::verify_size(&params, &gen.header).unwrap();
+
+	// The genesis block made it to the chain file, along with everything else.
+	let custom = CustomChainConfig::read(&chain_file).unwrap();
+	assert_eq!(custom.genesis_block().unwrap().hash(), gen.hash());
+	assert_eq!(custom.params(), params);
+	assert_eq!(custom.seeds.len(), 1);
+
+	// And isn't replaced by mistake.
+	assert!(Server::build_genesis(config.clone(), None).is_err());
+
+	// Only custom chains have one built.
+	let config = ServerConfig {
+		chain_type: ChainTypes::UserTesting,
+		..config
+	};
+	assert!(Server::build_genesis(config, None).is_err());
+
+	clean_output_dir(dir);
+}
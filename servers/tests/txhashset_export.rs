@@ -0,0 +1,155 @@
+// Copyright 2018 The Grin Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use self::chain::types::NoopAdapter;
+use self::chain::{Chain, Options};
+use self::core::core::hash::{Hash, Hashed};
+use self::core::core::verifier_cache::LruVerifierCache;
+use self::core::core::Block;
+use self::core::global::{self, ChainTypes};
+use self::core::{consensus, genesis, libtx, pow};
+use self::keychain::{ExtKeychain, ExtKeychainPath, Keychain};
+use self::servers::{Server, ServerConfig};
+use self::util::{Mutex, RwLock, StopState};
+use chrono::Duration;
+use grin_chain as chain;
+use grin_core as core;
+use grin_keychain as keychain;
+use grin_servers as servers;
+use grin_util as util;
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+
+fn clean_output_dir(dir_name: &str) {
+	let _ = fs::remove_dir_all(dir_name);
+}
+
+// Same chain the server commands open.
+fn open_chain(config: &ServerConfig) -> Chain {
+	Chain::init(
+		config.db_root.clone(),
+		Arc::new(NoopAdapter {}),
+		config.chain_params().unwrap(),
+		genesis::genesis_dev(),
+		pow::verify_size,
+		Arc::new(RwLock::new(LruVerifierCache::new())),
+		config.archive_mode.unwrap_or(false),
+		config.orphan_config.clone(),
+		Arc::new(Mutex::new(StopState::new())),
+	)
+	.unwrap()
+}
+
+// Mines and processes count empty blocks, returning them.
+fn mine_blocks<K>(chain: &Chain, keychain: &K, count: u32) -> Vec<Block>
+where
+	K: Keychain,
+{
+	let params = chain.params();
+	(1..=count)
+		.map(|n| {
+			let prev = chain.head_header().unwrap();
+			let next_header_info =
+				consensus::next_difficulty_with_params(params, 1, chain.difficulty_iter().unwrap());
+			let key_id = ExtKeychainPath::new(1, n, 0, 0, 0).to_identifier();
+			let reward = libtx::reward::output(keychain, &key_id, 0, false).unwrap();
+			let mut b =
+				Block::new(&prev, vec![], next_header_info.clone().difficulty, reward).unwrap();
+			b.header.timestamp = prev.timestamp + Duration::seconds(60);
+			b.header.pow.secondary_scaling = next_header_info.secondary_scaling;
+			chain.set_txhashset_roots(&mut b).unwrap();
+			pow::pow_size(
+				&mut b.header,
+				next_header_info.difficulty,
+				params.proofsize,
+				params.min_edge_bits,
+			)
+			.unwrap();
+			chain.process_block(b.clone(), Options::MINE).unwrap();
+			b
+		})
+		.collect()
+}
+
+fn config(db_root: &str) -> ServerConfig {
+	ServerConfig {
+		db_root: db_root.to_owned(),
+		chain_type: ChainTypes::AutomatedTesting,
+		archive_mode: Some(true),
+		..ServerConfig::default()
+	}
+}
+
+// Exports the txhashset of a node at a past block and imports it in a node
+// that only synced the headers, which can then carry on with the next blocks.
+#[test]
+fn txhashset_export_import() {
+	util::init_test_logger();
+	global::set_mining_mode(ChainTypes::AutomatedTesting);
+	let dir = ".grin_txhashset_export";
+	clean_output_dir(dir);
+	fs::create_dir_all(dir).unwrap();
+
+	let keychain = ExtKeychain::from_random_seed(false).unwrap();
+	let config_a = config(&format!("{}/a", dir));
+	let config_b = config(&format!("{}/b", dir));
+
+	let (blocks, archive_header) = {
+		let chain = open_chain(&config_a);
+		let blocks = mine_blocks(&chain, &keychain, 10);
+		(blocks, chain.txhashset_archive_header().unwrap())
+	};
+
+	// By default at the archive height, as sent to syncing peers.
+	let zip_default = Path::new(dir).join("default.zip");
+	let header = Server::export_txhashset(config_a.clone(), &zip_default, None).unwrap();
+	assert_eq!(header.hash(), archive_header.hash());
+	assert!(zip_default.exists());
+
+	// At any block of our chain otherwise.
+	let past = &blocks[4].header;
+	let zip_past = Path::new(dir).join("past.zip");
+	let header = Server::export_txhashset(config_a.clone(), &zip_past, Some(past.hash())).unwrap();
+	assert_eq!(header.hash(), past.hash());
+
+	// But not at a block we don't know.
+	let unknown = Hash::from_vec(&[7; 32]);
+	let zip_unknown = Path::new(dir).join("unknown.zip");
+	assert!(Server::export_txhashset(config_a.clone(), &zip_unknown, Some(unknown)).is_err());
+
+	// The importing node has to sync the headers first.
+	assert!(Server::import_txhashset(config_b.clone(), &zip_past, past.hash()).is_err());
+	{
+		let chain = open_chain(&config_b);
+		let headers: Vec<_> = blocks.iter().map(|b| b.header.clone()).collect();
+		chain.sync_block_headers(&headers, Options::SYNC).unwrap();
+	}
+	Server::import_txhashset(config_b.clone(), &zip_past, past.hash()).unwrap();
+
+	let chain = open_chain(&config_b);
+	let head = chain.head().unwrap();
+	assert_eq!(head.last_block_h, past.hash());
+	let roots = chain.get_txhashset_roots();
+	assert_eq!(roots.output_root, past.output_root);
+	assert_eq!(roots.rproof_root, past.range_proof_root);
+	assert_eq!(roots.kernel_root, past.kernel_root);
+
+	for b in &blocks[5..] {
+		chain.process_block(b.clone(), Options::NONE).unwrap();
+	}
+	assert_eq!(chain.head().unwrap().last_block_h, blocks[9].hash());
+
+	clean_output_dir(dir);
+}
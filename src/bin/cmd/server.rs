@@ -13,6 +13,7 @@
 // limitations under the License.
 
 /// Grin server commands processing
+use std::path::Path;
 use std::process::exit;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
@@ -23,6 +24,7 @@ use clap::ArgMatches;
 use ctrlc;
 
 use crate::config::GlobalConfig;
+use crate::core::core::hash::{Hash, Hashed};
 use crate::core::global;
 use crate::p2p::{PeerAddr, Seeding};
 use crate::servers;
@@ -75,23 +77,18 @@ fn start_server_tui(config: servers::ServerConfig) {
 /// stopping the Grin blockchain server. Processes all the command line
 /// arguments to build a proper configuration and runs Grin with that
 /// configuration.
-pub fn server_command(
-	server_args: Option<&ArgMatches<'_>>,
-	mut global_config: GlobalConfig,
-) -> i32 {
-	global::set_mining_mode(
-		global_config
-			.members
-			.as_mut()
-			.unwrap()
-			.server
-			.clone()
-			.chain_type,
-	);
-
+pub fn server_command(server_args: Option<&ArgMatches<'_>>, global_config: GlobalConfig) -> i32 {
 	// just get defaults from the global config
 	let mut server_config = global_config.members.as_ref().unwrap().server.clone();
 
+	match server_config.chain_params() {
+		Ok(params) => global::set_chain_params(params),
+		Err(e) => {
+			println!("Could not load chain parameters: {:?}", e);
+			return 1;
+		}
+	}
+
 	if let Some(a) = server_args {
 		if let Some(port) = a.value_of("port") {
 			server_config.p2p_config.port = port.parse().unwrap();
@@ -118,6 +115,10 @@ pub fn server_command(
 			server_config.p2p_config.seeding_type = Seeding::List;
 			server_config.p2p_config.seeds = Some(seed_addrs);
 		}
+
+		if a.is_present("reindex") {
+			server_config.reindex = Some(true);
+		}
 	}
 
 	if let Some(a) = server_args {
@@ -125,6 +126,66 @@ pub fn server_command(
 			("run", _) => {
 				start_server(server_config);
 			}
+			("import-txhashset", Some(args)) => {
+				let h = match parse_hash(args.value_of("header_hash").unwrap()) {
+					Ok(h) => h,
+					Err(e) => {
+						println!("Invalid header hash: {}", e);
+						return 1;
+					}
+				};
+				let zip_path = Path::new(args.value_of("zip").unwrap());
+				match servers::Server::import_txhashset(server_config, zip_path, h) {
+					Ok(()) => println!("Imported txhashset at {} from {:?}", h, zip_path),
+					Err(e) => {
+						println!("Could not import txhashset: {:?}", e);
+						return 1;
+					}
+				}
+			}
+			("genesis", Some(args)) => {
+				let prev_root = match args.value_of("prev_root").map(parse_hash) {
+					Some(Ok(h)) => Some(h),
+					Some(Err(e)) => {
+						println!("Invalid previous root: {}", e);
+						return 1;
+					}
+					None => None,
+				};
+				match servers::Server::build_genesis(server_config, prev_root) {
+					Ok(gen) => println!(
+						"Built genesis block {}, written to the chain file",
+						gen.hash()
+					),
+					Err(e) => {
+						println!("Could not build genesis block: {:?}", e);
+						return 1;
+					}
+				}
+			}
+			("export-txhashset", Some(args)) => {
+				let h = match args.value_of("header_hash").map(parse_hash) {
+					Some(Ok(h)) => Some(h),
+					Some(Err(e)) => {
+						println!("Invalid header hash: {}", e);
+						return 1;
+					}
+					None => None,
+				};
+				let zip_path = Path::new(args.value_of("zip").unwrap());
+				match servers::Server::export_txhashset(server_config, zip_path, h) {
+					Ok(header) => println!(
+						"Exported txhashset at {} {} to {:?}",
+						header.height,
+						header.hash(),
+						zip_path
+					),
+					Err(e) => {
+						println!("Could not export txhashset: {:?}", e);
+						return 1;
+					}
+				}
+			}
 			("", _) => {
 				println!("Subcommand required, use 'grin help server' for details");
 			}
@@ -141,3 +202,7 @@ pub fn server_command(
 	}
 	0
 }
+
+fn parse_hash(hex: &str) -> Result<Hash, String> {
+	Hash::from_hex(hex).map_err(|e| format!("{:?}", e))
+}
@@ -142,7 +142,13 @@ fn real_main() -> i32 {
 		}
 		init_logger(Some(l));
 
-		global::set_mining_mode(config.members.unwrap().server.clone().chain_type);
+		match config.members.unwrap().server.chain_params() {
+			Ok(params) => global::set_chain_params(params),
+			Err(e) => {
+				println!("Could not load chain parameters: {:?}", e);
+				return 1;
+			}
+		}
 
 		if let Some(file_path) = &config.config_file_path {
 			info!(
@@ -159,6 +159,13 @@ impl TUIStatusListener for TUIStatusView {
 										)
 					}
 				}
+				SyncStatus::TxHashsetPibd { received, total } => {
+					let percent = if total > 0 { received * 100 / total } else { 0 };
+					format!(
+						"Downloading chain state segments for state sync: {}%, step 2/4",
+						percent
+					)
+				}
 				SyncStatus::TxHashsetSetup => {
 					"Preparing chain state for validation, step 3/4".to_string()
 				}
@@ -199,6 +206,17 @@ impl TUIStatusListener for TUIStatusView {
 					};
 					format!("Downloading blocks: {}%, step 4/4", percent)
 				}
+				SyncStatus::Reindex {
+					current_height,
+					highest_height,
+				} => {
+					let percent = if highest_height == 0 {
+						0
+					} else {
+						current_height * 100 / highest_height
+					};
+					format!("Reindexing chain state from local blocks: {}%", percent)
+				}
 			}
 		};
 		/*let basic_mining_config_status = {
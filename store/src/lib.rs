@@ -33,6 +33,7 @@ extern crate grin_util as util;
 
 pub mod leaf_set;
 pub mod lmdb;
+pub mod migration;
 pub mod pmmr;
 pub mod prune_list;
 pub mod types;
@@ -22,7 +22,9 @@ use lmdb_zero as lmdb;
 use lmdb_zero::traits::CreateCursor;
 use lmdb_zero::LmdbResultExt;
 
-use crate::core::ser;
+use crate::core::global::ChainParams;
+use crate::core::ser::{self, ProtocolVersion};
+use crate::migration::protocol_key;
 use crate::util::{RwLock, RwLockReadGuard};
 
 /// number of bytes to grow the database by when needed
@@ -45,6 +47,12 @@ pub enum Error {
 	/// Wraps a serialization error for Writeable or Readable
 	#[fail(display = "Serialization Error")]
 	SerErr(String),
+	/// The db was written with a newer schema than the one we support
+	#[fail(
+		display = "DB schema version {} newer than supported version {}",
+		_0, _1
+	)]
+	SchemaVersionErr(u32, u32),
 }
 
 impl From<lmdb::error::Error> for Error {
@@ -68,6 +76,8 @@ pub struct Store {
 	env: Arc<lmdb::Environment>,
 	db: RwLock<Option<Arc<lmdb::Database<'static>>>>,
 	name: String,
+	protocol_version: ProtocolVersion,
+	chain_params: Option<ChainParams>,
 }
 
 impl Store {
@@ -107,10 +117,12 @@ impl Store {
 			full_path,
 			env.info().as_ref().unwrap().mapsize
 		);
-		let res = Store {
+		let mut res = Store {
 			env: Arc::new(env),
 			db: RwLock::new(None),
 			name: db_name,
+			protocol_version: ProtocolVersion::local(),
+			chain_params: None,
 		};
 
 		{
@@ -121,9 +133,50 @@ impl Store {
 				&lmdb::DatabaseOptions::new(lmdb::db::CREATE),
 			)?));
 		}
+		res.protocol_version = res.init_protocol_version()?;
 		Ok(res)
 	}
 
+	/// Protocol version the values of the db are serialized with.
+	pub fn protocol_version(&self) -> ProtocolVersion {
+		self.protocol_version
+	}
+
+	/// Reads the values of the db (blocks and headers) as belonging to the
+	/// chain with the provided parameters.
+	pub fn with_chain_params(self, params: ChainParams) -> Store {
+		Store {
+			chain_params: Some(params),
+			..self
+		}
+	}
+
+	// Reads the protocol version recorded in the db, recording our own one
+	// in a new db. Dbs predating the record were written with version 1.
+	fn init_protocol_version(&self) -> Result<ProtocolVersion, Error> {
+		if let Some(version) = self.get_ser(&protocol_key())? {
+			return Ok(version);
+		}
+		let version = if self.is_empty()? {
+			ProtocolVersion::local()
+		} else {
+			ProtocolVersion(1)
+		};
+		let batch = self.batch()?;
+		batch.put_ser(&protocol_key(), &version)?;
+		batch.commit()?;
+		Ok(version)
+	}
+
+	fn is_empty(&self) -> Result<bool, Error> {
+		let db = self.db.read();
+		let txn = lmdb::ReadTransaction::new(self.env.clone())?;
+		let access = txn.access();
+		let mut cursor = txn.cursor(db.as_ref().unwrap().clone())?;
+		let first: Option<(&[u8], &[u8])> = cursor.first(&access).to_opt()?;
+		Ok(first.is_none())
+	}
+
 	/// Opens the database environment
 	pub fn open(&self) -> Result<(), Error> {
 		let mut w = self.db.write();
@@ -230,7 +283,7 @@ impl Store {
 	) -> Result<Option<T>, Error> {
 		let res: lmdb::error::Result<&[u8]> = access.get(&db.as_ref().unwrap(), key);
 		match res.to_opt() {
-			Ok(Some(mut res)) => match ser::deserialize(&mut res) {
+			Ok(Some(res)) => match deserialize(res, self.protocol_version, &self.chain_params) {
 				Ok(res) => Ok(Some(res)),
 				Err(e) => Err(Error::SerErr(format!("{}", e))),
 			},
@@ -259,6 +312,8 @@ impl Store {
 			cursor,
 			seek: false,
 			prefix: from.to_vec(),
+			version: self.protocol_version,
+			chain_params: self.chain_params.clone(),
 			_marker: marker::PhantomData,
 		})
 	}
@@ -296,7 +351,7 @@ impl<'a> Batch<'a> {
 	/// Writes a single key and its `Writeable` value to the db. Encapsulates
 	/// serialization.
 	pub fn put_ser<W: ser::Writeable>(&self, key: &[u8], value: &W) -> Result<(), Error> {
-		let ser_value = ser::ser_vec(value);
+		let ser_value = ser::ser_vec(value, self.store.protocol_version);
 		match ser_value {
 			Ok(data) => self.put(key, &data),
 			Err(err) => Err(Error::SerErr(format!("{}", err))),
@@ -360,6 +415,8 @@ where
 	cursor: Arc<lmdb::Cursor<'static, 'static>>,
 	seek: bool,
 	prefix: Vec<u8>,
+	version: ProtocolVersion,
+	chain_params: Option<ChainParams>,
 	_marker: marker::PhantomData<T>,
 }
 
@@ -393,7 +450,7 @@ where
 	fn deser_if_prefix_match(&self, key: &[u8], value: &[u8]) -> Option<(Vec<u8>, T)> {
 		let plen = self.prefix.len();
 		if plen == 0 || key[0..plen] == self.prefix[..] {
-			if let Ok(value) = ser::deserialize(&mut &value[..]) {
+			if let Ok(value) = deserialize(value, self.version, &self.chain_params) {
 				Some((key.to_vec(), value))
 			} else {
 				None
@@ -403,3 +460,15 @@ where
 		}
 	}
 }
+
+// Deserializes a value of the db, with the chain parameters if we have them.
+fn deserialize<T: ser::Readable>(
+	mut data: &[u8],
+	version: ProtocolVersion,
+	params: &Option<ChainParams>,
+) -> Result<T, ser::Error> {
+	match params {
+		Some(params) => ser::deserialize_with_params(&mut data, version, params),
+		None => ser::deserialize(&mut data, version),
+	}
+}
@@ -0,0 +1,173 @@
+// Copyright 2018 The Grin Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Schema versioning of our databases and ordered migrations between schema
+//! versions.
+//!
+//! The schema version is kept in the db itself. A db without a version is
+//! considered to be at version 0 (created before versioning was introduced).
+//! Migrations are run in order, one step at a time, each step being committed
+//! in the same batch as a progress marker so an interrupted migration
+//! resumes where it left off on the next start.
+
+use crate::core::ser::{self, Readable, Reader, Writeable, Writer};
+use crate::lmdb::{Batch, Error, Store};
+use crate::to_key;
+
+/// Prefix reserved for schema related keys in all our dbs.
+const SCHEMA_PREFIX: u8 = '#' as u8;
+const VERSION_KEY: &'static str = "version";
+const PROGRESS_KEY: &'static str = "progress";
+const PROTOCOL_KEY: &'static str = "protocol";
+
+fn version_key() -> Vec<u8> {
+	to_key(SCHEMA_PREFIX, &mut VERSION_KEY.as_bytes().to_vec())
+}
+
+fn progress_key() -> Vec<u8> {
+	to_key(SCHEMA_PREFIX, &mut PROGRESS_KEY.as_bytes().to_vec())
+}
+
+/// Key of the protocol version the values of a db are serialized with.
+pub(crate) fn protocol_key() -> Vec<u8> {
+	to_key(SCHEMA_PREFIX, &mut PROTOCOL_KEY.as_bytes().to_vec())
+}
+
+/// The schema version of a db.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SchemaVersion(pub u32);
+
+impl Writeable for SchemaVersion {
+	fn write<W: Writer>(&self, writer: &mut W) -> Result<(), ser::Error> {
+		writer.write_u32(self.0)
+	}
+}
+
+impl Readable for SchemaVersion {
+	fn read(reader: &mut dyn Reader) -> Result<SchemaVersion, ser::Error> {
+		Ok(SchemaVersion(reader.read_u32()?))
+	}
+}
+
+/// Progress of an in-flight migration, the opaque marker being provided by
+/// the migration itself.
+struct MigrationProgress {
+	version: u32,
+	marker: Vec<u8>,
+}
+
+impl Writeable for MigrationProgress {
+	fn write<W: Writer>(&self, writer: &mut W) -> Result<(), ser::Error> {
+		writer.write_u32(self.version)?;
+		writer.write_bytes(&self.marker)
+	}
+}
+
+impl Readable for MigrationProgress {
+	fn read(reader: &mut dyn Reader) -> Result<MigrationProgress, ser::Error> {
+		let version = reader.read_u32()?;
+		let marker = reader.read_bytes_len_prefix()?;
+		Ok(MigrationProgress { version, marker })
+	}
+}
+
+/// A migration of the db from the previous schema version to `version()`.
+pub trait Migration {
+	/// The schema version the db is at once this migration has completed.
+	fn version(&self) -> u32;
+
+	/// Short description of the migration, for logging purposes.
+	fn description(&self) -> &str;
+
+	/// Runs the next step of the migration in the provided batch, starting
+	/// from the provided progress marker (none for the first step).
+	/// Returns the marker to resume from, or none once the migration is
+	/// complete. Steps should be kept reasonably small, the batch is
+	/// committed after each one.
+	fn step(&self, batch: &Batch<'_>, progress: Option<Vec<u8>>) -> Result<Option<Vec<u8>>, Error>;
+}
+
+/// Reads the schema version of the db, version 0 if none was ever saved.
+pub fn schema_version(store: &Store) -> Result<u32, Error> {
+	let version: Option<SchemaVersion> = store.get_ser(&version_key())?;
+	Ok(version.map(|v| v.0).unwrap_or(0))
+}
+
+/// Brings the db up to the `current` schema version by running all the
+/// migrations (expected in version order) newer than the schema version of
+/// the db. Refuses to touch a db with a schema newer than `current`.
+pub fn migrate(
+	store: &Store,
+	current: u32,
+	migrations: &[Box<dyn Migration + '_>],
+) -> Result<(), Error> {
+	let version = schema_version(store)?;
+	if version > current {
+		return Err(Error::SchemaVersionErr(version, current));
+	}
+
+	for migration in migrations.iter().filter(|m| m.version() > version) {
+		if migration.version() > current {
+			break;
+		}
+		info!(
+			"migrate: running migration to schema version {}: {}",
+			migration.version(),
+			migration.description()
+		);
+
+		let progress: Option<MigrationProgress> = store.get_ser(&progress_key())?;
+		let mut marker = match progress {
+			Some(ref p) if p.version == migration.version() => {
+				info!("migrate: resuming interrupted migration");
+				Some(p.marker.clone())
+			}
+			_ => None,
+		};
+
+		loop {
+			let batch = store.batch()?;
+			marker = migration.step(&batch, marker)?;
+			match marker {
+				Some(ref m) => batch.put_ser(
+					&progress_key(),
+					&MigrationProgress {
+						version: migration.version(),
+						marker: m.clone(),
+					},
+				)?,
+				None => {
+					if batch.exists(&progress_key())? {
+						batch.delete(&progress_key())?;
+					}
+					batch.put_ser(&version_key(), &SchemaVersion(migration.version()))?;
+				}
+			}
+			batch.commit()?;
+			if marker.is_none() {
+				break;
+			}
+		}
+
+		info!("migrate: db now at schema version {}", migration.version());
+	}
+
+	// Nothing (left) to migrate, make sure the version is recorded.
+	if schema_version(store)? < current {
+		let batch = store.batch()?;
+		batch.put_ser(&version_key(), &SchemaVersion(current))?;
+		batch.commit()?;
+	}
+	Ok(())
+}
@@ -18,7 +18,7 @@ use std::{fs, io, time};
 use crate::core::core::hash::{Hash, Hashed};
 use crate::core::core::pmmr::{self, family, Backend};
 use crate::core::core::BlockHeader;
-use crate::core::ser::{FixedLength, PMMRable};
+use crate::core::ser::{FixedLength, PMMRable, ProtocolVersion};
 use crate::leaf_set::LeafSet;
 use crate::prune_list::PruneList;
 use crate::types::{AppendOnlyFile, DataFile, SizeEntry, SizeInfo};
@@ -32,6 +32,10 @@ const PMMR_PRUN_FILE: &str = "pmmr_prun.bin";
 const PMMR_SIZE_FILE: &str = "pmmr_size.bin";
 const REWIND_FILE_CLEANUP_DURATION_SECONDS: u64 = 60 * 60 * 24; // 24 hours as seconds
 
+/// The MMR files are exchanged as is in txhashset archives, so their
+/// serialization stays the same whatever protocol version we speak.
+const PMMR_PROTOCOL_VERSION: ProtocolVersion = ProtocolVersion(1);
+
 /// The list of PMMR_Files for internal purposes
 pub const PMMR_FILES: [&str; 4] = [
 	PMMR_HASH_FILE,
@@ -209,14 +213,23 @@ impl<T: PMMRable> PMMRBackend<T> {
 			SizeInfo::VariableSize(Box::new(AppendOnlyFile::open(
 				data_dir.join(PMMR_SIZE_FILE),
 				SizeInfo::FixedSize(SizeEntry::LEN as u16),
+				PMMR_PROTOCOL_VERSION,
 			)?))
 		};
 
 		// Hash file is always "fixed size" and we use 32 bytes per hash.
 		let hash_size_info = SizeInfo::FixedSize(Hash::LEN as u16);
 
-		let hash_file = DataFile::open(&data_dir.join(PMMR_HASH_FILE), hash_size_info)?;
-		let data_file = DataFile::open(&data_dir.join(PMMR_DATA_FILE), size_info)?;
+		let hash_file = DataFile::open(
+			&data_dir.join(PMMR_HASH_FILE),
+			hash_size_info,
+			PMMR_PROTOCOL_VERSION,
+		)?;
+		let data_file = DataFile::open(
+			&data_dir.join(PMMR_DATA_FILE),
+			size_info,
+			PMMR_PROTOCOL_VERSION,
+		)?;
 
 		let leaf_set_path = data_dir.join(PMMR_LEAF_FILE);
 
@@ -297,6 +310,38 @@ impl<T: PMMRable> PMMRBackend<T> {
 		self.leaf_set.flush()
 	}
 
+	/// Appends a hash to the hash file. The caller is responsible for
+	/// appending the hashes in position order, skipping compacted nodes.
+	/// Used, along with `append_data` and `append_pruned_root`, to rebuild a
+	/// (pruned) MMR node by node when receiving it in segments.
+	pub fn append_hash(&mut self, hash: Hash) -> io::Result<()> {
+		self.hash_file.append(&hash)?;
+		Ok(())
+	}
+
+	/// Appends a leaf element to the data file, see `append_hash`.
+	pub fn append_data(&mut self, data: &T::E) -> io::Result<()> {
+		self.data_file.append(data)?;
+		Ok(())
+	}
+
+	/// Records the node at the provided position as the root of a compacted
+	/// subtree, see `append_hash`.
+	pub fn append_pruned_root(&mut self, pos: u64) {
+		self.prune_list.append(pos);
+	}
+
+	/// Adds the leaf at the provided position to the leaf_set.
+	pub fn add_leaf(&mut self, pos: u64) {
+		self.leaf_set.add(pos);
+	}
+
+	/// Syncs the prune_list to disk, only required after `append_pruned_root`
+	/// as compaction takes care of it otherwise.
+	pub fn sync_prune_list(&mut self) -> io::Result<()> {
+		self.prune_list.flush()
+	}
+
 	/// Discard the current, non synced state of the backend.
 	pub fn discard(&mut self) {
 		self.hash_file.discard();
@@ -245,6 +245,14 @@ impl PruneList {
 		}
 	}
 
+	/// Push the node at the provided position in the prune list as a pruned
+	/// root, as is (without compacting it with a pruned sibling). Caches are
+	/// only rebuilt on flush. Used when rebuilding a pruned MMR whose pruned
+	/// subtrees are already known.
+	pub fn append(&mut self, pos: u64) {
+		self.bitmap.add(pos as u32);
+	}
+
 	/// Number of entries in the prune_list.
 	pub fn len(&self) -> u64 {
 		self.bitmap.cardinality()
@@ -0,0 +1,171 @@
+// Copyright 2018 The Grin Developers
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use grin_store as store;
+use grin_util as util;
+
+use self::store::migration::{self, Migration};
+use grin_core::ser::ProtocolVersion;
+use std::cell::RefCell;
+use std::fs;
+use std::rc::Rc;
+
+fn clean_output_dir(test_dir: &str) {
+	let _ = fs::remove_dir_all(test_dir);
+}
+
+fn setup(test_dir: &str) {
+	util::init_test_logger();
+	clean_output_dir(test_dir);
+}
+
+fn step_key(version: u32, step: u64) -> Vec<u8> {
+	store::to_key(b'T', &mut format!("{}-{}", version, step).into_bytes())
+}
+
+/// Test migration writing one key per step, optionally failing at a given step.
+struct TestMigration {
+	version: u32,
+	steps: u64,
+	fail_at: Option<u64>,
+	run: Rc<RefCell<Vec<u64>>>,
+}
+
+impl TestMigration {
+	fn new(version: u32, steps: u64, fail_at: Option<u64>) -> TestMigration {
+		TestMigration {
+			version,
+			steps,
+			fail_at,
+			run: Rc::new(RefCell::new(vec![])),
+		}
+	}
+}
+
+impl Migration for TestMigration {
+	fn version(&self) -> u32 {
+		self.version
+	}
+
+	fn description(&self) -> &str {
+		"test migration"
+	}
+
+	fn step(
+		&self,
+		batch: &store::Batch<'_>,
+		progress: Option<Vec<u8>>,
+	) -> Result<Option<Vec<u8>>, store::Error> {
+		let step = progress
+			.map(|m| String::from_utf8(m).unwrap().parse().unwrap())
+			.unwrap_or(0);
+		batch.put(&step_key(self.version, step), &[1])?;
+		if self.fail_at == Some(step) {
+			return Err(store::Error::SerErr("simulated crash".to_owned()));
+		}
+		self.run.borrow_mut().push(step);
+		if step + 1 < self.steps {
+			Ok(Some(format!("{}", step + 1).into_bytes()))
+		} else {
+			Ok(None)
+		}
+	}
+}
+
+#[test]
+fn migrate_in_order() -> Result<(), store::Error> {
+	let test_dir = "test_output/migrate_in_order";
+	setup(test_dir);
+	{
+		let store = store::Store::new(test_dir, Some("test"), None, None)?;
+		assert_eq!(migration::schema_version(&store)?, 0);
+
+		let migrations: Vec<Box<dyn Migration>> = vec![
+			Box::new(TestMigration::new(1, 3, None)),
+			Box::new(TestMigration::new(2, 2, None)),
+		];
+		migration::migrate(&store, 2, &migrations)?;
+		assert_eq!(migration::schema_version(&store)?, 2);
+		for step in 0..3 {
+			assert!(store.exists(&step_key(1, step))?);
+		}
+		for step in 0..2 {
+			assert!(store.exists(&step_key(2, step))?);
+		}
+
+		// Nothing left to run when already at the current version.
+		let again = vec![Box::new(TestMigration::new(2, 2, None)) as Box<dyn Migration>];
+		migration::migrate(&store, 2, &again)?;
+		assert_eq!(migration::schema_version(&store)?, 2);
+
+		// A db with a newer schema than we support is refused.
+		match migration::migrate(&store, 1, &[]) {
+			Err(store::Error::SchemaVersionErr(2, 1)) => {}
+			res => panic!("unexpected result: {:?}", res),
+		}
+
+		// Bumping the version without any migration simply records it.
+		migration::migrate(&store, 3, &[])?;
+		assert_eq!(migration::schema_version(&store)?, 3);
+	}
+	clean_output_dir(test_dir);
+	Ok(())
+}
+
+#[test]
+fn migrate_resumes_after_crash() -> Result<(), store::Error> {
+	let test_dir = "test_output/migrate_resumes";
+	setup(test_dir);
+	{
+		let store = store::Store::new(test_dir, Some("test"), None, None)?;
+
+		// Crash in the middle of the migration, the failed step is discarded.
+		let crashing = vec![Box::new(TestMigration::new(1, 4, Some(2))) as Box<dyn Migration>];
+		assert!(migration::migrate(&store, 1, &crashing).is_err());
+		assert_eq!(migration::schema_version(&store)?, 0);
+		assert!(store.exists(&step_key(1, 1))?);
+		assert!(!store.exists(&step_key(1, 2))?);
+
+		// Restarting picks up from the last committed step.
+		let resumed = TestMigration::new(1, 4, None);
+		let run = resumed.run.clone();
+		let migrations = vec![Box::new(resumed) as Box<dyn Migration>];
+		migration::migrate(&store, 1, &migrations)?;
+		assert_eq!(*run.borrow(), vec![2, 3]);
+		assert_eq!(migration::schema_version(&store)?, 1);
+		for step in 0..4 {
+			assert!(store.exists(&step_key(1, step))?);
+		}
+	}
+	clean_output_dir(test_dir);
+	Ok(())
+}
+
+#[test]
+fn protocol_version_recorded() -> Result<(), store::Error> {
+	let test_dir = "test_output/protocol_version";
+	setup(test_dir);
+	{
+		let store = store::Store::new(test_dir, Some("test"), None, None)?;
+		assert_eq!(store.protocol_version(), ProtocolVersion::local());
+	}
+	{
+		// The version recorded on creation is kept on reopening.
+		let store = store::Store::new(test_dir, Some("test"), None, None)?;
+		assert_eq!(store.protocol_version(), ProtocolVersion::local());
+		assert!(store.exists(&store::to_key(b'#', &mut b"protocol".to_vec()))?);
+	}
+	clean_output_dir(test_dir);
+	Ok(())
+}
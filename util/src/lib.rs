@@ -40,7 +40,7 @@ pub use crate::logger::{init_logger, init_test_logger};
 
 // Static secp instance
 pub mod secp_static;
-pub use crate::secp_static::static_secp_instance;
+pub use crate::secp_static::{static_secp_instance, with_thread_secp};
 
 pub mod types;
 pub use crate::types::{LogLevel, LoggingConfig, ZeroingString};
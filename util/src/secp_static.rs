@@ -26,6 +26,22 @@ lazy_static! {
 		= Arc::new(Mutex::new(secp::Secp256k1::with_caps(secp::ContextFlag::Commit)));
 }
 
+thread_local! {
+	/// Thread local secp instance, allows verification to run concurrently on
+	/// multiple threads without contending on the lock of the static instance.
+	static THREAD_SECP: secp::Secp256k1 = secp::Secp256k1::with_caps(secp::ContextFlag::Commit);
+}
+
+/// Calls the provided closure with the secp instance local to the current thread.
+/// Only to be used for verification (rangeproofs, signatures), nothing secret
+/// goes through these instances so we do not randomize them.
+pub fn with_thread_secp<F, T>(f: F) -> T
+where
+	F: FnOnce(&secp::Secp256k1) -> T,
+{
+	THREAD_SECP.with(f)
+}
+
 /// Returns the static instance, but calls randomize on it as well
 /// (Recommended to avoid side channel attacks
 pub fn static_secp_instance() -> Arc<Mutex<secp::Secp256k1>> {